@@ -0,0 +1,576 @@
+use crate::common::errors::EmulatorError;
+use crate::savestate::{Section, SectionReader};
+
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14,
+    12, 16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
+];
+const DUTY_CYCLES: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+const TRIANGLE_SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0,
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+];
+const NOISE_PERIODS: [u16; 16] = [4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068];
+const DMC_RATES: [u16; 16] = [428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54];
+
+#[derive(Default)]
+pub(super) struct LengthCounter {
+    enabled: bool,
+    halt: bool,
+    value: u8,
+}
+
+impl LengthCounter {
+    fn load(&mut self, index: u8) {
+        if self.enabled {
+            self.value = LENGTH_TABLE[index as usize];
+        }
+    }
+
+    pub(super) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.value = 0;
+        }
+    }
+
+    pub(super) fn active(&self) -> bool {
+        self.value > 0
+    }
+
+    fn clock(&mut self) {
+        if !self.halt && self.value > 0 {
+            self.value -= 1;
+        }
+    }
+
+    fn save(&self, section: &mut Section) {
+        section.write_bool(self.enabled);
+        section.write_bool(self.halt);
+        section.write_u8(self.value);
+    }
+
+    fn load_state(&mut self, reader: &mut SectionReader) -> Result<(), EmulatorError> {
+        self.enabled = reader.read_bool()?;
+        self.halt = reader.read_bool()?;
+        self.value = reader.read_u8()?;
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Envelope {
+    start: bool,
+    constant: bool,
+    looping: bool,
+    volume: u8,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    fn write(&mut self, data: u8) {
+        self.looping = data & 0x20 != 0;
+        self.constant = data & 0x10 != 0;
+        self.volume = data & 0x0F;
+    }
+
+    fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+        } else if self.divider == 0 {
+            self.divider = self.volume;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.looping {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.constant { self.volume } else { self.decay }
+    }
+
+    fn save(&self, section: &mut Section) {
+        section.write_bool(self.start);
+        section.write_bool(self.constant);
+        section.write_bool(self.looping);
+        section.write_u8(self.volume);
+        section.write_u8(self.divider);
+        section.write_u8(self.decay);
+    }
+
+    fn load_state(&mut self, reader: &mut SectionReader) -> Result<(), EmulatorError> {
+        self.start = reader.read_bool()?;
+        self.constant = reader.read_bool()?;
+        self.looping = reader.read_bool()?;
+        self.volume = reader.read_u8()?;
+        self.divider = reader.read_u8()?;
+        self.decay = reader.read_u8()?;
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub(super) struct Pulse {
+    /// Pulse 1 negates with ones' complement in its sweep unit, pulse 2 with two's complement.
+    ones_complement: bool,
+    pub(super) length: LengthCounter,
+    envelope: Envelope,
+    duty: u8,
+    step: u8,
+    period: u16,
+    timer: u16,
+    sweep_enabled: bool,
+    sweep_period: u8,
+    sweep_negate: bool,
+    sweep_shift: u8,
+    sweep_divider: u8,
+    sweep_reload: bool,
+}
+
+impl Pulse {
+    pub(super) fn new(ones_complement: bool) -> Self {
+        Pulse { ones_complement, ..Pulse::default() }
+    }
+
+    pub(super) fn write(&mut self, register: u16, data: u8) {
+        match register % 4 {
+            0 => {
+                self.duty = data >> 6;
+                self.length.halt = data & 0x20 != 0;
+                self.envelope.write(data);
+            }
+            1 => {
+                self.sweep_enabled = data & 0x80 != 0;
+                self.sweep_period = (data >> 4) & 0b111;
+                self.sweep_negate = data & 0x08 != 0;
+                self.sweep_shift = data & 0b111;
+                self.sweep_reload = true;
+            }
+            2 => self.period = self.period & 0x0700 | data as u16,
+            _ => {
+                self.period = self.period & 0x00FF | ((data & 0b111) as u16) << 8;
+                self.length.load(data >> 3);
+                self.step = 0;
+                self.envelope.start = true;
+            }
+        }
+    }
+
+    /// Clocked every other CPU cycle.
+    pub(super) fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            self.step = (self.step + 1) % 8;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub(super) fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub(super) fn clock_half_frame(&mut self) {
+        self.length.clock();
+        if self.sweep_divider == 0 && self.sweep_enabled && self.sweep_shift > 0 && !self.muted() {
+            self.period = self.sweep_target();
+        }
+        if self.sweep_divider == 0 || self.sweep_reload {
+            self.sweep_divider = self.sweep_period;
+            self.sweep_reload = false;
+        } else {
+            self.sweep_divider -= 1;
+        }
+    }
+
+    fn sweep_target(&self) -> u16 {
+        let change = self.period >> self.sweep_shift;
+        if self.sweep_negate {
+            self.period.saturating_sub(change + self.ones_complement as u16)
+        } else {
+            self.period + change
+        }
+    }
+
+    fn muted(&self) -> bool {
+        self.period < 8 || self.sweep_target() > 0x07FF
+    }
+
+    pub(super) fn output(&self) -> u8 {
+        if !self.length.active() || self.muted() || DUTY_CYCLES[self.duty as usize][self.step as usize] == 0 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+
+    pub(super) fn save(&self, section: &mut Section) {
+        self.length.save(section);
+        self.envelope.save(section);
+        section.write_u8(self.duty);
+        section.write_u8(self.step);
+        section.write_u16(self.period);
+        section.write_u16(self.timer);
+        section.write_bool(self.sweep_enabled);
+        section.write_u8(self.sweep_period);
+        section.write_bool(self.sweep_negate);
+        section.write_u8(self.sweep_shift);
+        section.write_u8(self.sweep_divider);
+        section.write_bool(self.sweep_reload);
+    }
+
+    pub(super) fn load_state(&mut self, reader: &mut SectionReader) -> Result<(), EmulatorError> {
+        self.length.load_state(reader)?;
+        self.envelope.load_state(reader)?;
+        self.duty = reader.read_u8()? & 0b11;
+        self.step = reader.read_u8()? % 8;
+        self.period = reader.read_u16()?;
+        self.timer = reader.read_u16()?;
+        self.sweep_enabled = reader.read_bool()?;
+        self.sweep_period = reader.read_u8()?;
+        self.sweep_negate = reader.read_bool()?;
+        self.sweep_shift = reader.read_u8()? & 0b111;
+        self.sweep_divider = reader.read_u8()?;
+        self.sweep_reload = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub(super) struct Triangle {
+    pub(super) length: LengthCounter,
+    step: u8,
+    period: u16,
+    timer: u16,
+    linear_reload_value: u8,
+    linear_counter: u8,
+    linear_reload: bool,
+}
+
+impl Triangle {
+    pub(super) fn write(&mut self, register: u16, data: u8) {
+        match register % 4 {
+            0 => {
+                self.length.halt = data & 0x80 != 0;
+                self.linear_reload_value = data & 0x7F;
+            }
+            1 => {}
+            2 => self.period = self.period & 0x0700 | data as u16,
+            _ => {
+                self.period = self.period & 0x00FF | ((data & 0b111) as u16) << 8;
+                self.length.load(data >> 3);
+                self.linear_reload = true;
+            }
+        }
+    }
+
+    /// Clocked every CPU cycle.
+    pub(super) fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            if self.length.active() && self.linear_counter > 0 {
+                self.step = (self.step + 1) % 32;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub(super) fn clock_quarter_frame(&mut self) {
+        if self.linear_reload {
+            self.linear_counter = self.linear_reload_value;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        // The control flag doubles as the length counter halt flag.
+        if !self.length.halt {
+            self.linear_reload = false;
+        }
+    }
+
+    pub(super) fn clock_half_frame(&mut self) {
+        self.length.clock();
+    }
+
+    pub(super) fn output(&self) -> u8 {
+        TRIANGLE_SEQUENCE[self.step as usize]
+    }
+
+    pub(super) fn save(&self, section: &mut Section) {
+        self.length.save(section);
+        section.write_u8(self.step);
+        section.write_u16(self.period);
+        section.write_u16(self.timer);
+        section.write_u8(self.linear_reload_value);
+        section.write_u8(self.linear_counter);
+        section.write_bool(self.linear_reload);
+    }
+
+    pub(super) fn load_state(&mut self, reader: &mut SectionReader) -> Result<(), EmulatorError> {
+        self.length.load_state(reader)?;
+        self.step = reader.read_u8()? % 32;
+        self.period = reader.read_u16()?;
+        self.timer = reader.read_u16()?;
+        self.linear_reload_value = reader.read_u8()?;
+        self.linear_counter = reader.read_u8()?;
+        self.linear_reload = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+pub(super) struct Noise {
+    pub(super) length: LengthCounter,
+    envelope: Envelope,
+    short_mode: bool,
+    period: u16,
+    timer: u16,
+    shift: u16,
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Noise {
+            length: LengthCounter::default(),
+            envelope: Envelope::default(),
+            short_mode: false,
+            period: NOISE_PERIODS[0],
+            timer: 0,
+            shift: 1,
+        }
+    }
+}
+
+impl Noise {
+    pub(super) fn write(&mut self, register: u16, data: u8) {
+        match register % 4 {
+            0 => {
+                self.length.halt = data & 0x20 != 0;
+                self.envelope.write(data);
+            }
+            1 => {}
+            2 => {
+                self.short_mode = data & 0x80 != 0;
+                self.period = NOISE_PERIODS[(data & 0x0F) as usize];
+            }
+            _ => {
+                self.length.load(data >> 3);
+                self.envelope.start = true;
+            }
+        }
+    }
+
+    /// Clocked every CPU cycle; the period table is in CPU cycles.
+    pub(super) fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period - 1;
+            let tap = if self.short_mode { 6 } else { 1 };
+            let feedback = (self.shift ^ (self.shift >> tap)) & 1;
+            self.shift = (self.shift >> 1) | feedback << 14;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub(super) fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub(super) fn clock_half_frame(&mut self) {
+        self.length.clock();
+    }
+
+    pub(super) fn output(&self) -> u8 {
+        if !self.length.active() || self.shift & 1 != 0 { 0 } else { self.envelope.output() }
+    }
+
+    pub(super) fn save(&self, section: &mut Section) {
+        self.length.save(section);
+        self.envelope.save(section);
+        section.write_bool(self.short_mode);
+        section.write_u16(self.period);
+        section.write_u16(self.timer);
+        section.write_u16(self.shift);
+    }
+
+    pub(super) fn load_state(&mut self, reader: &mut SectionReader) -> Result<(), EmulatorError> {
+        self.length.load_state(reader)?;
+        self.envelope.load_state(reader)?;
+        self.short_mode = reader.read_bool()?;
+        self.period = reader.read_u16()?.max(1);
+        self.timer = reader.read_u16()?;
+        self.shift = reader.read_u16()?;
+        Ok(())
+    }
+}
+
+pub(super) struct Dmc {
+    pub(super) irq: bool,
+    irq_enabled: bool,
+    looping: bool,
+    rate: u16,
+    timer: u16,
+    output: u8,
+    sample_address: u16,
+    sample_length: u16,
+    address: u16,
+    pub(super) bytes_remaining: u16,
+    buffer: Option<u8>,
+    shift: u8,
+    bits_remaining: u8,
+    silence: bool,
+}
+
+impl Default for Dmc {
+    fn default() -> Self {
+        Dmc {
+            irq: false,
+            irq_enabled: false,
+            looping: false,
+            rate: DMC_RATES[0],
+            timer: 0,
+            output: 0,
+            sample_address: 0xC000,
+            sample_length: 1,
+            address: 0xC000,
+            bytes_remaining: 0,
+            buffer: None,
+            shift: 0,
+            bits_remaining: 8,
+            silence: true,
+        }
+    }
+}
+
+impl Dmc {
+    pub(super) fn write(&mut self, register: u16, data: u8) {
+        match register % 4 {
+            0 => {
+                self.irq_enabled = data & 0x80 != 0;
+                if !self.irq_enabled {
+                    self.irq = false;
+                }
+                self.looping = data & 0x40 != 0;
+                self.rate = DMC_RATES[(data & 0x0F) as usize];
+            }
+            1 => self.output = data & 0x7F,
+            2 => self.sample_address = 0xC000 | (data as u16) << 6,
+            _ => self.sample_length = ((data as u16) << 4) + 1,
+        }
+    }
+
+    pub(super) fn set_enabled(&mut self, enabled: bool) {
+        self.irq = false;
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    /// The address the memory reader needs to fetch next, if its buffer is empty.
+    pub(super) fn pending_fetch(&self) -> Option<u16> {
+        (self.buffer.is_none() && self.bytes_remaining > 0).then_some(self.address)
+    }
+
+    pub(super) fn fill(&mut self, data: u8) {
+        self.buffer = Some(data);
+        self.address = if self.address == 0xFFFF { 0x8000 } else { self.address + 1 };
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq = true;
+            }
+        }
+    }
+
+    /// Clocked every CPU cycle; the rate table is in CPU cycles.
+    pub(super) fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.rate - 1;
+        if !self.silence {
+            if self.shift & 1 != 0 {
+                if self.output <= 125 {
+                    self.output += 2;
+                }
+            } else if self.output >= 2 {
+                self.output -= 2;
+            }
+        }
+        self.shift >>= 1;
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.buffer.take() {
+                Some(data) => {
+                    self.silence = false;
+                    self.shift = data;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+
+    pub(super) fn output(&self) -> u8 {
+        self.output
+    }
+
+    pub(super) fn save(&self, section: &mut Section) {
+        section.write_bool(self.irq);
+        section.write_bool(self.irq_enabled);
+        section.write_bool(self.looping);
+        section.write_u16(self.rate);
+        section.write_u16(self.timer);
+        section.write_u8(self.output);
+        section.write_u16(self.sample_address);
+        section.write_u16(self.sample_length);
+        section.write_u16(self.address);
+        section.write_u16(self.bytes_remaining);
+        section.write_bool(self.buffer.is_some());
+        section.write_u8(self.buffer.unwrap_or(0));
+        section.write_u8(self.shift);
+        section.write_u8(self.bits_remaining);
+        section.write_bool(self.silence);
+    }
+
+    pub(super) fn load_state(&mut self, reader: &mut SectionReader) -> Result<(), EmulatorError> {
+        self.irq = reader.read_bool()?;
+        self.irq_enabled = reader.read_bool()?;
+        self.looping = reader.read_bool()?;
+        self.rate = reader.read_u16()?.max(1);
+        self.timer = reader.read_u16()?;
+        self.output = reader.read_u8()? & 0x7F;
+        self.sample_address = reader.read_u16()?;
+        self.sample_length = reader.read_u16()?;
+        self.address = reader.read_u16()?;
+        self.bytes_remaining = reader.read_u16()?;
+        let buffered = reader.read_bool()?;
+        let buffer = reader.read_u8()?;
+        self.buffer = buffered.then_some(buffer);
+        self.shift = reader.read_u8()?;
+        self.bits_remaining = reader.read_u8()?.clamp(1, 8);
+        self.silence = reader.read_bool()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,266 @@
+mod channels;
+mod test;
+
+use crate::apu::channels::{Noise, Pulse, Triangle, Dmc};
+use crate::common::constants::{APU_STATUS, CONTROLLER_2, CPU_CLOCK_RATE};
+use crate::common::errors::EmulatorError;
+use crate::savestate::{SaveState, Section};
+
+const QUARTER_FRAME_CYCLES: [u16; 2] = [7457, 22371];
+const HALF_FRAME_CYCLE: u16 = 14913;
+const FOUR_STEP_CYCLES: u16 = 29829;
+const FIVE_STEP_CYCLES: u16 = 37281;
+/// Pole of the high-pass filter that removes the mixer's DC offset, like the console's output stage.
+const HIGH_PASS_POLE: f32 = 0.995;
+
+/// The 2A03's sound channels, frame counter and mixer. Samples are only produced once an output
+/// rate is set with `set_sample_rate`, and accumulate until the front-end takes them.
+pub struct APU {
+    pulse: [Pulse; 2],
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+    five_step: bool,
+    irq_inhibit: bool,
+    frame_cycle: u16,
+    frame_irq: bool,
+    cycle: u64,
+    sample_rate: Option<f64>,
+    sample_clock: f64,
+    sample_sum: f32,
+    sample_count: u32,
+    filter_input: f32,
+    filter_output: f32,
+    samples: Vec<f32>,
+}
+
+impl Default for APU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl APU {
+    pub fn new() -> Self {
+        APU {
+            pulse: [Pulse::new(true), Pulse::new(false)],
+            triangle: Triangle::default(),
+            noise: Noise::default(),
+            dmc: Dmc::default(),
+            five_step: false,
+            irq_inhibit: false,
+            frame_cycle: 0,
+            frame_irq: false,
+            cycle: 0,
+            sample_rate: None,
+            sample_clock: 0.0,
+            sample_sum: 0.0,
+            sample_count: 0,
+            filter_input: 0.0,
+            filter_output: 0.0,
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn power_on(&mut self) {
+        let sample_rate = self.sample_rate;
+        *self = APU::new();
+        self.sample_rate = sample_rate;
+    }
+
+    pub fn sample_rate(&self) -> Option<f64> {
+        self.sample_rate
+    }
+
+    /// Sets the rate, in Hz, at which samples are produced. Front-ends can nudge it to keep their
+    /// audio buffer from running dry or overflowing.
+    pub fn set_sample_rate(&mut self, rate: f64) {
+        self.sample_rate = Some(rate);
+    }
+
+    /// Takes the samples produced since the last call, in the range -1.0 to 1.0.
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.samples)
+    }
+
+    pub fn frame_irq(&self) -> bool {
+        self.frame_irq
+    }
+
+    pub fn dmc_irq(&self) -> bool {
+        self.dmc.irq
+    }
+
+    /// Reads $4015, which acknowledges the frame interrupt.
+    pub fn read_status(&mut self) -> u8 {
+        let status = self.peek_status();
+        self.frame_irq = false;
+        status
+    }
+
+    pub fn peek_status(&self) -> u8 {
+        self.pulse[0].length.active() as u8
+            | (self.pulse[1].length.active() as u8) << 1
+            | (self.triangle.length.active() as u8) << 2
+            | (self.noise.length.active() as u8) << 3
+            | ((self.dmc.bytes_remaining > 0) as u8) << 4
+            | (self.frame_irq as u8) << 6
+            | (self.dmc.irq as u8) << 7
+    }
+
+    pub fn write_register(&mut self, address: u16, data: u8) {
+        match address {
+            0x4000..=0x4003 => self.pulse[0].write(address, data),
+            0x4004..=0x4007 => self.pulse[1].write(address, data),
+            0x4008..=0x400B => self.triangle.write(address, data),
+            0x400C..=0x400F => self.noise.write(address, data),
+            0x4010..=0x4013 => self.dmc.write(address, data),
+            APU_STATUS => {
+                self.pulse[0].length.set_enabled(data & 0x01 != 0);
+                self.pulse[1].length.set_enabled(data & 0x02 != 0);
+                self.triangle.length.set_enabled(data & 0x04 != 0);
+                self.noise.length.set_enabled(data & 0x08 != 0);
+                self.dmc.set_enabled(data & 0x10 != 0);
+            }
+            CONTROLLER_2 => {
+                self.five_step = data & 0x80 != 0;
+                self.irq_inhibit = data & 0x40 != 0;
+                if self.irq_inhibit {
+                    self.frame_irq = false;
+                }
+                self.frame_cycle = 0;
+                if self.five_step {
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Advances the APU by `cycles` CPU cycles, fetching DMC sample bytes through `read`. Returns
+    /// the CPU cycles stolen by those fetches.
+    pub fn tick(&mut self, cycles: u64, mut read: impl FnMut(u16) -> u8) -> u64 {
+        let mut stolen = 0;
+        for _ in 0..cycles {
+            self.clock_frame_counter();
+            self.triangle.clock_timer();
+            self.noise.clock_timer();
+            self.dmc.clock_timer();
+            if self.cycle % 2 == 1 {
+                self.pulse.iter_mut().for_each(Pulse::clock_timer);
+            }
+            if let Some(address) = self.dmc.pending_fetch() {
+                self.dmc.fill(read(address));
+                stolen += 4;
+            }
+            self.cycle += 1;
+            if let Some(rate) = self.sample_rate {
+                self.output_sample(rate);
+            }
+        }
+        stolen
+    }
+
+    fn clock_frame_counter(&mut self) {
+        self.frame_cycle += 1;
+        match (self.frame_cycle, self.five_step) {
+            (cycle, _) if QUARTER_FRAME_CYCLES.contains(&cycle) => self.clock_quarter_frame(),
+            (HALF_FRAME_CYCLE, _) | (FIVE_STEP_CYCLES, true) => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+                if self.five_step && self.frame_cycle == FIVE_STEP_CYCLES {
+                    self.frame_cycle = 0;
+                }
+            }
+            (FOUR_STEP_CYCLES, false) => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+                if !self.irq_inhibit {
+                    self.frame_irq = true;
+                }
+                self.frame_cycle = 0;
+            }
+            _ => {}
+        }
+    }
+
+    fn clock_quarter_frame(&mut self) {
+        self.pulse.iter_mut().for_each(Pulse::clock_quarter_frame);
+        self.triangle.clock_quarter_frame();
+        self.noise.clock_quarter_frame();
+    }
+
+    fn clock_half_frame(&mut self) {
+        self.pulse.iter_mut().for_each(Pulse::clock_half_frame);
+        self.triangle.clock_half_frame();
+        self.noise.clock_half_frame();
+    }
+
+    /// The non-linear mixer, from 0.0 to about 1.0.
+    fn mix(&self) -> f32 {
+        let pulse = (self.pulse[0].output() + self.pulse[1].output()) as f32;
+        let pulse_out = if pulse == 0.0 { 0.0 } else { 95.88 / (8128.0 / pulse + 100.0) };
+        let tnd = self.triangle.output() as f32 / 8227.0 + self.noise.output() as f32 / 12241.0 + self.dmc.output() as f32 / 22638.0;
+        let tnd_out = if tnd == 0.0 { 0.0 } else { 159.79 / (1.0 / tnd + 100.0) };
+        pulse_out + tnd_out
+    }
+
+    /// Averages the mixer over each output period and high-pass filters the result.
+    fn output_sample(&mut self, rate: f64) {
+        self.sample_sum += self.mix();
+        self.sample_count += 1;
+        self.sample_clock += rate;
+        if self.sample_clock < CPU_CLOCK_RATE {
+            return;
+        }
+        self.sample_clock -= CPU_CLOCK_RATE;
+        let input = self.sample_sum / self.sample_count as f32;
+        self.filter_output = HIGH_PASS_POLE * self.filter_output + input - self.filter_input;
+        self.filter_input = input;
+        self.samples.push(self.filter_output.clamp(-1.0, 1.0));
+        self.sample_sum = 0.0;
+        self.sample_count = 0;
+    }
+
+    pub(crate) fn save_state(&self, state: &mut SaveState) {
+        let mut section = Section::new(*b"APU ");
+        self.pulse.iter().for_each(|pulse| pulse.save(&mut section));
+        self.triangle.save(&mut section);
+        self.noise.save(&mut section);
+        self.dmc.save(&mut section);
+        section.write_bool(self.five_step);
+        section.write_bool(self.irq_inhibit);
+        section.write_u16(self.frame_cycle);
+        section.write_bool(self.frame_irq);
+        section.write_u64(self.cycle);
+        state.add_section(section);
+    }
+
+    /// Restores the channels; the output rate and any samples not yet taken are kept.
+    /// Decodes the APU section into a new APU, leaving this one as it is until `restore`.
+    pub(crate) fn decode_state(&self, state: &SaveState) -> Result<APU, EmulatorError> {
+        let mut section = state.section(*b"APU ")?;
+        let mut apu = APU::new();
+        for pulse in apu.pulse.iter_mut() {
+            pulse.load_state(&mut section)?;
+        }
+        apu.triangle.load_state(&mut section)?;
+        apu.noise.load_state(&mut section)?;
+        apu.dmc.load_state(&mut section)?;
+        apu.five_step = section.read_bool()?;
+        apu.irq_inhibit = section.read_bool()?;
+        apu.frame_cycle = section.read_u16()?;
+        apu.frame_irq = section.read_bool()?;
+        apu.cycle = section.read_u64()?;
+        section.finish()?;
+        apu.sample_rate = self.sample_rate;
+        Ok(apu)
+    }
+
+    /// Replaces this APU with a decoded one, keeping the samples not yet played.
+    pub(crate) fn restore(&mut self, mut apu: APU) {
+        apu.samples = std::mem::take(&mut self.samples);
+        *self = apu;
+    }
+}
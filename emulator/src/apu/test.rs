@@ -0,0 +1,167 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod test {
+    use crate::apu::APU;
+    use crate::common::constants::{APU_STATUS, CONTROLLER_2};
+    use crate::cpu::CPU;
+    use crate::memory::memory::Memory;
+    use crate::savestate::SaveState;
+
+    const FRAME_COUNTER_CYCLES: u64 = 29830;
+
+    fn tick(apu: &mut APU, cycles: u64) -> u64 {
+        apu.tick(cycles, |_| 0)
+    }
+
+    #[test]
+    fn test_length_counter_status() {
+        let mut apu = APU::new();
+        apu.write_register(0x4003, 0x08);
+        assert_eq!(apu.read_status() & 0x01, 0, "a disabled channel ignores length loads");
+
+        apu.write_register(APU_STATUS, 0x0F);
+        apu.write_register(0x4003, 0x08);
+        apu.write_register(0x400B, 0x08);
+        apu.write_register(0x400F, 0x08);
+        assert_eq!(apu.read_status() & 0x0F, 0b1101);
+
+        // Index 1 loads a length of 254; index 3 loads 2, which runs out after two half frames.
+        apu.write_register(0x4007, 0x18);
+        assert_eq!(apu.read_status() & 0x0F, 0b1111);
+        tick(&mut apu, FRAME_COUNTER_CYCLES);
+        assert_eq!(apu.read_status() & 0x0F, 0b1101);
+        apu.write_register(APU_STATUS, 0x08);
+        assert_eq!(apu.read_status() & 0x0F, 0b1000);
+    }
+
+    #[test]
+    fn test_length_counter_halt() {
+        let mut apu = APU::new();
+        apu.write_register(APU_STATUS, 0x01);
+        apu.write_register(0x4000, 0x20);
+        apu.write_register(0x4003, 0x18);
+        tick(&mut apu, FRAME_COUNTER_CYCLES * 4);
+        assert_eq!(apu.read_status() & 0x01, 0x01);
+    }
+
+    #[test]
+    fn test_frame_irq() {
+        let mut apu = APU::new();
+        tick(&mut apu, FRAME_COUNTER_CYCLES - 2);
+        assert!(!apu.frame_irq());
+        tick(&mut apu, 1);
+        assert!(apu.frame_irq());
+        assert_eq!(apu.read_status() & 0x40, 0x40);
+        assert!(!apu.frame_irq(), "reading $4015 acknowledges the interrupt");
+
+        apu.write_register(CONTROLLER_2, 0x40);
+        tick(&mut apu, FRAME_COUNTER_CYCLES * 2);
+        assert!(!apu.frame_irq());
+
+        apu.write_register(CONTROLLER_2, 0x80);
+        tick(&mut apu, FRAME_COUNTER_CYCLES * 2);
+        assert!(!apu.frame_irq(), "the five-step sequence never interrupts");
+    }
+
+    #[test]
+    fn test_sample_rate() {
+        let mut apu = APU::new();
+        tick(&mut apu, 10_000);
+        assert!(apu.take_samples().is_empty());
+
+        apu.set_sample_rate(44_100.0);
+        tick(&mut apu, 1_789_773);
+        let samples = apu.take_samples();
+        assert!((44_099..=44_101).contains(&samples.len()), "{}", samples.len());
+        assert!(apu.take_samples().is_empty());
+    }
+
+    #[test]
+    fn test_pulse_output() {
+        let mut apu = APU::new();
+        apu.set_sample_rate(48_000.0);
+        apu.write_register(APU_STATUS, 0x01);
+        apu.write_register(0x4000, 0xBF);
+        apu.write_register(0x4002, 0xFD);
+        apu.write_register(0x4003, 0x08);
+        tick(&mut apu, 30_000);
+        let samples = apu.take_samples();
+        let (low, high) = samples.iter().fold((f32::MAX, f32::MIN), |(low, high), sample| (low.min(*sample), high.max(*sample)));
+        assert!(high - low > 0.1, "a 440Hz square wave at full volume swings the output");
+
+        apu.write_register(APU_STATUS, 0x00);
+        tick(&mut apu, 30_000);
+        let samples = apu.take_samples();
+        let (low, high) = samples[samples.len() / 2..].iter().fold((f32::MAX, f32::MIN), |(low, high), sample| (low.min(*sample), high.max(*sample)));
+        assert!(high - low < 0.01, "a silenced channel only leaves the filter decaying");
+    }
+
+    #[test]
+    fn test_dmc_fetches_samples() {
+        let mut apu = APU::new();
+        apu.write_register(0x4010, 0x0F);
+        apu.write_register(0x4011, 0x40);
+        apu.write_register(0x4012, 0x00);
+        apu.write_register(0x4013, 0x01);
+        apu.write_register(APU_STATUS, 0x10);
+        assert_eq!(apu.read_status() & 0x10, 0x10);
+
+        let mut fetched = Vec::new();
+        let mut stolen = 0;
+        // At the fastest rate a byte lasts 8 * 54 cycles.
+        for _ in 0..8000 {
+            stolen += apu.tick(1, |address| {
+                fetched.push(address);
+                0xFF
+            });
+        }
+        assert_eq!(fetched, (0xC000..0xC011).collect::<Vec<u16>>());
+        assert_eq!(stolen, 17 * 4);
+        assert_eq!(apu.read_status() & 0x10, 0);
+    }
+
+    #[test]
+    fn test_dmc_irq() {
+        let mut apu = APU::new();
+        apu.write_register(0x4010, 0x8F);
+        apu.write_register(0x4013, 0x00);
+        apu.write_register(APU_STATUS, 0x10);
+        tick(&mut apu, 1);
+        assert!(apu.dmc_irq());
+        assert_eq!(apu.read_status() & 0x80, 0x80);
+        apu.write_register(APU_STATUS, 0x00);
+        assert!(!apu.dmc_irq());
+    }
+
+    #[test]
+    fn test_apu_registers_on_bus() {
+        // LDA #$01; STA $4015; LDA #$08; STA $4003; BRK
+        let mut cpu = CPU::with_program(&[0xA9, 0x01, 0x8D, 0x15, 0x40, 0xA9, 0x08, 0x8D, 0x03, 0x40, 0x00]);
+        while cpu.step().unwrap() {}
+        assert_eq!(cpu.read(APU_STATUS).unwrap() & 0x01, 0x01);
+    }
+
+    #[test]
+    fn test_apu_state_round_trip() {
+        let mut apu = APU::new();
+        apu.write_register(APU_STATUS, 0x1F);
+        apu.write_register(0x4000, 0x3F);
+        apu.write_register(0x4002, 0x80);
+        apu.write_register(0x4003, 0x08);
+        apu.write_register(0x400E, 0x83);
+        apu.write_register(0x400F, 0x08);
+        tick(&mut apu, 12_345);
+        let mut state = SaveState::new();
+        apu.save_state(&mut state);
+
+        let mut restored = APU::new();
+        let decoded = restored.decode_state(&state).unwrap();
+        restored.restore(decoded);
+        apu.set_sample_rate(44_100.0);
+        restored.set_sample_rate(44_100.0);
+        tick(&mut apu, 20_000);
+        tick(&mut restored, 20_000);
+        assert_eq!(apu.take_samples(), restored.take_samples());
+        assert_eq!(apu.read_status(), restored.read_status());
+    }
+}
@@ -0,0 +1,553 @@
+mod test;
+
+use std::collections::HashMap;
+use crate::common::errors::EmulatorError;
+use crate::cpu::opcode::{get_opcode_by_name_and_address_mode, get_opcodes_by_name, Opcode};
+use crate::cpu::types::AddressingMode;
+
+const MNEMONIC_ALIASES: [(&str, &str); 15] = [
+    ("ANC", "AAC"),
+    ("ALR", "ASR"),
+    ("AHX", "AXA"),
+    ("SHA", "AXA"),
+    ("DOP", "NOP"),
+    ("TOP", "NOP"),
+    ("ISC", "ISB"),
+    ("JAM", "KIL"),
+    ("LAS", "LAR"),
+    ("LXA", "ATX"),
+    ("SBX", "AXS"),
+    ("SHX", "SXA"),
+    ("SHY", "SYA"),
+    ("ANE", "XAA"),
+    ("TAS", "XAS"),
+];
+
+#[macro_export]
+macro_rules! asm {
+    ($($line:expr),+ $(,)?) => {
+        $crate::assembler::assemble(&[$($line),+].join("\n"), $crate::common::constants::PRG_ROM_START)
+            .unwrap_or_else(|e| panic!("{}", e))
+    };
+}
+
+pub fn assemble(source: &str, origin: u16) -> Result<Vec<u8>, EmulatorError> {
+    Assembler::new(origin).assemble(source)
+}
+
+#[derive(Debug, Clone)]
+enum Expression {
+    Number(i32),
+    Symbol(String),
+    ProgramCounter,
+    Low(Box<Expression>),
+    High(Box<Expression>),
+    Negate(Box<Expression>),
+    Add(Box<Expression>, Box<Expression>),
+    Subtract(Box<Expression>, Box<Expression>),
+}
+
+impl Expression {
+    fn evaluate(&self, symbols: &HashMap<String, i32>, pc: u16) -> Option<i32> {
+        match self {
+            Expression::Number(value) => Some(*value),
+            Expression::Symbol(name) => symbols.get(name).copied(),
+            Expression::ProgramCounter => Some(pc as i32),
+            Expression::Low(inner) => Some(inner.evaluate(symbols, pc)? & 0xFF),
+            Expression::High(inner) => Some((inner.evaluate(symbols, pc)? >> 8) & 0xFF),
+            Expression::Negate(inner) => inner.evaluate(symbols, pc)?.checked_neg(),
+            Expression::Add(left, right) => left.evaluate(symbols, pc)?.checked_add(right.evaluate(symbols, pc)?),
+            Expression::Subtract(left, right) => left.evaluate(symbols, pc)?.checked_sub(right.evaluate(symbols, pc)?),
+        }
+    }
+
+    fn undefined_symbol<'a>(&'a self, symbols: &HashMap<String, i32>) -> Option<&'a str> {
+        match self {
+            Expression::Symbol(name) if !symbols.contains_key(name) => Some(name),
+            Expression::Low(inner) | Expression::High(inner) | Expression::Negate(inner) => inner.undefined_symbol(symbols),
+            Expression::Add(left, right) | Expression::Subtract(left, right) => {
+                left.undefined_symbol(symbols).or_else(|| right.undefined_symbol(symbols))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Index {
+    None,
+    X,
+    Y,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Width {
+    Auto,
+    ZeroPage,
+    Absolute,
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    None,
+    Accumulator,
+    Immediate(Expression),
+    Direct(Expression, Index, Width),
+    Indirect(Expression),
+    IndexedIndirect(Expression),
+    IndirectIndexed(Expression),
+}
+
+#[derive(Debug, Clone)]
+enum DataItem {
+    Value(Expression),
+    Text(Vec<u8>),
+}
+
+#[derive(Debug, Clone)]
+enum Statement {
+    Instruction(String, Operand),
+    Byte(Vec<DataItem>),
+    Word(Vec<Expression>),
+    Org(Expression),
+    Assign(String, Expression),
+}
+
+struct Line {
+    number: usize,
+    label: Option<String>,
+    statement: Option<Statement>,
+}
+
+pub struct Assembler {
+    base: u16,
+    origin: u16,
+    symbols: HashMap<String, i32>,
+}
+
+impl Assembler {
+    pub fn new(origin: u16) -> Self {
+        Assembler {
+            base: origin,
+            origin,
+            symbols: HashMap::new(),
+        }
+    }
+
+    pub fn origin(&self) -> u16 {
+        self.origin
+    }
+
+    pub fn label(&self, name: &str) -> Option<u16> {
+        self.symbols.get(name).map(|value| *value as u16)
+    }
+
+    pub fn assemble(&mut self, source: &str) -> Result<Vec<u8>, EmulatorError> {
+        let lines = source.lines()
+            .enumerate()
+            .map(|(i, text)| parse_line(text).map_err(|message| error(i + 1, &message)).map(|(label, statement)| {
+                Line { number: i + 1, label, statement }
+            }))
+            .collect::<Result<Vec<Line>, EmulatorError>>()?;
+
+        self.symbols.clear();
+        let opcodes = self.first_pass(&lines)?;
+        self.second_pass(&lines, &opcodes)
+    }
+
+    // Assigns addresses to labels and fixes the size of every instruction, so forward
+    // references that later resolve to zero page keep the absolute form chosen here.
+    fn first_pass(&mut self, lines: &[Line]) -> Result<Vec<Option<&'static Opcode>>, EmulatorError> {
+        let mut pc = self.base;
+        let mut emitted = false;
+        self.origin = self.base;
+        let mut opcodes = Vec::with_capacity(lines.len());
+        for line in lines {
+            if let Some(label) = &line.label {
+                self.define(line.number, label, pc as i32)?;
+            }
+            let mut opcode = None;
+            match &line.statement {
+                Some(Statement::Instruction(mnemonic, operand)) => {
+                    let selected = select_opcode(mnemonic, operand, |expression| expression.evaluate(&self.symbols, pc))
+                        .map_err(|message| error(line.number, &message))?;
+                    pc = pc.wrapping_add(selected.bytes as u16);
+                    opcode = Some(selected);
+                    emitted = true;
+                }
+                Some(Statement::Byte(items)) => {
+                    let size: usize = items.iter().map(|item| match item {
+                        DataItem::Value(_) => 1,
+                        DataItem::Text(text) => text.len(),
+                    }).sum();
+                    pc = pc.wrapping_add(size as u16);
+                    emitted = true;
+                }
+                Some(Statement::Word(values)) => {
+                    pc = pc.wrapping_add(2 * values.len() as u16);
+                    emitted = true;
+                }
+                Some(Statement::Org(expression)) => {
+                    pc = self.resolve(line.number, expression, pc)? as u16;
+                    if !emitted {
+                        self.origin = pc;
+                    }
+                }
+                Some(Statement::Assign(name, expression)) => {
+                    let value = self.resolve(line.number, expression, pc)?;
+                    self.define(line.number, name, value)?;
+                }
+                None => {}
+            }
+            opcodes.push(opcode);
+        }
+        Ok(opcodes)
+    }
+
+    fn second_pass(&self, lines: &[Line], opcodes: &[Option<&'static Opcode>]) -> Result<Vec<u8>, EmulatorError> {
+        let mut pc = self.origin;
+        let mut output = Vec::new();
+        for (line, opcode) in lines.iter().zip(opcodes) {
+            let mut bytes = Vec::new();
+            match &line.statement {
+                Some(Statement::Instruction(_, operand)) => {
+                    let opcode = opcode.unwrap();
+                    bytes.push(opcode.code);
+                    bytes.extend(self.encode_operand(line.number, opcode, operand, pc)?);
+                }
+                Some(Statement::Byte(items)) => {
+                    for item in items {
+                        match item {
+                            DataItem::Value(expression) => {
+                                let value = self.resolve(line.number, expression, pc)?;
+                                bytes.push(check_byte(line.number, value)?);
+                            }
+                            DataItem::Text(text) => bytes.extend(text),
+                        }
+                    }
+                }
+                Some(Statement::Word(values)) => {
+                    for expression in values {
+                        let value = self.resolve(line.number, expression, pc)?;
+                        bytes.extend(check_word(line.number, value)?.to_le_bytes());
+                    }
+                }
+                Some(Statement::Org(expression)) => {
+                    let address = self.resolve(line.number, expression, pc)? as u16;
+                    if output.is_empty() {
+                        pc = address;
+                        continue;
+                    }
+                    if address < pc {
+                        return Err(error(line.number, &format!(".org ${:0>4X} is behind the current address ${:0>4X}", address, pc)));
+                    }
+                    bytes.resize((address - pc) as usize, 0);
+                }
+                Some(Statement::Assign(_, _)) | None => {}
+            }
+            pc = pc.wrapping_add(bytes.len() as u16);
+            output.extend(bytes);
+        }
+        Ok(output)
+    }
+
+    fn encode_operand(&self, line: usize, opcode: &Opcode, operand: &Operand, pc: u16) -> Result<Vec<u8>, EmulatorError> {
+        let expression = match operand {
+            Operand::None | Operand::Accumulator => return Ok(vec![]),
+            Operand::Immediate(expression)
+            | Operand::Direct(expression, _, _)
+            | Operand::Indirect(expression)
+            | Operand::IndexedIndirect(expression)
+            | Operand::IndirectIndexed(expression) => expression,
+        };
+        let value = self.resolve(line, expression, pc)?;
+        if opcode.address_mode == AddressingMode::Relative {
+            let offset = value.checked_sub(pc as i32 + 2);
+            let Some(offset) = offset.filter(|offset| (-128..=127).contains(offset)) else {
+                return Err(error(line, &format!("branch target ${:0>4X} is out of range", value)));
+            };
+            return Ok(vec![offset as u8]);
+        }
+        match opcode.bytes {
+            2 => Ok(vec![check_byte(line, value)?]),
+            _ => Ok(check_word(line, value)?.to_le_bytes().to_vec()),
+        }
+    }
+
+    fn resolve(&self, line: usize, expression: &Expression, pc: u16) -> Result<i32, EmulatorError> {
+        expression.evaluate(&self.symbols, pc).ok_or_else(|| match expression.undefined_symbol(&self.symbols) {
+            Some(name) => error(line, &format!("undefined symbol: {}", name)),
+            None => error(line, "expression is out of range"),
+        })
+    }
+
+    fn define(&mut self, line: usize, name: &str, value: i32) -> Result<(), EmulatorError> {
+        if self.symbols.insert(name.to_string(), value).is_some() {
+            return Err(error(line, &format!("duplicate symbol: {}", name)));
+        }
+        Ok(())
+    }
+}
+
+fn select_opcode<F>(mnemonic: &str, operand: &Operand, evaluate: F) -> Result<&'static Opcode, String>
+    where
+        F: Fn(&Expression) -> Option<i32> {
+    let name = MNEMONIC_ALIASES.iter()
+        .find(|(alias, _)| *alias == mnemonic)
+        .map(|(_, name)| *name)
+        .unwrap_or(mnemonic);
+    let candidates = get_opcodes_by_name(name);
+    if candidates.is_empty() {
+        return Err(format!("unknown mnemonic: {}", mnemonic));
+    }
+    let supports = |mode: AddressingMode| candidates.iter().any(|opcode| opcode.address_mode == mode);
+
+    let mode = match operand {
+        Operand::None if supports(AddressingMode::Accumulator) && !supports(AddressingMode::Implied) => AddressingMode::Accumulator,
+        Operand::None => AddressingMode::Implied,
+        Operand::Accumulator => AddressingMode::Accumulator,
+        Operand::Immediate(_) => AddressingMode::Immediate,
+        Operand::Indirect(_) => AddressingMode::Indirect,
+        Operand::IndexedIndirect(_) => AddressingMode::IndexedIndirect,
+        Operand::IndirectIndexed(_) => AddressingMode::IndirectIndexed,
+        Operand::Direct(_, _, _) if supports(AddressingMode::Relative) => AddressingMode::Relative,
+        Operand::Direct(expression, index, width) => {
+            let (zero_page, absolute) = match index {
+                Index::None => (AddressingMode::ZeroPage, AddressingMode::Absolute),
+                Index::X => (AddressingMode::ZeroPageX, AddressingMode::AbsoluteX),
+                Index::Y => (AddressingMode::ZeroPageY, AddressingMode::AbsoluteY),
+            };
+            let fits_zero_page = matches!(evaluate(expression), Some(value) if (0..=0xFF).contains(&value));
+            match width {
+                Width::ZeroPage => zero_page,
+                Width::Absolute => absolute,
+                Width::Auto if fits_zero_page && supports(zero_page) => zero_page,
+                Width::Auto if !supports(absolute) => zero_page,
+                Width::Auto => absolute,
+            }
+        }
+    };
+    get_opcode_by_name_and_address_mode(name, mode)
+        .ok_or_else(|| format!("{} does not support {:?} addressing", mnemonic, mode))
+}
+
+fn parse_line(text: &str) -> Result<(Option<String>, Option<Statement>), String> {
+    let mut rest = strip_comment(text).trim();
+    let mut label = None;
+
+    if let Some(end) = rest.find(':') {
+        let candidate = rest[..end].trim();
+        if is_identifier(candidate) && !rest[..end].contains(char::is_whitespace) {
+            label = Some(candidate.to_string());
+            rest = rest[end + 1..].trim();
+        }
+    }
+    if rest.is_empty() {
+        return Ok((label, None));
+    }
+
+    let (head, tail) = match rest.find(char::is_whitespace) {
+        Some(end) => (&rest[..end], rest[end..].trim()),
+        None => (rest, ""),
+    };
+
+    if let Some(directive) = head.strip_prefix('.') {
+        let statement = match directive.to_ascii_lowercase().as_str() {
+            "byte" | "db" => Some(Statement::Byte(parse_data(tail)?)),
+            "word" | "dw" | "addr" => Some(Statement::Word(split_list(tail).iter().map(|item| parse_expression(item)).collect::<Result<_, _>>()?)),
+            "org" => Some(Statement::Org(parse_expression(tail)?)),
+            "setcpu" | "segment" => None,
+            _ => return Err(format!("unknown directive: {}", head)),
+        };
+        return Ok((label, statement));
+    }
+
+    if let Some(value) = tail.strip_prefix('=') {
+        if !is_identifier(head) {
+            return Err(format!("invalid symbol name: {}", head));
+        }
+        return Ok((label, Some(Statement::Assign(head.to_string(), parse_expression(value)?))));
+    }
+    if let Some(end) = rest.find('=') {
+        let name = rest[..end].trim();
+        if is_identifier(name) {
+            return Ok((label, Some(Statement::Assign(name.to_string(), parse_expression(&rest[end + 1..])?))));
+        }
+    }
+
+    if !head.chars().all(|c| c.is_ascii_alphabetic()) || head.len() != 3 {
+        return Err(format!("invalid mnemonic: {}", head));
+    }
+    Ok((label, Some(Statement::Instruction(head.to_ascii_uppercase(), parse_operand(tail)?))))
+}
+
+fn strip_comment(text: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            ';' if !in_string => return &text[..i],
+            _ => {}
+        }
+    }
+    text
+}
+
+fn parse_operand(text: &str) -> Result<Operand, String> {
+    let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    let upper = text.to_ascii_uppercase();
+    if text.is_empty() {
+        return Ok(Operand::None);
+    }
+    if upper == "A" {
+        return Ok(Operand::Accumulator);
+    }
+    if let Some(value) = text.strip_prefix('#') {
+        return Ok(Operand::Immediate(parse_expression(value)?));
+    }
+    if text.starts_with('(') {
+        if upper.ends_with(",X)") {
+            return Ok(Operand::IndexedIndirect(parse_expression(&text[1..text.len() - 3])?));
+        }
+        if upper.ends_with("),Y") {
+            return Ok(Operand::IndirectIndexed(parse_expression(&text[1..text.len() - 3])?));
+        }
+        if text.ends_with(')') {
+            return Ok(Operand::Indirect(parse_expression(&text[1..text.len() - 1])?));
+        }
+        return Err(format!("invalid indirect operand: {}", text));
+    }
+
+    let (value, index) = if upper.ends_with(",X") {
+        (&text[..text.len() - 2], Index::X)
+    } else if upper.ends_with(",Y") {
+        (&text[..text.len() - 2], Index::Y)
+    } else {
+        (text.as_str(), Index::None)
+    };
+    let (value, width) = match value.get(..2).map(|prefix| prefix.to_ascii_lowercase()) {
+        Some(prefix) if prefix == "a:" => (&value[2..], Width::Absolute),
+        Some(prefix) if prefix == "z:" => (&value[2..], Width::ZeroPage),
+        _ => (value, Width::Auto),
+    };
+    Ok(Operand::Direct(parse_expression(value)?, index, width))
+}
+
+fn parse_data(text: &str) -> Result<Vec<DataItem>, String> {
+    split_list(text).iter().map(|item| {
+        match item.strip_prefix('"').and_then(|item| item.strip_suffix('"')) {
+            Some(text) => Ok(DataItem::Text(text.as_bytes().to_vec())),
+            None => Ok(DataItem::Value(parse_expression(item)?)),
+        }
+    }).collect()
+}
+
+fn split_list(text: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut current = String::new();
+    let mut in_string = false;
+    for c in text.chars() {
+        match c {
+            '"' => {
+                in_string = !in_string;
+                current.push(c);
+            }
+            ',' if !in_string => items.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    items.push(current);
+    items.into_iter().map(|item| item.trim().to_string()).collect()
+}
+
+fn parse_expression(text: &str) -> Result<Expression, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err("missing expression".to_string());
+    }
+    let mut terms = Vec::new();
+    let mut operators = Vec::new();
+    let mut start = 0;
+    let mut expect_term = true;
+    for (i, c) in text.char_indices() {
+        if expect_term {
+            if !c.is_whitespace() && !"<>-".contains(c) {
+                expect_term = false;
+            }
+            continue;
+        }
+        if c == '+' || c == '-' {
+            terms.push(&text[start..i]);
+            operators.push(c);
+            start = i + 1;
+            expect_term = true;
+        }
+    }
+    terms.push(&text[start..]);
+
+    let mut expression = parse_term(terms[0])?;
+    for (operator, term) in operators.iter().zip(&terms[1..]) {
+        let right = Box::new(parse_term(term)?);
+        expression = match operator {
+            '+' => Expression::Add(Box::new(expression), right),
+            _ => Expression::Subtract(Box::new(expression), right),
+        };
+    }
+    Ok(expression)
+}
+
+fn parse_term(text: &str) -> Result<Expression, String> {
+    let text = text.trim();
+    if let Some(inner) = text.strip_prefix('<') {
+        return Ok(Expression::Low(Box::new(parse_term(inner)?)));
+    }
+    if let Some(inner) = text.strip_prefix('>') {
+        return Ok(Expression::High(Box::new(parse_term(inner)?)));
+    }
+    if let Some(inner) = text.strip_prefix('-') {
+        return Ok(Expression::Negate(Box::new(parse_term(inner)?)));
+    }
+    if text == "*" {
+        return Ok(Expression::ProgramCounter);
+    }
+    let number = if let Some(hex) = text.strip_prefix('$') {
+        i32::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = text.strip_prefix('%') {
+        i32::from_str_radix(binary, 2).ok()
+    } else if text.len() == 3 && text.starts_with('\'') && text.ends_with('\'') {
+        Some(text.as_bytes()[1] as i32)
+    } else if text.starts_with(|c: char| c.is_ascii_digit()) {
+        text.parse().ok()
+    } else if is_identifier(text) {
+        return Ok(Expression::Symbol(text.to_string()));
+    } else {
+        None
+    };
+    number.map(Expression::Number).ok_or_else(|| format!("invalid value: {}", text))
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '@')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn check_byte(line: usize, value: i32) -> Result<u8, EmulatorError> {
+    if (-128..=0xFF).contains(&value) {
+        Ok(value as u8)
+    } else {
+        Err(error(line, &format!("value ${:X} does not fit in a byte", value)))
+    }
+}
+
+fn check_word(line: usize, value: i32) -> Result<u16, EmulatorError> {
+    if (-0x8000..=0xFFFF).contains(&value) {
+        Ok(value as u16)
+    } else {
+        Err(error(line, &format!("value ${:X} does not fit in a word", value)))
+    }
+}
+
+fn error(line: usize, message: &str) -> EmulatorError {
+    EmulatorError::AssemblyError(line, message.to_string())
+}
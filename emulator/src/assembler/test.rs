@@ -0,0 +1,189 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod test {
+    use std::fs::File;
+    use std::io::Read;
+    use crate::asm;
+    use crate::assembler::{assemble, Assembler};
+    use crate::cartridge::rom::Rom;
+    use crate::common::errors::EmulatorError;
+    use crate::disassembler::Disassembler;
+
+    #[test]
+    fn test_addressing_modes() {
+        let program = assemble("
+            LDA #$05
+            LDA $10
+            LDA $10,X
+            LDX $10,Y
+            LDA $1234
+            LDA $1234,X
+            LDA $1234,Y
+            JMP ($1234)
+            LDA ($10,X)
+            LDA ($10),Y
+            ASL A
+            ASL
+            CLC
+        ", 0x8000).unwrap();
+        assert_eq!(program, vec![
+            0xA9, 0x05,
+            0xA5, 0x10,
+            0xB5, 0x10,
+            0xB6, 0x10,
+            0xAD, 0x34, 0x12,
+            0xBD, 0x34, 0x12,
+            0xB9, 0x34, 0x12,
+            0x6C, 0x34, 0x12,
+            0xA1, 0x10,
+            0xB1, 0x10,
+            0x0A,
+            0x0A,
+            0x18,
+        ]);
+    }
+
+    #[test]
+    fn test_zero_page_fallback_and_forced_width() {
+        let program = assemble("
+            LDA $0010,Y
+            STA a:$10
+            STA z:$0010
+        ", 0x8000).unwrap();
+        assert_eq!(program, vec![0xB9, 0x10, 0x00, 0x8D, 0x10, 0x00, 0x85, 0x10]);
+    }
+
+    #[test]
+    fn test_labels_and_branches() {
+        let mut assembler = Assembler::new(0xC000);
+        let program = assembler.assemble("
+            start:  LDX #$03
+            loop:   DEX
+                    BNE loop
+                    BEQ done
+                    NOP
+            done:   JMP start
+        ").unwrap();
+        assert_eq!(program, vec![0xA2, 0x03, 0xCA, 0xD0, 0xFD, 0xF0, 0x01, 0xEA, 0x4C, 0x00, 0xC0]);
+        assert_eq!(assembler.label("loop"), Some(0xC002));
+        assert_eq!(assembler.label("done"), Some(0xC008));
+    }
+
+    #[test]
+    fn test_forward_reference_keeps_absolute_width() {
+        let program = assemble("
+            LDA value
+            RTS
+            value = $10
+        ", 0x8000).unwrap();
+        assert_eq!(program, vec![0xAD, 0x10, 0x00, 0x60]);
+
+        let program = assemble("
+            LDA table,X
+            RTS
+            table: .byte 1, 2
+        ", 0x0000).unwrap();
+        assert_eq!(program, vec![0xBD, 0x04, 0x00, 0x60, 0x01, 0x02]);
+    }
+
+    #[test]
+    fn test_directives_and_expressions() {
+        let mut assembler = Assembler::new(0x8000);
+        let program = assembler.assemble("
+            PPUCTRL = $2000
+                    .org $C000
+            reset:  LDA #<vectors
+                    LDY #>vectors
+                    STA PPUCTRL+1
+                    .byte $01, %10, 3, 'A', \"hi\", -1
+            vectors:
+                    .word reset, *-2
+        ").unwrap();
+        assert_eq!(assembler.origin(), 0xC000);
+        assert_eq!(program, vec![
+            0xA9, 0x0E,
+            0xA0, 0xC0,
+            0x8D, 0x01, 0x20,
+            0x01, 0x02, 0x03, 0x41, 0x68, 0x69, 0xFF,
+            0x00, 0xC0, 0x0C, 0xC0,
+        ]);
+    }
+
+    #[test]
+    fn test_org_pads_gaps() {
+        let program = assemble("
+            NOP
+            .org $8004
+            NOP
+        ", 0x8000).unwrap();
+        assert_eq!(program, vec![0xEA, 0x00, 0x00, 0x00, 0xEA]);
+        assert!(assemble("NOP\n.org $7000", 0x8000).is_err());
+    }
+
+    #[test]
+    fn test_unofficial_mnemonics() {
+        let program = assemble("
+            LAX $10
+            SAX $10
+            DCP $1234,X
+            ISB ($10),Y
+            SLO $10
+            ALR #$0F
+            ANC #$80
+            NOP $10
+            NOP
+            SBC #$01
+        ", 0x8000).unwrap();
+        assert_eq!(program, vec![
+            0xA7, 0x10,
+            0x87, 0x10,
+            0xDF, 0x34, 0x12,
+            0xF3, 0x10,
+            0x07, 0x10,
+            0x4B, 0x0F,
+            0x0B, 0x80,
+            0x04, 0x10,
+            0xEA,
+            0xE9, 0x01,
+        ]);
+    }
+
+    #[test]
+    fn test_errors_report_line() {
+        assert!(matches!(assemble("NOP\nFOO #$01", 0x8000), Err(EmulatorError::AssemblyError(2, _))));
+        assert!(matches!(assemble("LDA #$100", 0x8000), Err(EmulatorError::AssemblyError(1, _))));
+        assert!(matches!(assemble("JMP ($10),Y", 0x8000), Err(EmulatorError::AssemblyError(1, _))));
+        assert!(matches!(assemble("BNE missing", 0x8000), Err(EmulatorError::AssemblyError(1, _))));
+        assert!(matches!(assemble("a: NOP\na: NOP", 0x8000), Err(EmulatorError::AssemblyError(2, _))));
+        for source in [".word $7FFFFFFF+1", "min = -$7FFFFFFF-1\nLDA #-min", ".byte 0-$7FFFFFFF-2", "BNE -$7FFFFFFF-1"] {
+            match assemble(source, 0x8000) {
+                Err(EmulatorError::AssemblyError(line, message)) => {
+                    assert_eq!(line, source.lines().count(), "{}", source);
+                    assert!(message.contains("out of range"), "{}: {}", source, message);
+                }
+                result => panic!("{}: {:?}", source, result),
+            }
+        }
+        let far_branch = format!("loop: {}\nBNE loop", "NOP\n".repeat(200));
+        assert!(assemble(&far_branch, 0x8000).is_err());
+    }
+
+    #[test]
+    fn test_asm_macro() {
+        let program = asm!("LDA #$05", "STA $0200", "BRK");
+        assert_eq!(program, vec![0xA9, 0x05, 0x8D, 0x00, 0x02, 0x00]);
+    }
+
+    #[test]
+    fn test_disassembly_round_trip() {
+        let mut file = File::open("../test roms/nestest.nes").unwrap();
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes).unwrap();
+        let rom = Rom::new(&bytes).unwrap();
+        let mut disassembler = Disassembler::from_prg_bank(&rom.prg_rom, 0).unwrap();
+        disassembler.analyze_vectors();
+
+        let program = assemble(&disassembler.to_ca65(), 0x0000).unwrap();
+        assert_eq!(program, rom.prg_rom);
+    }
+}
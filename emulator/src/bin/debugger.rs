@@ -0,0 +1,379 @@
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::process;
+use emulator::common::logger::trace;
+use emulator::cpu::CPU;
+use emulator::cpu::types::ProcessorStatus;
+use emulator::debugger::{disassemble_around, AccessKind, Breakpoint, Debugger, FrameKind, StopReason, Watchpoint};
+use emulator::debugger::expression::Expression;
+use emulator::debugger::gdb::GdbServer;
+use emulator::disassembler::format_listing;
+use emulator::memory::memory::Memory;
+
+const USAGE: &str = "Usage: debugger <rom.nes> [--pc ADDR] [--gdb PORT] [--strict-access]";
+const HELP: &str = "\
+Addresses and values are hexadecimal, counts are decimal.
+Expressions use registers (a x y sp pc p), flags (c z i d v n), [ADDR], word[ADDR],
+cycles, frame, scanline, bank, numbers ($FF, 0xFF, %101, 255) and C operators.
+  s, step [N]              execute N instructions (default 1)
+  n, next                  step over JSR
+  finish                   run until the current frame returns
+  c, continue              run until a breakpoint or watchpoint
+  b, break ADDR [if EXPR]  set a breakpoint, optionally conditional
+  b, break if EXPR         break anywhere once EXPR becomes true
+  d, delete N              delete breakpoint N
+  cond N [EXPR]            set or clear the condition of breakpoint N
+  ignore N COUNT           ignore the next COUNT hits of breakpoint N
+  eval EXPR                evaluate an expression
+  w, watch ADDR[-END] [rwx]  watch reads, writes and/or execution (default w)
+  unwatch N                delete watchpoint N
+  i, info                  list breakpoints and watchpoints
+  r, regs                  show registers
+  set REG VALUE            set a, x, y, sp, pc, p or a flag (c z i d v n)
+  x, mem ADDR [LEN]        dump memory (default 64 bytes)
+  poke ADDR BYTE...        write bytes to memory
+  dis, disasm [ADDR] [N]   disassemble around PC or from ADDR
+  bt, backtrace            show the call stack
+  q, quit                  exit";
+
+struct Session {
+    cpu: CPU,
+    debugger: Debugger,
+    gdb_port: Option<u16>,
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let session = match parse_args(&args) {
+        Ok(session) => session,
+        Err(message) => {
+            eprintln!("{}\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+    match session.gdb_port {
+        Some(port) => serve_gdb(session, port),
+        None => repl(session),
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Session, String> {
+    let mut rom_path = None;
+    let mut pc = None;
+    let mut gdb_port = None;
+    let mut strict_access = false;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--pc" => pc = Some(parse_hex(iter.next().ok_or("--pc needs a value")?)?),
+            "--gdb" => {
+                let port = iter.next().ok_or("--gdb needs a port")?;
+                gdb_port = Some(port.parse().map_err(|_| format!("invalid port: {}", port))?);
+            }
+            "--strict-access" => strict_access = true,
+            _ if arg.starts_with('-') => return Err(format!("unknown option: {}", arg)),
+            _ => rom_path = Some(arg.clone()),
+        }
+    }
+    let rom_path = rom_path.ok_or("missing ROM path")?;
+    let bytes = fs::read(&rom_path).map_err(|e| format!("{}: {}", rom_path, e))?;
+
+    let mut cpu = CPU::new();
+    cpu.load(&bytes).map_err(|e| e.to_string())?;
+    cpu.set_strict_access(strict_access);
+    cpu.reset().map_err(|e| e.to_string())?;
+    if let Some(pc) = pc {
+        cpu.program_counter = pc;
+    }
+    Ok(Session { cpu, debugger: Debugger::new(), gdb_port })
+}
+
+fn serve_gdb(mut session: Session, port: u16) {
+    let result = GdbServer::bind(port).and_then(|server| {
+        println!("Waiting for GDB on {}", server.local_addr()?);
+        server.serve(&mut session.cpu, &mut session.debugger)
+    });
+    if let Err(e) = result {
+        eprintln!("GDB server: {}", e);
+        process::exit(1);
+    }
+}
+
+fn repl(mut session: Session) {
+    let stdin = io::stdin();
+    print_location(&session.cpu);
+    loop {
+        print!("(dbg) ");
+        io::stdout().flush().unwrap();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+            break;
+        }
+        match session.execute(line.trim()) {
+            Ok(true) => break,
+            Ok(false) => {}
+            Err(message) => println!("error: {}", message),
+        }
+    }
+}
+
+impl Session {
+    fn execute(&mut self, line: &str) -> Result<bool, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((&command, args)) = words.split_first() else { return Ok(false) };
+        match command {
+            "s" | "step" => {
+                let count = args.first().map(|count| count.parse::<usize>().map_err(|_| "invalid count")).transpose()?.unwrap_or(1);
+                for _ in 0..count {
+                    let reason = self.debugger.step(&mut self.cpu).map_err(|e| e.to_string())?;
+                    if reason != StopReason::Step {
+                        self.report(reason);
+                        return Ok(false);
+                    }
+                }
+                print_location(&self.cpu);
+            }
+            "n" | "next" => {
+                let reason = self.debugger.next(&mut self.cpu).map_err(|e| e.to_string())?;
+                self.report(reason);
+            }
+            "finish" => {
+                let reason = self.debugger.finish(&mut self.cpu).map_err(|e| e.to_string())?;
+                self.report(reason);
+            }
+            "c" | "continue" => {
+                let reason = self.debugger.continue_execution(&mut self.cpu).map_err(|e| e.to_string())?;
+                self.report(reason);
+            }
+            "b" | "break" => {
+                let breakpoint = match args {
+                    [] => return Err("missing address".to_string()),
+                    ["if", condition @ ..] => Breakpoint::when(parse_expression(condition)?),
+                    [address] => Breakpoint::at(parse_hex(address)?),
+                    [address, "if", condition @ ..] => Breakpoint::at(parse_hex(address)?).with_condition(parse_expression(condition)?),
+                    _ => return Err("usage: break ADDR [if EXPR]".to_string()),
+                };
+                let id = self.debugger.add_breakpoint(breakpoint.clone());
+                println!("{}", describe_breakpoint(id, &breakpoint));
+            }
+            "d" | "delete" => {
+                let id = parse_id(args.first())?;
+                self.debugger.remove_breakpoint(id).ok_or(format!("no breakpoint {}", id))?;
+            }
+            "cond" => {
+                let id = parse_id(args.first())?;
+                let condition = if args.len() > 1 { Some(parse_expression(&args[1..])?) } else { None };
+                let breakpoint = self.debugger.breakpoint_mut(id).ok_or(format!("no breakpoint {}", id))?;
+                if breakpoint.address.is_none() && condition.is_none() {
+                    return Err(format!("breakpoint {} has no address and needs a condition", id));
+                }
+                breakpoint.condition = condition;
+            }
+            "ignore" => {
+                let id = parse_id(args.first())?;
+                let count: u64 = args.get(1).and_then(|count| count.parse().ok()).ok_or("missing count")?;
+                let breakpoint = self.debugger.breakpoint_mut(id).ok_or(format!("no breakpoint {}", id))?;
+                breakpoint.ignore_count = breakpoint.hit_count + count;
+            }
+            "eval" => {
+                let expression = parse_expression(args)?;
+                match expression.evaluate(&self.cpu) {
+                    Some(value) => println!("{} = {} (${:X})", expression, value, value),
+                    None => return Err(format!("{} cannot be evaluated", expression)),
+                }
+            }
+            "w" | "watch" => {
+                let (start, end) = parse_range(args.first().ok_or("missing address")?)?;
+                let kinds = args.get(1).copied().unwrap_or("w");
+                if kinds.is_empty() || !kinds.chars().all(|c| "rwx".contains(c)) {
+                    return Err(format!("invalid access kinds: {}", kinds));
+                }
+                let watchpoint = Watchpoint::new(start, end, kinds.contains('r'), kinds.contains('w'), kinds.contains('x'));
+                let index = self.debugger.add_watchpoint(watchpoint);
+                println!("Watchpoint {}: ${:0>4X}-${:0>4X} {}", index, start, end, kinds);
+            }
+            "unwatch" => {
+                let index = args.first().and_then(|index| index.parse().ok()).ok_or("missing watchpoint number")?;
+                self.debugger.remove_watchpoint(index).ok_or(format!("no watchpoint {}", index))?;
+            }
+            "i" | "info" => {
+                for (id, breakpoint) in self.debugger.breakpoints() {
+                    println!("{}", describe_breakpoint(id, breakpoint));
+                }
+                for (index, watchpoint) in self.debugger.watchpoints().iter().enumerate() {
+                    let kinds: String = [(watchpoint.read, 'r'), (watchpoint.write, 'w'), (watchpoint.execute, 'x')]
+                        .iter().filter(|(enabled, _)| *enabled).map(|(_, c)| *c).collect();
+                    println!("Watchpoint {}: ${:0>4X}-${:0>4X} {}", index, watchpoint.start, watchpoint.end, kinds);
+                }
+            }
+            "r" | "regs" => print_location(&self.cpu),
+            "set" => {
+                let (name, value) = match args {
+                    [name, value] => (*name, parse_hex(value)?),
+                    _ => return Err("usage: set REG VALUE".to_string()),
+                };
+                set_register(&mut self.cpu, name, value)?;
+                print_location(&self.cpu);
+            }
+            "x" | "mem" => {
+                let address = parse_hex(args.first().ok_or("missing address")?)?;
+                let length = args.get(1).map(|length| parse_hex(length)).transpose()?.unwrap_or(0x40);
+                dump_memory(&self.cpu, address, length);
+            }
+            "poke" => {
+                let address = parse_hex(args.first().ok_or("missing address")?)?;
+                for (offset, value) in args[1..].iter().enumerate() {
+                    let value = parse_hex(value)?;
+                    if value > 0xFF {
+                        return Err(format!("value ${:X} does not fit in a byte", value));
+                    }
+                    self.cpu.write(address.wrapping_add(offset as u16), value as u8).map_err(|e| e.to_string())?;
+                }
+            }
+            "dis" | "disasm" => {
+                let instructions = match args.first() {
+                    Some(address) => {
+                        let count = args.get(1).map(|count| count.parse().map_err(|_| "invalid count")).transpose()?.unwrap_or(10);
+                        disassemble_around(&self.cpu, parse_hex(address)?, 0, count)
+                    }
+                    None => disassemble_around(&self.cpu, self.cpu.program_counter, 5, 5),
+                };
+                for instruction in instructions {
+                    let marker = if instruction.address == self.cpu.program_counter { "=>" } else { "  " };
+                    println!("{} {}", marker, format_listing(&instruction));
+                }
+            }
+            "bt" | "backtrace" => {
+                println!("#0  ${:0>4X}", self.cpu.program_counter);
+                for (depth, frame) in self.debugger.backtrace().iter().rev().enumerate() {
+                    let kind = match frame.kind {
+                        FrameKind::Subroutine => "JSR",
+                        FrameKind::Interrupt => "interrupt",
+                    };
+                    println!("#{}  ${:0>4X} in ${:0>4X} ({} from ${:0>4X})", depth + 1, frame.return_address, frame.target, kind, frame.call_site);
+                }
+            }
+            "h" | "help" => println!("{}", HELP),
+            "q" | "quit" => return Ok(true),
+            _ => return Err(format!("unknown command: {} (try help)", command)),
+        }
+        Ok(false)
+    }
+
+    fn report(&self, reason: StopReason) {
+        match reason {
+            StopReason::Breakpoint(id) => println!("Breakpoint {}", id),
+            StopReason::Watchpoint(address, kind) => {
+                let kind = match kind {
+                    AccessKind::Read => "read",
+                    AccessKind::Write => "write",
+                    AccessKind::Execute => "execute",
+                };
+                println!("Watchpoint: {} at ${:0>4X}", kind, address);
+            }
+            StopReason::Break(address) => println!("BRK at ${:0>4X}", address),
+            StopReason::Step | StopReason::Finished | StopReason::Interrupted => {}
+        }
+        print_location(&self.cpu);
+    }
+}
+
+fn print_location(cpu: &CPU) {
+    match trace(cpu) {
+        Ok(line) => println!("{}", line),
+        Err(e) => println!("${:0>4X}: {}", cpu.program_counter, e),
+    }
+}
+
+fn set_register(cpu: &mut CPU, name: &str, value: u16) -> Result<(), String> {
+    let byte = || if value <= 0xFF { Ok(value as u8) } else { Err(format!("value ${:X} does not fit in a byte", value)) };
+    let flag = || match value {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err("flags take 0 or 1".to_string()),
+    };
+    match name.to_ascii_lowercase().as_str() {
+        "a" => cpu.register_a = byte()?,
+        "x" => cpu.register_x = byte()?,
+        "y" => cpu.register_y = byte()?,
+        "sp" => cpu.stack_pointer = byte()?,
+        "pc" => cpu.program_counter = value,
+        "p" => cpu.status = ProcessorStatus::from_u8(byte()?),
+        "c" => cpu.status.carry = flag()?,
+        "z" => cpu.status.zero = flag()?,
+        "i" => cpu.status.interrupt_disable = flag()?,
+        "d" => cpu.status.decimal_mode = flag()?,
+        "v" => cpu.status.overflow = flag()?,
+        "n" => cpu.status.negative = flag()?,
+        _ => return Err(format!("unknown register: {}", name)),
+    }
+    Ok(())
+}
+
+fn dump_memory(cpu: &CPU, start_address: u16, length: u16) {
+    let end = start_address as u32 + length as u32;
+    let mut row = (start_address & 0xFFF0) as u32;
+    while row < end {
+        let mut line = format!("{:0>4X}:", row);
+        for offset in 0..16 {
+            let address = row + offset;
+            if address < start_address as u32 || address >= end || address > 0xFFFF {
+                line.push_str("   ");
+                continue;
+            }
+            match cpu.peek(address as u16) {
+                Ok(byte) => line.push_str(&format!(" {:0>2X}", byte)),
+                Err(_) => line.push_str(" --"),
+            }
+        }
+        println!("{}", line);
+        row += 16;
+    }
+}
+
+fn describe_breakpoint(id: usize, breakpoint: &Breakpoint) -> String {
+    let mut description = format!("Breakpoint {}", id);
+    if let Some(address) = breakpoint.address {
+        description.push_str(&format!(" at ${:0>4X}", address));
+    }
+    if let Some(condition) = &breakpoint.condition {
+        description.push_str(&format!(" if {}", condition));
+    }
+    if breakpoint.hit_count > 0 {
+        description.push_str(&format!(", hit {} times", breakpoint.hit_count));
+    }
+    if breakpoint.ignore_count > breakpoint.hit_count {
+        description.push_str(&format!(", ignoring next {}", breakpoint.ignore_count - breakpoint.hit_count));
+    }
+    description
+}
+
+fn parse_expression(words: &[&str]) -> Result<Expression, String> {
+    Expression::parse(&words.join(" ")).map_err(|e| e.to_string())
+}
+
+fn parse_id(text: Option<&&str>) -> Result<usize, String> {
+    text.and_then(|id| id.parse().ok()).ok_or("missing breakpoint number".to_string())
+}
+
+fn parse_hex(text: &str) -> Result<u16, String> {
+    let digits = text.trim_start_matches('$').trim_start_matches("0x");
+    u16::from_str_radix(digits, 16).map_err(|_| format!("invalid value: {}", text))
+}
+
+fn parse_range(text: &str) -> Result<(u16, u16), String> {
+    match text.split_once('-') {
+        Some((start, end)) => {
+            let (start, end) = (parse_hex(start)?, parse_hex(end)?);
+            if end < start {
+                return Err(format!("invalid range: {}", text));
+            }
+            Ok((start, end))
+        }
+        None => {
+            let address = parse_hex(text)?;
+            Ok((address, address))
+        }
+    }
+}
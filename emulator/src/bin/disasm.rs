@@ -0,0 +1,68 @@
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::process;
+use emulator::cartridge::rom::Rom;
+use emulator::disassembler::Disassembler;
+
+const USAGE: &str = "Usage: disasm <rom.nes> [--bank N] [--unofficial] [--output FILE]";
+
+struct Args {
+    rom_path: String,
+    bank: usize,
+    follow_unofficial: bool,
+    output: Option<String>,
+}
+
+fn main() {
+    let args = match parse_args(env::args().skip(1).collect()) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{}\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(message) = run(&args) {
+        eprintln!("disasm: {}", message);
+        process::exit(1);
+    }
+}
+
+fn run(args: &Args) -> Result<(), String> {
+    let bytes = fs::read(&args.rom_path).map_err(|e| format!("{}: {}", args.rom_path, e))?;
+    let rom = Rom::new(&bytes).map_err(|e| e.to_string())?;
+    let mut disassembler = Disassembler::from_prg_bank(&rom.prg_rom, args.bank).map_err(|e| e.to_string())?;
+    disassembler.follow_unofficial = args.follow_unofficial;
+    disassembler.analyze_vectors();
+
+    let mut source = format!("; {} - PRG bank {}\n", args.rom_path, args.bank);
+    source.push_str(&disassembler.to_ca65());
+    match &args.output {
+        Some(path) => fs::write(path, source).map_err(|e| format!("{}: {}", path, e)),
+        None => io::stdout().write_all(source.as_bytes()).map_err(|e| e.to_string()),
+    }
+}
+
+fn parse_args(args: Vec<String>) -> Result<Args, String> {
+    let mut rom_path = None;
+    let mut bank = 0;
+    let mut follow_unofficial = false;
+    let mut output = None;
+    let mut iter = args.into_iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--bank" => {
+                let value = iter.next().ok_or("--bank needs a value")?;
+                bank = value.parse().map_err(|_| format!("invalid bank: {}", value))?;
+            }
+            "--unofficial" => follow_unofficial = true,
+            "--output" | "-o" => output = Some(iter.next().ok_or("--output needs a value")?),
+            "--help" | "-h" => return Err(String::new()),
+            _ if arg.starts_with('-') => return Err(format!("unknown option: {}", arg)),
+            _ if rom_path.is_none() => rom_path = Some(arg),
+            _ => return Err(format!("unexpected argument: {}", arg)),
+        }
+    }
+    let rom_path = rom_path.ok_or("missing ROM path")?;
+    Ok(Args { rom_path, bank, follow_unofficial, output })
+}
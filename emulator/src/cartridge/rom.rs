@@ -9,16 +9,8 @@ pub struct Rom {
     pub mirroring: Mirroring,
 }
 
-impl Rom {
-    pub fn new(bytes: &Vec<u8>) -> Result<Self, EmulatorError> {
-        Self::validate_file_format(bytes)?;
-        let (mirroring, mapper) = Self::parse_control_bytes(bytes)?;
-        let (prg_rom, chr_rom) = Self::extract_rom_sections(bytes)?;
-
-        Ok(Rom { prg_rom, chr_rom, mapper, mirroring })
-    }
-
-    pub fn default() -> Self {
+impl Default for Rom {
+    fn default() -> Self {
         Rom {
             prg_rom: vec![0; PRG_ROM_PAGE_SIZE],
             chr_rom: vec![0; CHR_ROM_PAGE_SIZE],
@@ -26,6 +18,16 @@ impl Rom {
             mirroring: Mirroring::Horizontal,
         }
     }
+}
+
+impl Rom {
+    pub fn new(bytes: &[u8]) -> Result<Self, EmulatorError> {
+        Self::validate_file_format(bytes)?;
+        let (mirroring, mapper) = Self::parse_control_bytes(bytes)?;
+        let (prg_rom, chr_rom) = Self::extract_rom_sections(bytes)?;
+
+        Ok(Rom { prg_rom, chr_rom, mapper, mirroring })
+    }
 
     fn validate_file_format(bytes: &[u8]) -> Result<(), EmulatorError> {
         if bytes.starts_with(&NES_TAG) {
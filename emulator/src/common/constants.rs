@@ -3,13 +3,37 @@ pub const RAM_START: u16 = 0x0000;
 pub const RAM_END: u16 = 0x1FFF;
 pub const PPU_START: u16 = 0x2000;
 pub const PPU_END: u16 = 0x3FFF;
+pub const APU_START: u16 = 0x4000;
+pub const APU_END: u16 = 0x4013;
+pub const OAM_DMA: u16 = 0x4014;
+pub const APU_STATUS: u16 = 0x4015;
+pub const CONTROLLER_1: u16 = 0x4016;
+pub const CONTROLLER_2: u16 = 0x4017;
+pub const PRG_RAM_START: u16 = 0x6000;
+pub const PRG_RAM_END: u16 = 0x7FFF;
+pub const PRG_RAM_SIZE: usize = 8192;
 pub const PRG_ROM_START: u16 = 0x8000;
 pub const PRG_ROM_END: u16 = 0xFFFF;
 
 pub const STACK_START: u16 = 0x0100;
 pub const STACK_POINTER_INIT: u8 = 0xFD;
+pub const NMI_VECTOR: u16 = 0xFFFA;
 pub const IRQ_VECTOR: u16 = 0xFFFE;
 pub const PC_START_ADDRESS: u16 = 0xFFFC;
+pub const XAA_MAGIC: u8 = 0xFF;
+
+pub const CPU_CLOCK_RATE: f64 = 1_789_773.0;
+pub const PPU_DOTS_PER_CPU_CYCLE: u64 = 3;
+pub const PPU_DOTS_PER_SCANLINE: u16 = 341;
+pub const SCANLINES_PER_FRAME: u16 = 262;
+pub const VBLANK_SCANLINE: u16 = 241;
+pub const PRE_RENDER_SCANLINE: u16 = 261;
+pub const OAM_DMA_CYCLES: u64 = 513;
+pub const SCREEN_WIDTH: usize = 256;
+pub const SCREEN_HEIGHT: usize = 240;
+pub const VRAM_SIZE: usize = 2048;
+pub const NAMETABLE_START: u16 = 0x2000;
+pub const PALETTE_START: u16 = 0x3F00;
 
 pub const NES_TAG: [u8; 4] = [0x4E, 0x45, 0x53, 0x1A];
 pub const PRG_ROM_PAGE_SIZE: usize = 16384;
@@ -17,4 +41,8 @@ pub const CHR_ROM_PAGE_SIZE: usize = 8192;
 pub const NES_HEADER_SIZE: usize = 16;
 pub const NES_TRAINER_SIZE: usize = 512;
 
-pub static DEBUG: bool = true;
\ No newline at end of file
+pub const SAVE_STATE_MAGIC: [u8; 4] = *b"NESS";
+pub const SAVE_STATE_VERSION: u16 = 10;
+pub const SAVE_STATE_HEADER_SIZE: usize = 18;
+
+pub static DEBUG: bool = false;
\ No newline at end of file
@@ -8,6 +8,14 @@ pub enum EmulatorError {
     AccessViolation(u16),
     InvalidNesFile,
     RomNotLoaded,
+    InvalidBank(usize),
+    AssemblyError(usize, String),
+    InvalidExpression(String),
+    InvalidSaveState(String),
+    UnsupportedSaveStateVersion(u16),
+    SaveStateRomMismatch(u32, u32),
+    InvalidMovie(usize, String),
+    MovieRomMismatch,
 }
 
 impl fmt::Display for EmulatorError {
@@ -19,6 +27,16 @@ impl fmt::Display for EmulatorError {
             EmulatorError::AccessViolation(address) => write!(f, "Access violation at address: {:x}", address),
             EmulatorError::InvalidNesFile => write!(f, "Invalid iNES file."),
             EmulatorError::RomNotLoaded => write!(f, "No loaded rom."),
+            EmulatorError::InvalidBank(bank) => write!(f, "Invalid PRG bank: {}", bank),
+            EmulatorError::AssemblyError(line, message) => write!(f, "Assembly error on line {}: {}", line, message),
+            EmulatorError::InvalidExpression(message) => write!(f, "Invalid expression: {}", message),
+            EmulatorError::InvalidSaveState(message) => write!(f, "Invalid save state: {}", message),
+            EmulatorError::UnsupportedSaveStateVersion(version) => write!(f, "Unsupported save state version: {}", version),
+            EmulatorError::SaveStateRomMismatch(state, loaded) => {
+                write!(f, "Save state was made for a different ROM (state CRC32 {:08X}, loaded ROM {:08X})", state, loaded)
+            }
+            EmulatorError::InvalidMovie(line, message) => write!(f, "Invalid movie on line {}: {}", line, message),
+            EmulatorError::MovieRomMismatch => write!(f, "Movie was recorded with a different ROM."),
         }
     }
 }
\ No newline at end of file
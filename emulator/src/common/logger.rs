@@ -2,12 +2,12 @@ use crate::common::errors::EmulatorError;
 use crate::cpu::CPU;
 use crate::cpu::types::AddressingMode;
 use crate::cpu::opcode::get_opcode;
+use crate::disassembler::{format_operand, Instruction};
 use crate::memory::memory::Memory;
 
 const PC_WIDTH: usize = 6;
 const CODE_WIDTH: usize = 9;
 const INSTRUCTION_WIDTH: usize = 33;
-const PPU_HALF_WITDH: usize = 4;
 const NO_DATA_LOAD_OPCODES: [&str; 2] = ["JMP", "JSR"];
 
 pub fn trace(cpu: &CPU) -> Result<String, EmulatorError> {
@@ -28,10 +28,10 @@ fn get_pc_str(cpu: &CPU) -> String {
 
 fn get_code_str(cpu: &CPU) -> Result<String, EmulatorError> {
     let mut code = String::new();
-    let opcode_code = cpu.read(cpu.program_counter)?;
+    let opcode_code = cpu.peek(cpu.program_counter)?;
     if let Some(opcode) = get_opcode(opcode_code) {
         for i in 0..opcode.bytes {
-            let byte = cpu.read(cpu.program_counter + i as u16)?;
+            let byte = cpu.peek(cpu.program_counter + i as u16)?;
             code.push_str(&format!("{:0>2X} ", byte));
         }
     }
@@ -41,107 +41,66 @@ fn get_code_str(cpu: &CPU) -> Result<String, EmulatorError> {
 
 fn get_instruction_str(cpu: &CPU) -> Result<String, EmulatorError> {
     let mut instruction = String::new();
-    let opcode_code = cpu.read(cpu.program_counter)?;
+    let opcode_code = cpu.peek(cpu.program_counter)?;
     if let Some(opcode) = get_opcode(opcode_code) {
         if opcode.unofficial {
-            instruction.push_str("*");
+            instruction.push('*');
         }
         else {
-            instruction.push_str(" ");
+            instruction.push(' ');
         }
         instruction.push_str(&format!("{} ", opcode.name));
-        let low_byte = if opcode.bytes > 1 { Some(cpu.read(cpu.program_counter + 1)?) } else { None };
-        let high_byte = if opcode.bytes == 3 { Some(cpu.read(cpu.program_counter + 2)?) } else { None };
+        let operand = match opcode.bytes {
+            2 => cpu.peek(cpu.program_counter + 1)? as u16,
+            3 => cpu.peek_u16(cpu.program_counter + 1)?,
+            _ => 0,
+        };
+        let decoded = Instruction { address: cpu.program_counter, opcode, operand };
+        instruction.push_str(&format_operand(opcode.address_mode, &decoded.operand_value()));
         let data_load = !NO_DATA_LOAD_OPCODES.contains(&opcode.name);
-        instruction.push_str(&get_address_string(opcode.address_mode, cpu, low_byte, high_byte, data_load)?);
+        instruction.push_str(&get_operand_values(opcode.address_mode, cpu, operand, data_load)?);
     }
 
     instruction = format!("{:<width$}", instruction, width = INSTRUCTION_WIDTH);
     Ok(instruction)
 }
 
-fn get_address_string(mode: AddressingMode, cpu: &CPU, low_byte: Option<u8>, high_byte: Option<u8>, data_load: bool) -> Result<String, EmulatorError> {
-    let mut address = String::new();
-    match mode {
-        AddressingMode::Accumulator => {
-            address.push('A');
+/// What the operand points at, and the addresses on the way there.
+fn get_operand_values(mode: AddressingMode, cpu: &CPU, operand: u16, data_load: bool) -> Result<String, EmulatorError> {
+    let values = match mode {
+        AddressingMode::ZeroPage => format!(" = {:0>2X}", cpu.peek(operand)?),
+        AddressingMode::ZeroPageX | AddressingMode::ZeroPageY => {
+            let index = if mode == AddressingMode::ZeroPageX { cpu.register_x } else { cpu.register_y };
+            let real_address = (operand as u8).wrapping_add(index);
+            format!(" @ {:0>2X} = {:0>2X}", real_address, cpu.peek(real_address as u16)?)
         }
-        AddressingMode::Immediate => {
-            address.push_str(&format!("#${:0>2X}", low_byte.unwrap()));
-        }
-        AddressingMode::ZeroPage => {
-            address.push_str(&format!("${:0>2X}", low_byte.unwrap()));
-            let value = cpu.read(low_byte.unwrap() as u16)?;
-            address.push_str(&format!(" = {:0>2X}", value));
-        }
-        AddressingMode::ZeroPageX => {
-            address.push_str(&format!("${:0>2X},X", low_byte.unwrap()));
-            let real_address = low_byte.unwrap().wrapping_add(cpu.register_x);
-            let value = cpu.read(real_address as u16)?;
-            address.push_str(&format!(" @ {:0>2X} = {:0>2X}", real_address, value));
-        }
-        AddressingMode::ZeroPageY => {
-            address.push_str(&format!("${:0>2X},Y", low_byte.unwrap()));
-            let real_address = low_byte.unwrap().wrapping_add(cpu.register_y);
-            let value = cpu.read(real_address as u16)?;
-            address.push_str(&format!(" @ {:0>2X} = {:0>2X}", real_address, value));
-        }
-        AddressingMode::Relative => {
-            let offset = low_byte.unwrap() as i8;
-            let real_address = cpu.program_counter.wrapping_add(2).wrapping_add(offset as u16);
-            address.push_str(&format!("${:0>4X}", real_address));
-        }
-        AddressingMode::Absolute => {
-            address.push_str(&format!("${:0>2X}{:0>2X}", high_byte.unwrap(), low_byte.unwrap()));
-            if data_load {
-                let addr = u16::from_le_bytes([low_byte.unwrap(), high_byte.unwrap()]);
-                let value = cpu.read(addr)?;
-                address.push_str(&format!(" = {:0>2X}", value));
-            }
-        }
-        AddressingMode::AbsoluteX => {
-            address.push_str(&format!("${:0>2X}{:0>2X},X", high_byte.unwrap(), low_byte.unwrap()));
-            let addr = u16::from_le_bytes([low_byte.unwrap(), high_byte.unwrap()]);
-            let real_address = addr.wrapping_add(cpu.register_x as u16);
-            let value = cpu.read(real_address)?;
-            address.push_str(&format!(" @ {:0>4X} = {:0>2X}", real_address, value));
-        }
-        AddressingMode::AbsoluteY => {
-            address.push_str(&format!("${:0>2X}{:0>2X},Y", high_byte.unwrap(), low_byte.unwrap()));
-            let addr = u16::from_le_bytes([low_byte.unwrap(), high_byte.unwrap()]);
-            let real_address = addr.wrapping_add(cpu.register_y as u16);
-            let value = cpu.read(real_address)?;
-            address.push_str(&format!(" @ {:0>4X} = {:0>2X}", real_address, value));
+        AddressingMode::Absolute if data_load => format!(" = {:0>2X}", cpu.peek(operand)?),
+        AddressingMode::AbsoluteX | AddressingMode::AbsoluteY => {
+            let index = if mode == AddressingMode::AbsoluteX { cpu.register_x } else { cpu.register_y };
+            let real_address = operand.wrapping_add(index as u16);
+            format!(" @ {:0>4X} = {:0>2X}", real_address, cpu.peek(real_address)?)
         }
         AddressingMode::Indirect => {
-            address.push_str(&format!("(${:0>2X}{:0>2X})", high_byte.unwrap(), low_byte.unwrap()));
-            let reference = u16::from_le_bytes([low_byte.unwrap(), high_byte.unwrap()]);
-            let value = if reference & 0x00FF == 0x00FF {
-                let low_byte = cpu.read(reference)?;
-                let high_byte = cpu.read(reference & 0xFF00)?;
-                u16::from_le_bytes([low_byte, high_byte])
+            let value = if operand & 0x00FF == 0x00FF {
+                u16::from_le_bytes([cpu.peek(operand)?, cpu.peek(operand & 0xFF00)?])
             } else {
-                cpu.read_u16(reference)?
+                cpu.peek_u16(operand)?
             };
-            address.push_str(&format!(" = {:0>4X}", value));
+            format!(" = {:0>4X}", value)
         }
         AddressingMode::IndexedIndirect => {
-            address.push_str(&format!("(${:0>2X},X)", low_byte.unwrap()));
-            let reference = low_byte.unwrap().wrapping_add(cpu.register_x);
-            let real_address = cpu.read_u16_zero_page(reference)?;
-            let value = cpu.read(real_address)?;
-            address.push_str(&format!(" @ {:0>2X} = {:0>4X} = {:0>2X}", reference, real_address, value));
+            let reference = (operand as u8).wrapping_add(cpu.register_x);
+            let real_address = cpu.peek_u16_zero_page(reference)?;
+            format!(" @ {:0>2X} = {:0>4X} = {:0>2X}", reference, real_address, cpu.peek(real_address)?)
         }
         AddressingMode::IndirectIndexed => {
-            address.push_str(&format!("(${:0>2X}),Y", low_byte.unwrap()));
-            let reference = cpu.read_u16_zero_page(low_byte.unwrap())?;
+            let reference = cpu.peek_u16_zero_page(operand as u8)?;
             let real_address = reference.wrapping_add(cpu.register_y as u16);
-            let value = cpu.read(real_address)?;
-            address.push_str(&format!(" = {:0>4X} @ {:0>4X} = {:0>2X}", reference, real_address, value));
+            format!(" = {:0>4X} @ {:0>4X} = {:0>2X}", reference, real_address, cpu.peek(real_address)?)
         }
-        _ => {}
-    }
-    Ok(address)
+        _ => String::new(),
+    };
+    Ok(values)
 }
 
 fn get_register_string(cpu: &CPU) -> String {
@@ -2,4 +2,5 @@ pub mod constants;
 pub mod util;
 pub mod errors;
 pub mod types;
-pub mod logger;
\ No newline at end of file
+pub mod logger;
+pub mod png;
\ No newline at end of file
@@ -0,0 +1,57 @@
+use crate::common::util::crc32;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+/// Encodes 8-bit RGB pixels as a PNG. The image data is stored in uncompressed deflate blocks,
+/// which every decoder accepts and keeps the encoder trivial.
+pub fn encode_png(width: u32, height: u32, rgb: &[u8]) -> Vec<u8> {
+    let stride = width as usize * 3;
+    let mut scanlines = Vec::with_capacity(rgb.len() + height as usize);
+    for row in rgb.chunks(stride.max(1)).take(height as usize) {
+        scanlines.push(0);
+        scanlines.extend_from_slice(row);
+    }
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut data = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = scanlines.chunks(MAX_STORED_BLOCK).collect();
+    if blocks.is_empty() {
+        data.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    for (index, block) in blocks.iter().enumerate() {
+        data.push((index == blocks.len() - 1) as u8);
+        data.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        data.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        data.extend_from_slice(block);
+    }
+    data.extend_from_slice(&adler32(&scanlines).to_be_bytes());
+
+    let mut png = PNG_SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &data);
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_chunk(png: &mut Vec<u8>, tag: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(tag);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
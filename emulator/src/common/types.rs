@@ -1,6 +1,34 @@
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Mirroring {
     Vertical,
     Horizontal,
     FourScreen,
-}
\ No newline at end of file
+    /// All four nametables show the console's first 1KB page.
+    SingleScreenA,
+    /// All four nametables show the console's second 1KB page.
+    SingleScreenB,
+}
+
+impl Mirroring {
+    /// Where each of the four nametables at $2000, $2400, $2800 and $2C00 is mapped.
+    pub fn nametables(self) -> [Nametable; 4] {
+        use Nametable::{Cartridge, Console};
+        match self {
+            Mirroring::Vertical => [Console(0), Console(1), Console(0), Console(1)],
+            Mirroring::Horizontal => [Console(0), Console(0), Console(1), Console(1)],
+            Mirroring::FourScreen => [Console(0), Console(1), Cartridge(0), Cartridge(1)],
+            Mirroring::SingleScreenA => [Console(0); 4],
+            Mirroring::SingleScreenB => [Console(1); 4],
+        }
+    }
+}
+
+/// A 1KB page of RAM that one of the PPU's nametables can be mapped to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Nametable {
+    /// One of the two pages of nametable RAM inside the console.
+    Console(u8),
+    /// A page of RAM on the cartridge, like the extra nametables of four-screen boards or a
+    /// mapper's expansion RAM.
+    Cartridge(u8),
+}
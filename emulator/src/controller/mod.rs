@@ -0,0 +1,80 @@
+mod test;
+
+pub const BUTTON_A: u8 = 0b0000_0001;
+pub const BUTTON_B: u8 = 0b0000_0010;
+pub const BUTTON_SELECT: u8 = 0b0000_0100;
+pub const BUTTON_START: u8 = 0b0000_1000;
+pub const BUTTON_UP: u8 = 0b0001_0000;
+pub const BUTTON_DOWN: u8 = 0b0010_0000;
+pub const BUTTON_LEFT: u8 = 0b0100_0000;
+pub const BUTTON_RIGHT: u8 = 0b1000_0000;
+
+/// Standard controller: button bits are in the order the shift register reports them, A first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Controller {
+    buttons: u8,
+    strobe: bool,
+    shift: u8,
+}
+
+impl Default for Controller {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Controller {
+    pub fn new() -> Self {
+        Controller {
+            buttons: 0,
+            strobe: false,
+            shift: 0,
+        }
+    }
+
+    pub fn buttons(&self) -> u8 {
+        self.buttons
+    }
+
+    pub fn set_buttons(&mut self, buttons: u8) {
+        self.buttons = buttons;
+        if self.strobe {
+            self.shift = buttons;
+        }
+    }
+
+    pub fn set_button(&mut self, button: u8, pressed: bool) {
+        let buttons = if pressed { self.buttons | button } else { self.buttons & !button };
+        self.set_buttons(buttons);
+    }
+
+    pub fn write(&mut self, value: u8) {
+        self.strobe = value & 1 != 0;
+        if self.strobe {
+            self.shift = self.buttons;
+        }
+    }
+
+    pub fn read(&mut self) -> u8 {
+        let bit = self.peek();
+        if !self.strobe {
+            self.shift = self.shift >> 1 | 0x80;
+        }
+        bit
+    }
+
+    /// The bit the next read returns, without shifting.
+    pub fn peek(&self) -> u8 {
+        if self.strobe { self.buttons & 1 } else { self.shift & 1 }
+    }
+
+    pub(crate) fn state(&self) -> (u8, bool, u8) {
+        (self.buttons, self.strobe, self.shift)
+    }
+
+    pub(crate) fn restore(&mut self, buttons: u8, strobe: bool, shift: u8) {
+        self.buttons = buttons;
+        self.strobe = strobe;
+        self.shift = shift;
+    }
+}
@@ -0,0 +1,46 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod test {
+    use crate::controller::{Controller, BUTTON_A, BUTTON_DOWN, BUTTON_RIGHT, BUTTON_START};
+    use crate::cpu::CPU;
+    use crate::memory::memory::Memory;
+
+    #[test]
+    fn test_shift_register() {
+        let mut controller = Controller::new();
+        controller.set_buttons(BUTTON_A | BUTTON_START | BUTTON_RIGHT);
+        controller.write(1);
+        assert_eq!(controller.read(), 1);
+        assert_eq!(controller.read(), 1);
+        controller.write(0);
+        let bits: Vec<u8> = (0..10).map(|_| controller.read()).collect();
+        assert_eq!(bits, vec![1, 0, 0, 1, 0, 0, 0, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_buttons_latch_on_strobe() {
+        let mut controller = Controller::new();
+        controller.write(1);
+        controller.write(0);
+        controller.set_button(BUTTON_A, true);
+        assert_eq!(controller.read(), 0);
+        controller.write(1);
+        controller.write(0);
+        assert_eq!(controller.read(), 1);
+        controller.set_button(BUTTON_A, false);
+        assert_eq!(controller.buttons(), 0);
+    }
+
+    #[test]
+    fn test_controller_ports() {
+        let mut cpu = CPU::new();
+        cpu.controller(0).set_buttons(BUTTON_A);
+        cpu.controller(1).set_buttons(BUTTON_DOWN);
+        cpu.write(0x4016, 1).unwrap();
+        cpu.write(0x4016, 0).unwrap();
+        let port_0: Vec<u8> = (0..8).map(|_| cpu.read(0x4016).unwrap()).collect();
+        let port_1: Vec<u8> = (0..8).map(|_| cpu.read(0x4017).unwrap()).collect();
+        assert_eq!(port_0, vec![1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(port_1, vec![0, 0, 0, 0, 0, 1, 0, 0]);
+    }
+}
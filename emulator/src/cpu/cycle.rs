@@ -0,0 +1,36 @@
+//! The cycle-stepped core. Every read and write the CPU makes is a bus cycle that runs the PPU
+//! and APU before the next one, so register accesses land on the exact dot and interrupts are
+//! seen on the cycle they arrive.
+
+use crate::common::errors::EmulatorError;
+use crate::cpu::opcode::get_opcode;
+use crate::cpu::CPU;
+
+impl CPU {
+    pub(super) fn step_cycles(&mut self) -> Result<bool, EmulatorError> {
+        self.interrupted_at = None;
+        let opcode_u8 = self.bus_read(self.program_counter)?;
+        let opcode = get_opcode(opcode_u8).ok_or(EmulatorError::InvalidOpcode(opcode_u8))?;
+        let running = self.execute(opcode_u8, opcode)?;
+        if self.interrupt_polled {
+            self.interrupted_at = Some(self.program_counter);
+            self.interrupt_sequence()?;
+        }
+        Ok(running)
+    }
+
+    /// Finishes a bus cycle: the PPU and APU run for it, DMA takes the cycles it stole, and the
+    /// interrupt lines are sampled. Instructions act on what they were a cycle earlier, at the
+    /// end of their penultimate cycle.
+    pub(super) fn end_cycle(&mut self) {
+        self.cycles += 1;
+        self.bus.tick(1);
+        let stolen = self.bus.take_dma_cycles();
+        if stolen != 0 {
+            self.cycles += stolen;
+            self.bus.tick(stolen);
+        }
+        self.interrupt_polled = self.interrupt_line;
+        self.interrupt_line = self.interrupt_requested();
+    }
+}
@@ -1,9 +1,8 @@
-use crate::common::constants::{DEBUG, IRQ_VECTOR, STACK_START};
+use crate::common::constants::{DEBUG, IRQ_VECTOR, NMI_VECTOR, STACK_START};
 use crate::common::errors::EmulatorError;
 use crate::common::util::{is_negative, overflows_negative, overflows_positive};
 use crate::cpu::types::ProcessorStatus;
 use crate::cpu::CPU;
-use crate::memory::memory::Memory;
 
 pub fn lda(cpu: &mut CPU, param: u8) {
     cpu.register_a = param;
@@ -26,16 +25,16 @@ pub fn ldy(cpu: &mut CPU, param: u8) {
     cpu.status.negative = is_negative(cpu.register_y);
 }
 
-pub fn sta(cpu: &mut CPU, param: u16) {
-    cpu.write(param, cpu.register_a).unwrap();
+pub fn sta(cpu: &mut CPU, param: u16) -> Result<(), EmulatorError> {
+    cpu.bus_write(param, cpu.register_a)
 }
 
-pub fn stx(cpu: &mut CPU, param: u16) {
-    cpu.write(param, cpu.register_x).unwrap();
+pub fn stx(cpu: &mut CPU, param: u16) -> Result<(), EmulatorError> {
+    cpu.bus_write(param, cpu.register_x)
 }
 
-pub fn sty(cpu: &mut CPU, param: u16) {
-    cpu.write(param, cpu.register_y).unwrap();
+pub fn sty(cpu: &mut CPU, param: u16) -> Result<(), EmulatorError> {
+    cpu.bus_write(param, cpu.register_y)
 }
 
 pub fn adc(cpu: &mut CPU, param: u8) {
@@ -64,9 +63,9 @@ pub fn sbc(cpu: &mut CPU, param: u8) {
 }
 
 pub fn inc(cpu: &mut CPU, address: u16) -> Result<(), EmulatorError>{
-    let value = cpu.read(address)?;
+    let value = read_for_modify(cpu, address)?;
     let result = value.wrapping_add(1);
-    cpu.write(address, result).unwrap();
+    cpu.bus_write(address, result)?;
 
     cpu.status.zero = result == 0;
     cpu.status.negative = is_negative(result);
@@ -88,9 +87,9 @@ pub fn iny(cpu: &mut CPU) {
 }
 
 pub fn dec(cpu: &mut CPU, param: u16) -> Result<(), EmulatorError>{
-    let value = cpu.read(param)?;
+    let value = read_for_modify(cpu, param)?;
     let result = value.wrapping_sub(1);
-    cpu.write(param, result).unwrap();
+    cpu.bus_write(param, result)?;
 
     cpu.status.zero = result == 0;
     cpu.status.negative = is_negative(result);
@@ -199,10 +198,10 @@ pub fn asl_accumulator(cpu: &mut CPU) {
 }
 
 pub fn asl(cpu: &mut CPU, address: u16) -> Result<(), EmulatorError>{
-    let value = cpu.read(address)?;
+    let value = read_for_modify(cpu, address)?;
     cpu.status.carry = value & 0b1000_0000 != 0;
     let result = value.wrapping_shl(1);
-    cpu.write(address, result)?;
+    cpu.bus_write(address, result)?;
     cpu.status.zero = result == 0;
     cpu.status.negative = is_negative(result);
     Ok(())
@@ -216,10 +215,10 @@ pub fn lsr_accumulator(cpu: &mut CPU) {
 }
 
 pub fn lsr(cpu: &mut CPU, address: u16) -> Result<(), EmulatorError>{
-    let value = cpu.read(address)?;
+    let value = read_for_modify(cpu, address)?;
     cpu.status.carry = value & 0b0000_0001 != 0;
     let result = value.wrapping_shr(1);
-    cpu.write(address, result)?;
+    cpu.bus_write(address, result)?;
     cpu.status.zero = result == 0;
     cpu.status.negative = false;
     Ok(())
@@ -234,11 +233,11 @@ pub fn rol_accumulator(cpu: &mut CPU) {
 }
 
 pub fn rol(cpu: &mut CPU, address: u16) -> Result<(), EmulatorError>{
-    let value = cpu.read(address)?;
+    let value = read_for_modify(cpu, address)?;
     let carry = if cpu.status.carry { 1 } else { 0 };
     cpu.status.carry = value & 0b1000_0000 != 0;
     let result = value.wrapping_shl(1) | carry;
-    cpu.write(address, result)?;
+    cpu.bus_write(address, result)?;
     cpu.status.zero = result == 0;
     cpu.status.negative = is_negative(result);
     Ok(())
@@ -253,11 +252,11 @@ pub fn ror_accumulator(cpu: &mut CPU) {
 }
 
 pub fn ror(cpu: &mut CPU, address: u16) -> Result<(), EmulatorError>{
-    let value = cpu.read(address)?;
+    let value = read_for_modify(cpu, address)?;
     let carry = if cpu.status.carry { 0b1000_0000 } else { 0 };
     cpu.status.carry = value & 0b0000_0001 != 0;
     let result = value.wrapping_shr(1) | carry;
-    cpu.write(address, result)?;
+    cpu.bus_write(address, result)?;
     cpu.status.zero = result == 0;
     cpu.status.negative = is_negative(result);
     Ok(())
@@ -342,12 +341,12 @@ pub fn pha(cpu: &mut CPU) -> Result<(), EmulatorError> {
 }
 
 pub fn php(cpu: &mut CPU) -> Result<(), EmulatorError> {
-    let status = cpu.status.to_u8() | 0b0001_0000;
-    stack_push(cpu, status)?;
+    stack_push(cpu, cpu.status.to_stack(true))?;
     Ok(())
 }
 
 pub fn pla(cpu: &mut CPU) -> Result<(), EmulatorError> {
+    stack_dummy_read(cpu)?;
     cpu.register_a = stack_pop(cpu)?;
     cpu.status.zero = cpu.register_a == 0;
     cpu.status.negative = is_negative(cpu.register_a);
@@ -355,9 +354,9 @@ pub fn pla(cpu: &mut CPU) -> Result<(), EmulatorError> {
 }
 
 pub fn plp(cpu: &mut CPU) -> Result<(), EmulatorError> {
+    stack_dummy_read(cpu)?;
     let status_bits = stack_pop(cpu)?;
     cpu.status = ProcessorStatus::from_u8(status_bits);
-    cpu.status.break_command = false;
     Ok(())
 }
 
@@ -389,13 +388,18 @@ pub fn sed(cpu: &mut CPU) {
     cpu.status.decimal_mode = true;
 }
 
-pub fn jsr(cpu: &mut CPU, address: u16) -> Result<(), EmulatorError> {
-    let return_address = cpu.program_counter + 2;
+/// JSR reads the low byte of the target, pushes the return address and only then reads the
+/// high byte, so it can jump to code that rewrites the stack.
+pub fn jsr(cpu: &mut CPU) -> Result<(), EmulatorError> {
+    let target_low = cpu.bus_read(cpu.program_counter.wrapping_add(1))?;
+    stack_dummy_read(cpu)?;
+    let return_address = cpu.program_counter.wrapping_add(2);
     let return_address_low = (return_address & 0x00FF) as u8;
     let return_address_high = ((return_address & 0xFF00) >> 8) as u8;
 
     stack_push(cpu, return_address_high)?;
     stack_push(cpu, return_address_low)?;
+    let address = u16::from_le_bytes([target_low, cpu.bus_read(return_address)?]);
 
     if DEBUG {
         print!("\n  Jumped to address: {:04X}", address);
@@ -407,34 +411,53 @@ pub fn jsr(cpu: &mut CPU, address: u16) -> Result<(), EmulatorError> {
 }
 
 pub fn rts(cpu: &mut CPU) -> Result<(), EmulatorError> {
+    stack_dummy_read(cpu)?;
     let return_address_low = stack_pop(cpu)?;
     let return_address_high = stack_pop(cpu)?;
     if DEBUG {
         print!("\n  Returned to address: {:02X}{:02X}", return_address_high, return_address_low);
     }
     cpu.program_counter = u16::from_le_bytes([return_address_low, return_address_high]);
+    // The last byte of the JSR is read again while the address is incremented past it.
+    cpu.bus_read(cpu.program_counter)?;
     Ok(())
 }
 
 pub fn rti(cpu: &mut CPU) -> Result<(), EmulatorError> {
+    stack_dummy_read(cpu)?;
+    // The flags are restored before the return address is pulled, so an IRQ they enable is
+    // taken straight after the RTI.
     let status_bits = stack_pop(cpu)?;
+    cpu.status = ProcessorStatus::from_u8(status_bits);
     let return_address_low = stack_pop(cpu)?;
     let return_address_high = stack_pop(cpu)?;
     if DEBUG {
         print!("\n  Returned to address: {:02X}{:02X}", return_address_high, return_address_low);
     }
     cpu.program_counter = u16::from_le_bytes([return_address_low, return_address_high]);
-    cpu.status = ProcessorStatus::from_u8(status_bits);
-    cpu.status.break_command = false;
     Ok(())
 }
 
+/// BRK skips the byte after it, so it returns two bytes on.
 pub fn brk(cpu: &mut CPU) -> Result<(), EmulatorError> {
+    cpu.program_counter = cpu.program_counter.wrapping_add(2);
+    interrupt(cpu, true)
+}
+
+/// Runs an IRQ or NMI, or the rest of a BRK, from the pushes on. The vector is only picked
+/// once the return address is on the stack, so an NMI arriving by then hijacks a BRK or IRQ
+/// and leaves the B flag it pushed as the only trace of it.
+pub fn interrupt(cpu: &mut CPU, brk: bool) -> Result<(), EmulatorError> {
     stack_push(cpu, (cpu.program_counter >> 8) as u8)?;
     stack_push(cpu, cpu.program_counter as u8)?;
-    let status = cpu.status.to_u8() | 0b0001_0000;
-    stack_push(cpu, status)?;
-    cpu.program_counter = cpu.read_u16(IRQ_VECTOR)?;
+    let vector = if cpu.bus.poll_nmi() { NMI_VECTOR } else { IRQ_VECTOR };
+    stack_push(cpu, cpu.status.to_stack(brk))?;
+    cpu.status.interrupt_disable = true;
+    let low = cpu.bus_read(vector)?;
+    cpu.program_counter = u16::from_le_bytes([low, cpu.bus_read(vector + 1)?]);
+    // Interrupts aren't polled during the sequence, so the handler's first instruction runs
+    // before another is taken.
+    cpu.interrupt_polled = false;
     Ok(())
 }
 
@@ -445,9 +468,9 @@ pub fn aac(cpu: &mut CPU, param: u8) {
     cpu.status.carry = cpu.status.negative;
 }
 
-pub fn sax(cpu: &mut CPU, address: u16) {
+pub fn sax(cpu: &mut CPU, address: u16) -> Result<(), EmulatorError> {
     let result = cpu.register_a & cpu.register_x;
-    cpu.write(address, result).unwrap();
+    cpu.bus_write(address, result)
 }
 
 pub fn arr(cpu: &mut CPU, param: u8) {
@@ -486,30 +509,29 @@ pub fn arr(cpu: &mut CPU, param: u8) {
 pub fn asr(cpu: &mut CPU, param: u8) {
     cpu.register_a &= param;
     cpu.status.carry = (cpu.register_a & 1) != 0;
-    cpu.register_a = cpu.register_a >> 1;
+    cpu.register_a >>= 1;
     cpu.status.zero = cpu.register_a == 0;
     cpu.status.negative = is_negative(cpu.register_a);
 }
 
 pub fn atx(cpu: &mut CPU, param: u8) {
-    cpu.register_a &= param;
+    cpu.register_a = (cpu.register_a | cpu.xaa_magic()) & param;
     cpu.status.zero = cpu.register_a == 0;
     cpu.status.negative = is_negative(cpu.register_a);
     cpu.register_x = cpu.register_a;
 }
 
-pub fn axa(cpu: &mut CPU, address: u16) -> Result<(), EmulatorError> {
-    let mut result = cpu.register_a & cpu.register_x;
-    result = result & 7;
-    cpu.write(address, result)?;
+pub fn axa(cpu: &mut CPU, address: u16, uncarried: u16) -> Result<(), EmulatorError> {
+    let (address, value) = cpu.unstable_store("AXA", address, uncarried);
+    cpu.bus_write(address, value)?;
     Ok(())
 }
 
 pub fn dcp(cpu: &mut CPU, address: u16) -> Result<(), EmulatorError> {
-    let value = cpu.read(address)?;
+    let value = read_for_modify(cpu, address)?;
     let decrement = value.wrapping_sub(1);
     let result = cpu.register_a.wrapping_sub(decrement);
-    cpu.write(address, decrement)?;
+    cpu.bus_write(address, decrement)?;
     cpu.status.zero = result == 0;
     cpu.status.negative = is_negative(result);
     cpu.status.carry = cpu.register_a >= decrement;
@@ -517,9 +539,9 @@ pub fn dcp(cpu: &mut CPU, address: u16) -> Result<(), EmulatorError> {
 }
 
 pub fn isb(cpu: &mut CPU, address: u16) -> Result<(), EmulatorError> {
-    let value = cpu.read(address)?;
+    let value = read_for_modify(cpu, address)?;
     let increment = value.wrapping_add(1);
-    cpu.write(address, increment)?;
+    cpu.bus_write(address, increment)?;
     let carry = if cpu.status.carry { 0 } else { 1 };
     let increment = increment.wrapping_add(carry);
     let (result, cout) = cpu.register_a.overflowing_sub(increment);
@@ -542,11 +564,11 @@ pub fn lar(cpu: &mut CPU, param: u8) {
 }
 
 pub fn rla(cpu: &mut CPU, address: u16) -> Result<(), EmulatorError> {
-    let value = cpu.read(address)?;
+    let value = read_for_modify(cpu, address)?;
     let carry = if cpu.status.carry { 1 } else { 0 };
     cpu.status.carry = value & 0b1000_0000 != 0;
     let result = value.wrapping_shl(1) | carry;
-    cpu.write(address, result)?;
+    cpu.bus_write(address, result)?;
     let result_and = result & cpu.register_a;
     cpu.register_a = result_and;
     cpu.status.zero = result_and == 0;
@@ -555,11 +577,11 @@ pub fn rla(cpu: &mut CPU, address: u16) -> Result<(), EmulatorError> {
 }
 
 pub fn rra(cpu: &mut CPU, address: u16) -> Result<(), EmulatorError> {
-    let value = cpu.read(address)?;
+    let value = read_for_modify(cpu, address)?;
     let carry_old = if cpu.status.carry { 0b1000_0000 } else { 0 };
     let carry_rotate = value & 1;
     let result = value.wrapping_shr(1) | carry_old;
-    cpu.write(address, result)?;
+    cpu.bus_write(address, result)?;
     let old_a = cpu.register_a;
     let (result_sum, cout) = cpu.register_a.overflowing_add(result + carry_rotate);
     cpu.register_a = result_sum;
@@ -571,7 +593,7 @@ pub fn rra(cpu: &mut CPU, address: u16) -> Result<(), EmulatorError> {
 }
 
 pub fn axs(cpu: &mut CPU, address: u16) ->  Result<(), EmulatorError> {
-    let value = cpu.read(address)?;
+    let value = cpu.bus_read(address)?;
     let result = cpu.register_a & cpu.register_x;
     let (sub_result, cout) = result.overflowing_sub(value);
     cpu.status.zero = sub_result == 0;
@@ -582,7 +604,7 @@ pub fn axs(cpu: &mut CPU, address: u16) ->  Result<(), EmulatorError> {
 }
 
 pub fn lax(cpu: &mut CPU, address: u16) ->  Result<(), EmulatorError> {
-    let value = cpu.read(address)?;
+    let value = cpu.bus_read(address)?;
     cpu.status.zero = value ==0;
     cpu.status.negative = is_negative(value);
     cpu.register_a = value;
@@ -591,10 +613,10 @@ pub fn lax(cpu: &mut CPU, address: u16) ->  Result<(), EmulatorError> {
 }
 
 pub fn slo(cpu: &mut CPU, address: u16) -> Result<(), EmulatorError> {
-    let value = cpu.read(address)?;
+    let value = read_for_modify(cpu, address)?;
     cpu.status.carry = value & 0b1000_0000 != 0;
     let result = value.wrapping_shl(1);
-    cpu.write(address, result)?;
+    cpu.bus_write(address, result)?;
     cpu.register_a |= result;
     cpu.status.zero = cpu.register_a == 0;
     cpu.status.negative = is_negative(cpu.register_a);
@@ -602,42 +624,58 @@ pub fn slo(cpu: &mut CPU, address: u16) -> Result<(), EmulatorError> {
 }
 
 pub fn sre(cpu: &mut CPU, address: u16) -> Result<(), EmulatorError> {
-    let value = cpu.read(address)?;
+    let value = read_for_modify(cpu, address)?;
     cpu.status.carry = value & 0b0000_0001 != 0;
     let result = value.wrapping_shr(1);
-    cpu.write(address, result)?;
+    cpu.bus_write(address, result)?;
     cpu.register_a ^= result;
     cpu.status.zero = cpu.register_a == 0;
     cpu.status.negative = is_negative(cpu.register_a);
     Ok(())
 }
 
-pub fn sxa(cpu: &mut CPU, address: u16) -> Result<(), EmulatorError> {
-    let address_high = (address >> 8) as u8;
-    let result = (cpu.register_x & address_high).wrapping_add(1);
-    cpu.write(address, result)?;
+pub fn sxa(cpu: &mut CPU, address: u16, uncarried: u16) -> Result<(), EmulatorError> {
+    let (address, value) = cpu.unstable_store("SXA", address, uncarried);
+    cpu.bus_write(address, value)?;
+    Ok(())
+}
+
+pub fn sya(cpu: &mut CPU, address: u16, uncarried: u16) -> Result<(), EmulatorError> {
+    let (address, value) = cpu.unstable_store("SYA", address, uncarried);
+    cpu.bus_write(address, value)?;
     Ok(())
 }
 
-pub fn sya(cpu: &mut CPU, address: u16) -> Result<(), EmulatorError> {
-    let address_high = (address >> 8) as u8;
-    let result = (cpu.register_y & address_high).wrapping_add(1);
-    cpu.write(address, result)?;
+pub fn xaa(cpu: &mut CPU, param: u8) {
+    cpu.register_a = (cpu.register_a | cpu.xaa_magic()) & cpu.register_x & param;
+    cpu.status.zero = cpu.register_a == 0;
+    cpu.status.negative = is_negative(cpu.register_a);
+}
+
+pub fn xas(cpu: &mut CPU, address: u16, uncarried: u16) -> Result<(), EmulatorError> {
+    cpu.stack_pointer = cpu.register_a & cpu.register_x;
+    let (address, value) = cpu.unstable_store("XAS", address, uncarried);
+    cpu.bus_write(address, value)?;
     Ok(())
 }
 
-pub fn xas(cpu: &mut CPU, address: u16) -> Result<(), EmulatorError> {
-    let new_sp = cpu.register_a & cpu.register_x;
-    cpu.stack_pointer = new_sp;
-    let address_high = (address >> 8) as u8;
-    let result = (new_sp & address_high).wrapping_add(1);
-    cpu.write(address, result)?;
+/// Read-modify-write instructions write the value they read straight back while they modify
+/// it, which registers and mappers see as a second write.
+fn read_for_modify(cpu: &mut CPU, address: u16) -> Result<u8, EmulatorError> {
+    let value = cpu.bus_read(address)?;
+    cpu.bus_write(address, value)?;
+    Ok(value)
+}
+
+/// Pulls spend a cycle reading the stack before the pointer moves.
+fn stack_dummy_read(cpu: &mut CPU) -> Result<(), EmulatorError> {
+    cpu.bus_read(cpu.stack_pointer as u16 + STACK_START)?;
     Ok(())
 }
 
 fn stack_push(cpu: &mut CPU, value: u8) -> Result<(), EmulatorError> {
     let sp_address = cpu.stack_pointer as u16 + STACK_START;
-    cpu.write(sp_address, value)?;
+    cpu.bus_write(sp_address, value)?;
     cpu.stack_pointer = cpu.stack_pointer.wrapping_sub(1);
     if DEBUG {
         print!("\n  Pushed {:02X} to stack at {:#04X}", value, sp_address);
@@ -648,7 +686,7 @@ fn stack_push(cpu: &mut CPU, value: u8) -> Result<(), EmulatorError> {
 fn stack_pop(cpu: &mut CPU) -> Result<u8, EmulatorError> {
     cpu.stack_pointer = cpu.stack_pointer.wrapping_add(1);
     let sp_address = cpu.stack_pointer as u16 + STACK_START;
-    let value = cpu.read(sp_address)?;
+    let value = cpu.bus_read(sp_address)?;
     if DEBUG {
         print!("\n  Popped {:02X} from stack at {:#04X}", value, sp_address);
     }
@@ -1,15 +1,22 @@
 pub mod opcode;
 pub mod types;
 mod test;
+mod cycle;
 mod instructions;
 
-use crate::common::constants::{DEBUG, PC_START_ADDRESS, STACK_POINTER_INIT};
+use crate::common::constants::{DEBUG, PC_START_ADDRESS, STACK_POINTER_INIT, XAA_MAGIC};
 use crate::common::errors::EmulatorError;
-use crate::cpu::opcode::{get_opcode};
-use crate::cpu::types::{AddressingMode, ProcessorStatus};
+use crate::cpu::opcode::{get_opcode, Opcode};
+use crate::cpu::types::{AddressingMode, Core, ProcessorStatus};
 use crate::memory::bus::Bus;
 use crate::memory::memory::Memory;
 use crate::cartridge::rom::Rom;
+use crate::savestate;
+use crate::controller::Controller;
+use crate::ppu::PPU;
+use crate::apu::APU;
+
+const PAGE_CROSS_OPCODES: [&str; 12] = ["LDA", "LDX", "LDY", "ADC", "SBC", "AND", "EOR", "ORA", "CMP", "LAX", "LAR", "NOP"];
 
 pub struct CPU {
     tests: bool,
@@ -19,33 +26,45 @@ pub struct CPU {
     pub register_x: u8,
     pub register_y: u8,
     pub status: ProcessorStatus,
+    pub cycles: u64,
     pub(crate) bus: Bus,
+    /// Return address of the interrupt serviced at the end of the last step, if any.
+    pub(crate) interrupted_at: Option<u16>,
+    core: Core,
+    /// Whether an interrupt was wanted at the end of the last bus cycle, and of the one before,
+    /// which is the one an instruction polls.
+    pub(crate) interrupt_line: bool,
+    pub(crate) interrupt_polled: bool,
+    /// The bits XAA and LXA see set in A whatever it holds, which varies from chip to chip.
+    xaa_magic: u8,
 }
 
 impl Memory for CPU {
-    fn read(&self, address: u16) -> Result<u8, EmulatorError> {
+    fn read(&mut self, address: u16) -> Result<u8, EmulatorError> {
         self.bus.read(address)
     }
 
-    fn write(&mut self, address: u16, value: u8) -> Result<(), EmulatorError> {
-        self.bus.write(address, value)
-    }
-
-    fn read_u16(&self, address: u16) -> Result<u16, EmulatorError> {
-        self.bus.read_u16(address)
+    fn peek(&self, address: u16) -> Result<u8, EmulatorError> {
+        self.bus.peek(address)
     }
 
-    fn read_u16_zero_page(&self, address: u8) -> Result<u16, EmulatorError> {
-        self.bus.read_u16_zero_page(address)
+    fn write(&mut self, address: u16, value: u8) -> Result<(), EmulatorError> {
+        self.bus.write(address, value)
     }
+}
 
-    fn write_u16(&mut self, address: u16, value: u16) -> Result<(), EmulatorError> {
-        self.bus.write_u16(address, value)
+impl Default for CPU {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl CPU {
     pub fn new() -> CPU {
+        Self::with_core(Core::default())
+    }
+
+    pub fn with_core(core: Core) -> CPU {
         CPU {
             tests: false,
             program_counter: 0,
@@ -53,12 +72,43 @@ impl CPU {
             register_a: 0,
             register_x: 0,
             register_y: 0,
-            status: ProcessorStatus::new(),
+            status: ProcessorStatus { interrupt_disable: true, ..ProcessorStatus::new() },
+            cycles: 0,
             bus: Bus::new(),
+            interrupted_at: None,
+            core,
+            interrupt_line: false,
+            interrupt_polled: false,
+            xaa_magic: XAA_MAGIC,
         }
     }
+
+    pub fn core(&self) -> Core {
+        self.core
+    }
+
+    /// A CPU with `program` at $8000 and the PC on it, for tests.
+    #[cfg(test)]
+    pub(crate) fn with_program(program: &[u8]) -> CPU {
+        Self::with_interrupt_handlers(Core::default(), program, &[])
+    }
+
+    /// A CPU with `program` at $8000 and the PC on it, NMIs going to `nmi_handler` at $8100 and
+    /// IRQs to $8200, for tests.
+    #[cfg(test)]
+    pub(crate) fn with_interrupt_handlers(core: Core, program: &[u8], nmi_handler: &[u8]) -> CPU {
+        use crate::common::constants::PRG_ROM_START;
+        let mut cpu = CPU::with_core(core);
+        let mut rom = Rom::default();
+        rom.prg_rom[..program.len()].copy_from_slice(program);
+        rom.prg_rom[0x100..0x100 + nmi_handler.len()].copy_from_slice(nmi_handler);
+        rom.prg_rom[0x3FFA..].copy_from_slice(&[0x00, 0x81, 0x00, 0x80, 0x00, 0x82]);
+        cpu.bus.load_rom(rom);
+        cpu.program_counter = PRG_ROM_START;
+        cpu
+    }
     
-    pub fn load(&mut self, rom: &Vec<u8>) -> Result<(), EmulatorError> {
+    pub fn load(&mut self, rom: &[u8]) -> Result<(), EmulatorError> {
         let rom = Rom::new(rom)?;
         self.bus.load_rom(rom);
         Ok(())
@@ -70,422 +120,670 @@ impl CPU {
         self.register_a = 0;
         self.register_x = 0;
         self.register_y = 0;
-        self.status = ProcessorStatus::new();
+        self.status = ProcessorStatus { interrupt_disable: true, ..ProcessorStatus::new() };
+        self.cycles = 7;
         Ok(())
     }
 
+    pub fn power_on(&mut self) -> Result<(), EmulatorError> {
+        self.bus.power_on();
+        self.reset()
+    }
+
+    pub fn controller(&mut self, port: usize) -> &mut Controller {
+        self.bus.controller(port)
+    }
+
+    pub fn ppu(&self) -> &PPU {
+        self.bus.ppu()
+    }
+
+    pub fn ppu_mut(&mut self) -> &mut PPU {
+        self.bus.ppu_mut()
+    }
+
+    pub fn apu(&mut self) -> &mut APU {
+        self.bus.apu()
+    }
+
+    /// Makes unmapped reads and writes fail with `AccessViolation` instead of hitting open bus.
+    pub fn set_strict_access(&mut self, strict: bool) {
+        self.bus.set_strict_access(strict);
+    }
+
+    /// Sets the constant XAA and LXA OR into A before the AND. $FF, the default, matches most
+    /// consoles and test ROMs; $EE and $00 are also seen.
+    pub fn set_xaa_magic(&mut self, magic: u8) {
+        self.xaa_magic = magic;
+    }
+
+    pub(crate) fn xaa_magic(&self) -> u8 {
+        self.xaa_magic
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        savestate::save(self)
+    }
+
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), EmulatorError> {
+        savestate::load(self, state)
+    }
+
     pub fn run<F>(&mut self, mut callback: F) -> Result<(), EmulatorError>
         where
             F: FnMut(&mut CPU) -> Result<(), EmulatorError> {
         loop {
             callback(self)?;
+            if !self.step()? {
+                break;
+            }
+        }
+        Ok(())
+    }
 
-            let opcode_u8 = self.read(self.program_counter)?;
-            let opcode = get_opcode(opcode_u8).ok_or(EmulatorError::InvalidOpcode(opcode_u8))?;
-            let mut increase_pc = true;
-            if DEBUG {
-                print!("\nExec: {:?} at PC: {:#04X} | Addressing mode: {:?}", opcode.name, self.program_counter, opcode.address_mode);
-                if opcode.bytes == 2 {
-                    let byte = self.read(self.program_counter + 1)?;
-                    print!(" | param: {:#04X}", byte);
-                }
-                if opcode.bytes == 3 {
-                    let byte = self.read_u16(self.program_counter + 1)?;
-                    print!(" | param: {:#06X}", byte);
-                }
+    pub fn run_frame<F>(&mut self, mut callback: F) -> Result<bool, EmulatorError>
+        where
+            F: FnMut(&mut CPU) -> Result<(), EmulatorError> {
+        let frame = self.frame();
+        while self.frame() == frame {
+            callback(self)?;
+            if !self.step()? {
+                return Ok(false);
             }
+        }
+        Ok(true)
+    }
 
-            match opcode.name {
-                // Load and Store
-                "LDA" => {
-                    let param_address = self.get_param_address(&opcode.address_mode)?;
-                    let param = self.read(param_address)?;
-                    instructions::lda(self, param);
-                }
-                "LDX" => {
-                    let param_address = self.get_param_address(&opcode.address_mode)?;
-                    let param = self.read(param_address)?;
-                    instructions::ldx(self, param);
-                }
-                "LDY" => {
-                    let param_address = self.get_param_address(&opcode.address_mode)?;
-                    let param = self.read(param_address)?;
-                    instructions::ldy(self, param);
-                }
-                "STA" => {
-                    let param_address = self.get_param_address(&opcode.address_mode)?;
-                    instructions::sta(self, param_address);
-                }
-                "STX" => {
-                    let param_address = self.get_param_address(&opcode.address_mode)?;
-                    instructions::stx(self, param_address);
-                }
-                "STY" => {
-                    let param_address = self.get_param_address(&opcode.address_mode)?;
-                    instructions::sty(self, param_address);
-                }
-                // Arithmetic
-                "ADC" => {
-                    let param_address = self.get_param_address(&opcode.address_mode)?;
-                    let param = self.read(param_address)?;
-                    instructions::adc(self, param);
-                }
-                "SBC" => {
-                    let param_address = self.get_param_address(&opcode.address_mode)?;
-                    let param = self.read(param_address)?;
-                    instructions::sbc(self, param);
-                }
-                // Increment and Decrement
-                "INC" => {
-                    let param_address = self.get_param_address(&opcode.address_mode)?;
-                    instructions::inc(self, param_address)?;
-                }
-                "INX" => {
-                    instructions::inx(self);
-                }
-                "INY" => {
-                    instructions::iny(self);
-                }
-                "DEC" => {
-                    let param_address = self.get_param_address(&opcode.address_mode)?;
-                    instructions::dec(self, param_address)?;
-                }
-                "DEX" => {
-                    instructions::dex(self);
-                }
-                "DEY" => {
-                    instructions::dey(self);
-                }
-                // Register Transfer
-                "TAX" => {
-                    instructions::tax(self);
-                }
-                "TAY" => {
-                    instructions::tay(self);
-                }
-                "TXA" => {
-                    instructions::txa(self);
-                }
-                "TYA" => {
-                    instructions::tya(self);
-                }
-                // Logical
-                "AND" => {
-                    let param_address = self.get_param_address(&opcode.address_mode)?;
-                    let param = self.read(param_address)?;
-                    instructions::and(self, param);
-                }
-                "EOR" => {
-                    let param_address = self.get_param_address(&opcode.address_mode)?;
-                    let param = self.read(param_address)?;
-                    instructions::eor(self, param);
-                }
-                "ORA" => {
-                    let param_address = self.get_param_address(&opcode.address_mode)?;
-                    let param = self.read(param_address)?;
-                    instructions::ora(self, param);
-                }
-                // Compare and Bit Test
-                "CMP" => {
-                    let param_address = self.get_param_address(&opcode.address_mode)?;
-                    let param = self.read(param_address)?;
-                    instructions::cmp(self, param);
-                }
-                "CPX" => {
-                    let param_address = self.get_param_address(&opcode.address_mode)?;
-                    let param = self.read(param_address)?;
-                    instructions::cpx(self, param);
-                }
-                "CPY" => {
-                    let param_address = self.get_param_address(&opcode.address_mode)?;
-                    let param = self.read(param_address)?;
-                    instructions::cpy(self, param);
-                }
-                "BIT" => {
-                    let param_address = self.get_param_address(&opcode.address_mode)?;
-                    let param = self.read(param_address)?;
-                    instructions::bit(self, param);
-                }
-                // Shift and Rotate
-                "ASL" => {
-                    if opcode.address_mode == AddressingMode::Accumulator {
-                        instructions::asl_accumulator(self);
-                    } else {
-                        let param_address = self.get_param_address(&opcode.address_mode)?;
-                        instructions::asl(self, param_address)?;
-                    }
-                }
-                "LSR" => {
-                    if opcode.address_mode == AddressingMode::Accumulator {
-                        instructions::lsr_accumulator(self);
-                    } else {
-                        let param_address = self.get_param_address(&opcode.address_mode)?;
-                        instructions::lsr(self, param_address)?;
-                    }
-                }
-                "ROL" => {
-                    if opcode.address_mode == AddressingMode::Accumulator {
-                        instructions::rol_accumulator(self);
-                    } else {
-                        let param_address = self.get_param_address(&opcode.address_mode)?;
-                        instructions::rol(self, param_address)?;
-                    }
-                }
-                "ROR" => {
-                    if opcode.address_mode == AddressingMode::Accumulator {
-                        instructions::ror_accumulator(self);
-                    } else {
-                        let param_address = self.get_param_address(&opcode.address_mode)?;
-                        instructions::ror(self, param_address)?;
-                    }
-                }
-                // Jump and Branch
-                "JMP" => {
-                    let address = self.get_param_address(&opcode.address_mode)?;
-                    instructions::jmp(self, address);
-                    increase_pc = false;
-                }
-                "BCC" => {
-                    let offset = self.get_param_address(&opcode.address_mode)? as i8;
-                    instructions::bcc(self, offset)?;
-                }
-                "BCS" => {
-                    let offset = self.get_param_address(&opcode.address_mode)? as i8;
-                    instructions::bcs(self, offset)?;
-                }
-                "BEQ" => {
-                    let offset = self.get_param_address(&opcode.address_mode)? as i8;
-                    instructions::beq(self, offset)?;
-                }
-                "BMI" => {
-                    let offset = self.get_param_address(&opcode.address_mode)? as i8;
-                    instructions::bmi(self, offset)?;
-                }
-                "BNE" => {
-                    let offset = self.get_param_address(&opcode.address_mode)? as i8;
-                    instructions::bne(self, offset)?;
-                }
-                "BPL" => {
-                    let offset = self.get_param_address(&opcode.address_mode)? as i8;
-                    instructions::bpl(self, offset)?;
-                }
-                "BVC" => {
-                    let offset = self.get_param_address(&opcode.address_mode)? as i8;
-                    instructions::bvc(self, offset)?;
-                }
-                "BVS" => {
-                    let offset = self.get_param_address(&opcode.address_mode)? as i8;
-                    instructions::bvs(self, offset)?;
-                }
-                // Stack
-                "TSX" => {
-                    instructions::tsx(self);
-                }
-                "TXS" => {
-                    instructions::txs(self);
-                }
-                "PHA" => {
-                    instructions::pha(self)?;
-                }
-                "PHP" => {
-                    instructions::php(self)?;
-                }
-                "PLA" => {
-                    instructions::pla(self)?;
-                }
-                "PLP" => {
-                    instructions::plp(self)?;
-                }
-                // Status Flag Changes
-                "CLC" => {
-                    instructions::clc(self);
-                }
-                "CLD" => {
-                    instructions::cld(self);
-                }
-                "CLI" => {
-                    instructions::cli(self);
-                }
-                "CLV" => {
-                    instructions::clv(self);
-                }
-                "SEC" => {
-                    instructions::sec(self);
-                }
-                "SED" => {
-                    instructions::sed(self);
-                }
-                "SEI" => {
-                    instructions::sei(self);
-                }
-                // Subroutine and Interrupt
-                "JSR" => {
-                    let address = self.get_param_address(&opcode.address_mode)?;
-                    instructions::jsr(self, address)?;
-                    increase_pc = false;
-                }
-                "RTS" => {
-                    instructions::rts(self)?;
-                }
-                "BRK" => {
-                    if !self.tests {
-                        instructions::brk(self)?;
-                    }
-                    else {
-                        self.program_counter += opcode.bytes as u16;
-                    }
-                    break;
-                }
-                "RTI" => {
-                    instructions::rti(self)?;
-                    increase_pc = false;
-                }
-                "NOP" => {}
-                // Unofficial
-                "AAC" => {
-                    let param_address = self.get_param_address(&opcode.address_mode)?;
-                    let param = self.read(param_address)?;
-                    instructions::aac(self, param);
-                }
-                "SAX" => {
-                    let param = self.get_param_address(&opcode.address_mode)?;
-                    instructions::sax(self, param);
-                }
-                "ARR" => {
-                    let param_address = self.get_param_address(&opcode.address_mode)?;
-                    let param = self.read(param_address)?;
-                    instructions::arr(self, param);
-                }
-                "ASR" => {
-                    let param_address = self.get_param_address(&opcode.address_mode)?;
-                    let param = self.read(param_address)?;
-                    instructions::asr(self, param);
-                }
-                "ATX" => {
-                    let param_address = self.get_param_address(&opcode.address_mode)?;
-                    let param = self.read(param_address)?;
-                    instructions::atx(self, param);
-                }
-                "AXA" => {
-                    let param_address = self.get_param_address(&opcode.address_mode)?;
-                    instructions::axa(self, param_address)?
-                }
-                "AXS" => {
-                    let param_address = self.get_param_address(&opcode.address_mode)?;
-                    instructions::axs(self, param_address)?
-                }
-                "DCP" => {
-                    let param_address = self.get_param_address(&opcode.address_mode)?;
-                    instructions::dcp(self, param_address)?;
-                }
-                "ISB" => {
-                    let param_address = self.get_param_address(&opcode.address_mode)?;
-                    instructions::isb(self, param_address)?;
-                }
-                "KIL" => {
-                    Err(EmulatorError::UnimplementedOpcode(opcode_u8))?;
-                }
-                "LAR" => {
-                    let param_address = self.get_param_address(&opcode.address_mode)?;
-                    let param = self.read(param_address)?;
-                    instructions::lar(self, param);
-                }
-                "LAX" => {
-                    let param_address = self.get_param_address(&opcode.address_mode)?;
-                    instructions::lax(self, param_address)?;
-                }
-                "RLA" => {
-                    let param_address = self.get_param_address(&opcode.address_mode)?;
-                    instructions::rla(self, param_address)?;
-                }
-                "RRA" => {
-                    let param_address = self.get_param_address(&opcode.address_mode)?;
-                    instructions::rra(self, param_address)?;
+    pub fn step(&mut self) -> Result<bool, EmulatorError> {
+        match self.core {
+            Core::InstructionStepped => self.step_instruction(),
+            Core::CycleStepped => self.step_cycles(),
+        }
+    }
+
+    fn step_instruction(&mut self) -> Result<bool, EmulatorError> {
+        let start_pc = self.program_counter;
+        let start_cycles = self.cycles;
+        self.interrupted_at = None;
+        let opcode_u8 = self.bus_read(start_pc)?;
+        let opcode = get_opcode(opcode_u8).ok_or(EmulatorError::InvalidOpcode(opcode_u8))?;
+        let branch_taken = self.branch_taken(opcode.name);
+        self.cycles += opcode.cycles as u64;
+        if PAGE_CROSS_OPCODES.contains(&opcode.name) && self.crosses_page(&opcode.address_mode)? {
+            self.cycles += 1;
+        }
+        // Catch the rest of the system up to the instruction's last bus access, which is where
+        // reads and writes of the PPU's registers land, before executing it.
+        let access_cycles = self.cycles - start_cycles - 1;
+        self.bus.tick(access_cycles);
+        let running = self.execute(opcode_u8, opcode)?;
+        if branch_taken {
+            let next = start_pc.wrapping_add(opcode.bytes as u16);
+            self.cycles += if next & 0xFF00 != self.program_counter & 0xFF00 { 2 } else { 1 };
+        }
+        self.cycles += self.bus.take_dma_cycles();
+        self.bus.tick(self.cycles - start_cycles - access_cycles);
+        if self.interrupt_requested() {
+            self.interrupted_at = Some(self.program_counter);
+            self.interrupt_sequence()?;
+            self.cycles += 7;
+            self.bus.tick(7);
+        }
+        Ok(running)
+    }
+
+    /// Executes the instruction whose opcode was just fetched, with the same bus accesses in
+    /// the same order as the 6502, dummy ones included. Returns false on BRK in tests.
+    fn execute(&mut self, opcode_u8: u8, opcode: &Opcode) -> Result<bool, EmulatorError> {
+        let mut increase_pc = true;
+        let mut running = true;
+        let start_pc = self.program_counter;
+        let branch_taken = self.branch_taken(opcode.name);
+        if DEBUG {
+            print!("\nExec: {:?} at PC: {:#04X} | Addressing mode: {:?}", opcode.name, self.program_counter, opcode.address_mode);
+            if opcode.bytes == 2 {
+                let byte = self.peek(self.program_counter + 1)?;
+                print!(" | param: {:#04X}", byte);
+            }
+            if opcode.bytes == 3 {
+                let byte = self.peek_u16(self.program_counter + 1)?;
+                print!(" | param: {:#06X}", byte);
+            }
+        }
+        if matches!(opcode.address_mode, AddressingMode::Implied | AddressingMode::Accumulator) {
+            // One-byte instructions still read the byte after the opcode.
+            self.bus_read(start_pc.wrapping_add(1))?;
+        }
+
+        match opcode.name {
+            // Load and Store
+            "LDA" => {
+                let param_address = self.get_param_address(&opcode.address_mode)?;
+                let param = self.bus_read(param_address)?;
+                instructions::lda(self, param);
+            }
+            "LDX" => {
+                let param_address = self.get_param_address(&opcode.address_mode)?;
+                let param = self.bus_read(param_address)?;
+                instructions::ldx(self, param);
+            }
+            "LDY" => {
+                let param_address = self.get_param_address(&opcode.address_mode)?;
+                let param = self.bus_read(param_address)?;
+                instructions::ldy(self, param);
+            }
+            "STA" => {
+                let param_address = self.get_write_address(&opcode.address_mode)?;
+                instructions::sta(self, param_address)?;
+            }
+            "STX" => {
+                let param_address = self.get_write_address(&opcode.address_mode)?;
+                instructions::stx(self, param_address)?;
+            }
+            "STY" => {
+                let param_address = self.get_write_address(&opcode.address_mode)?;
+                instructions::sty(self, param_address)?;
+            }
+            // Arithmetic
+            "ADC" => {
+                let param_address = self.get_param_address(&opcode.address_mode)?;
+                let param = self.bus_read(param_address)?;
+                instructions::adc(self, param);
+            }
+            "SBC" => {
+                let param_address = self.get_param_address(&opcode.address_mode)?;
+                let param = self.bus_read(param_address)?;
+                instructions::sbc(self, param);
+            }
+            // Increment and Decrement
+            "INC" => {
+                let param_address = self.get_write_address(&opcode.address_mode)?;
+                instructions::inc(self, param_address)?;
+            }
+            "INX" => {
+                instructions::inx(self);
+            }
+            "INY" => {
+                instructions::iny(self);
+            }
+            "DEC" => {
+                let param_address = self.get_write_address(&opcode.address_mode)?;
+                instructions::dec(self, param_address)?;
+            }
+            "DEX" => {
+                instructions::dex(self);
+            }
+            "DEY" => {
+                instructions::dey(self);
+            }
+            // Register Transfer
+            "TAX" => {
+                instructions::tax(self);
+            }
+            "TAY" => {
+                instructions::tay(self);
+            }
+            "TXA" => {
+                instructions::txa(self);
+            }
+            "TYA" => {
+                instructions::tya(self);
+            }
+            // Logical
+            "AND" => {
+                let param_address = self.get_param_address(&opcode.address_mode)?;
+                let param = self.bus_read(param_address)?;
+                instructions::and(self, param);
+            }
+            "EOR" => {
+                let param_address = self.get_param_address(&opcode.address_mode)?;
+                let param = self.bus_read(param_address)?;
+                instructions::eor(self, param);
+            }
+            "ORA" => {
+                let param_address = self.get_param_address(&opcode.address_mode)?;
+                let param = self.bus_read(param_address)?;
+                instructions::ora(self, param);
+            }
+            // Compare and Bit Test
+            "CMP" => {
+                let param_address = self.get_param_address(&opcode.address_mode)?;
+                let param = self.bus_read(param_address)?;
+                instructions::cmp(self, param);
+            }
+            "CPX" => {
+                let param_address = self.get_param_address(&opcode.address_mode)?;
+                let param = self.bus_read(param_address)?;
+                instructions::cpx(self, param);
+            }
+            "CPY" => {
+                let param_address = self.get_param_address(&opcode.address_mode)?;
+                let param = self.bus_read(param_address)?;
+                instructions::cpy(self, param);
+            }
+            "BIT" => {
+                let param_address = self.get_param_address(&opcode.address_mode)?;
+                let param = self.bus_read(param_address)?;
+                instructions::bit(self, param);
+            }
+            // Shift and Rotate
+            "ASL" => {
+                if opcode.address_mode == AddressingMode::Accumulator {
+                    instructions::asl_accumulator(self);
+                } else {
+                    let param_address = self.get_write_address(&opcode.address_mode)?;
+                    instructions::asl(self, param_address)?;
                 }
-                "SLO" => {
-                    let param_address = self.get_param_address(&opcode.address_mode)?;
-                    instructions::slo(self, param_address)?;
+            }
+            "LSR" => {
+                if opcode.address_mode == AddressingMode::Accumulator {
+                    instructions::lsr_accumulator(self);
+                } else {
+                    let param_address = self.get_write_address(&opcode.address_mode)?;
+                    instructions::lsr(self, param_address)?;
                 }
-                "SRE" => {
-                    let param_address = self.get_param_address(&opcode.address_mode)?;
-                    instructions::sre(self, param_address)?;
+            }
+            "ROL" => {
+                if opcode.address_mode == AddressingMode::Accumulator {
+                    instructions::rol_accumulator(self);
+                } else {
+                    let param_address = self.get_write_address(&opcode.address_mode)?;
+                    instructions::rol(self, param_address)?;
                 }
-                "SXA" => {
-                    let param_address = self.get_param_address(&opcode.address_mode)?;
-                    instructions::sxa(self, param_address)?;
+            }
+            "ROR" => {
+                if opcode.address_mode == AddressingMode::Accumulator {
+                    instructions::ror_accumulator(self);
+                } else {
+                    let param_address = self.get_write_address(&opcode.address_mode)?;
+                    instructions::ror(self, param_address)?;
                 }
-                "SYA" => {
-                    let param_address = self.get_param_address(&opcode.address_mode)?;
-                    instructions::sya(self, param_address)?;
+            }
+            // Jump and Branch
+            "JMP" => {
+                let address = self.get_param_address(&opcode.address_mode)?;
+                instructions::jmp(self, address);
+                increase_pc = false;
+            }
+            "BCC" => {
+                let offset = self.get_param_address(&opcode.address_mode)? as i8;
+                instructions::bcc(self, offset)?;
+            }
+            "BCS" => {
+                let offset = self.get_param_address(&opcode.address_mode)? as i8;
+                instructions::bcs(self, offset)?;
+            }
+            "BEQ" => {
+                let offset = self.get_param_address(&opcode.address_mode)? as i8;
+                instructions::beq(self, offset)?;
+            }
+            "BMI" => {
+                let offset = self.get_param_address(&opcode.address_mode)? as i8;
+                instructions::bmi(self, offset)?;
+            }
+            "BNE" => {
+                let offset = self.get_param_address(&opcode.address_mode)? as i8;
+                instructions::bne(self, offset)?;
+            }
+            "BPL" => {
+                let offset = self.get_param_address(&opcode.address_mode)? as i8;
+                instructions::bpl(self, offset)?;
+            }
+            "BVC" => {
+                let offset = self.get_param_address(&opcode.address_mode)? as i8;
+                instructions::bvc(self, offset)?;
+            }
+            "BVS" => {
+                let offset = self.get_param_address(&opcode.address_mode)? as i8;
+                instructions::bvs(self, offset)?;
+            }
+            // Stack
+            "TSX" => {
+                instructions::tsx(self);
+            }
+            "TXS" => {
+                instructions::txs(self);
+            }
+            "PHA" => {
+                instructions::pha(self)?;
+            }
+            "PHP" => {
+                instructions::php(self)?;
+            }
+            "PLA" => {
+                instructions::pla(self)?;
+            }
+            "PLP" => {
+                instructions::plp(self)?;
+            }
+            // Status Flag Changes
+            "CLC" => {
+                instructions::clc(self);
+            }
+            "CLD" => {
+                instructions::cld(self);
+            }
+            "CLI" => {
+                instructions::cli(self);
+            }
+            "CLV" => {
+                instructions::clv(self);
+            }
+            "SEC" => {
+                instructions::sec(self);
+            }
+            "SED" => {
+                instructions::sed(self);
+            }
+            "SEI" => {
+                instructions::sei(self);
+            }
+            // Subroutine and Interrupt
+            "JSR" => {
+                instructions::jsr(self)?;
+                increase_pc = false;
+            }
+            "RTS" => {
+                instructions::rts(self)?;
+            }
+            "BRK" => {
+                if !self.tests {
+                    instructions::brk(self)?;
                 }
-                "XAA" => {
-                    { }
+                else {
+                    self.program_counter += opcode.bytes as u16;
                 }
-                "XAS" => {
+                increase_pc = false;
+                running = false;
+            }
+            "RTI" => {
+                instructions::rti(self)?;
+                increase_pc = false;
+            }
+            "NOP" => {
+                if opcode.address_mode != AddressingMode::Implied {
                     let param_address = self.get_param_address(&opcode.address_mode)?;
-                    instructions::xas(self, param_address)?;
+                    self.bus_read(param_address)?;
                 }
-                _ => return Err(EmulatorError::UnimplementedOpcode(opcode_u8)),
             }
-            if increase_pc {
-                self.program_counter += opcode.bytes as u16;
+            // Unofficial
+            "AAC" => {
+                let param_address = self.get_param_address(&opcode.address_mode)?;
+                let param = self.bus_read(param_address)?;
+                instructions::aac(self, param);
+            }
+            "SAX" => {
+                let param = self.get_write_address(&opcode.address_mode)?;
+                instructions::sax(self, param)?;
+            }
+            "ARR" => {
+                let param_address = self.get_param_address(&opcode.address_mode)?;
+                let param = self.bus_read(param_address)?;
+                instructions::arr(self, param);
+            }
+            "ASR" => {
+                let param_address = self.get_param_address(&opcode.address_mode)?;
+                let param = self.bus_read(param_address)?;
+                instructions::asr(self, param);
+            }
+            "ATX" => {
+                let param_address = self.get_param_address(&opcode.address_mode)?;
+                let param = self.bus_read(param_address)?;
+                instructions::atx(self, param);
+            }
+            "AXA" => {
+                let param_address = self.get_write_address(&opcode.address_mode)?;
+                let uncarried = self.uncarried_address(&opcode.address_mode, param_address).unwrap_or(param_address);
+                instructions::axa(self, param_address, uncarried)?
+            }
+            "AXS" => {
+                let param_address = self.get_param_address(&opcode.address_mode)?;
+                instructions::axs(self, param_address)?
+            }
+            "DCP" => {
+                let param_address = self.get_write_address(&opcode.address_mode)?;
+                instructions::dcp(self, param_address)?;
+            }
+            "ISB" => {
+                let param_address = self.get_write_address(&opcode.address_mode)?;
+                instructions::isb(self, param_address)?;
+            }
+            "KIL" => {
+                Err(EmulatorError::UnimplementedOpcode(opcode_u8))?;
+            }
+            "LAR" => {
+                let param_address = self.get_param_address(&opcode.address_mode)?;
+                let param = self.bus_read(param_address)?;
+                instructions::lar(self, param);
+            }
+            "LAX" => {
+                let param_address = self.get_param_address(&opcode.address_mode)?;
+                instructions::lax(self, param_address)?;
+            }
+            "RLA" => {
+                let param_address = self.get_write_address(&opcode.address_mode)?;
+                instructions::rla(self, param_address)?;
+            }
+            "RRA" => {
+                let param_address = self.get_write_address(&opcode.address_mode)?;
+                instructions::rra(self, param_address)?;
+            }
+            "SLO" => {
+                let param_address = self.get_write_address(&opcode.address_mode)?;
+                instructions::slo(self, param_address)?;
             }
+            "SRE" => {
+                let param_address = self.get_write_address(&opcode.address_mode)?;
+                instructions::sre(self, param_address)?;
+            }
+            "SXA" => {
+                let param_address = self.get_write_address(&opcode.address_mode)?;
+                let uncarried = self.uncarried_address(&opcode.address_mode, param_address).unwrap_or(param_address);
+                instructions::sxa(self, param_address, uncarried)?;
+            }
+            "SYA" => {
+                let param_address = self.get_write_address(&opcode.address_mode)?;
+                let uncarried = self.uncarried_address(&opcode.address_mode, param_address).unwrap_or(param_address);
+                instructions::sya(self, param_address, uncarried)?;
+            }
+            "XAA" => {
+                let param_address = self.get_param_address(&opcode.address_mode)?;
+                let param = self.bus_read(param_address)?;
+                instructions::xaa(self, param);
+            }
+            "XAS" => {
+                let param_address = self.get_write_address(&opcode.address_mode)?;
+                let uncarried = self.uncarried_address(&opcode.address_mode, param_address).unwrap_or(param_address);
+                instructions::xas(self, param_address, uncarried)?;
+            }
+            _ => return Err(EmulatorError::UnimplementedOpcode(opcode_u8)),
+        }
+        if branch_taken {
+            // A taken branch reads the next opcode while it adds the offset, and the wrong page
+            // too when the offset carries. Without the carry it doesn't poll interrupts on its
+            // last cycle, so one that arrived on the cycle before waits an instruction.
+            if self.interrupt_line && !self.interrupt_polled {
+                self.interrupt_line = false;
+            }
+            let next = start_pc.wrapping_add(opcode.bytes as u16);
+            let target = self.program_counter.wrapping_add(opcode.bytes as u16);
+            self.bus_read(next)?;
+            if next & 0xFF00 != target & 0xFF00 {
+                self.bus_read(next & 0xFF00 | target & 0x00FF)?;
+            }
+        }
+        if increase_pc {
+            self.program_counter += opcode.bytes as u16;
+        }
+        Ok(running)
+    }
+
+    /// Whether an NMI is waiting or the IRQ line is held with IRQs enabled.
+    fn interrupt_requested(&self) -> bool {
+        self.bus.nmi_pending() || self.bus.irq() && !self.status.interrupt_disable
+    }
+
+    /// Services an IRQ or NMI: the CPU reads the next opcode twice and throws it away before
+    /// pushing the return address.
+    fn interrupt_sequence(&mut self) -> Result<(), EmulatorError> {
+        self.bus_read(self.program_counter)?;
+        self.bus_read(self.program_counter)?;
+        instructions::interrupt(self, false)
+    }
+
+    /// A read by the CPU itself, which takes a cycle on the cycle-stepped core. `Memory::read`
+    /// is for everything else and leaves the clock alone.
+    pub(crate) fn bus_read(&mut self, address: u16) -> Result<u8, EmulatorError> {
+        let value = self.bus.read(address)?;
+        if self.core == Core::CycleStepped {
+            self.end_cycle();
+        }
+        Ok(value)
+    }
+
+    pub(crate) fn bus_write(&mut self, address: u16, value: u8) -> Result<(), EmulatorError> {
+        self.bus.write(address, value)?;
+        if self.core == Core::CycleStepped {
+            self.end_cycle();
         }
         Ok(())
     }
 
-    fn get_param_address(&self, mode: &AddressingMode) -> Result<u16, EmulatorError> {
+    pub fn frame(&self) -> u64 {
+        self.bus.ppu().frame()
+    }
+
+    pub fn scanline(&self) -> u16 {
+        self.bus.ppu().scanline()
+    }
+
+    fn branch_taken(&self, name: &str) -> bool {
+        match name {
+            "BCC" => !self.status.carry,
+            "BCS" => self.status.carry,
+            "BEQ" => self.status.zero,
+            "BNE" => !self.status.zero,
+            "BMI" => self.status.negative,
+            "BPL" => !self.status.negative,
+            "BVC" => !self.status.overflow,
+            "BVS" => self.status.overflow,
+            _ => false,
+        }
+    }
+
+    /// Looks ahead at the operand, so it peeks rather than reads.
+    fn crosses_page(&self, mode: &AddressingMode) -> Result<bool, EmulatorError> {
         let param = self.program_counter + 1;
-        match mode {
-            AddressingMode::Immediate => Ok(param),
-            AddressingMode::ZeroPage => {
-                let address = self.read(param)?;
-                Ok(address as u16)
-            }
-            AddressingMode::ZeroPageX => {
-                let address = self.read(param)?;
-                Ok((address.wrapping_add(self.register_x)) as u16)
-            }
-            AddressingMode::ZeroPageY => {
-                let address = self.read(param)?;
-                Ok((address.wrapping_add(self.register_y)) as u16)
-            }
-            AddressingMode::Absolute => {
-                let address = self.read_u16(param)?;
-                Ok(address)
-            }
-            AddressingMode::AbsoluteX => {
-                let address = self.read_u16(param)?;
-                Ok(address.wrapping_add(self.register_x as u16))
-            }
-            AddressingMode::AbsoluteY => {
-                let address = self.read_u16(param)?;
-                Ok(address.wrapping_add(self.register_y as u16))
-            }
-            AddressingMode::Indirect => {
-                let reference = self.read_u16(param)?;
-                let address = if reference & 0x00FF == 0x00FF {
-                    let low_byte = self.read(reference)?;
-                    let high_byte = self.read(reference & 0xFF00)?;
-                    u16::from_le_bytes([low_byte, high_byte])
-                } else {
-                    self.read_u16(reference)?
-                };
-                Ok(address)
-            }
-            AddressingMode::IndexedIndirect => {
-                let address = self.read(param)?.wrapping_add(self.register_x);
-                Ok(self.read_u16_zero_page(address)?)
-            }
-            AddressingMode::IndirectIndexed => {
-                let address = self.read(param)?;
-                Ok(self.read_u16_zero_page(address)?.wrapping_add(self.register_y as u16))
-            }
-            AddressingMode::Relative => {
-                let address = self.read(param)?;
-                Ok(address as u16)
+        let (base, index) = match mode {
+            AddressingMode::AbsoluteX => (self.peek_u16(param)?, self.register_x),
+            AddressingMode::AbsoluteY => (self.peek_u16(param)?, self.register_y),
+            AddressingMode::IndirectIndexed => (self.peek_u16_zero_page(self.peek(param)?)?, self.register_y),
+            _ => return Ok(false),
+        };
+        Ok(base & 0xFF00 != base.wrapping_add(index as u16) & 0xFF00)
+    }
+
+    /// The operand address of an instruction that reads it. Indexing across a page reads the
+    /// address without the carry into the high byte first, while the CPU fixes it up.
+    pub(crate) fn get_param_address(&mut self, mode: &AddressingMode) -> Result<u16, EmulatorError> {
+        let address = self.fetch_param_address(mode)?;
+        match self.uncarried_address(mode, address) {
+            Some(uncarried) if uncarried != address => {
+                self.bus_read(uncarried)?;
             }
-            _ => Err(EmulatorError::UnimplementedAddressingMode(format!("{:?}", mode))),
+            _ => {}
+        }
+        Ok(address)
+    }
+
+    /// The operand address of an instruction that writes it. A write can't be taken back, so
+    /// indexed modes always spend a cycle on the dummy read, carry or not.
+    pub(crate) fn get_write_address(&mut self, mode: &AddressingMode) -> Result<u16, EmulatorError> {
+        let address = self.fetch_param_address(mode)?;
+        if let Some(uncarried) = self.uncarried_address(mode, address) {
+            self.bus_read(uncarried)?;
+        }
+        Ok(address)
+    }
+
+    fn fetch_param_address(&mut self, mode: &AddressingMode) -> Result<u16, EmulatorError> {
+        let (pc, x, y) = (self.program_counter, self.register_x, self.register_y);
+        param_address(mode, pc, x, y, |address| self.bus_read(address))
+    }
+
+    /// Where an indexed mode points before the carry from adding the index reaches the high byte.
+    pub(crate) fn uncarried_address(&self, mode: &AddressingMode, address: u16) -> Option<u16> {
+        let index = match mode {
+            AddressingMode::AbsoluteX => self.register_x,
+            AddressingMode::AbsoluteY | AddressingMode::IndirectIndexed => self.register_y,
+            _ => return None,
+        };
+        Some(address.wrapping_sub(index as u16) & 0xFF00 | address & 0x00FF)
+    }
+
+    /// Where SHA, SHX, SHY and TAS store, and what. The register meets one more than the high
+    /// byte of the base address on the bus, so they store the AND of the two, and when the index
+    /// carries that value replaces the high byte of the address as well.
+    pub(crate) fn unstable_store(&self, name: &str, address: u16, uncarried: u16) -> (u16, u8) {
+        let register = match name {
+            "SXA" => self.register_x,
+            "SYA" => self.register_y,
+            _ => self.register_a & self.register_x,
+        };
+        let value = register & ((uncarried >> 8) as u8).wrapping_add(1);
+        if address == uncarried {
+            (address, value)
+        } else {
+            ((value as u16) << 8 | address & 0x00FF, value)
+        }
+    }
+
+    /// The operand address the next instruction will use, found without side effects.
+    pub(crate) fn peek_param_address(&self, mode: &AddressingMode) -> Result<u16, EmulatorError> {
+        param_address(mode, self.program_counter, self.register_x, self.register_y, |address| self.peek(address))
+    }
+}
+
+fn param_address(mode: &AddressingMode, pc: u16, x: u8, y: u8, mut read: impl FnMut(u16) -> Result<u8, EmulatorError>)
+                 -> Result<u16, EmulatorError> {
+    let param = pc + 1;
+    match mode {
+        AddressingMode::Immediate => Ok(param),
+        AddressingMode::ZeroPage | AddressingMode::Relative => Ok(read(param)? as u16),
+        // Indexing the zero page spends a cycle reading the unindexed address.
+        AddressingMode::ZeroPageX => {
+            let base = read(param)?;
+            read(base as u16)?;
+            Ok(base.wrapping_add(x) as u16)
+        }
+        AddressingMode::ZeroPageY => {
+            let base = read(param)?;
+            read(base as u16)?;
+            Ok(base.wrapping_add(y) as u16)
+        }
+        AddressingMode::Absolute => read_pointer(&mut read, param, param + 1),
+        AddressingMode::AbsoluteX => Ok(read_pointer(&mut read, param, param + 1)?.wrapping_add(x as u16)),
+        AddressingMode::AbsoluteY => Ok(read_pointer(&mut read, param, param + 1)?.wrapping_add(y as u16)),
+        AddressingMode::Indirect => {
+            // The pointer's high byte comes from the start of the same page when it crosses one.
+            let reference = read_pointer(&mut read, param, param + 1)?;
+            read_pointer(&mut read, reference, reference & 0xFF00 | reference.wrapping_add(1) & 0x00FF)
         }
+        AddressingMode::IndexedIndirect => {
+            let base = read(param)?;
+            read(base as u16)?;
+            let pointer = base.wrapping_add(x);
+            read_pointer(&mut read, pointer as u16, pointer.wrapping_add(1) as u16)
+        }
+        AddressingMode::IndirectIndexed => {
+            let pointer = read(param)?;
+            Ok(read_pointer(&mut read, pointer as u16, pointer.wrapping_add(1) as u16)?.wrapping_add(y as u16))
+        }
+        _ => Err(EmulatorError::UnimplementedAddressingMode(format!("{:?}", mode))),
     }
 }
+
+fn read_pointer(read: &mut impl FnMut(u16) -> Result<u8, EmulatorError>, low: u16, high: u16) -> Result<u16, EmulatorError> {
+    let low_byte = read(low)?;
+    Ok(u16::from_le_bytes([low_byte, read(high)?]))
+}
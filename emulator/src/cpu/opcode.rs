@@ -30,7 +30,15 @@ pub fn get_opcode(code: u8) -> Option<&'static Opcode> {
 }
 
 pub fn get_opcode_by_name_and_address_mode(name: &str, address_mode: AddressingMode) -> Option<&'static Opcode> {
-    OPCODES.values().find(|opcode| opcode.name == name && opcode.address_mode == address_mode)
+    OPCODES.values()
+        .filter(|opcode| opcode.name == name && opcode.address_mode == address_mode)
+        .min_by_key(|opcode| (opcode.unofficial, opcode.code))
+}
+
+pub fn get_opcodes_by_name(name: &str) -> Vec<&'static Opcode> {
+    let mut opcodes: Vec<&'static Opcode> = OPCODES.values().filter(|opcode| opcode.name == name).collect();
+    opcodes.sort_by_key(|opcode| opcode.code);
+    opcodes
 }
 
 lazy_static! {
@@ -45,8 +53,8 @@ lazy_static! {
         add_opcode(m, Opcode::new("LDA", 0xAD, 3, 4, AddressingMode::Absolute, false));
         add_opcode(m, Opcode::new("LDA", 0xBD, 3, 4, AddressingMode::AbsoluteX, false));
         add_opcode(m, Opcode::new("LDA", 0xB9, 3, 4, AddressingMode::AbsoluteY, false));
-        add_opcode(m, Opcode::new("LDA", 0xA1, 2, 4, AddressingMode::IndexedIndirect, false));
-        add_opcode(m, Opcode::new("LDA", 0xB1, 2, 4, AddressingMode::IndirectIndexed, false));
+        add_opcode(m, Opcode::new("LDA", 0xA1, 2, 6, AddressingMode::IndexedIndirect, false));
+        add_opcode(m, Opcode::new("LDA", 0xB1, 2, 5, AddressingMode::IndirectIndexed, false));
         add_opcode(m, Opcode::new("LDX", 0xA2, 2, 2, AddressingMode::Immediate, false));
         add_opcode(m, Opcode::new("LDX", 0xA6, 2, 3, AddressingMode::ZeroPage, false));
         add_opcode(m, Opcode::new("LDX", 0xB6, 2, 4, AddressingMode::ZeroPageY, false));
@@ -1,18 +1,16 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod test {
-    use crate::common::constants::{IRQ_VECTOR, RAM_SIZE, PRG_ROM_START, STACK_START};
-    use crate::cpu::opcode::get_opcode_by_name_and_address_mode;
+    use crate::common::constants::{IRQ_VECTOR, RAM_SIZE, PRG_ROM_START, STACK_POINTER_INIT, STACK_START};
+    use crate::asm;
+    use crate::cpu::opcode::{get_opcode, get_opcode_by_name_and_address_mode};
+    use crate::cpu::types::Core;
+    use crate::debugger::AccessKind;
     use super::super::*;
 
     fn initialize_cpu(program: Vec<u8>) -> CPU {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::with_program(&program);
         cpu.tests = true;
-        cpu.program_counter = PRG_ROM_START;
-        let mut rom = Rom::default();
-        for (i, byte) in program.iter().enumerate() {
-            rom.prg_rom[i] = *byte;
-        }
-        cpu.bus.load_rom(rom);
         cpu
     }
 
@@ -1393,7 +1391,7 @@ mod test {
         let initial_pc = PRG_ROM_START + offset;
         let mut program = vec![0; offset as usize];
         program.extend_from_slice(&[code, branch as u8, 0]);
-        program.extend_from_slice(&[0; RAM_SIZE as usize]);
+        program.extend_from_slice(&[0; RAM_SIZE]);
         let mut cpu = initialize_cpu(program);
         cpu.status.carry = false;
         cpu.program_counter += offset;
@@ -1411,7 +1409,7 @@ mod test {
         let initial_pc = PRG_ROM_START + offset;
         let mut program = vec![0; offset as usize];
         program.extend_from_slice(&[code, branch as u8, 0]);
-        program.extend_from_slice(&[0; RAM_SIZE as usize]);
+        program.extend_from_slice(&[0; RAM_SIZE]);
         let mut cpu = initialize_cpu(program);
         cpu.status.carry = true;
         cpu.program_counter += offset;
@@ -1500,7 +1498,7 @@ mod test {
         let mut cpu = initialize_cpu(program);
         cpu.status = status;
         cpu.run(|_| Ok(())).unwrap();
-        assert_eq!(cpu.read(address).unwrap(), status.to_u8() | 0b0001_0000);
+        assert_eq!(cpu.read(address).unwrap(), status.to_stack(true));
         assert_eq!(cpu.stack_pointer, STACK_POINTER_INIT - 1);
     }
 
@@ -1514,7 +1512,7 @@ mod test {
         cpu.status = status;
         cpu.stack_pointer = 0x00;
         cpu.run(|_| Ok(())).unwrap();
-        assert_eq!(cpu.read(address).unwrap(), status.to_u8() | 0b0001_0000);
+        assert_eq!(cpu.read(address).unwrap(), status.to_stack(true));
         assert_eq!(cpu.stack_pointer, 0xFF);
     }
 
@@ -1581,7 +1579,8 @@ mod test {
         let address = STACK_POINTER_INIT as u16 + 0x0100 - test_offset;
         let program = vec![code, 0, 0];
         let mut cpu = initialize_cpu(program);
-        cpu.write(address, test_value).unwrap();
+        // B and bit 5 aren't flags, so what was pushed in them makes no difference.
+        cpu.write(address, test_value ^ 0b0011_0000).unwrap();
         cpu.stack_pointer -= test_offset as u8 + 1;
         cpu.run(|_| Ok(())).unwrap();
         let new = cpu.status.to_u8();
@@ -1667,7 +1666,7 @@ mod test {
         let return_address = initial_pc + 2;
         let mut program = vec![0; 0x0200];
         program.extend_from_slice(&[code, target_address_low, target_address_high]);
-        program.extend_from_slice(&[0; RAM_SIZE as usize]);
+        program.extend_from_slice(&[0; RAM_SIZE]);
         let mut cpu = initialize_cpu(program);
         cpu.program_counter = initial_pc;
         cpu.stack_pointer = initial_stack_pointer;
@@ -1693,7 +1692,7 @@ mod test {
         let target_address = (target_address_high as u16) << 8 | target_address_low as u16;
         let mut program = vec![0; 0x0200];
         program.extend_from_slice(&[jsr.code, target_address_low, target_address_high]);
-        program.extend_from_slice(&[0; RAM_SIZE as usize]);
+        program.extend_from_slice(&[0; RAM_SIZE]);
         let mut cpu = initialize_cpu(program);
         cpu.program_counter = initial_pc;
         cpu.stack_pointer = initial_stack_pointer;
@@ -1718,7 +1717,7 @@ mod test {
         let mut cpu = initialize_cpu(program);
         cpu.stack_pointer = initial_stack_pointer;
         cpu.status = initial_status;
-        cpu.write(0x01FD, return_status_u8 | 0b0001_0000).unwrap();
+        cpu.write(0x01FD, return_status_u8 ^ 0b0011_0000).unwrap();
         cpu.write(0x01FE, return_address_low).unwrap();
         cpu.write(0x01FF, return_address_high as u8).unwrap();
         cpu.run(|_| Ok(())).unwrap();
@@ -1749,8 +1748,9 @@ mod test {
         let stored_pc = (stored_pc_high as u16) << 8 | stored_pc_low as u16;
         assert_eq!(cpu.stack_pointer, 0x02);
         assert_eq!(cpu.program_counter, 0x1ABC);
-        assert_eq!(stored_status, initial_status.to_u8() | 0b0001_0000);
-        assert_eq!(stored_pc, initial_pc);
+        assert_eq!(stored_status, initial_status.to_stack(true));
+        // BRK returns past its padding byte.
+        assert_eq!(stored_pc, initial_pc + 2);
     }
 
     #[test]
@@ -1974,7 +1974,7 @@ mod test {
         let code = get_opcode_by_name_and_address_mode("ATX", AddressingMode::Immediate).unwrap().code;
         let accumulator = 0b1001_0110;
         let param = 0b1111_0011;
-        let expected = 0b1001_0010;
+        let expected = 0b1111_0011;
         let program = vec![code, param, 0];
         let mut cpu = initialize_cpu(program);
         cpu.register_a = accumulator;
@@ -2006,7 +2006,7 @@ mod test {
         let code = get_opcode_by_name_and_address_mode("AXA", AddressingMode::AbsoluteY).unwrap().code;
         let accumulator = 0b1101_1111;
         let x = 0b1111_1011;
-        let expected = 0b0000_0011;
+        let expected = 0b0001_1011;
         let address_high = 0x1A;
         let address_low = 0xBC;
         let address = (address_high as u16) << 8 | address_low as u16;
@@ -2291,11 +2291,11 @@ mod test {
         let address = (address_high as u16) << 8 | (address_low as u16 + 5);
         let program = vec![code, address_low, address_high, 0];
         let mut cpu = initialize_cpu(program);
-        cpu.write(address as u16, memory_value as u8).unwrap();
+        cpu.write(address, memory_value).unwrap();
         cpu.register_y = 5;
         cpu.stack_pointer = stack_pointer;
         cpu.run(|_| Ok(())).unwrap();
-        let stored = cpu.read(address as u16).unwrap();
+        let stored = cpu.read(address).unwrap();
         assert_eq!(stored, memory_value);
         assert_eq!(cpu.stack_pointer, expected);
         assert_eq!(cpu.register_a, expected);
@@ -2315,11 +2315,11 @@ mod test {
         let address = (address_high as u16) << 8 | (address_low as u16 + 1);
         let program = vec![code, address_low, address_high, 0];
         let mut cpu = initialize_cpu(program);
-        cpu.write(address as u16, memory_value as u8).unwrap();
+        cpu.write(address, memory_value).unwrap();
         cpu.register_y = 1;
         cpu.stack_pointer = stack_pointer;
         cpu.run(|_| Ok(())).unwrap();
-        let stored = cpu.read(address as u16).unwrap();
+        let stored = cpu.read(address).unwrap();
         assert_eq!(stored, memory_value);
         assert_eq!(cpu.stack_pointer, expected);
         assert_eq!(cpu.register_a, expected);
@@ -2339,11 +2339,11 @@ mod test {
         let address = (address_high as u16) << 8 | (address_low as u16 + 3);
         let program = vec![code, address_low, address_high, 0];
         let mut cpu = initialize_cpu(program);
-        cpu.write(address as u16, memory_value as u8).unwrap();
+        cpu.write(address, memory_value).unwrap();
         cpu.register_y = 3;
         cpu.stack_pointer = stack_pointer;
         cpu.run(|_| Ok(())).unwrap();
-        let stored = cpu.read(address as u16).unwrap();
+        let stored = cpu.read(address).unwrap();
         assert_eq!(stored, memory_value);
         assert_eq!(cpu.stack_pointer, expected);
         assert_eq!(cpu.register_a, expected);
@@ -2363,7 +2363,7 @@ mod test {
         let mut cpu = initialize_cpu(program);
         cpu.write(address, memory_value).unwrap();
         cpu.run(|_| Ok(())).unwrap();
-        let stored = cpu.read(address as u16).unwrap();
+        let stored = cpu.read(address).unwrap();
         assert_eq!(stored, memory_value);
         assert_eq!(cpu.register_a, memory_value);
         assert_eq!(cpu.register_x, memory_value);
@@ -2382,7 +2382,7 @@ mod test {
         let mut cpu = initialize_cpu(program);
         cpu.write(address, memory_value).unwrap();
         cpu.run(|_| Ok(())).unwrap();
-        let stored = cpu.read(address as u16).unwrap();
+        let stored = cpu.read(address).unwrap();
         assert_eq!(stored, memory_value);
         assert_eq!(cpu.register_a, memory_value);
         assert_eq!(cpu.register_x, memory_value);
@@ -2401,7 +2401,7 @@ mod test {
         let mut cpu = initialize_cpu(program);
         cpu.write(address, memory_value).unwrap();
         cpu.run(|_| Ok(())).unwrap();
-        let stored = cpu.read(address as u16).unwrap();
+        let stored = cpu.read(address).unwrap();
         assert_eq!(stored, memory_value);
         assert_eq!(cpu.register_a, memory_value);
         assert_eq!(cpu.register_x, memory_value);
@@ -2839,7 +2839,7 @@ mod test {
         let y_register = 0x03;
         let x_register = 0b0000_0101;
         let address_high = 0b0000_0011;
-        let expected_high_address = 0b0000_0010;
+        let expected_high_address = 0b0000_0100;
         let address_low = 0xC0;
         let address = (address_high as u16) << 8 | (address_low + y_register) as u16;
         let program = vec![code, address_low, address_high, 0];
@@ -2857,7 +2857,7 @@ mod test {
         let x_register = 0x05;
         let y_register = 0b0000_1110;
         let address_high = 0b0000_0101;
-        let expected_high_address = 0b0000_0101;
+        let expected_high_address = 0b0000_0110;
         let address_low = 0xC0;
         let address = (address_high as u16) << 8 | (address_low + x_register) as u16;
         let program = vec![code, address_low, address_high, 0];
@@ -2878,7 +2878,7 @@ mod test {
         let x_register = 0b0000_0111;
         let a_register = 0b0000_0101;
         let sp = 0b0000_0101;
-        let result = 0b0000_0010;
+        let result = 0b0000_0100;
         let address = (address_high as u16) << 8 | (address_low + y_register) as u16;
         let program = vec![code, address_low, address_high, 0];
         let mut cpu = initialize_cpu(program);
@@ -2890,4 +2890,394 @@ mod test {
         assert_eq!(stored, result);
         assert_eq!(cpu.stack_pointer, sp);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_unstable_stores_corrupt_the_address_on_page_cross() {
+        // The value stored, register & (high byte + 1), becomes the high byte of the address.
+        for (name, mode, a, x, y, expected) in [
+            ("AXA", AddressingMode::AbsoluteY, 0b0000_1011, 0b0001_0110, 0x12, 0b0000_0010),
+            ("AXA", AddressingMode::IndirectIndexed, 0b0000_1111, 0b0001_0100, 0x12, 0b0000_0100),
+            ("SXA", AddressingMode::AbsoluteY, 0x00, 0b0001_0101, 0x12, 0b0000_0100),
+            ("SYA", AddressingMode::AbsoluteX, 0x00, 0x12, 0b0001_0011, 0b0000_0010),
+            ("XAS", AddressingMode::AbsoluteY, 0b1111_0111, 0b0010_0101, 0x12, 0b0000_0100),
+        ] {
+            let code = get_opcode_by_name_and_address_mode(name, mode).unwrap().code;
+            let program = vec![code, 0xF0, 0x05, 0];
+            let mut cpu = initialize_cpu(program);
+            cpu.write(0xF0, 0xF0).unwrap();
+            cpu.write(0xF1, 0x05).unwrap();
+            (cpu.register_a, cpu.register_x, cpu.register_y) = (a, x, y);
+            cpu.run(|_| Ok(())).unwrap();
+            let address = (expected as u16) << 8 | 0x02;
+            assert_eq!(cpu.read(address).unwrap(), expected, "{} {:?}", name, mode);
+            assert_eq!(cpu.read(0x0602).unwrap(), 0, "{} {:?}", name, mode);
+        }
+    }
+
+    #[test]
+    fn test_xaa() {
+        let code = get_opcode_by_name_and_address_mode("XAA", AddressingMode::Immediate).unwrap().code;
+        let program = vec![code, 0b1100_1100, 0];
+        let mut cpu = initialize_cpu(program.clone());
+        cpu.register_a = 0b0000_0000;
+        cpu.register_x = 0b1010_1010;
+        cpu.run(|_| Ok(())).unwrap();
+        assert_eq!(cpu.register_a, 0b1000_1000);
+        assert!(cpu.status.negative);
+        assert!(!cpu.status.zero);
+
+        let mut cpu = initialize_cpu(program);
+        cpu.set_xaa_magic(0xEE);
+        cpu.register_a = 0b0000_0000;
+        cpu.register_x = 0b1010_1011;
+        cpu.run(|_| Ok(())).unwrap();
+        assert_eq!(cpu.register_a, 0b1000_1000);
+    }
+
+    #[test]
+    fn test_xaa_magic() {
+        for (magic, a, expected) in [(0xFF, 0x00, 0x33), (0xEE, 0x00, 0x22), (0xEE, 0x11, 0x33), (0x00, 0x0F, 0x03)] {
+            for (name, result) in [("XAA", expected & 0x3F), ("ATX", expected)] {
+                let code = get_opcode_by_name_and_address_mode(name, AddressingMode::Immediate).unwrap().code;
+                let mut cpu = initialize_cpu(vec![code, 0x33, 0]);
+                cpu.set_xaa_magic(magic);
+                cpu.register_a = a;
+                cpu.register_x = 0x3F;
+                cpu.run(|_| Ok(())).unwrap();
+                assert_eq!(cpu.register_a, result, "{} with ${:02X}", name, magic);
+                assert_eq!(cpu.status.zero, result == 0);
+            }
+        }
+    }
+
+    /// One run of an unstable opcode: the registers going in, the base address or immediate
+    /// operand, and what should come out of it.
+    struct UnstableCase {
+        code: u8,
+        a: u8,
+        x: u8,
+        y: u8,
+        operand: u16,
+        result: (u8, u8, u8, u8),
+        store: Option<(u16, u8)>,
+        accesses: &'static [(u16, AccessKind)],
+    }
+
+    #[test]
+    fn test_unstable_opcodes_per_cycle() {
+        use crate::debugger::AccessKind::{Read, Write};
+        // result is A, X, SP and the flags. Stores leave the flags alone; XAA and ATX set N and Z.
+        // The stores read the uncarried address before writing, and on a page cross the value
+        // they store replaces the high byte of the address they write.
+        let cases = [
+            UnstableCase { code: 0x8B, a: 0x00, x: 0xAA, y: 0x00, operand: 0xCC, result: (0x88, 0xAA, 0xFD, 0xA4), store: None,
+                           accesses: &[(0x8000, Read), (0x8001, Read)] },
+            UnstableCase { code: 0x8B, a: 0x00, x: 0x0F, y: 0x00, operand: 0xF0, result: (0x00, 0x0F, 0xFD, 0x26), store: None,
+                           accesses: &[(0x8000, Read), (0x8001, Read)] },
+            UnstableCase { code: 0xAB, a: 0x00, x: 0x55, y: 0x00, operand: 0xCC, result: (0xCC, 0xCC, 0xFD, 0xA4), store: None,
+                           accesses: &[(0x8000, Read), (0x8001, Read)] },
+            UnstableCase { code: 0xAB, a: 0x12, x: 0x55, y: 0x00, operand: 0x00, result: (0x00, 0x00, 0xFD, 0x26), store: None,
+                           accesses: &[(0x8000, Read), (0x8001, Read)] },
+            // AXA absolute,Y.
+            UnstableCase { code: 0x9F, a: 0x0B, x: 0x16, y: 0x10, operand: 0x0560, result: (0x0B, 0x16, 0xFD, 0x24), store: Some((0x0570, 0x02)),
+                           accesses: &[(0x8000, Read), (0x8001, Read), (0x8002, Read), (0x0570, Read), (0x0570, Write)] },
+            UnstableCase { code: 0x9F, a: 0x0B, x: 0x16, y: 0x10, operand: 0x05F8, result: (0x0B, 0x16, 0xFD, 0x24), store: Some((0x0208, 0x02)),
+                           accesses: &[(0x8000, Read), (0x8001, Read), (0x8002, Read), (0x0508, Read), (0x0208, Write)] },
+            // AXA (indirect),Y, with the pointer at $F0.
+            UnstableCase { code: 0x93, a: 0x0F, x: 0x14, y: 0x10, operand: 0x0560, result: (0x0F, 0x14, 0xFD, 0x24), store: Some((0x0570, 0x04)),
+                           accesses: &[(0x8000, Read), (0x8001, Read), (0x00F0, Read), (0x00F1, Read), (0x0570, Read), (0x0570, Write)] },
+            UnstableCase { code: 0x93, a: 0x0F, x: 0x14, y: 0x10, operand: 0x05F8, result: (0x0F, 0x14, 0xFD, 0x24), store: Some((0x0408, 0x04)),
+                           accesses: &[(0x8000, Read), (0x8001, Read), (0x00F0, Read), (0x00F1, Read), (0x0508, Read), (0x0408, Write)] },
+            // SXA absolute,Y.
+            UnstableCase { code: 0x9E, a: 0x00, x: 0x13, y: 0x10, operand: 0x0560, result: (0x00, 0x13, 0xFD, 0x24), store: Some((0x0570, 0x02)),
+                           accesses: &[(0x8000, Read), (0x8001, Read), (0x8002, Read), (0x0570, Read), (0x0570, Write)] },
+            UnstableCase { code: 0x9E, a: 0x00, x: 0x13, y: 0x10, operand: 0x05F8, result: (0x00, 0x13, 0xFD, 0x24), store: Some((0x0208, 0x02)),
+                           accesses: &[(0x8000, Read), (0x8001, Read), (0x8002, Read), (0x0508, Read), (0x0208, Write)] },
+            // SYA absolute,X.
+            UnstableCase { code: 0x9C, a: 0x00, x: 0x10, y: 0x15, operand: 0x0560, result: (0x00, 0x10, 0xFD, 0x24), store: Some((0x0570, 0x04)),
+                           accesses: &[(0x8000, Read), (0x8001, Read), (0x8002, Read), (0x0570, Read), (0x0570, Write)] },
+            UnstableCase { code: 0x9C, a: 0x00, x: 0x10, y: 0x15, operand: 0x05F8, result: (0x00, 0x10, 0xFD, 0x24), store: Some((0x0408, 0x04)),
+                           accesses: &[(0x8000, Read), (0x8001, Read), (0x8002, Read), (0x0508, Read), (0x0408, Write)] },
+            // XAS absolute,Y, which also puts A & X in the stack pointer.
+            UnstableCase { code: 0x9B, a: 0xF7, x: 0x2B, y: 0x10, operand: 0x0560, result: (0xF7, 0x2B, 0x23, 0x24), store: Some((0x0570, 0x02)),
+                           accesses: &[(0x8000, Read), (0x8001, Read), (0x8002, Read), (0x0570, Read), (0x0570, Write)] },
+            UnstableCase { code: 0x9B, a: 0xF7, x: 0x2B, y: 0x10, operand: 0x05F8, result: (0xF7, 0x2B, 0x23, 0x24), store: Some((0x0208, 0x02)),
+                           accesses: &[(0x8000, Read), (0x8001, Read), (0x8002, Read), (0x0508, Read), (0x0208, Write)] },
+        ];
+        for case in cases {
+            let opcode = get_opcode(case.code).unwrap();
+            let [low, high] = case.operand.to_le_bytes();
+            let program = match opcode.address_mode {
+                AddressingMode::Immediate => vec![case.code, low],
+                AddressingMode::IndirectIndexed => vec![case.code, 0xF0],
+                _ => vec![case.code, low, high],
+            };
+            for core in [Core::InstructionStepped, Core::CycleStepped] {
+                let context = format!("{} {:?} ${:04X} on {:?}", opcode.name, opcode.address_mode, case.operand, core);
+                let mut cpu = CPU::with_interrupt_handlers(core, &program, &[]);
+                cpu.write(0xF0, low).unwrap();
+                cpu.write(0xF1, high).unwrap();
+                (cpu.register_a, cpu.register_x, cpu.register_y) = (case.a, case.x, case.y);
+                cpu.stack_pointer = STACK_POINTER_INIT;
+                cpu.status = ProcessorStatus::from_u8(0x24);
+                cpu.cycles = 0;
+                cpu.bus.accesses = Some(Vec::new());
+                cpu.step().unwrap();
+                let accesses = cpu.bus.accesses.take().unwrap();
+                assert_eq!((cpu.register_a, cpu.register_x, cpu.stack_pointer, cpu.status.to_u8()), case.result, "{}", context);
+                assert_eq!(cpu.register_y, case.y, "{}", context);
+                assert_eq!(accesses, case.accesses, "{}", context);
+                assert_eq!(cpu.cycles, opcode.cycles as u64, "{}", context);
+                assert_eq!(cpu.program_counter, PRG_ROM_START + opcode.bytes as u16, "{}", context);
+                if let Some((address, value)) = case.store {
+                    assert_eq!(cpu.read(address).unwrap(), value, "{}", context);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_asm_subroutine_loop() {
+        let program = asm!(
+            "        LDX #$05",
+            "        LDA #$00",
+            "loop:   JSR add_two",
+            "        DEX",
+            "        BNE loop",
+            "        STA $0200",
+            "        BRK",
+            "add_two:",
+            "        CLC",
+            "        ADC #$02",
+            "        RTS",
+        );
+        let mut cpu = initialize_cpu(program);
+        cpu.run(|_| Ok(())).unwrap();
+        assert_eq!(cpu.read(0x0200).unwrap(), 0x0A);
+        assert_eq!(cpu.register_x, 0);
+        assert_eq!(cpu.stack_pointer, STACK_POINTER_INIT);
+    }
+
+    #[test]
+    fn test_asm_indirect_indexed_copy() {
+        let program = asm!(
+            "        LDA #<source",
+            "        STA $10",
+            "        LDA #>source",
+            "        STA $11",
+            "        LDY #$02",
+            "copy:   LDA ($10),Y",
+            "        STA $0300,Y",
+            "        DEY",
+            "        BPL copy",
+            "        BRK",
+            "source: .byte $11, $22, $33",
+        );
+        let mut cpu = initialize_cpu(program);
+        cpu.run(|_| Ok(())).unwrap();
+        assert_eq!(cpu.read(0x0300).unwrap(), 0x11);
+        assert_eq!(cpu.read(0x0301).unwrap(), 0x22);
+        assert_eq!(cpu.read(0x0302).unwrap(), 0x33);
+    }
+
+    #[test]
+    fn test_open_bus() {
+        let program = asm!(
+            "        LDA $5000",
+            "        STA $10",
+            "        LDA $4000",
+            "        STA $11",
+            "        LDX #$01",
+            "        STX $4016",
+            "        LDA $4016",
+            "        STA $12",
+            "        BRK",
+        );
+        let mut cpu = initialize_cpu(program);
+        cpu.controller(0).set_buttons(1);
+        cpu.run(|_| Ok(())).unwrap();
+        // The last byte on the bus was the high byte of the operand.
+        assert_eq!(cpu.read(0x10).unwrap(), 0x50);
+        assert_eq!(cpu.read(0x11).unwrap(), 0x40);
+        assert_eq!(cpu.read(0x12).unwrap(), 0x41);
+
+        // $4015 is read inside the CPU, leaving the last byte on the bus for the next read.
+        cpu.write(0x4000, 0x5A).unwrap();
+        assert_eq!(cpu.read(0x4015).unwrap() & !0x20, 0x00);
+        assert_eq!(cpu.read(0x5000).unwrap(), 0x5A);
+    }
+
+    #[test]
+    fn test_strict_access() {
+        let program = asm!(
+            "        LDA #$01",
+            "        STA $5000",
+            "        LDA $5000",
+            "        BRK",
+        );
+        let mut cpu = initialize_cpu(program.clone());
+        cpu.run(|_| Ok(())).unwrap();
+        assert_eq!(cpu.register_a, 0x50);
+
+        let mut cpu = initialize_cpu(program);
+        cpu.set_strict_access(true);
+        assert!(matches!(cpu.run(|_| Ok(())), Err(EmulatorError::AccessViolation(0x5000))));
+        assert_eq!(cpu.register_a, 0x01);
+    }
+
+    #[test]
+    fn test_page_crossing_dummy_read() {
+        let program = asm!(
+            "        LDA #$01",
+            "        STA $4016",
+            "        LSR",
+            "        STA $4016",
+            "        LDX #$26",
+            "        LDA $40F0,X",
+            "        LDA $4016",
+            "        STA $10",
+            "        LDA $3FF0,X",
+            "        STA $11",
+            "        BRK",
+        );
+        let mut cpu = initialize_cpu(program);
+        cpu.controller(0).set_buttons(0b0000_0101);
+        cpu.run(|_| Ok(())).unwrap();
+        // Crossing into $41xx first reads $4016 and shifts out A. Crossing into $40xx reads
+        // $3F16 first, so the next button, select, is read once.
+        assert_eq!(cpu.read(0x10).unwrap() & 1, 0);
+        assert_eq!(cpu.read(0x11).unwrap() & 1, 1);
+    }
+
+    #[test]
+    fn test_indexed_store_dummy_read() {
+        let program = asm!(
+            "        LDA #$21",
+            "        STA $2006",
+            "        LDX #$00",
+            "        STA $2002,X",
+            "        LDA #$22",
+            "        STA $2006",
+            "        LDA #$00",
+            "        STA $2006",
+            "        BRK",
+        );
+        let mut cpu = initialize_cpu(program);
+        cpu.run(|_| Ok(())).unwrap();
+        // The store read $2002 before writing it, which reset the address latch.
+        assert_eq!(cpu.ppu().vram_address(), 0x2200);
+    }
+
+    #[test]
+    fn test_read_modify_write_writes_twice() {
+        let program = asm!(
+            "        LDA #$21",
+            "        STA $2003",
+            "        INC $2006",
+            "        BRK",
+        );
+        let mut cpu = initialize_cpu(program);
+        cpu.run(|_| Ok(())).unwrap();
+        // INC read $21 off the PPU's bus, wrote it back as the high byte and $22 as the low one.
+        assert_eq!(cpu.ppu().vram_address(), 0x2122);
+    }
+
+    #[test]
+    fn test_cycle_stepped_core_timing() {
+        // Each bus access is a cycle, so the cycle-stepped core counting them has to agree with
+        // the opcode table, page crosses and taken branches included. Both CPUs run the same
+        // instructions, so they stay in step.
+        let mut cpus = [Core::CycleStepped, Core::InstructionStepped].map(|core| CPU::with_interrupt_handlers(core, &[], &[]));
+        for code in 0..=255 {
+            let Some(opcode) = get_opcode(code) else { continue };
+            if opcode.name == "KIL" {
+                continue;
+            }
+            for (operand, index, flags) in [(0x40, 0x00, 0x00), (0x40, 0xF0, 0xFF), (0xC0, 0xF0, 0x00), (0xC0, 0x00, 0xFF)] {
+                let [cycle_stepped, instruction_stepped] = cpus.each_mut().map(|cpu| {
+                    for (offset, byte) in [code, operand, 0x02].into_iter().enumerate() {
+                        cpu.write(PRG_ROM_START + offset as u16, byte).unwrap();
+                    }
+                    for pointer in [operand, operand.wrapping_add(index)] {
+                        cpu.write(pointer as u16, 0xF8).unwrap();
+                        cpu.write(pointer.wrapping_add(1) as u16, 0x03).unwrap();
+                    }
+                    cpu.program_counter = PRG_ROM_START;
+                    cpu.stack_pointer = STACK_POINTER_INIT;
+                    cpu.register_x = index;
+                    cpu.register_y = index;
+                    cpu.status = ProcessorStatus::from_u8(flags | 0b0000_0100);
+                    cpu.cycles = 0;
+                    cpu.step().unwrap();
+                    (cpu.cycles, cpu.program_counter, cpu.register_a, cpu.register_x, cpu.register_y, cpu.stack_pointer, cpu.status)
+                });
+                assert_eq!(cycle_stepped, instruction_stepped, "{} {:?} ${:02X}, index ${:02X}, flags ${:02X}",
+                           opcode.name, opcode.address_mode, operand, index, flags);
+            }
+        }
+    }
+
+    #[test]
+    fn test_cycle_stepped_core_polls_irq_on_penultimate_cycle() {
+        let program = asm!(
+            "        CLI",
+            "        INX",
+            "        INX",
+        );
+        let mut cpu = CPU::with_interrupt_handlers(Core::CycleStepped, &program, &[]);
+        cpu.write(0x4017, 0x00).unwrap();
+        cpu.bus.tick(30_000);
+        assert!(cpu.bus.irq());
+        // CLI clears the flag on its last cycle, after the poll, so one more instruction runs.
+        cpu.step().unwrap();
+        assert_eq!(cpu.interrupted_at, None);
+        cpu.step().unwrap();
+        assert_eq!(cpu.interrupted_at, Some(0x8002));
+        assert_eq!(cpu.register_x, 1);
+        assert_eq!(cpu.program_counter, 0x8200);
+        assert!(cpu.status.interrupt_disable);
+    }
+
+    #[test]
+    fn test_cycle_stepped_core_takes_irq_after_sei() {
+        let program = asm!(
+            "        SEI",
+            "        INX",
+        );
+        let mut cpu = CPU::with_interrupt_handlers(Core::CycleStepped, &program, &[]);
+        cpu.status.interrupt_disable = false;
+        cpu.write(0x4017, 0x00).unwrap();
+        cpu.bus.tick(30_000);
+        cpu.step().unwrap();
+        assert_eq!(cpu.interrupted_at, Some(0x8001));
+        assert_eq!(cpu.register_x, 0);
+        // The IRQ was polled before SEI set the flag, but the flags it pushed have it set.
+        assert_eq!(cpu.read(STACK_START + 0xFB).unwrap() & 0b0001_0100, 0b0000_0100);
+    }
+
+    #[test]
+    fn test_nmi_hijacks_brk() {
+        let program = asm!(
+            "loop:   JMP loop",
+            "        LDA #$80",
+            "        STA $2000",
+            "        BRK",
+            "        NOP",
+        );
+        let mut cpu = CPU::with_interrupt_handlers(Core::CycleStepped, &program, &asm!("INX"));
+        cpu.run_frame(|_| Ok(())).unwrap();
+        cpu.program_counter = 0x8003;
+        cpu.step().unwrap();
+        // Enabling NMIs during vblank raises one on the store's last cycle, too late for it to
+        // poll, so it lands during the BRK and takes over its vector.
+        cpu.step().unwrap();
+        assert_eq!(cpu.interrupted_at, None);
+        cpu.step().unwrap();
+        assert_eq!(cpu.program_counter, 0x8100);
+        assert_eq!(cpu.read(STACK_START + 0xFB).unwrap() & 0b0001_0000, 0b0001_0000);
+        assert_eq!(cpu.read_u16(STACK_START + 0xFC).unwrap(), 0x800A);
+        assert!(!cpu.bus.nmi_pending());
+        cpu.step().unwrap();
+        assert_eq!((cpu.interrupted_at, cpu.register_x), (None, 1));
+    }
+}
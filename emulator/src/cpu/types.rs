@@ -1,14 +1,21 @@
+const BREAK_FLAG: u8 = 0b0001_0000;
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct ProcessorStatus {
     pub carry: bool,
     pub zero: bool,
     pub interrupt_disable: bool,
     pub decimal_mode: bool,
-    pub break_command: bool,
     pub overflow: bool,
     pub negative: bool,
 }
 
+impl Default for ProcessorStatus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ProcessorStatus {
     pub fn new() -> ProcessorStatus {
         ProcessorStatus {
@@ -16,12 +23,13 @@ impl ProcessorStatus {
             zero: false,
             interrupt_disable: false,
             decimal_mode: false,
-            break_command: false,
             overflow: false,
             negative: false,
         }
     }
 
+    /// P as the debugger and trace logs show it. Bits 4 and 5 aren't flags: 5 reads as set and
+    /// the B flag only exists in the copies pushed to the stack.
     pub fn to_u8(&self) -> u8 {
         let mut status = 0b0010_0000;
         if self.carry {
@@ -36,9 +44,6 @@ impl ProcessorStatus {
         if self.decimal_mode {
             status |= 0b0000_1000;
         }
-        if self.break_command {
-            status |= 0b0001_0000;
-        }
         if self.overflow {
             status |= 0b0100_0000;
         }
@@ -48,13 +53,19 @@ impl ProcessorStatus {
         status
     }
 
+    /// The copy of P pushed to the stack, with B set by PHP and BRK and clear for IRQ and NMI,
+    /// which is how a handler tells them apart.
+    pub fn to_stack(&self, brk: bool) -> u8 {
+        if brk { self.to_u8() | BREAK_FLAG } else { self.to_u8() }
+    }
+
+    /// P as pulled by PLP and RTI, which ignore bits 4 and 5.
     pub fn from_u8(status: u8) -> ProcessorStatus {
         ProcessorStatus {
             carry: status & 0b0000_0001 != 0,
             zero: status & 0b0000_0010 != 0,
             interrupt_disable: status & 0b0000_0100 != 0,
             decimal_mode: status & 0b0000_1000 != 0,
-            break_command: status & 0b0001_0000 != 0,
             overflow: status & 0b0100_0000 != 0,
             negative: status & 0b1000_0000 != 0,
         }
@@ -76,4 +87,16 @@ pub enum AddressingMode {
     IndexedIndirect,
     IndirectIndexed,
     Accumulator
-}
\ No newline at end of file
+}
+
+/// How the CPU keeps the rest of the console in step with it.
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub enum Core {
+    /// Runs an instruction at a time, catching the PPU and APU up before its last bus access
+    /// and after it. The faster of the two.
+    #[default]
+    InstructionStepped,
+    /// Runs the PPU and APU for every bus cycle and polls interrupts on the penultimate cycle
+    /// of each instruction, as timing-sensitive games and test ROMs need.
+    CycleStepped,
+}
@@ -0,0 +1,409 @@
+use std::fmt;
+use crate::common::errors::EmulatorError;
+use crate::cpu::CPU;
+use crate::memory::memory::Memory;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Variable {
+    A,
+    X,
+    Y,
+    StackPointer,
+    ProgramCounter,
+    Status,
+    Carry,
+    Zero,
+    InterruptDisable,
+    Decimal,
+    Overflow,
+    Negative,
+    Cycles,
+    Frame,
+    Scanline,
+    Bank,
+}
+
+const VARIABLES: [(&str, Variable); 17] = [
+    ("a", Variable::A),
+    ("x", Variable::X),
+    ("y", Variable::Y),
+    ("sp", Variable::StackPointer),
+    ("pc", Variable::ProgramCounter),
+    ("p", Variable::Status),
+    ("c", Variable::Carry),
+    ("z", Variable::Zero),
+    ("i", Variable::InterruptDisable),
+    ("d", Variable::Decimal),
+    ("v", Variable::Overflow),
+    ("n", Variable::Negative),
+    ("cycles", Variable::Cycles),
+    ("cycle", Variable::Cycles),
+    ("frame", Variable::Frame),
+    ("scanline", Variable::Scanline),
+    ("bank", Variable::Bank),
+];
+
+pub trait Context {
+    fn variable(&self, variable: Variable) -> i64;
+    fn peek(&self, address: u16) -> Option<u8>;
+}
+
+impl Context for CPU {
+    fn variable(&self, variable: Variable) -> i64 {
+        match variable {
+            Variable::A => self.register_a as i64,
+            Variable::X => self.register_x as i64,
+            Variable::Y => self.register_y as i64,
+            Variable::StackPointer => self.stack_pointer as i64,
+            Variable::ProgramCounter => self.program_counter as i64,
+            Variable::Status => self.status.to_u8() as i64,
+            Variable::Carry => self.status.carry as i64,
+            Variable::Zero => self.status.zero as i64,
+            Variable::InterruptDisable => self.status.interrupt_disable as i64,
+            Variable::Decimal => self.status.decimal_mode as i64,
+            Variable::Overflow => self.status.overflow as i64,
+            Variable::Negative => self.status.negative as i64,
+            Variable::Cycles => self.cycles as i64,
+            Variable::Frame => self.frame() as i64,
+            Variable::Scanline => self.scanline() as i64,
+            Variable::Bank => self.bus.prg_bank(self.program_counter).map_or(-1, |bank| bank as i64),
+        }
+    }
+
+    fn peek(&self, address: u16) -> Option<u8> {
+        Memory::peek(self, address).ok()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOperator {
+    Not,
+    Negate,
+    Complement,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOperator {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    BitOr,
+    BitXor,
+    BitAnd,
+    ShiftLeft,
+    ShiftRight,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+}
+
+const BINARY_OPERATORS: [(&str, BinaryOperator, u8); 18] = [
+    ("||", BinaryOperator::Or, 1),
+    ("&&", BinaryOperator::And, 2),
+    ("==", BinaryOperator::Equal, 3),
+    ("!=", BinaryOperator::NotEqual, 3),
+    ("<=", BinaryOperator::LessEqual, 4),
+    (">=", BinaryOperator::GreaterEqual, 4),
+    ("<<", BinaryOperator::ShiftLeft, 8),
+    (">>", BinaryOperator::ShiftRight, 8),
+    ("<", BinaryOperator::Less, 4),
+    (">", BinaryOperator::Greater, 4),
+    ("|", BinaryOperator::BitOr, 5),
+    ("^", BinaryOperator::BitXor, 6),
+    ("&", BinaryOperator::BitAnd, 7),
+    ("+", BinaryOperator::Add, 9),
+    ("-", BinaryOperator::Subtract, 9),
+    ("*", BinaryOperator::Multiply, 10),
+    ("/", BinaryOperator::Divide, 10),
+    ("%", BinaryOperator::Modulo, 10),
+];
+const UNARY_PRECEDENCE: u8 = 11;
+
+impl BinaryOperator {
+    fn symbol(&self) -> &'static str {
+        BINARY_OPERATORS.iter().find(|(_, operator, _)| operator == self).map(|(symbol, _, _)| *symbol).unwrap()
+    }
+
+    fn precedence(&self) -> u8 {
+        BINARY_OPERATORS.iter().find(|(_, operator, _)| operator == self).map(|(_, _, precedence)| *precedence).unwrap()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Number(i64),
+    Variable(Variable),
+    Byte(Box<Expression>),
+    Word(Box<Expression>),
+    Unary(UnaryOperator, Box<Expression>),
+    Binary(BinaryOperator, Box<Expression>, Box<Expression>),
+}
+
+impl Expression {
+    pub fn parse(source: &str) -> Result<Expression, EmulatorError> {
+        let mut parser = Parser { tokens: tokenize(source)?, position: 0 };
+        let expression = parser.expression(0)?;
+        match parser.tokens.get(parser.position) {
+            None => Ok(expression),
+            Some(token) => Err(EmulatorError::InvalidExpression(format!("unexpected {}", token))),
+        }
+    }
+
+    /// Returns `None` when the value cannot be computed, e.g. reading unmapped memory or dividing by zero.
+    pub fn evaluate<C: Context>(&self, context: &C) -> Option<i64> {
+        match self {
+            Expression::Number(value) => Some(*value),
+            Expression::Variable(variable) => Some(context.variable(*variable)),
+            Expression::Byte(address) => {
+                let address = address.evaluate(context)? as u16;
+                context.peek(address).map(|byte| byte as i64)
+            }
+            Expression::Word(address) => {
+                let address = address.evaluate(context)? as u16;
+                let low_byte = context.peek(address)?;
+                let high_byte = context.peek(address.wrapping_add(1))?;
+                Some(u16::from_le_bytes([low_byte, high_byte]) as i64)
+            }
+            Expression::Unary(operator, operand) => {
+                let value = operand.evaluate(context)?;
+                Some(match operator {
+                    UnaryOperator::Not => (value == 0) as i64,
+                    UnaryOperator::Negate => value.wrapping_neg(),
+                    UnaryOperator::Complement => !value,
+                })
+            }
+            Expression::Binary(BinaryOperator::Or, left, right) => {
+                Some((left.evaluate(context)? != 0 || right.evaluate(context)? != 0) as i64)
+            }
+            Expression::Binary(BinaryOperator::And, left, right) => {
+                Some((left.evaluate(context)? != 0 && right.evaluate(context)? != 0) as i64)
+            }
+            Expression::Binary(operator, left, right) => {
+                let (left, right) = (left.evaluate(context)?, right.evaluate(context)?);
+                match operator {
+                    BinaryOperator::Equal => Some((left == right) as i64),
+                    BinaryOperator::NotEqual => Some((left != right) as i64),
+                    BinaryOperator::Less => Some((left < right) as i64),
+                    BinaryOperator::LessEqual => Some((left <= right) as i64),
+                    BinaryOperator::Greater => Some((left > right) as i64),
+                    BinaryOperator::GreaterEqual => Some((left >= right) as i64),
+                    BinaryOperator::BitOr => Some(left | right),
+                    BinaryOperator::BitXor => Some(left ^ right),
+                    BinaryOperator::BitAnd => Some(left & right),
+                    BinaryOperator::ShiftLeft => Some(left.wrapping_shl(right as u32)),
+                    BinaryOperator::ShiftRight => Some(left.wrapping_shr(right as u32)),
+                    BinaryOperator::Add => Some(left.wrapping_add(right)),
+                    BinaryOperator::Subtract => Some(left.wrapping_sub(right)),
+                    BinaryOperator::Multiply => Some(left.wrapping_mul(right)),
+                    BinaryOperator::Divide => left.checked_div(right),
+                    BinaryOperator::Modulo => left.checked_rem(right),
+                    BinaryOperator::Or | BinaryOperator::And => unreachable!(),
+                }
+            }
+        }
+    }
+
+    pub fn is_true<C: Context>(&self, context: &C) -> bool {
+        self.evaluate(context).is_some_and(|value| value != 0)
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Expression::Unary(..) => UNARY_PRECEDENCE,
+            Expression::Binary(operator, ..) => operator.precedence(),
+            _ => u8::MAX,
+        }
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expression::Number(value) if *value < 0 => write!(f, "-${:X}", value.unsigned_abs()),
+            Expression::Number(value) => write!(f, "${:X}", value),
+            Expression::Variable(variable) => {
+                let name = VARIABLES.iter().find(|(_, v)| v == variable).map(|(name, _)| *name).unwrap();
+                write!(f, "{}", name.to_ascii_uppercase())
+            }
+            Expression::Byte(address) => write!(f, "[{}]", address),
+            Expression::Word(address) => write!(f, "word[{}]", address),
+            Expression::Unary(operator, operand) => {
+                let symbol = match operator {
+                    UnaryOperator::Not => "!",
+                    UnaryOperator::Negate => "-",
+                    UnaryOperator::Complement => "~",
+                };
+                if operand.precedence() < UNARY_PRECEDENCE {
+                    write!(f, "{}({})", symbol, operand)
+                } else {
+                    write!(f, "{}{}", symbol, operand)
+                }
+            }
+            Expression::Binary(operator, left, right) => {
+                let precedence = operator.precedence();
+                if left.precedence() < precedence {
+                    write!(f, "({})", left)?;
+                } else {
+                    write!(f, "{}", left)?;
+                }
+                write!(f, " {} ", operator.symbol())?;
+                if right.precedence() <= precedence {
+                    write!(f, "({})", right)
+                } else {
+                    write!(f, "{}", right)
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Identifier(String),
+    Symbol(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Number(value) => write!(f, "number {}", value),
+            Token::Identifier(name) => write!(f, "'{}'", name),
+            Token::Symbol(symbol) => write!(f, "'{}'", symbol),
+        }
+    }
+}
+
+const SYMBOLS: [&str; 23] = [
+    "||", "&&", "==", "!=", "<=", ">=", "<<", ">>",
+    "<", ">", "|", "^", "&", "+", "-", "*", "/", "%", "!", "~", "(", ")", "[",
+];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, EmulatorError> {
+    let mut tokens = Vec::new();
+    let mut rest = source.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let length = if c == '$' || c.is_ascii_digit() || (c == '%' && is_binary_literal(&tokens, rest)) {
+            let (value, length) = number(rest)?;
+            tokens.push(Token::Number(value));
+            length
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let length = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+            tokens.push(Token::Identifier(rest[..length].to_ascii_lowercase()));
+            length
+        } else if c == ']' {
+            tokens.push(Token::Symbol("]"));
+            1
+        } else if let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) {
+            tokens.push(Token::Symbol(symbol));
+            symbol.len()
+        } else {
+            return Err(EmulatorError::InvalidExpression(format!("unexpected character '{}'", c)));
+        };
+        rest = rest[length..].trim_start();
+    }
+    Ok(tokens)
+}
+
+fn is_binary_literal(tokens: &[Token], rest: &str) -> bool {
+    let follows_operand = matches!(tokens.last(), Some(Token::Number(_) | Token::Identifier(_) | Token::Symbol(")" | "]")));
+    !follows_operand && rest[1..].starts_with(['0', '1'])
+}
+
+fn number(text: &str) -> Result<(i64, usize), EmulatorError> {
+    let (radix, prefix) = if text.starts_with('$') {
+        (16, 1)
+    } else if text.starts_with("0x") || text.starts_with("0X") {
+        (16, 2)
+    } else if text.starts_with('%') {
+        (2, 1)
+    } else {
+        (10, 0)
+    };
+    let digits = &text[prefix..];
+    let length = digits.find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(digits.len());
+    i64::from_str_radix(&digits[..length], radix)
+        .map(|value| (value, prefix + length))
+        .map_err(|_| EmulatorError::InvalidExpression(format!("invalid number '{}'", &text[..prefix + length])))
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn expression(&mut self, minimum_precedence: u8) -> Result<Expression, EmulatorError> {
+        let mut left = self.unary()?;
+        while let Some(Token::Symbol(symbol)) = self.tokens.get(self.position) {
+            let Some(&(_, operator, precedence)) = BINARY_OPERATORS.iter().find(|(s, _, _)| s == symbol) else { break };
+            if precedence < minimum_precedence {
+                break;
+            }
+            self.position += 1;
+            let right = self.expression(precedence + 1)?;
+            left = Expression::Binary(operator, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expression, EmulatorError> {
+        let operator = match self.tokens.get(self.position) {
+            Some(Token::Symbol("!")) => UnaryOperator::Not,
+            Some(Token::Symbol("-")) => UnaryOperator::Negate,
+            Some(Token::Symbol("~")) => UnaryOperator::Complement,
+            _ => return self.primary(),
+        };
+        self.position += 1;
+        Ok(Expression::Unary(operator, Box::new(self.unary()?)))
+    }
+
+    fn primary(&mut self) -> Result<Expression, EmulatorError> {
+        let token = self.tokens.get(self.position).cloned()
+            .ok_or_else(|| EmulatorError::InvalidExpression("unexpected end of expression".to_string()))?;
+        self.position += 1;
+        match token {
+            Token::Number(value) => Ok(Expression::Number(value)),
+            Token::Symbol("(") => {
+                let expression = self.expression(0)?;
+                self.expect(")")?;
+                Ok(expression)
+            }
+            Token::Symbol("[") => Ok(Expression::Byte(Box::new(self.indirect()?))),
+            Token::Identifier(name) if name == "word" || name == "byte" => {
+                self.expect("[")?;
+                let address = Box::new(self.indirect()?);
+                Ok(if name == "word" { Expression::Word(address) } else { Expression::Byte(address) })
+            }
+            Token::Identifier(name) => VARIABLES.iter()
+                .find(|(variable, _)| *variable == name)
+                .map(|(_, variable)| Expression::Variable(*variable))
+                .ok_or_else(|| EmulatorError::InvalidExpression(format!("unknown variable '{}'", name))),
+            token => Err(EmulatorError::InvalidExpression(format!("unexpected {}", token))),
+        }
+    }
+
+    fn indirect(&mut self) -> Result<Expression, EmulatorError> {
+        let address = self.expression(0)?;
+        self.expect("]")?;
+        Ok(address)
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), EmulatorError> {
+        match self.tokens.get(self.position) {
+            Some(Token::Symbol(found)) if *found == symbol => {
+                self.position += 1;
+                Ok(())
+            }
+            Some(token) => Err(EmulatorError::InvalidExpression(format!("expected '{}', found {}", symbol, token))),
+            None => Err(EmulatorError::InvalidExpression(format!("expected '{}'", symbol))),
+        }
+    }
+}
@@ -0,0 +1,351 @@
+use std::collections::HashMap;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use crate::common::errors::EmulatorError;
+use crate::cpu::CPU;
+use crate::cpu::types::ProcessorStatus;
+use crate::debugger::{AccessKind, Breakpoint, Debugger, StopReason, Watchpoint};
+use crate::memory::memory::Memory;
+
+const INTERRUPT: u8 = 0x03;
+const INTERRUPT_POLL_INTERVAL: usize = 1000;
+const REGISTER_COUNT: usize = 6;
+/// The largest packet we advertise in qSupported, which also bounds memory reads.
+const PACKET_SIZE: usize = 0x1000;
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.nes.6502.core">
+    <reg name="a" bitsize="8" regnum="0"/>
+    <reg name="x" bitsize="8"/>
+    <reg name="y" bitsize="8"/>
+    <reg name="sp" bitsize="8"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="p" bitsize="8"/>
+  </feature>
+</target>
+"#;
+
+pub struct GdbServer {
+    listener: TcpListener,
+}
+
+impl GdbServer {
+    /// Listens on the loopback interface only; port 0 picks a free port.
+    pub fn bind(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        Ok(GdbServer { listener })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accepts one client and serves it until it detaches, kills the target or disconnects.
+    pub fn serve(&self, cpu: &mut CPU, debugger: &mut Debugger) -> io::Result<()> {
+        let (stream, _) = self.listener.accept()?;
+        stream.set_nodelay(true)?;
+        let mut session = Session { stream, no_ack: false, breakpoints: HashMap::new() };
+        session.run(cpu, debugger)
+    }
+}
+
+struct Session {
+    stream: TcpStream,
+    no_ack: bool,
+    breakpoints: HashMap<u16, usize>,
+}
+
+impl Session {
+    fn run(&mut self, cpu: &mut CPU, debugger: &mut Debugger) -> io::Result<()> {
+        while let Some(packet) = self.receive()? {
+            let reply = match packet.as_bytes().first() {
+                Some(b'k') => return Ok(()),
+                Some(b'D') => {
+                    self.send("OK")?;
+                    return Ok(());
+                }
+                Some(b'c') => self.resume(cpu, debugger, &packet[1..], false),
+                Some(b's') => self.resume(cpu, debugger, &packet[1..], true),
+                _ => self.handle(cpu, debugger, &packet),
+            };
+            self.send(&reply)?;
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, cpu: &mut CPU, debugger: &mut Debugger, packet: &str) -> String {
+        let (command, arguments) = packet.split_at(packet.chars().next().map_or(0, |c| c.len_utf8()));
+        let reply = match command {
+            "?" => Some("S05".to_string()),
+            "g" => Some(read_registers(cpu).iter().map(|value| to_hex(value)).collect()),
+            "G" => write_registers(cpu, arguments),
+            "p" => usize::from_str_radix(arguments, 16).ok()
+                .and_then(|register| read_registers(cpu).get(register).map(|value| to_hex(value))),
+            "P" => arguments.split_once('=').and_then(|(register, value)| {
+                write_register(cpu, usize::from_str_radix(register, 16).ok()?, &from_hex(value)?)
+            }),
+            "m" => parse_range(arguments).and_then(|(address, length)| read_memory(cpu, address, length)),
+            "M" => arguments.split_once(':').and_then(|(range, data)| {
+                let (address, length) = parse_range(range)?;
+                write_memory(cpu, address, &from_hex(data).filter(|bytes| bytes.len() == length)?)
+            }),
+            "Z" => self.insert_point(debugger, arguments),
+            "z" => self.remove_point(debugger, arguments),
+            "H" => Some("OK".to_string()),
+            "q" => return query(arguments),
+            "Q" if arguments == "StartNoAckMode" => {
+                self.no_ack = true;
+                Some("OK".to_string())
+            }
+            _ => return String::new(),
+        };
+        reply.unwrap_or_else(|| "E01".to_string())
+    }
+
+    fn resume(&mut self, cpu: &mut CPU, debugger: &mut Debugger, address: &str, single_step: bool) -> String {
+        if !address.is_empty() {
+            match u16::from_str_radix(address, 16) {
+                Ok(address) => cpu.program_counter = address,
+                Err(_) => return "E01".to_string(),
+            }
+        }
+        let result = if single_step {
+            debugger.step(cpu)
+        } else {
+            let stream = &mut self.stream;
+            let mut count = 0;
+            debugger.continue_until(cpu, |_| {
+                count += 1;
+                count % INTERRUPT_POLL_INTERVAL == 0 && interrupt_requested(stream)
+            })
+        };
+        stop_reply(result)
+    }
+
+    fn insert_point(&mut self, debugger: &mut Debugger, arguments: &str) -> Option<String> {
+        let (kind, address, length) = parse_point(arguments)?;
+        match kind {
+            '0' | '1' => {
+                self.breakpoints.entry(address).or_insert_with(|| debugger.add_breakpoint(Breakpoint::at(address)));
+            }
+            _ => {
+                debugger.add_watchpoint(watchpoint(kind, address, length)?);
+            }
+        }
+        Some("OK".to_string())
+    }
+
+    fn remove_point(&mut self, debugger: &mut Debugger, arguments: &str) -> Option<String> {
+        let (kind, address, length) = parse_point(arguments)?;
+        match kind {
+            '0' | '1' => {
+                let id = self.breakpoints.remove(&address)?;
+                debugger.remove_breakpoint(id)?;
+            }
+            _ => {
+                let watchpoint = watchpoint(kind, address, length)?;
+                let index = debugger.watchpoints().iter().position(|existing| *existing == watchpoint)?;
+                debugger.remove_watchpoint(index);
+            }
+        }
+        Some("OK".to_string())
+    }
+
+    fn receive(&mut self) -> io::Result<Option<String>> {
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(b'$') => {}
+                Some(_) => continue,
+            }
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                }
+            }
+            let mut checksum = [0; 2];
+            self.stream.read_exact(&mut checksum)?;
+            let valid = std::str::from_utf8(&checksum).ok()
+                .and_then(|checksum| u8::from_str_radix(checksum, 16).ok())
+                .is_some_and(|checksum| checksum == packet_checksum(&data));
+            if !self.no_ack {
+                self.stream.write_all(if valid { b"+" } else { b"-" })?;
+            }
+            if valid {
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            }
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", data, packet_checksum(data.as_bytes()));
+        loop {
+            self.stream.write_all(packet.as_bytes())?;
+            if self.no_ack {
+                return Ok(());
+            }
+            match self.read_byte()? {
+                Some(b'-') => continue,
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+        match self.stream.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+}
+
+fn query(arguments: &str) -> String {
+    if arguments.starts_with("Supported") {
+        return format!("PacketSize={:x};qXfer:features:read+;QStartNoAckMode+", PACKET_SIZE);
+    }
+    if let Some(range) = arguments.strip_prefix("Xfer:features:read:target.xml:") {
+        return match parse_range(range) {
+            Some((offset, length)) => {
+                let start = (offset as usize).min(TARGET_XML.len());
+                let end = start.saturating_add(length).min(TARGET_XML.len());
+                let marker = if end == TARGET_XML.len() { 'l' } else { 'm' };
+                format!("{}{}", marker, &TARGET_XML[start..end])
+            }
+            None => "E01".to_string(),
+        };
+    }
+    match arguments {
+        "Attached" => "1".to_string(),
+        "C" => "QC1".to_string(),
+        "fThreadInfo" => "m1".to_string(),
+        "sThreadInfo" => "l".to_string(),
+        _ => String::new(),
+    }
+}
+
+fn stop_reply(result: Result<StopReason, EmulatorError>) -> String {
+    match result {
+        Ok(StopReason::Watchpoint(address, AccessKind::Read)) => format!("T05rwatch:{:04x};", address),
+        Ok(StopReason::Watchpoint(address, AccessKind::Write)) => format!("T05watch:{:04x};", address),
+        Ok(StopReason::Interrupted) => "S02".to_string(),
+        Ok(_) => "S05".to_string(),
+        Err(_) => "S04".to_string(),
+    }
+}
+
+fn interrupt_requested(stream: &mut TcpStream) -> bool {
+    if stream.set_nonblocking(true).is_err() {
+        return false;
+    }
+    let mut byte = [0];
+    let requested = match stream.read(&mut byte) {
+        Ok(0) => true,
+        Ok(_) => byte[0] == INTERRUPT,
+        Err(e) => e.kind() != ErrorKind::WouldBlock,
+    };
+    let _ = stream.set_nonblocking(false);
+    requested
+}
+
+fn read_registers(cpu: &CPU) -> [Vec<u8>; REGISTER_COUNT] {
+    [
+        vec![cpu.register_a],
+        vec![cpu.register_x],
+        vec![cpu.register_y],
+        vec![cpu.stack_pointer],
+        cpu.program_counter.to_le_bytes().to_vec(),
+        vec![cpu.status.to_u8()],
+    ]
+}
+
+fn write_registers(cpu: &mut CPU, data: &str) -> Option<String> {
+    let bytes = from_hex(data)?;
+    if bytes.len() != 7 {
+        return None;
+    }
+    let mut offset = 0;
+    for register in 0..REGISTER_COUNT {
+        let size = if register == 4 { 2 } else { 1 };
+        write_register(cpu, register, &bytes[offset..offset + size])?;
+        offset += size;
+    }
+    Some("OK".to_string())
+}
+
+fn write_register(cpu: &mut CPU, register: usize, value: &[u8]) -> Option<String> {
+    match (register, value) {
+        (0, [value]) => cpu.register_a = *value,
+        (1, [value]) => cpu.register_x = *value,
+        (2, [value]) => cpu.register_y = *value,
+        (3, [value]) => cpu.stack_pointer = *value,
+        (4, [low, high]) => cpu.program_counter = u16::from_le_bytes([*low, *high]),
+        (5, [value]) => cpu.status = ProcessorStatus::from_u8(*value),
+        _ => return None,
+    }
+    Some("OK".to_string())
+}
+
+fn read_memory(cpu: &CPU, address: u16, length: usize) -> Option<String> {
+    if length > PACKET_SIZE {
+        return None;
+    }
+    let bytes: Vec<u8> = (0..length)
+        .map_while(|offset| cpu.peek(address.wrapping_add(offset as u16)).ok())
+        .collect();
+    if bytes.is_empty() && length > 0 {
+        return None;
+    }
+    Some(to_hex(&bytes))
+}
+
+fn write_memory(cpu: &mut CPU, address: u16, bytes: &[u8]) -> Option<String> {
+    for (offset, byte) in bytes.iter().enumerate() {
+        cpu.write(address.wrapping_add(offset as u16), *byte).ok()?;
+    }
+    Some("OK".to_string())
+}
+
+fn watchpoint(kind: char, address: u16, length: usize) -> Option<Watchpoint> {
+    if length > 0x10000 - address as usize {
+        return None;
+    }
+    let end = (address as usize + length.max(1) - 1) as u16;
+    match kind {
+        '2' => Some(Watchpoint::new(address, end, false, true, false)),
+        '3' => Some(Watchpoint::new(address, end, true, false, false)),
+        '4' => Some(Watchpoint::new(address, end, true, true, false)),
+        _ => None,
+    }
+}
+
+fn parse_point(arguments: &str) -> Option<(char, u16, usize)> {
+    let (kind, range) = arguments.split_once(',')?;
+    let range = range.split(';').next()?;
+    let (address, length) = parse_range(range)?;
+    Some((kind.chars().next()?, address, length))
+}
+
+fn parse_range(text: &str) -> Option<(u16, usize)> {
+    let (address, length) = text.split_once(',')?;
+    Some((u16::from_str_radix(address, 16).ok()?, usize::from_str_radix(length, 16).ok()?))
+}
+
+fn packet_checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok()).collect()
+}
@@ -0,0 +1,415 @@
+pub mod expression;
+pub mod gdb;
+mod test;
+
+use std::collections::BTreeMap;
+use crate::common::constants::STACK_START;
+use crate::common::errors::EmulatorError;
+use crate::cpu::CPU;
+use crate::cpu::opcode::get_opcode;
+use crate::cpu::types::AddressingMode;
+use crate::debugger::expression::Expression;
+use crate::disassembler::{decode, Instruction};
+use crate::memory::memory::Memory;
+
+const READ_OPCODES: [&str; 16] = [
+    "LDA", "LDX", "LDY", "ADC", "SBC", "AND", "EOR", "ORA", "CMP", "CPX", "CPY", "BIT", "LAX", "LAR", "NOP", "AXS",
+];
+const WRITE_OPCODES: [&str; 8] = ["STA", "STX", "STY", "SAX", "AXA", "SXA", "SYA", "XAS"];
+/// Stores whose address can be corrupted by the value they store.
+const UNSTABLE_STORE_OPCODES: [&str; 4] = ["AXA", "SXA", "SYA", "XAS"];
+const READ_MODIFY_WRITE_OPCODES: [&str; 12] = [
+    "INC", "DEC", "ASL", "LSR", "ROL", "ROR", "DCP", "ISB", "RLA", "RRA", "SLO", "SRE",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AccessKind {
+    Read,
+    Write,
+    Execute,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+impl Watchpoint {
+    pub fn new(start: u16, end: u16, read: bool, write: bool, execute: bool) -> Self {
+        Watchpoint { start, end, read, write, execute }
+    }
+
+    fn matches(&self, address: u16, kind: AccessKind) -> bool {
+        let enabled = match kind {
+            AccessKind::Read => self.read,
+            AccessKind::Write => self.write,
+            AccessKind::Execute => self.execute,
+        };
+        enabled && (self.start..=self.end).contains(&address)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Breakpoint {
+    pub address: Option<u16>,
+    pub condition: Option<Expression>,
+    pub ignore_count: u64,
+    pub hit_count: u64,
+    triggered: bool,
+}
+
+impl Breakpoint {
+    pub fn at(address: u16) -> Self {
+        Breakpoint { address: Some(address), condition: None, ignore_count: 0, hit_count: 0, triggered: false }
+    }
+
+    /// Breaks anywhere the condition becomes true, rather than on every instruction while it holds.
+    pub fn when(condition: Expression) -> Self {
+        Breakpoint { address: None, condition: Some(condition), ignore_count: 0, hit_count: 0, triggered: false }
+    }
+
+    pub fn with_condition(mut self, condition: Expression) -> Self {
+        self.condition = Some(condition);
+        self
+    }
+
+    fn hit(&mut self, cpu: &CPU) -> bool {
+        if self.address.is_some_and(|address| address != cpu.program_counter) {
+            return false;
+        }
+        let holds = self.condition.as_ref().is_none_or(|condition| condition.is_true(cpu));
+        let was_triggered = self.triggered;
+        self.triggered = holds;
+        if !holds || (self.address.is_none() && was_triggered) {
+            return false;
+        }
+        self.hit_count += 1;
+        self.hit_count > self.ignore_count
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameKind {
+    Subroutine,
+    Interrupt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame {
+    pub kind: FrameKind,
+    pub call_site: u16,
+    pub target: u16,
+    pub return_address: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopReason {
+    Step,
+    Breakpoint(usize),
+    Watchpoint(u16, AccessKind),
+    Break(u16),
+    Finished,
+    Interrupted,
+}
+
+pub struct Debugger {
+    breakpoints: BTreeMap<usize, Breakpoint>,
+    next_breakpoint: usize,
+    watchpoints: Vec<Watchpoint>,
+    frames: Vec<Frame>,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Debugger {
+            breakpoints: BTreeMap::new(),
+            next_breakpoint: 1,
+            watchpoints: Vec::new(),
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        let id = self.next_breakpoint;
+        self.breakpoints.insert(id, breakpoint);
+        self.next_breakpoint += 1;
+        id
+    }
+
+    pub fn remove_breakpoint(&mut self, id: usize) -> Option<Breakpoint> {
+        self.breakpoints.remove(&id)
+    }
+
+    pub fn breakpoint_mut(&mut self, id: usize) -> Option<&mut Breakpoint> {
+        self.breakpoints.get_mut(&id)
+    }
+
+    pub fn breakpoints(&self) -> Vec<(usize, &Breakpoint)> {
+        self.breakpoints.iter().map(|(id, breakpoint)| (*id, breakpoint)).collect()
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> usize {
+        self.watchpoints.push(watchpoint);
+        self.watchpoints.len() - 1
+    }
+
+    pub fn remove_watchpoint(&mut self, index: usize) -> Option<Watchpoint> {
+        if index < self.watchpoints.len() {
+            Some(self.watchpoints.remove(index))
+        } else {
+            None
+        }
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn backtrace(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub fn step(&mut self, cpu: &mut CPU) -> Result<StopReason, EmulatorError> {
+        let pc = cpu.program_counter;
+        let opcode_u8 = cpu.peek(pc)?;
+        let opcode = get_opcode(opcode_u8).ok_or(EmulatorError::InvalidOpcode(opcode_u8))?;
+        let accesses = predict_accesses(cpu)?;
+
+        let running = cpu.step()?;
+        self.track_frames(cpu, pc, opcode.name);
+        if let Some(return_address) = cpu.interrupted_at {
+            self.frames.push(Frame {
+                kind: FrameKind::Interrupt,
+                call_site: return_address,
+                target: cpu.program_counter,
+                return_address,
+            });
+        }
+
+        if !running {
+            return Ok(StopReason::Break(pc));
+        }
+        for (address, kind) in accesses {
+            if self.watchpoints.iter().any(|watchpoint| watchpoint.matches(address, kind)) {
+                return Ok(StopReason::Watchpoint(address, kind));
+            }
+        }
+        Ok(StopReason::Step)
+    }
+
+    pub fn next(&mut self, cpu: &mut CPU) -> Result<StopReason, EmulatorError> {
+        let opcode = cpu.peek(cpu.program_counter)?;
+        if get_opcode(opcode).map(|opcode| opcode.name) != Some("JSR") {
+            return self.step(cpu);
+        }
+        let depth = self.frames.len();
+        self.run(cpu, |debugger, _| debugger.frames.len() <= depth, |_| false)
+    }
+
+    pub fn finish(&mut self, cpu: &mut CPU) -> Result<StopReason, EmulatorError> {
+        if self.frames.is_empty() {
+            return self.continue_execution(cpu);
+        }
+        let depth = self.frames.len();
+        self.run(cpu, |debugger, _| debugger.frames.len() < depth, |_| false)
+    }
+
+    pub fn continue_execution(&mut self, cpu: &mut CPU) -> Result<StopReason, EmulatorError> {
+        self.run(cpu, |_, _| false, |_| false)
+    }
+
+    pub fn continue_until<F>(&mut self, cpu: &mut CPU, interrupt: F) -> Result<StopReason, EmulatorError>
+        where
+            F: FnMut(&CPU) -> bool {
+        self.run(cpu, |_, _| false, interrupt)
+    }
+
+    fn run<D, F>(&mut self, cpu: &mut CPU, mut done: D, mut interrupt: F) -> Result<StopReason, EmulatorError>
+        where
+            D: FnMut(&Debugger, &CPU) -> bool,
+            F: FnMut(&CPU) -> bool {
+        let mut first = true;
+        loop {
+            if !first {
+                if let Some(id) = self.check_breakpoints(cpu) {
+                    return Ok(StopReason::Breakpoint(id));
+                }
+                if self.watches_execution(cpu)? {
+                    return Ok(StopReason::Watchpoint(cpu.program_counter, AccessKind::Execute));
+                }
+                if interrupt(cpu) {
+                    return Ok(StopReason::Interrupted);
+                }
+            }
+            first = false;
+
+            match self.step(cpu)? {
+                StopReason::Step => {}
+                reason => return Ok(reason),
+            }
+            if done(self, cpu) {
+                return Ok(StopReason::Finished);
+            }
+        }
+    }
+
+    fn check_breakpoints(&mut self, cpu: &CPU) -> Option<usize> {
+        let mut hit = None;
+        for (id, breakpoint) in self.breakpoints.iter_mut() {
+            if breakpoint.hit(cpu) && hit.is_none() {
+                hit = Some(*id);
+            }
+        }
+        hit
+    }
+
+    fn watches_execution(&self, cpu: &CPU) -> Result<bool, EmulatorError> {
+        let pc = cpu.program_counter;
+        let opcode = cpu.peek(pc)?;
+        let size = get_opcode(opcode).map(|opcode| opcode.bytes).unwrap_or(1) as u16;
+        Ok((0..size).any(|offset| {
+            self.watchpoints.iter().any(|watchpoint| watchpoint.matches(pc.wrapping_add(offset), AccessKind::Execute))
+        }))
+    }
+
+    fn track_frames(&mut self, cpu: &CPU, pc: u16, name: &str) {
+        match name {
+            "JSR" => self.frames.push(Frame {
+                kind: FrameKind::Subroutine,
+                call_site: pc,
+                target: cpu.program_counter,
+                return_address: pc.wrapping_add(3),
+            }),
+            "BRK" => self.frames.push(Frame {
+                kind: FrameKind::Interrupt,
+                call_site: pc,
+                target: cpu.program_counter,
+                return_address: pc.wrapping_add(2),
+            }),
+            "RTS" => self.pop_frame(FrameKind::Subroutine),
+            "RTI" => self.pop_frame(FrameKind::Interrupt),
+            _ => {}
+        }
+    }
+
+    fn pop_frame(&mut self, kind: FrameKind) {
+        if let Some(index) = self.frames.iter().rposition(|frame| frame.kind == kind) {
+            self.frames.truncate(index);
+        }
+    }
+}
+
+pub fn predict_accesses(cpu: &CPU) -> Result<Vec<(u16, AccessKind)>, EmulatorError> {
+    let pc = cpu.program_counter;
+    let opcode_u8 = cpu.peek(pc)?;
+    let opcode = get_opcode(opcode_u8).ok_or(EmulatorError::InvalidOpcode(opcode_u8))?;
+    let stack = |offset: u8| STACK_START + cpu.stack_pointer.wrapping_add(offset) as u16;
+    let mut accesses = Vec::new();
+
+    match opcode.address_mode {
+        AddressingMode::Implied | AddressingMode::Accumulator | AddressingMode::Immediate | AddressingMode::Relative => {}
+        _ if opcode.name == "JMP" || opcode.name == "JSR" => {}
+        mode => {
+            let address = cpu.peek_param_address(&mode)?;
+            let writes = WRITE_OPCODES.contains(&opcode.name) || READ_MODIFY_WRITE_OPCODES.contains(&opcode.name);
+            let uncarried = cpu.uncarried_address(&mode, address);
+            match uncarried {
+                Some(uncarried) if writes || uncarried != address => accesses.push((uncarried, AccessKind::Read)),
+                _ => {}
+            }
+            if READ_OPCODES.contains(&opcode.name) || READ_MODIFY_WRITE_OPCODES.contains(&opcode.name) {
+                accesses.push((address, AccessKind::Read));
+            }
+            if UNSTABLE_STORE_OPCODES.contains(&opcode.name) {
+                let (target, _) = cpu.unstable_store(opcode.name, address, uncarried.unwrap_or(address));
+                accesses.push((target, AccessKind::Write));
+            } else if writes {
+                accesses.push((address, AccessKind::Write));
+            }
+        }
+    }
+    match opcode.name {
+        "PHA" | "PHP" => accesses.push((stack(0), AccessKind::Write)),
+        "PLA" | "PLP" => accesses.push((stack(1), AccessKind::Read)),
+        "JSR" => {
+            accesses.push((stack(0), AccessKind::Write));
+            accesses.push((stack(0xFF), AccessKind::Write));
+        }
+        "RTS" => {
+            accesses.push((stack(1), AccessKind::Read));
+            accesses.push((stack(2), AccessKind::Read));
+        }
+        "RTI" => {
+            (1..=3).for_each(|offset| accesses.push((stack(offset), AccessKind::Read)));
+        }
+        "BRK" => {
+            (0..3).for_each(|offset| accesses.push((stack(0u8.wrapping_sub(offset)), AccessKind::Write)));
+        }
+        _ => {}
+    }
+    Ok(accesses)
+}
+
+pub fn disassemble_around<M: Memory>(memory: &M, pc: u16, before: usize, after: usize) -> Vec<Instruction> {
+    let earliest = (pc as usize).saturating_sub(before.saturating_mul(3)) as u16;
+    let mut window_start = pc;
+    while window_start > earliest && memory.peek(window_start - 1).is_ok() {
+        window_start -= 1;
+    }
+    let length = after.saturating_add(1).saturating_mul(3).saturating_add((pc - window_start) as usize);
+    let bytes = read_window(memory, window_start, length);
+
+    let mut leading = Vec::new();
+    for start in window_start..=pc {
+        let mut address = start;
+        let mut candidate = Vec::new();
+        while address < pc {
+            match decode(&bytes, window_start, address) {
+                Some(instruction) => {
+                    candidate.push(instruction);
+                    address = instruction.next_address();
+                }
+                None => break,
+            }
+        }
+        if address == pc {
+            leading = candidate;
+            break;
+        }
+    }
+    let skip = leading.len().saturating_sub(before);
+    let mut instructions: Vec<Instruction> = leading.into_iter().skip(skip).collect();
+
+    let mut address = pc;
+    for _ in 0..=after {
+        match decode(&bytes, window_start, address) {
+            Some(instruction) => {
+                instructions.push(instruction);
+                address = instruction.next_address();
+            }
+            None => break,
+        }
+    }
+    instructions
+}
+
+fn read_window<M: Memory>(memory: &M, start: u16, length: usize) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for address in (start as usize..(start as usize).saturating_add(length)).take_while(|&address| address <= 0xFFFF) {
+        match memory.peek(address as u16) {
+            Ok(byte) => bytes.push(byte),
+            Err(_) => break,
+        }
+    }
+    bytes
+}
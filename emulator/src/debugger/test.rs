@@ -0,0 +1,429 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod test {
+    use crate::asm;
+    use crate::cpu::CPU;
+    use crate::debugger::{disassemble_around, AccessKind, Breakpoint, Debugger, FrameKind, StopReason, Watchpoint};
+    use crate::common::errors::EmulatorError;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::thread;
+    use crate::debugger::expression::Expression;
+    use crate::debugger::gdb::GdbServer;
+    use crate::memory::memory::Memory;
+
+    fn subroutine_program() -> Vec<u8> {
+        asm!(
+            "        LDX #$02",     // 8000
+            "loop:   JSR inner",    // 8002
+            "        DEX",          // 8005
+            "        BNE loop",     // 8006
+            "        STX $0300",    // 8008
+            "        BRK",          // 800B
+            "inner:  JSR leaf",     // 800C
+            "        RTS",          // 800F
+            "leaf:   INC $0200",    // 8010
+            "        RTS",          // 8013
+        )
+    }
+
+    #[test]
+    fn test_step_and_breakpoint() {
+        let mut cpu = CPU::with_program(&subroutine_program());
+        let mut debugger = Debugger::new();
+        assert_eq!(debugger.step(&mut cpu).unwrap(), StopReason::Step);
+        assert_eq!(cpu.program_counter, 0x8002);
+
+        let id = debugger.add_breakpoint(Breakpoint::at(0x8010));
+        assert_eq!(debugger.continue_execution(&mut cpu).unwrap(), StopReason::Breakpoint(id));
+        assert_eq!(cpu.program_counter, 0x8010);
+        assert_eq!(cpu.read(0x0200).unwrap(), 0);
+
+        assert_eq!(debugger.continue_execution(&mut cpu).unwrap(), StopReason::Breakpoint(id));
+        assert_eq!(cpu.read(0x0200).unwrap(), 1);
+
+        assert_eq!(debugger.remove_breakpoint(id).unwrap().hit_count, 2);
+        assert_eq!(debugger.continue_execution(&mut cpu).unwrap(), StopReason::Break(0x800B));
+        assert_eq!(cpu.read(0x0200).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_conditional_breakpoint() {
+        let mut cpu = CPU::with_program(&subroutine_program());
+        let mut debugger = Debugger::new();
+        let condition = Expression::parse("X == 1 && [$0200] >= 1").unwrap();
+        let id = debugger.add_breakpoint(Breakpoint::at(0x8010).with_condition(condition));
+        assert_eq!(debugger.continue_execution(&mut cpu).unwrap(), StopReason::Breakpoint(id));
+        assert_eq!(cpu.register_x, 1);
+        assert_eq!(cpu.read(0x0200).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_breakpoint_ignore_count() {
+        let mut cpu = CPU::with_program(&subroutine_program());
+        let mut debugger = Debugger::new();
+        let id = debugger.add_breakpoint(Breakpoint::at(0x8005));
+        debugger.breakpoint_mut(id).unwrap().ignore_count = 1;
+        assert_eq!(debugger.continue_execution(&mut cpu).unwrap(), StopReason::Breakpoint(id));
+        assert_eq!(cpu.register_x, 1);
+        assert_eq!(debugger.breakpoints()[0].1.hit_count, 2);
+    }
+
+    #[test]
+    fn test_breakpoint_without_address_triggers_on_change() {
+        let mut cpu = CPU::with_program(&asm!(
+            "        LDX #$00",
+            "loop:   INX",
+            "        CPX #$03",
+            "        BCC loop",
+            "        LDA #$FF",
+            "        NOP",
+            "        NOP",
+            "        BRK",
+        ));
+        let mut debugger = Debugger::new();
+        let id = debugger.add_breakpoint(Breakpoint::when(Expression::parse("A == $FF && X < 4").unwrap()));
+        assert_eq!(debugger.continue_execution(&mut cpu).unwrap(), StopReason::Breakpoint(id));
+        assert_eq!(cpu.program_counter, 0x8009);
+        assert_eq!(debugger.continue_execution(&mut cpu).unwrap(), StopReason::Break(0x800B));
+    }
+
+    #[test]
+    fn test_backtrace() {
+        let mut cpu = CPU::with_program(&subroutine_program());
+        let mut debugger = Debugger::new();
+        debugger.add_breakpoint(Breakpoint::at(0x8010));
+        debugger.continue_execution(&mut cpu).unwrap();
+
+        let frames = debugger.backtrace();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].kind, FrameKind::Subroutine);
+        assert_eq!(frames[0].call_site, 0x8002);
+        assert_eq!(frames[0].target, 0x800C);
+        assert_eq!(frames[0].return_address, 0x8005);
+        assert_eq!(frames[1].call_site, 0x800C);
+        assert_eq!(frames[1].target, 0x8010);
+
+        debugger.step(&mut cpu).unwrap();
+        debugger.step(&mut cpu).unwrap();
+        assert_eq!(debugger.backtrace().len(), 1);
+    }
+
+    #[test]
+    fn test_next_steps_over_subroutine() {
+        let mut cpu = CPU::with_program(&subroutine_program());
+        let mut debugger = Debugger::new();
+        debugger.step(&mut cpu).unwrap();
+        assert_eq!(debugger.next(&mut cpu).unwrap(), StopReason::Finished);
+        assert_eq!(cpu.program_counter, 0x8005);
+        assert_eq!(cpu.read(0x0200).unwrap(), 1);
+        assert!(debugger.backtrace().is_empty());
+
+        assert_eq!(debugger.next(&mut cpu).unwrap(), StopReason::Step);
+        assert_eq!(cpu.program_counter, 0x8006);
+    }
+
+    #[test]
+    fn test_finish_returns_from_frame() {
+        let mut cpu = CPU::with_program(&subroutine_program());
+        let mut debugger = Debugger::new();
+        debugger.add_breakpoint(Breakpoint::at(0x8010));
+        debugger.continue_execution(&mut cpu).unwrap();
+        assert_eq!(debugger.finish(&mut cpu).unwrap(), StopReason::Finished);
+        assert_eq!(cpu.program_counter, 0x800F);
+        assert_eq!(debugger.finish(&mut cpu).unwrap(), StopReason::Finished);
+        assert_eq!(cpu.program_counter, 0x8005);
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        let mut cpu = CPU::with_program(&subroutine_program());
+        let mut debugger = Debugger::new();
+        debugger.add_watchpoint(Watchpoint::new(0x0300, 0x03FF, false, true, false));
+        assert_eq!(debugger.continue_execution(&mut cpu).unwrap(), StopReason::Watchpoint(0x0300, AccessKind::Write));
+        assert_eq!(cpu.program_counter, 0x800B);
+
+        let mut cpu = CPU::with_program(&subroutine_program());
+        let mut debugger = Debugger::new();
+        debugger.add_watchpoint(Watchpoint::new(0x0200, 0x0200, true, false, false));
+        assert_eq!(debugger.continue_execution(&mut cpu).unwrap(), StopReason::Watchpoint(0x0200, AccessKind::Read));
+        assert_eq!(cpu.program_counter, 0x8013);
+
+        let mut cpu = CPU::with_program(&subroutine_program());
+        let mut debugger = Debugger::new();
+        let index = debugger.add_watchpoint(Watchpoint::new(0x800F, 0x800F, false, false, true));
+        assert_eq!(debugger.continue_execution(&mut cpu).unwrap(), StopReason::Watchpoint(0x800F, AccessKind::Execute));
+        assert!(debugger.remove_watchpoint(index).is_some());
+        assert!(debugger.watchpoints().is_empty());
+    }
+
+    #[test]
+    fn test_stack_watchpoint() {
+        let mut cpu = CPU::with_program(&subroutine_program());
+        let mut debugger = Debugger::new();
+        debugger.add_watchpoint(Watchpoint::new(0x01FC, 0x01FD, false, true, false));
+        assert_eq!(debugger.continue_execution(&mut cpu).unwrap(), StopReason::Watchpoint(0x01FD, AccessKind::Write));
+        assert_eq!(cpu.program_counter, 0x800C);
+    }
+
+    #[test]
+    fn test_watchpoints_see_dummy_reads() {
+        let program = asm!(
+            "        LDX #$20",
+            "        LDA $02F0,X",
+            "        STA $0400,X",
+            "        BRK",
+        );
+        let mut cpu = CPU::with_program(&program);
+        let mut debugger = Debugger::new();
+        debugger.add_watchpoint(Watchpoint::new(0x0210, 0x0210, true, false, false));
+        assert_eq!(debugger.continue_execution(&mut cpu).unwrap(), StopReason::Watchpoint(0x0210, AccessKind::Read));
+        assert_eq!(cpu.program_counter, 0x8005);
+
+        debugger.add_watchpoint(Watchpoint::new(0x0420, 0x0420, true, false, false));
+        assert_eq!(debugger.continue_execution(&mut cpu).unwrap(), StopReason::Watchpoint(0x0420, AccessKind::Read));
+        assert_eq!(cpu.program_counter, 0x8008);
+    }
+
+    #[test]
+    fn test_watchpoints_see_corrupted_unstable_stores() {
+        let program = asm!(
+            "        LDX #$15",
+            "        LDY #$12",
+            "        SXA $05F0,Y",
+            "        BRK",
+        );
+        let mut cpu = CPU::with_program(&program);
+        let mut debugger = Debugger::new();
+        // The page cross puts the stored $04 in the high byte of the address.
+        debugger.add_watchpoint(Watchpoint::new(0x0402, 0x0402, false, true, false));
+        assert_eq!(debugger.continue_execution(&mut cpu).unwrap(), StopReason::Watchpoint(0x0402, AccessKind::Write));
+        assert_eq!(cpu.program_counter, 0x8007);
+    }
+
+    #[test]
+    fn test_continue_until_interrupt() {
+        let mut cpu = CPU::with_program(&asm!("loop: JMP loop"));
+        let mut debugger = Debugger::new();
+        let mut count = 0;
+        let reason = debugger.continue_until(&mut cpu, |_| {
+            count += 1;
+            count == 10
+        }).unwrap();
+        assert_eq!(reason, StopReason::Interrupted);
+    }
+
+    #[test]
+    fn test_disassemble_around() {
+        let mut cpu = CPU::with_program(&subroutine_program());
+        let instructions = disassemble_around(&cpu, 0x8008, 3, 2);
+        let addresses: Vec<u16> = instructions.iter().map(|instruction| instruction.address).collect();
+        assert_eq!(addresses, vec![0x8002, 0x8005, 0x8006, 0x8008, 0x800B, 0x800C]);
+
+        let instructions = disassemble_around(&cpu, 0x8000, 3, 0);
+        assert_eq!(instructions.len(), 4);
+        assert_eq!(instructions[3].to_string(), "LDX #$02");
+
+        cpu.set_strict_access(true);
+        let instructions = disassemble_around(&cpu, 0x6000, 3, 0);
+        assert_eq!(instructions.len(), 1);
+        assert_eq!(instructions[0].to_string(), "BRK");
+
+        let instructions = disassemble_around(&cpu, 0x8008, usize::MAX, usize::MAX);
+        assert!(instructions.iter().any(|instruction| instruction.address == 0x8008));
+        // The window runs to the top of memory, less an instruction cut off by it.
+        let last = instructions.last().unwrap();
+        assert!(last.address as usize + last.opcode.bytes >= 0xFFFF);
+    }
+
+    fn evaluate(source: &str, cpu: &CPU) -> Option<i64> {
+        Expression::parse(source).unwrap().evaluate(cpu)
+    }
+
+    #[test]
+    fn test_expression_evaluation() {
+        let mut cpu = CPU::with_program(&subroutine_program());
+        cpu.register_a = 0xFF;
+        cpu.register_x = 3;
+        cpu.status.carry = true;
+        cpu.write(0x00FE, 0x42).unwrap();
+        cpu.write_u16(0x0010, 0xBEEF).unwrap();
+
+        assert_eq!(evaluate("a == $FF && x < 4", &cpu), Some(1));
+        assert_eq!(evaluate("A == 255 && X >= 4", &cpu), Some(0));
+        assert_eq!(evaluate("[$00FE]", &cpu), Some(0x42));
+        assert_eq!(evaluate("word[$10] == $BEEF", &cpu), Some(1));
+        assert_eq!(evaluate("[$0F + x - 2]", &cpu), Some(0xEF));
+        assert_eq!(evaluate("1 + 2 * 3 == 7 || 0", &cpu), Some(1));
+        assert_eq!(evaluate("(1 + 2) * 3", &cpu), Some(9));
+        assert_eq!(evaluate("%1010 | 0x05", &cpu), Some(0x0F));
+        assert_eq!(evaluate("10 % 4", &cpu), Some(2));
+        assert_eq!(evaluate("!C + -1 + ~0", &cpu), Some(-2));
+        assert_eq!(evaluate("PC == $8000 && bank == 0", &cpu), Some(1));
+        assert_eq!(evaluate("cycles == 0 && frame == 0 && scanline == 0", &cpu), Some(1));
+        assert_eq!(evaluate("1 / (x - 3)", &cpu), None);
+        assert_eq!(evaluate("[$5000]", &cpu), Some(0xBE));
+        cpu.set_strict_access(true);
+        assert_eq!(evaluate("[$5000]", &cpu), None);
+        assert!(!Expression::parse("[$5000] == 0").unwrap().is_true(&cpu));
+    }
+
+    #[test]
+    fn test_expression_parse_errors() {
+        assert!(matches!(Expression::parse("A =="), Err(EmulatorError::InvalidExpression(_))));
+        assert!(Expression::parse("(A == 1").is_err());
+        assert!(Expression::parse("word $10").is_err());
+        assert!(Expression::parse("foo > 1").is_err());
+        assert!(Expression::parse("A # 1").is_err());
+        assert!(Expression::parse("A 1").is_err());
+    }
+
+    #[test]
+    fn test_expression_display() {
+        let source = "A == $FF && (X < $4 || [$FE] != $0) && word[$10 + Y] - $1 > -$2";
+        let expression = Expression::parse(source).unwrap();
+        assert_eq!(expression.to_string(), source);
+        assert_eq!(Expression::parse("(1 - 2) - (3 - 4)").unwrap().to_string(), "$1 - $2 - ($3 - $4)");
+    }
+
+    #[test]
+    fn test_cycle_counting() {
+        let mut cpu = CPU::with_program(&asm!(
+            "        LDX #$01",      // 2
+            "        LDA $80FF,X",   // 4 + 1 page crossed
+            "        LDA $8000,X",   // 4
+            "        BNE skip",      // 2 + 1 taken
+            "skip:   BEQ skip",      // 2 not taken
+            "        BRK",
+        ));
+        let mut debugger = Debugger::new();
+        debugger.continue_execution(&mut cpu).unwrap();
+        assert_eq!(cpu.cycles, 2 + 5 + 4 + 3 + 2 + 7);
+    }
+
+    struct GdbClient {
+        stream: TcpStream,
+    }
+
+    impl GdbClient {
+        fn send_raw(&mut self, data: &[u8]) {
+            self.stream.write_all(data).unwrap();
+        }
+
+        fn request(&mut self, data: &str) -> String {
+            let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+            self.send_raw(format!("${}#{:02x}", data, checksum).as_bytes());
+            assert_eq!(self.read_byte(), b'+');
+            self.reply()
+        }
+
+        fn reply(&mut self) -> String {
+            while self.read_byte() != b'$' {}
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte() {
+                    b'#' => break,
+                    byte => data.push(byte),
+                }
+            }
+            let mut checksum = [0; 2];
+            self.stream.read_exact(&mut checksum).unwrap();
+            let expected = data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+            assert_eq!(u8::from_str_radix(std::str::from_utf8(&checksum).unwrap(), 16).unwrap(), expected);
+            self.send_raw(b"+");
+            String::from_utf8(data).unwrap()
+        }
+
+        fn read_byte(&mut self) -> u8 {
+            let mut byte = [0];
+            self.stream.read_exact(&mut byte).unwrap();
+            byte[0]
+        }
+    }
+
+    fn start_gdb_server(program: Vec<u8>) -> (GdbClient, thread::JoinHandle<CPU>) {
+        let server = GdbServer::bind(0).unwrap();
+        let address = server.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let mut cpu = CPU::with_program(&program);
+            let mut debugger = Debugger::new();
+            server.serve(&mut cpu, &mut debugger).unwrap();
+            cpu
+        });
+        let stream = TcpStream::connect(address).unwrap();
+        stream.set_nodelay(true).unwrap();
+        (GdbClient { stream }, handle)
+    }
+
+    #[test]
+    fn test_gdb_registers_and_memory() {
+        let (mut client, handle) = start_gdb_server(subroutine_program());
+        assert_eq!(client.request("qSupported:multiprocess+"), "PacketSize=1000;qXfer:features:read+;QStartNoAckMode+");
+        assert_eq!(client.request("?"), "S05");
+        assert_eq!(client.request("g"), "000000fd008024");
+        assert!(client.request("qXfer:features:read:target.xml:0,1000").starts_with("l<?xml"));
+        assert_eq!(client.request("qXfer:features:read:target.xml:ffff,ffffffffffffffff"), "l");
+
+        assert_eq!(client.request("P0=7f"), "OK");
+        assert_eq!(client.request("p0"), "7f");
+        assert_eq!(client.request("G01020380108024"), "OK");
+        assert_eq!(client.request("p4"), "1080");
+
+        assert_eq!(client.request("m8000,3"), "a20220");
+        assert_eq!(client.request("M0200,2:beef"), "OK");
+        assert_eq!(client.request("m0200,2"), "beef");
+        // Unmapped memory reads back the last byte written.
+        assert_eq!(client.request("m5000,1"), "ef");
+        assert_eq!(client.request("m0,1000").len(), 0x2000);
+        assert_eq!(client.request("m0,1001"), "E01");
+        assert_eq!(client.request("m0,ffffffff"), "E01");
+        assert_eq!(client.request("vMustReplyEmpty"), "");
+        assert_eq!(client.request("D"), "OK");
+
+        let cpu = handle.join().unwrap();
+        assert_eq!(cpu.register_a, 0x01);
+        assert_eq!(cpu.program_counter, 0x8010);
+        assert_eq!(cpu.peek(0x0201).unwrap(), 0xEF);
+    }
+
+    #[test]
+    fn test_gdb_breakpoints_and_stepping() {
+        let (mut client, handle) = start_gdb_server(subroutine_program());
+        assert_eq!(client.request("s"), "S05");
+        assert_eq!(client.request("p4"), "0280");
+
+        assert_eq!(client.request("Z0,8010,1"), "OK");
+        assert_eq!(client.request("c"), "S05");
+        assert_eq!(client.request("p4"), "1080");
+        assert_eq!(client.request("z0,8010,1"), "OK");
+        assert_eq!(client.request("z0,8010,1"), "E01");
+
+        assert_eq!(client.request("Z2,0300,1"), "OK");
+        assert_eq!(client.request("c"), "T05watch:0300;");
+        assert_eq!(client.request("z2,0300,1"), "OK");
+        assert_eq!(client.request("Z2,8000,10000"), "E01");
+        assert_eq!(client.request("Z2,FFFF,2"), "E01");
+        assert_eq!(client.request("Z2,0000,10000"), "OK");
+        assert_eq!(client.request("z2,0000,10000"), "OK");
+
+        assert_eq!(client.request("Z3,0200,1"), "OK");
+        assert_eq!(client.request("c8010"), "T05rwatch:0200;");
+        client.send_raw(b"$k#6b");
+        assert_eq!(client.read_byte(), b'+');
+
+        let cpu = handle.join().unwrap();
+        assert_eq!(cpu.program_counter, 0x8013);
+    }
+
+    #[test]
+    fn test_gdb_interrupt() {
+        let (mut client, handle) = start_gdb_server(asm!("loop: JMP loop"));
+        assert_eq!(client.request("QStartNoAckMode"), "OK");
+        client.send_raw(b"$c#63");
+        client.send_raw(&[0x03]);
+        assert_eq!(client.reply(), "S02");
+        client.send_raw(b"$D#44");
+        assert_eq!(client.reply(), "OK");
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,353 @@
+mod test;
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use crate::common::constants::{IRQ_VECTOR, NMI_VECTOR, PC_START_ADDRESS, PRG_ROM_PAGE_SIZE, PRG_ROM_START};
+use crate::common::errors::EmulatorError;
+use crate::cpu::opcode::{get_opcode, Opcode};
+use crate::cpu::types::AddressingMode;
+
+const STOP_OPCODES: [&str; 4] = ["RTS", "RTI", "BRK", "KIL"];
+const DATA_BYTES_PER_LINE: usize = 8;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Instruction {
+    pub address: u16,
+    pub opcode: &'static Opcode,
+    pub operand: u16,
+}
+
+impl Instruction {
+    pub fn bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.opcode.code];
+        if self.opcode.bytes > 1 {
+            bytes.push(self.operand as u8);
+        }
+        if self.opcode.bytes > 2 {
+            bytes.push((self.operand >> 8) as u8);
+        }
+        bytes
+    }
+
+    pub fn next_address(&self) -> u16 {
+        self.address.wrapping_add(self.opcode.bytes as u16)
+    }
+
+    pub fn target(&self) -> Option<u16> {
+        match self.opcode.address_mode {
+            AddressingMode::Relative => {
+                let offset = self.operand as u8 as i8;
+                Some(self.next_address().wrapping_add(offset as u16))
+            }
+            AddressingMode::Absolute if self.opcode.name == "JMP" || self.opcode.name == "JSR" => {
+                Some(self.operand)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn operand_str(&self, label: Option<&str>) -> String {
+        let value = match label {
+            Some(label) => label.to_string(),
+            None => format!("{}{}", absolute_prefix(self), self.operand_value()),
+        };
+        format_operand(self.opcode.address_mode, &value)
+    }
+
+    /// The operand as a number: where a branch goes, or the operand in two or four hex digits.
+    pub fn operand_value(&self) -> String {
+        match self.opcode.address_mode {
+            AddressingMode::Relative => format!("${:0>4X}", self.target().unwrap()),
+            _ if self.opcode.bytes == 3 => format!("${:0>4X}", self.operand),
+            _ => format!("${:0>2X}", self.operand),
+        }
+    }
+}
+
+/// Wraps an operand's value in the syntax of its addressing mode.
+pub fn format_operand(mode: AddressingMode, value: &str) -> String {
+    match mode {
+        AddressingMode::Implied => String::new(),
+        AddressingMode::Accumulator => "A".to_string(),
+        AddressingMode::Immediate => format!("#{}", value),
+        AddressingMode::ZeroPage | AddressingMode::Relative | AddressingMode::Absolute => value.to_string(),
+        AddressingMode::ZeroPageX | AddressingMode::AbsoluteX => format!("{},X", value),
+        AddressingMode::ZeroPageY | AddressingMode::AbsoluteY => format!("{},Y", value),
+        AddressingMode::Indirect => format!("({})", value),
+        AddressingMode::IndexedIndirect => format!("({},X)", value),
+        AddressingMode::IndirectIndexed => format!("({}),Y", value),
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operand = self.operand_str(None);
+        if operand.is_empty() {
+            write!(f, "{}", self.opcode.name)
+        } else {
+            write!(f, "{} {}", self.opcode.name, operand)
+        }
+    }
+}
+
+// ca65 picks zero page addressing for absolute operands below $100 unless forced.
+fn absolute_prefix(instruction: &Instruction) -> &'static str {
+    let absolute = matches!(instruction.opcode.address_mode, AddressingMode::Absolute | AddressingMode::AbsoluteX | AddressingMode::AbsoluteY);
+    if absolute && instruction.operand < 0x100 {
+        "a:"
+    } else {
+        ""
+    }
+}
+
+pub fn decode(bytes: &[u8], base: u16, address: u16) -> Option<Instruction> {
+    let offset = address.wrapping_sub(base) as usize;
+    let code = *bytes.get(offset)?;
+    let opcode = get_opcode(code)?;
+    if offset + opcode.bytes > bytes.len() {
+        return None;
+    }
+    let operand = match opcode.bytes {
+        2 => bytes[offset + 1] as u16,
+        3 => u16::from_le_bytes([bytes[offset + 1], bytes[offset + 2]]),
+        _ => 0,
+    };
+    Some(Instruction { address, opcode, operand })
+}
+
+pub fn disassemble_linear(bytes: &[u8], base: u16) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let address = base.wrapping_add(offset as u16);
+        match decode(bytes, base, address) {
+            Some(instruction) => {
+                offset += instruction.opcode.bytes;
+                instructions.push(instruction);
+            }
+            None => break,
+        }
+    }
+    instructions
+}
+
+pub fn format_listing(instruction: &Instruction) -> String {
+    let code: Vec<String> = instruction.bytes().iter().map(|byte| format!("{:0>2X}", byte)).collect();
+    let marker = if instruction.opcode.unofficial { '*' } else { ' ' };
+    format!("{:0>4X}  {:<9}{}{}", instruction.address, code.join(" "), marker, instruction)
+}
+
+pub struct Disassembler<'a> {
+    bytes: &'a [u8],
+    base: u16,
+    pub follow_unofficial: bool,
+    instructions: BTreeMap<u16, Instruction>,
+    code: Vec<bool>,
+    labels: BTreeMap<u16, String>,
+}
+
+impl<'a> Disassembler<'a> {
+    pub fn new(bytes: &'a [u8], base: u16) -> Self {
+        Disassembler {
+            bytes,
+            base,
+            follow_unofficial: false,
+            instructions: BTreeMap::new(),
+            code: vec![false; bytes.len()],
+            labels: BTreeMap::new(),
+        }
+    }
+
+    pub fn from_prg_bank(prg_rom: &'a [u8], bank: usize) -> Result<Self, EmulatorError> {
+        let bank_count = prg_rom.len() / PRG_ROM_PAGE_SIZE;
+        if bank_count == 0 {
+            return Err(EmulatorError::InvalidBank(bank));
+        }
+        if bank_count <= 2 {
+            if bank != 0 {
+                return Err(EmulatorError::InvalidBank(bank));
+            }
+            let base = PRG_ROM_START + (2 - bank_count as u16) * PRG_ROM_PAGE_SIZE as u16;
+            return Ok(Self::new(prg_rom, base));
+        }
+        if bank >= bank_count {
+            return Err(EmulatorError::InvalidBank(bank));
+        }
+        let base = if bank == bank_count - 1 { PRG_ROM_START + PRG_ROM_PAGE_SIZE as u16 } else { PRG_ROM_START };
+        let start = bank * PRG_ROM_PAGE_SIZE;
+        Ok(Self::new(&prg_rom[start..start + PRG_ROM_PAGE_SIZE], base))
+    }
+
+    pub fn contains(&self, address: u16) -> bool {
+        address >= self.base && ((address - self.base) as usize) < self.bytes.len()
+    }
+
+    pub fn read_u16(&self, address: u16) -> Option<u16> {
+        if !self.contains(address) || !self.contains(address.wrapping_add(1)) {
+            return None;
+        }
+        let offset = (address - self.base) as usize;
+        Some(u16::from_le_bytes([self.bytes[offset], self.bytes[offset + 1]]))
+    }
+
+    pub fn add_label(&mut self, address: u16, name: &str) {
+        self.labels.insert(address, name.to_string());
+    }
+
+    pub fn analyze_vectors(&mut self) {
+        let vectors = [(NMI_VECTOR, "nmi"), (PC_START_ADDRESS, "reset"), (IRQ_VECTOR, "irq")];
+        let mut entries = Vec::new();
+        for (vector, name) in vectors {
+            if let Some(address) = self.read_u16(vector) {
+                if self.contains(address) && !self.labels.contains_key(&address) {
+                    self.add_label(address, name);
+                }
+                entries.push(address);
+            }
+        }
+        self.analyze(&entries);
+    }
+
+    pub fn analyze(&mut self, entry_points: &[u16]) {
+        let mut queue: VecDeque<u16> = entry_points.iter().copied().collect();
+        while let Some(start) = queue.pop_front() {
+            let mut address = start;
+            loop {
+                if !self.contains(address) || self.instructions.contains_key(&address) {
+                    break;
+                }
+                let Some(instruction) = decode(self.bytes, self.base, address) else { break };
+                if instruction.opcode.unofficial && !self.follow_unofficial {
+                    break;
+                }
+                let offset = (address - self.base) as usize;
+                if self.code[offset..offset + instruction.opcode.bytes].iter().any(|&is_code| is_code) {
+                    break;
+                }
+                self.code[offset..offset + instruction.opcode.bytes].iter_mut().for_each(|is_code| *is_code = true);
+                self.instructions.insert(address, instruction);
+
+                if let Some(target) = instruction.target() {
+                    if self.contains(target) {
+                        self.labels.entry(target).or_insert_with(|| format!("L{:0>4X}", target));
+                        queue.push_back(target);
+                    }
+                }
+                if instruction.opcode.name == "JMP" || STOP_OPCODES.contains(&instruction.opcode.name) {
+                    break;
+                }
+                address = instruction.next_address();
+            }
+        }
+    }
+
+    pub fn instructions(&self) -> Vec<Instruction> {
+        self.instructions.values().copied().collect()
+    }
+
+    pub fn label(&self, address: u16) -> Option<&str> {
+        self.labels.get(&address).map(|label| label.as_str())
+    }
+
+    pub fn to_ca65(&self) -> String {
+        let mut output = String::new();
+        output.push_str(".setcpu \"6502\"\n");
+        output.push_str(&format!(".org ${:0>4X}\n\n", self.base));
+
+        let vector_table = self.vector_table();
+        let mut data: Vec<u8> = Vec::new();
+        let mut data_start = self.base;
+        let mut offset = 0;
+        while offset < self.bytes.len() {
+            let address = self.base.wrapping_add(offset as u16);
+            let is_label = self.is_placeable(address);
+            let instruction = self.instructions.get(&address);
+            let at_vectors = vector_table.is_some() && address == NMI_VECTOR;
+
+            if !data.is_empty() && (is_label || instruction.is_some() || at_vectors || data.len() == DATA_BYTES_PER_LINE) {
+                output.push_str(&format_data(&data, data_start));
+                data.clear();
+            }
+            if is_label {
+                output.push_str(&format!("{}:\n", self.labels[&address]));
+            }
+            if at_vectors {
+                output.push_str(vector_table.as_ref().unwrap());
+                break;
+            }
+            match instruction {
+                Some(instruction) if !instruction.opcode.unofficial => {
+                    let label = instruction.target().and_then(|target| self.label_for_operand(target));
+                    output.push_str(&format!("        {:<23} ; ${:0>4X}\n", self.format_instruction(instruction, label), address));
+                    offset += instruction.opcode.bytes;
+                }
+                Some(instruction) => {
+                    let bytes: Vec<String> = instruction.bytes().iter().map(|byte| format!("${:0>2X}", byte)).collect();
+                    let line = format!(".byte {}", bytes.join(","));
+                    output.push_str(&format!("        {:<23} ; ${:0>4X} {}\n", line, address, instruction));
+                    offset += instruction.opcode.bytes;
+                }
+                None => {
+                    if data.is_empty() {
+                        data_start = address;
+                    }
+                    data.push(self.bytes[offset]);
+                    offset += 1;
+                }
+            }
+        }
+        if !data.is_empty() {
+            output.push_str(&format_data(&data, data_start));
+        }
+        output
+    }
+
+    fn is_code(&self, address: u16) -> bool {
+        self.contains(address) && self.code[(address - self.base) as usize]
+    }
+
+    fn is_placeable(&self, address: u16) -> bool {
+        self.labels.contains_key(&address) && (self.instructions.contains_key(&address) || !self.is_code(address))
+    }
+
+    fn label_for_operand(&self, address: u16) -> Option<&str> {
+        if self.is_placeable(address) {
+            self.label(address)
+        } else {
+            None
+        }
+    }
+
+    fn format_instruction(&self, instruction: &Instruction, label: Option<&str>) -> String {
+        let operand = instruction.operand_str(label);
+        if operand.is_empty() {
+            instruction.opcode.name.to_string()
+        } else {
+            format!("{} {}", instruction.opcode.name, operand)
+        }
+    }
+
+    fn vector_table(&self) -> Option<String> {
+        if !self.contains(NMI_VECTOR) || !self.contains(IRQ_VECTOR.wrapping_add(1)) {
+            return None;
+        }
+        if (NMI_VECTOR..=IRQ_VECTOR.wrapping_add(1)).any(|address| self.is_code(address)) {
+            return None;
+        }
+        let names: Vec<String> = [NMI_VECTOR, PC_START_ADDRESS, IRQ_VECTOR].iter()
+            .map(|&vector| {
+                let address = self.read_u16(vector).unwrap();
+                match self.label_for_operand(address) {
+                    Some(label) => label.to_string(),
+                    None => format!("${:0>4X}", address),
+                }
+            })
+            .collect();
+        Some(format!("        .word {}\n", names.join(", ")))
+    }
+}
+
+fn format_data(data: &[u8], address: u16) -> String {
+    let bytes: Vec<String> = data.iter().map(|byte| format!("${:0>2X}", byte)).collect();
+    format!("        {:<23} ; ${:0>4X}\n", format!(".byte {}", bytes.join(",")), address)
+}
@@ -0,0 +1,122 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod test {
+    use crate::disassembler::{decode, disassemble_linear, format_listing, Disassembler};
+
+    fn bank_with_vectors(program: &[u8], reset: u16, nmi: u16, irq: u16) -> Vec<u8> {
+        let mut bank = vec![0xFF; 0x4000];
+        bank[..program.len()].copy_from_slice(program);
+        bank[0x3FFA..0x3FFC].copy_from_slice(&nmi.to_le_bytes());
+        bank[0x3FFC..0x3FFE].copy_from_slice(&reset.to_le_bytes());
+        bank[0x3FFE..].copy_from_slice(&irq.to_le_bytes());
+        bank
+    }
+
+    #[test]
+    fn test_decode_operands() {
+        let bytes = [0xA9, 0x05, 0xBD, 0x34, 0x12, 0xB1, 0x10, 0x6C, 0xFF, 0x02, 0x0A];
+        let instructions = disassemble_linear(&bytes, 0x8000);
+        let text: Vec<String> = instructions.iter().map(|instruction| instruction.to_string()).collect();
+        assert_eq!(text, vec!["LDA #$05", "LDA $1234,X", "LDA ($10),Y", "JMP ($02FF)", "ASL A"]);
+    }
+
+    #[test]
+    fn test_decode_relative_target() {
+        let bytes = [0xEA, 0xD0, 0xFD];
+        let instruction = decode(&bytes, 0xC000, 0xC001).unwrap();
+        assert_eq!(instruction.target(), Some(0xC000));
+        assert_eq!(instruction.to_string(), "BNE $C000");
+    }
+
+    #[test]
+    fn test_decode_truncated_instruction() {
+        let bytes = [0xEA, 0xAD, 0x00];
+        assert!(decode(&bytes, 0x0000, 0x0001).is_none());
+        assert_eq!(disassemble_linear(&bytes, 0x0000).len(), 1);
+    }
+
+    #[test]
+    fn test_format_listing() {
+        let bytes = [0xA2, 0x01, 0x04, 0x10];
+        let instructions = disassemble_linear(&bytes, 0x0064);
+        assert_eq!(format_listing(&instructions[0]), "0064  A2 01     LDX #$01");
+        assert_eq!(format_listing(&instructions[1]), "0066  04 10    *NOP $10");
+    }
+
+    #[test]
+    fn test_analyze_follows_branches_and_stops_at_data() {
+        let program = [
+            0x78,             // C000 SEI
+            0xA2, 0x00,       // C001 LDX #$00
+            0xCA,             // C003 DEX
+            0xD0, 0xFD,       // C004 BNE $C003
+            0x20, 0x0C, 0xC0, // C006 JSR $C00C
+            0x4C, 0x06, 0xC0, // C009 JMP $C006
+            0x60,             // C00C RTS
+            0x12, 0x34,       // C00D data
+        ];
+        let bank = bank_with_vectors(&program, 0xC000, 0xC00C, 0xC00C);
+        let mut disassembler = Disassembler::new(&bank, 0xC000);
+        disassembler.analyze_vectors();
+        let addresses: Vec<u16> = disassembler.instructions().iter().map(|instruction| instruction.address).collect();
+        assert_eq!(addresses, vec![0xC000, 0xC001, 0xC003, 0xC004, 0xC006, 0xC009, 0xC00C]);
+        assert_eq!(disassembler.label(0xC000), Some("reset"));
+        assert_eq!(disassembler.label(0xC003), Some("LC003"));
+        assert_eq!(disassembler.label(0xC00C), Some("nmi"));
+    }
+
+    #[test]
+    fn test_to_ca65() {
+        let program = [
+            0xA9, 0x10,       // C000 LDA #$10
+            0x8D, 0x10, 0x00, // C002 STA a:$0010
+            0xF0, 0xFE,       // C005 BEQ $C005
+            0x40,             // C007 RTI
+            0xAB, 0xCD,       // C008 data
+        ];
+        let bank = bank_with_vectors(&program, 0xC000, 0xC007, 0xC007);
+        let mut disassembler = Disassembler::new(&bank, 0xC000);
+        disassembler.analyze_vectors();
+        let output = disassembler.to_ca65();
+        let lines: Vec<&str> = output.lines().map(|line| line.trim_end()).collect();
+        assert_eq!(lines[0], ".setcpu \"6502\"");
+        assert_eq!(lines[1], ".org $C000");
+        assert_eq!(lines[3], "reset:");
+        assert_eq!(lines[4], "        LDA #$10                ; $C000");
+        assert_eq!(lines[5], "        STA a:$0010             ; $C002");
+        assert_eq!(lines[6], "LC005:");
+        assert_eq!(lines[7], "        BEQ LC005               ; $C005");
+        assert_eq!(lines[8], "nmi:");
+        assert_eq!(lines[9], "        RTI                     ; $C007");
+        assert_eq!(lines[10], "        .byte $AB,$CD,$FF,$FF,$FF,$FF,$FF,$FF ; $C008");
+        assert_eq!(lines[lines.len() - 1], "        .word nmi, reset, nmi");
+    }
+
+    #[test]
+    fn test_unofficial_opcodes_are_emitted_as_bytes() {
+        let program = [0xA7, 0x10, 0x60];
+        let bank = bank_with_vectors(&program, 0xC000, 0xC000, 0xC000);
+        let mut disassembler = Disassembler::new(&bank, 0xC000);
+        disassembler.follow_unofficial = true;
+        disassembler.analyze_vectors();
+        let output = disassembler.to_ca65();
+        assert!(output.contains("        .byte $A7,$10           ; $C000 LAX $10\n"));
+        assert!(output.contains("        RTS                     ; $C002\n"));
+    }
+
+    #[test]
+    fn test_from_prg_bank() {
+        let prg_rom = vec![0; 0x4000];
+        assert!(Disassembler::from_prg_bank(&prg_rom, 0).unwrap().contains(0xC000));
+        assert!(!Disassembler::from_prg_bank(&prg_rom, 0).unwrap().contains(0xBFFF));
+        assert!(Disassembler::from_prg_bank(&prg_rom, 1).is_err());
+
+        let prg_rom = vec![0; 0x4000 * 8];
+        assert!(Disassembler::from_prg_bank(&prg_rom, 3).unwrap().contains(0x8000));
+        assert!(Disassembler::from_prg_bank(&prg_rom, 7).unwrap().contains(0xFFFF));
+        assert!(Disassembler::from_prg_bank(&prg_rom, 8).is_err());
+
+        assert!(Disassembler::from_prg_bank(&[], 0).is_err());
+        assert!(Disassembler::from_prg_bank(&[0; 0x100], 0).is_err());
+    }
+}
@@ -1,6 +1,14 @@
 pub mod cpu;
 pub mod ppu;
+pub mod apu;
 pub mod memory;
 pub mod common;
-mod cartridge;
+pub mod cartridge;
+pub mod controller;
+pub mod disassembler;
+pub mod assembler;
+pub mod debugger;
+pub mod savestate;
+pub mod rewind;
+pub mod movie;
 mod test;
@@ -1,25 +1,220 @@
 use std::fs::File;
 use std::io::Write;
 use crate::memory::memory::Memory;
+use crate::apu::APU;
 use crate::cartridge::rom::Rom;
 use crate::common::errors::EmulatorError;
-use crate::common::constants::{PPU_END, PPU_START, RAM_END, RAM_SIZE, RAM_START, PRG_ROM_START, PRG_ROM_END, PRG_ROM_PAGE_SIZE};
+use crate::common::constants::{APU_END, APU_START, APU_STATUS, CONTROLLER_1, CONTROLLER_2, OAM_DMA, OAM_DMA_CYCLES, PPU_DOTS_PER_CPU_CYCLE, PPU_END, PPU_START, RAM_END, RAM_SIZE, RAM_START, PRG_RAM_END, PRG_RAM_SIZE, PRG_RAM_START, PRG_ROM_START, PRG_ROM_END, PRG_ROM_PAGE_SIZE};
+use crate::common::util::{crc32, md5};
+use crate::controller::Controller;
+#[cfg(test)]
+use crate::debugger::AccessKind;
+use crate::ppu::PPU;
+use crate::savestate::{SaveState, Section};
+
+/// Bits of $4015 the APU leaves undriven.
+const APU_STATUS_OPEN_BUS: u8 = 0b0010_0000;
+/// Bits of $4016 and $4017 a standard controller leaves undriven.
+const CONTROLLER_OPEN_BUS: u8 = 0b1110_0000;
 
 pub struct Bus {
    cpu_ram: [u8; RAM_SIZE],
-   rom: Option<Rom>
+   prg_ram: [u8; PRG_RAM_SIZE],
+   rom: Option<Rom>,
+   rom_crc: u32,
+   rom_md5: [u8; 16],
+   controllers: [Controller; 2],
+   ppu: PPU,
+   apu: APU,
+   dma_cycles: u64,
+   /// The last value on the CPU's data bus, which unmapped reads return.
+   open_bus: u8,
+   /// Fails unmapped accesses with `AccessViolation` instead, to catch stray pointers.
+   strict_access: bool,
+   /// Every read and write in order, once a test that checks bus cycles sets it to `Some`.
+   #[cfg(test)]
+   pub(crate) accesses: Option<Vec<(u16, AccessKind)>>,
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Bus {
    pub fn new() -> Self{
        Bus {
            cpu_ram: [0; RAM_SIZE],
-           rom: None
+           prg_ram: [0; PRG_RAM_SIZE],
+           rom: None,
+           rom_crc: 0,
+           rom_md5: [0; 16],
+           controllers: [Controller::new(), Controller::new()],
+           ppu: PPU::new(),
+           apu: APU::new(),
+           dma_cycles: 0,
+           open_bus: 0,
+           strict_access: false,
+           #[cfg(test)]
+           accesses: None,
        }
    }
 
     pub fn load_rom(&mut self, rom: Rom) {
-         self.rom = Some(rom);
+        let contents = [rom.prg_rom.as_slice(), rom.chr_rom.as_slice()].concat();
+        self.rom_crc = crc32(&contents);
+        self.rom_md5 = md5(&contents);
+        self.ppu.load_chr(&rom.chr_rom, rom.mirroring);
+        self.rom = Some(rom);
+    }
+
+    pub fn rom_crc(&self) -> u32 {
+        self.rom_crc
+    }
+
+    pub fn rom_md5(&self) -> [u8; 16] {
+        self.rom_md5
+    }
+
+    pub fn controller(&mut self, port: usize) -> &mut Controller {
+        &mut self.controllers[port]
+    }
+
+    /// The buttons held on both controllers.
+    pub fn buttons(&self) -> [u8; 2] {
+        self.controllers.each_ref().map(Controller::buttons)
+    }
+
+    pub fn power_on(&mut self) {
+        self.cpu_ram = [0; RAM_SIZE];
+        self.prg_ram = [0; PRG_RAM_SIZE];
+        self.controllers = [Controller::new(), Controller::new()];
+        self.ppu.power_on();
+        self.apu.power_on();
+        self.dma_cycles = 0;
+        self.open_bus = 0;
+    }
+
+    pub fn strict_access(&self) -> bool {
+        self.strict_access
+    }
+
+    pub fn set_strict_access(&mut self, strict: bool) {
+        self.strict_access = strict;
+    }
+
+    fn unmapped(&self, address: u16) -> Result<u8, EmulatorError> {
+        if self.strict_access {
+            Err(EmulatorError::AccessViolation(address))
+        } else {
+            Ok(self.open_bus)
+        }
+    }
+
+    pub fn ppu(&self) -> &PPU {
+        &self.ppu
+    }
+
+    pub fn ppu_mut(&mut self) -> &mut PPU {
+        &mut self.ppu
+    }
+
+    pub fn apu(&mut self) -> &mut APU {
+        &mut self.apu
+    }
+
+    /// Advances the PPU and the APU by `cycles` CPU cycles.
+    pub(crate) fn tick(&mut self, cycles: u64) {
+        self.ppu.tick(cycles * PPU_DOTS_PER_CPU_CYCLE);
+        let rom = &self.rom;
+        self.dma_cycles += self.apu.tick(cycles, |address| rom.as_ref().map_or(0, |rom| read_prg_rom(rom, address)));
+    }
+
+    pub(crate) fn poll_nmi(&mut self) -> bool {
+        self.ppu.poll_nmi()
+    }
+
+    /// Whether an NMI is waiting, without acknowledging it.
+    pub(crate) fn nmi_pending(&self) -> bool {
+        self.ppu.nmi_pending()
+    }
+
+    /// The IRQ line, held by the APU's frame counter and DMC until acknowledged.
+    pub(crate) fn irq(&self) -> bool {
+        self.apu.frame_irq() || self.apu.dmc_irq()
+    }
+
+    /// CPU cycles stolen by OAM and DMC DMA since the last call.
+    pub(crate) fn take_dma_cycles(&mut self) -> u64 {
+        std::mem::take(&mut self.dma_cycles)
+    }
+
+    fn oam_dma(&mut self, page: u8) -> Result<(), EmulatorError> {
+        let mut data = [0; 256];
+        for (offset, byte) in data.iter_mut().enumerate() {
+            *byte = self.read(u16::from_le_bytes([offset as u8, page]))?;
+        }
+        self.ppu.write_oam_dma(&data);
+        self.dma_cycles += OAM_DMA_CYCLES;
+        Ok(())
+    }
+
+    pub(crate) fn save_state(&self, state: &mut SaveState) {
+        let mut ram = Section::new(*b"RAM ");
+        ram.write_bytes(&self.cpu_ram);
+        ram.write_u8(self.open_bus);
+        state.add_section(ram);
+        let mut prg_ram = Section::new(*b"PRAM");
+        prg_ram.write_bytes(&self.prg_ram);
+        state.add_section(prg_ram);
+        let mut controllers = Section::new(*b"CTRL");
+        for controller in &self.controllers {
+            let (buttons, strobe, shift) = controller.state();
+            controllers.write_u8(buttons);
+            controllers.write_bool(strobe);
+            controllers.write_u8(shift);
+        }
+        state.add_section(controllers);
+        self.ppu.save_state(state);
+        self.apu.save_state(state);
+    }
+
+    pub(crate) fn load_state(&mut self, state: &SaveState) -> Result<(), EmulatorError> {
+        let mut ram = state.section(*b"RAM ")?;
+        let cpu_ram = ram.read_array()?;
+        let open_bus = ram.read_u8()?;
+        ram.finish()?;
+        let mut prg_ram = state.section(*b"PRAM")?;
+        let cart_ram = prg_ram.read_array()?;
+        prg_ram.finish()?;
+        let mut controllers = state.section(*b"CTRL")?;
+        let mut controller_states = Vec::new();
+        for _ in 0..self.controllers.len() {
+            controller_states.push((controllers.read_u8()?, controllers.read_bool()?, controllers.read_u8()?));
+        }
+        controllers.finish()?;
+        let apu = self.apu.decode_state(state)?;
+        // The PPU only changes once its whole section has decoded, so it goes last.
+        self.ppu.load_state(state)?;
+        self.apu.restore(apu);
+        self.cpu_ram = cpu_ram;
+        self.open_bus = open_bus;
+        self.prg_ram = cart_ram;
+        for (controller, (buttons, strobe, shift)) in self.controllers.iter_mut().zip(controller_states) {
+            controller.restore(buttons, strobe, shift);
+        }
+        Ok(())
+    }
+
+    pub fn prg_bank(&self, address: u16) -> Option<usize> {
+        match (&self.rom, address) {
+            (Some(rom), PRG_ROM_START ..= PRG_ROM_END) if !rom.prg_rom.is_empty() => {
+                let v_address = (address - PRG_ROM_START) as usize % rom.prg_rom.len();
+                Some(v_address / PRG_ROM_PAGE_SIZE)
+            }
+            _ => None,
+        }
     }
 
     pub fn dump_memory(&self) {
@@ -28,58 +223,74 @@ impl Bus {
         for i in 0..RAM_SIZE {
             dump.push_str(&format!("\n{:0>4x}: {:0>2X} ", i, self.cpu_ram[i]));
         }
-        for i in 0..prg_rom.len() {
-            dump.push_str(&format!("\n{:0>4x}: {:0>2X} ", i + PRG_ROM_START as usize, prg_rom[i]));
+        for (i, byte) in prg_rom.iter().enumerate() {
+            dump.push_str(&format!("\n{:0>4x}: {:0>2X} ", i + PRG_ROM_START as usize, byte));
         }
         let mut file = File::create("../dump.txt").expect("TODO: panic message");
         let _ = file.write_all(dump.as_bytes());
     }
 }
 
+/// Reads PRG ROM, mirroring a single 16KB bank into both halves of $8000-$FFFF.
+fn read_prg_rom(rom: &Rom, address: u16) -> u8 {
+    let v_address = (address - PRG_ROM_START) as usize;
+    if rom.prg_rom.len() == PRG_ROM_PAGE_SIZE {
+        rom.prg_rom[v_address % PRG_ROM_PAGE_SIZE]
+    }
+    else {
+        rom.prg_rom[v_address]
+    }
+}
+
 impl Memory for Bus {
-    fn read(&self, address: u16) -> Result<u8, EmulatorError> {
+    fn read(&mut self, address: u16) -> Result<u8, EmulatorError> {
+        let value = match address {
+            PPU_START ..= PPU_END => self.ppu.read_register(address),
+            APU_STATUS => self.apu.read_status() | self.open_bus & APU_STATUS_OPEN_BUS,
+            CONTROLLER_1 => self.controllers[0].read() | self.open_bus & CONTROLLER_OPEN_BUS,
+            CONTROLLER_2 => self.controllers[1].read() | self.open_bus & CONTROLLER_OPEN_BUS,
+            _ => self.peek(address)?,
+        };
+        // $4015 is inside the 2A03, so reading it doesn't drive the external data bus.
+        if address != APU_STATUS {
+            self.open_bus = value;
+        }
+        #[cfg(test)]
+        if let Some(accesses) = &mut self.accesses {
+            accesses.push((address, AccessKind::Read));
+        }
+        Ok(value)
+    }
+
+    fn peek(&self, address: u16) -> Result<u8, EmulatorError> {
         match address {
             RAM_START ..= RAM_END => {
                 let mirror_address = (address % RAM_SIZE as u16) as usize;
                 Ok(self.cpu_ram[mirror_address])
             }
-            PPU_START ..= PPU_END => {
-                Err(EmulatorError::AccessViolation(address))
+            PPU_START ..= PPU_END => Ok(self.ppu.peek_register(address)),
+            APU_STATUS => Ok(self.apu.peek_status() | self.open_bus & APU_STATUS_OPEN_BUS),
+            CONTROLLER_1 => Ok(self.controllers[0].peek() | self.open_bus & CONTROLLER_OPEN_BUS),
+            CONTROLLER_2 => Ok(self.controllers[1].peek() | self.open_bus & CONTROLLER_OPEN_BUS),
+            PRG_RAM_START ..= PRG_RAM_END => {
+                Ok(self.prg_ram[(address - PRG_RAM_START) as usize])
             }
             PRG_ROM_START ..= PRG_ROM_END => {
-                let v_address = address - PRG_ROM_START;
                 match &self.rom {
-                    Some(rom) => Ok({
-                        if rom.prg_rom.len() == PRG_ROM_PAGE_SIZE && v_address >= PRG_ROM_PAGE_SIZE as u16 {
-                            let v_address = v_address % PRG_ROM_PAGE_SIZE as u16;
-                            rom.prg_rom[v_address as usize]
-                        }
-                        else {
-                            rom.prg_rom[v_address as usize]
-                        }
-                    }),
+                    Some(rom) => Ok(read_prg_rom(rom, address)),
                     None => Err(EmulatorError::RomNotLoaded)
                 }
             }
-            _ => {
-                Err(EmulatorError::AccessViolation(address))
-            }
+            _ => self.unmapped(address),
         }
     }
 
-    fn read_u16(&self, address: u16) -> Result<u16, EmulatorError> {
-        let low_byte = self.read(address)?;
-        let high_byte = self.read(address.wrapping_add(1))?;
-        Ok(u16::from_le_bytes([low_byte, high_byte]))
-    }
-
-    fn read_u16_zero_page(&self, address: u8) -> Result<u16, EmulatorError> {
-        let low_byte = self.read(address as u16)?;
-        let high_byte = self.read(address.wrapping_add(1) as u16)?;
-        Ok(u16::from_le_bytes([low_byte, high_byte]))
-    }
-
     fn write(&mut self, address: u16, data: u8) -> Result<(), EmulatorError> {
+        self.open_bus = data;
+        #[cfg(test)]
+        if let Some(accesses) = &mut self.accesses {
+            accesses.push((address, AccessKind::Write));
+        }
         match address {
             RAM_START ..= RAM_END => {
                 let mirror_address = (address % RAM_SIZE as u16) as usize;
@@ -87,34 +298,38 @@ impl Memory for Bus {
                 Ok(())
             }
             PPU_START ..= PPU_END => {
-                Err(EmulatorError::AccessViolation(address))
+                self.ppu.write_register(address, data);
+                Ok(())
+            }
+            OAM_DMA => self.oam_dma(data),
+            CONTROLLER_1 => {
+                self.controllers.iter_mut().for_each(|controller| controller.write(data));
+                Ok(())
+            }
+            APU_START ..= APU_END | APU_STATUS | CONTROLLER_2 => {
+                self.apu.write_register(address, data);
+                Ok(())
+            }
+            PRG_RAM_START ..= PRG_RAM_END => {
+                self.prg_ram[(address - PRG_RAM_START) as usize] = data;
+                Ok(())
             }
             PRG_ROM_START ..= PRG_ROM_END => {
                 let v_address = address - PRG_ROM_START;
                 match &mut self.rom {
-                    Some(rom) => Ok({
+                    Some(rom) => {
                         if rom.prg_rom.len() == PRG_ROM_PAGE_SIZE && v_address >= PRG_ROM_PAGE_SIZE as u16 {
                             rom.prg_rom[(v_address % PRG_ROM_PAGE_SIZE as u16) as usize] = data;
                         }
                         else {
                             rom.prg_rom[v_address as usize] = data;
                         }
-                    }),
+                        Ok(())
+                    }
                     None => Err(EmulatorError::RomNotLoaded)
                 }
             }
-            _ => {
-                Err(EmulatorError::AccessViolation(address))
-            }
+            _ => self.unmapped(address).map(|_| ()),
         }
     }
-
-    fn write_u16(&mut self, address: u16, value: u16) -> Result<(), EmulatorError> {
-        let low_byte = (value & 0xFF) as u8;
-        let high_byte = (value >> 8) as u8;
-
-        self.write(address, low_byte)?;
-        self.write(address + 1, high_byte)?;
-        Ok(())
-    }
 }
\ No newline at end of file
@@ -1,9 +1,41 @@
 use crate::common::errors::EmulatorError;
 
+/// The CPU's view of the address space. `read` is what the CPU does, with the side effects some
+/// registers have on being read; `peek` returns the same value without them, for debuggers,
+/// loggers and other observers.
 pub trait Memory {
-    fn read(&self, address: u16) -> Result<u8, EmulatorError>;
-    fn read_u16(&self, address: u16) -> Result<u16, EmulatorError>;
-    fn read_u16_zero_page(&self, address: u8) -> Result<u16, EmulatorError>;
+    fn read(&mut self, address: u16) -> Result<u8, EmulatorError>;
+    fn peek(&self, address: u16) -> Result<u8, EmulatorError>;
     fn write(&mut self, address: u16, value: u8) -> Result<(), EmulatorError>;
-    fn write_u16(&mut self, address: u16, value: u16) -> Result<(), EmulatorError>;
-}
\ No newline at end of file
+
+    fn read_u16(&mut self, address: u16) -> Result<u16, EmulatorError> {
+        let low_byte = self.read(address)?;
+        let high_byte = self.read(address.wrapping_add(1))?;
+        Ok(u16::from_le_bytes([low_byte, high_byte]))
+    }
+
+    /// Reads a pointer from the zero page, wrapping from $FF to $00 for the high byte.
+    fn read_u16_zero_page(&mut self, address: u8) -> Result<u16, EmulatorError> {
+        let low_byte = self.read(address as u16)?;
+        let high_byte = self.read(address.wrapping_add(1) as u16)?;
+        Ok(u16::from_le_bytes([low_byte, high_byte]))
+    }
+
+    fn peek_u16(&self, address: u16) -> Result<u16, EmulatorError> {
+        let low_byte = self.peek(address)?;
+        let high_byte = self.peek(address.wrapping_add(1))?;
+        Ok(u16::from_le_bytes([low_byte, high_byte]))
+    }
+
+    fn peek_u16_zero_page(&self, address: u8) -> Result<u16, EmulatorError> {
+        let low_byte = self.peek(address as u16)?;
+        let high_byte = self.peek(address.wrapping_add(1) as u16)?;
+        Ok(u16::from_le_bytes([low_byte, high_byte]))
+    }
+
+    fn write_u16(&mut self, address: u16, value: u16) -> Result<(), EmulatorError> {
+        let [low_byte, high_byte] = value.to_le_bytes();
+        self.write(address, low_byte)?;
+        self.write(address.wrapping_add(1), high_byte)
+    }
+}
@@ -1,2 +1,3 @@
 pub mod bus;
+#[allow(clippy::module_inception)]
 pub mod memory;
\ No newline at end of file
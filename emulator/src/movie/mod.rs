@@ -0,0 +1,235 @@
+mod test;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::common::errors::EmulatorError;
+use crate::common::util::{base64_decode, base64_encode, md5};
+use crate::cpu::CPU;
+
+const FM2_BUTTONS: &[u8; 8] = b"RLDUTSBA";
+const COMMAND_SOFT_RESET: u8 = 0b0000_0001;
+const COMMAND_HARD_RESET: u8 = 0b0000_0010;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct MovieFrame {
+    pub commands: u8,
+    pub ports: [u8; 2],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Movie {
+    pub rom_filename: String,
+    pub rom_checksum: [u8; 16],
+    pub guid: String,
+    pub rerecord_count: u32,
+    pub pal: bool,
+    pub comments: Vec<String>,
+    /// Our own save state format; movies starting from an FCEUX save state cannot be imported.
+    pub save_state: Option<Vec<u8>>,
+    pub frames: Vec<MovieFrame>,
+}
+
+impl Movie {
+    pub fn new(rom_checksum: [u8; 16]) -> Self {
+        Movie {
+            rom_filename: String::new(),
+            rom_checksum,
+            guid: generate_guid(&rom_checksum),
+            rerecord_count: 0,
+            pal: false,
+            comments: Vec::new(),
+            save_state: None,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn from_fm2(text: &str) -> Result<Movie, EmulatorError> {
+        let mut movie = Movie::new([0; 16]);
+        let mut has_checksum = false;
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let invalid = |message: String| EmulatorError::InvalidMovie(line_number, message);
+            let line = line.trim_end_matches('\r');
+            if line.starts_with('|') {
+                movie.frames.push(parse_frame(line).ok_or_else(|| invalid(format!("invalid input line '{}'", line)))?);
+                continue;
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            let number = || value.parse::<u32>().map_err(|_| invalid(format!("invalid value for {}: {}", key, value)));
+            match key {
+                "version" if number()? != 3 => return Err(invalid(format!("unsupported version {}", value))),
+                "rerecordCount" => movie.rerecord_count = number()?,
+                "palFlag" => movie.pal = number()? != 0,
+                "romFilename" => movie.rom_filename = value.to_string(),
+                "guid" => movie.guid = value.to_string(),
+                "comment" => movie.comments.push(value.to_string()),
+                "romChecksum" => {
+                    let checksum = decode_base64_field(value).filter(|checksum| checksum.len() == 16)
+                        .ok_or_else(|| invalid(format!("invalid ROM checksum {}", value)))?;
+                    movie.rom_checksum.copy_from_slice(&checksum);
+                    has_checksum = true;
+                }
+                "savestate" => {
+                    let state = decode_base64_field(value).ok_or_else(|| invalid("invalid save state".to_string()))?;
+                    movie.save_state = Some(state);
+                }
+                "fourscore" | "port2" | "FDS" if number()? != 0 => {
+                    return Err(invalid(format!("unsupported {} {}", key, value)));
+                }
+                "port0" | "port1" if number()? > 1 => return Err(invalid(format!("unsupported {} device {}", key, value))),
+                _ => {}
+            }
+        }
+        if !has_checksum {
+            return Err(EmulatorError::InvalidMovie(0, "missing romChecksum".to_string()));
+        }
+        Ok(movie)
+    }
+
+    pub fn to_fm2(&self) -> String {
+        let mut text = String::new();
+        text.push_str("version 3\n");
+        text.push_str("emuVersion 0\n");
+        text.push_str(&format!("rerecordCount {}\n", self.rerecord_count));
+        text.push_str(&format!("palFlag {}\n", self.pal as u8));
+        text.push_str(&format!("romFilename {}\n", self.rom_filename));
+        text.push_str(&format!("romChecksum base64:{}\n", base64_encode(&self.rom_checksum)));
+        text.push_str(&format!("guid {}\n", self.guid));
+        text.push_str("fourscore 0\nmicrophone 0\nport0 1\nport1 1\nport2 0\nFDS 0\nNewPPU 0\n");
+        for comment in &self.comments {
+            text.push_str(&format!("comment {}\n", comment));
+        }
+        if let Some(state) = &self.save_state {
+            text.push_str(&format!("savestate base64:{}\n", base64_encode(state)));
+        }
+        for frame in &self.frames {
+            text.push_str(&format!("|{}|{}|{}||\n", frame.commands, format_buttons(frame.ports[0]), format_buttons(frame.ports[1])));
+        }
+        text
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MovieMode {
+    /// Plays the recorded input back; live input is ignored until the movie ends.
+    ReadOnly,
+    /// Records live input, truncating the movie when an earlier state is loaded.
+    ReadWrite,
+}
+
+pub struct MovieSession {
+    pub movie: Movie,
+    pub mode: MovieMode,
+    start_frame: u64,
+}
+
+impl MovieSession {
+    /// Starts recording from power-on, or from the current state when `from_save_state` is set.
+    pub fn record(cpu: &mut CPU, from_save_state: bool) -> Result<MovieSession, EmulatorError> {
+        let mut movie = Movie::new(cpu.bus.rom_md5());
+        if from_save_state {
+            movie.save_state = Some(cpu.save_state());
+        } else {
+            cpu.power_on()?;
+        }
+        Ok(MovieSession { movie, mode: MovieMode::ReadWrite, start_frame: cpu.frame() })
+    }
+
+    pub fn play(cpu: &mut CPU, movie: Movie, mode: MovieMode) -> Result<MovieSession, EmulatorError> {
+        if movie.rom_checksum != cpu.bus.rom_md5() {
+            return Err(EmulatorError::MovieRomMismatch);
+        }
+        match &movie.save_state {
+            Some(state) => cpu.load_state(state)?,
+            None => cpu.power_on()?,
+        }
+        Ok(MovieSession { movie, mode, start_frame: cpu.frame() })
+    }
+
+    /// Index of the frame about to be emulated; follows save states loaded during the session.
+    pub fn frame_index(&self, cpu: &CPU) -> usize {
+        cpu.frame().saturating_sub(self.start_frame) as usize
+    }
+
+    pub fn is_finished(&self, cpu: &CPU) -> bool {
+        self.mode == MovieMode::ReadOnly && self.frame_index(cpu) >= self.movie.frames.len()
+    }
+
+    /// Call before each frame. Feeds the recorded input in read-only mode, or records `input` in
+    /// read-write mode. Once a read-only movie is finished, `input` is passed through.
+    pub fn apply_input(&mut self, cpu: &mut CPU, input: [u8; 2]) -> Result<(), EmulatorError> {
+        let index = self.frame_index(cpu);
+        let frame = match self.mode {
+            MovieMode::ReadOnly => match self.movie.frames.get(index) {
+                Some(frame) => *frame,
+                None => MovieFrame { commands: 0, ports: input },
+            },
+            MovieMode::ReadWrite => {
+                if index < self.movie.frames.len() {
+                    self.movie.frames.truncate(index);
+                    self.movie.rerecord_count += 1;
+                }
+                let frame = MovieFrame { commands: 0, ports: input };
+                self.movie.frames.resize(index, MovieFrame::default());
+                self.movie.frames.push(frame);
+                frame
+            }
+        };
+        if frame.commands & COMMAND_HARD_RESET != 0 {
+            // The frame count carries on through the power cycle, since it's what the position
+            // in the movie is kept in.
+            let frame_count = cpu.frame();
+            cpu.power_on()?;
+            cpu.ppu_mut().set_frame(frame_count);
+        } else if frame.commands & COMMAND_SOFT_RESET != 0 {
+            cpu.reset()?;
+        }
+        cpu.controller(0).set_buttons(frame.ports[0]);
+        cpu.controller(1).set_buttons(frame.ports[1]);
+        Ok(())
+    }
+}
+
+fn parse_frame(line: &str) -> Option<MovieFrame> {
+    let fields: Vec<&str> = line.split('|').collect();
+    if fields.len() < 4 || !fields[0].is_empty() {
+        return None;
+    }
+    let commands = if fields[1].is_empty() { 0 } else { fields[1].parse().ok()? };
+    Some(MovieFrame { commands, ports: [parse_buttons(fields[2])?, parse_buttons(fields[3])?] })
+}
+
+fn parse_buttons(field: &str) -> Option<u8> {
+    if field.is_empty() {
+        return Some(0);
+    }
+    if field.len() != FM2_BUTTONS.len() {
+        return None;
+    }
+    Some(field.bytes().enumerate().fold(0, |buttons, (i, c)| match c {
+        b'.' | b' ' => buttons,
+        _ => buttons | 0x80 >> i,
+    }))
+}
+
+fn format_buttons(buttons: u8) -> String {
+    FM2_BUTTONS.iter().enumerate()
+        .map(|(i, c)| if buttons & 0x80 >> i != 0 { *c as char } else { '.' })
+        .collect()
+}
+
+fn decode_base64_field(value: &str) -> Option<Vec<u8>> {
+    match value.strip_prefix("base64:") {
+        Some(encoded) => base64_decode(encoded),
+        None => (0..value.len()).step_by(2).map(|i| u8::from_str_radix(value.get(i..i + 2)?, 16).ok()).collect(),
+    }
+}
+
+fn generate_guid(seed: &[u8]) -> String {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_nanos()).unwrap_or(0);
+    let hash = md5(&[seed, &nanos.to_le_bytes()].concat());
+    let hex: String = hash.iter().map(|byte| format!("{:02X}", byte)).collect();
+    format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+}
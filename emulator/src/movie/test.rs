@@ -0,0 +1,191 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod test {
+    use crate::asm;
+    use crate::common::constants::PRG_ROM_START;
+    use crate::common::errors::EmulatorError;
+    use crate::common::util::{base64_decode, base64_encode, md5};
+    use crate::controller::{BUTTON_A, BUTTON_LEFT, BUTTON_START};
+    use crate::cpu::CPU;
+    use crate::memory::memory::Memory;
+    use crate::movie::{Movie, MovieFrame, MovieMode, MovieSession};
+
+    fn input_program() -> Vec<u8> {
+        asm!(
+            "loop:   LDA #$01",
+            "        STA $4016",
+            "        LDA #$00",
+            "        STA $4016",
+            "        LDX #$08",
+            "read:   LDA $4016",
+            "        LSR A",
+            "        ROL $10",
+            "        DEX",
+            "        BNE read",
+            "        LDA $10",
+            "        CLC",
+            "        ADC $11",
+            "        STA $11",
+            "        JMP loop",
+        )
+    }
+
+    fn run_frames(cpu: &mut CPU, session: &mut MovieSession, inputs: &[[u8; 2]]) {
+        for input in inputs {
+            session.apply_input(cpu, *input).unwrap();
+            cpu.run_frame(|_| Ok(())).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_md5_and_base64() {
+        let hex = |digest: [u8; 16]| digest.iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+        assert_eq!(hex(md5(b"")), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(hex(md5(b"abc")), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(hex(md5(&[b'a'; 100])), "36a92cc94a9e0fa21f625f8bfb007adf");
+        assert_eq!(base64_encode(b"Man"), "TWFu");
+        assert_eq!(base64_encode(b"Ma"), "TWE=");
+        assert_eq!(base64_encode(b"M"), "TQ==");
+        assert_eq!(base64_decode("TWE=").unwrap(), b"Ma");
+        assert!(base64_decode("T!==").is_none());
+    }
+
+    #[test]
+    fn test_parse_fceux_movie() {
+        let text = "version 3\nemuVersion 22020\nrerecordCount 7\npalFlag 0\nromFilename smb\n\
+            romChecksum base64:jjYwGG411HcjG/j9UOVM3Q==\nguid 51473540-E9D7-11E3-A0E8-44634B5CB4D9\n\
+            fourscore 0\nmicrophone 0\nport0 1\nport1 1\nport2 0\nFDS 0\nNewPPU 0\ncomment author someone\n\
+            |1|........|........||\n|0|R......A|.L......||\n|0|...UT...|........||\n";
+        let movie = Movie::from_fm2(text).unwrap();
+        assert_eq!(movie.rerecord_count, 7);
+        assert_eq!(movie.rom_filename, "smb");
+        assert_eq!(movie.rom_checksum[..4], [0x8E, 0x36, 0x30, 0x18]);
+        assert_eq!(movie.comments, vec!["author someone"]);
+        assert_eq!(movie.frames, vec![
+            MovieFrame { commands: 1, ports: [0, 0] },
+            MovieFrame { commands: 0, ports: [0x81, 0x40] },
+            MovieFrame { commands: 0, ports: [0x18, 0] },
+        ]);
+
+        assert!(matches!(Movie::from_fm2("version 3\n"), Err(EmulatorError::InvalidMovie(0, _))));
+        assert!(matches!(Movie::from_fm2("version 2\n"), Err(EmulatorError::InvalidMovie(1, _))));
+        let bad_input = "romChecksum base64:jjYwGG411HcjG/j9UOVM3Q==\n|0|RLD|........||\n";
+        assert!(matches!(Movie::from_fm2(bad_input), Err(EmulatorError::InvalidMovie(2, _))));
+        let fourscore = "romChecksum base64:jjYwGG411HcjG/j9UOVM3Q==\nfourscore 1\n";
+        assert!(matches!(Movie::from_fm2(fourscore), Err(EmulatorError::InvalidMovie(2, _))));
+    }
+
+    #[test]
+    fn test_fm2_round_trip() {
+        let mut movie = Movie::new(md5(b"rom"));
+        movie.rom_filename = "game.nes".to_string();
+        movie.rerecord_count = 3;
+        movie.comments.push("author tester".to_string());
+        movie.save_state = Some(vec![1, 2, 3, 4, 5]);
+        movie.frames = vec![
+            MovieFrame { commands: 0, ports: [BUTTON_A | BUTTON_LEFT, 0] },
+            MovieFrame { commands: 1, ports: [0, BUTTON_START] },
+        ];
+        let text = movie.to_fm2();
+        assert!(text.contains("|0|.L.....A|........||\n|1|........|....T...||\n"));
+        assert_eq!(Movie::from_fm2(&text).unwrap(), movie);
+    }
+
+    #[test]
+    fn test_record_and_play_back() {
+        let inputs = [[BUTTON_A, 0], [0, 0], [BUTTON_START | BUTTON_LEFT, BUTTON_A], [0xFF, 0], [0, 0]];
+        let mut cpu = CPU::with_program(&input_program());
+        cpu.write(0x11, 0x55).unwrap();
+        let mut session = MovieSession::record(&mut cpu, false).unwrap();
+        assert_eq!(cpu.read(0x11).unwrap(), 0);
+        run_frames(&mut cpu, &mut session, &inputs);
+        assert_eq!(session.movie.frames.len(), inputs.len());
+        let recorded = cpu.save_state();
+        let movie = Movie::from_fm2(&session.movie.to_fm2()).unwrap();
+
+        let mut cpu = CPU::with_program(&input_program());
+        let mut session = MovieSession::play(&mut cpu, movie, MovieMode::ReadOnly).unwrap();
+        run_frames(&mut cpu, &mut session, &[[0, 0]; 5]);
+        assert!(session.is_finished(&cpu));
+        assert_eq!(cpu.save_state(), recorded);
+    }
+
+    #[test]
+    fn test_play_back_resets() {
+        let inputs = [[BUTTON_A, 0], [BUTTON_A, 0], [BUTTON_LEFT, 0], [BUTTON_A, 0], [BUTTON_START, 0]];
+        for commands in [1, 2] {
+            let mut cpu = CPU::with_program(&input_program());
+            let mut session = MovieSession::record(&mut cpu, false).unwrap();
+            run_frames(&mut cpu, &mut session, &inputs);
+            let mut movie = session.movie;
+            movie.frames[2].commands = commands;
+
+            let mut expected = CPU::with_program(&input_program());
+            expected.power_on().unwrap();
+            expected.write(0x6000, 0x42).unwrap();
+            for (frame, input) in inputs.iter().enumerate() {
+                if frame == 2 && commands == 1 {
+                    expected.reset().unwrap();
+                } else if frame == 2 {
+                    expected.power_on().unwrap();
+                    expected.ppu_mut().set_frame(2);
+                }
+                expected.controller(0).set_buttons(input[0]);
+                expected.run_frame(|_| Ok(())).unwrap();
+            }
+
+            let mut session = MovieSession::play(&mut cpu, movie, MovieMode::ReadOnly).unwrap();
+            cpu.write(0x6000, 0x42).unwrap();
+            run_frames(&mut cpu, &mut session, &[[0, 0]; 5]);
+            assert!(session.is_finished(&cpu));
+            assert_eq!(cpu.save_state(), expected.save_state(), "command {}", commands);
+            // A soft reset leaves RAM alone; a power cycle clears it, PRG RAM included.
+            assert_eq!(cpu.read(0x6000).unwrap(), if commands == 2 { 0 } else { 0x42 });
+        }
+    }
+
+    #[test]
+    fn test_record_from_save_state() {
+        let mut cpu = CPU::with_program(&input_program());
+        cpu.program_counter = PRG_ROM_START;
+        cpu.run_frame(|_| Ok(())).unwrap();
+        let mut session = MovieSession::record(&mut cpu, true).unwrap();
+        run_frames(&mut cpu, &mut session, &[[BUTTON_A, 0], [0x0F, 0]]);
+        let recorded = cpu.save_state();
+
+        let mut cpu = CPU::with_program(&input_program());
+        let mut session = MovieSession::play(&mut cpu, session.movie, MovieMode::ReadOnly).unwrap();
+        assert_eq!(session.frame_index(&cpu), 0);
+        run_frames(&mut cpu, &mut session, &[[0, 0]; 2]);
+        assert_eq!(cpu.save_state(), recorded);
+    }
+
+    #[test]
+    fn test_rerecording() {
+        let mut cpu = CPU::with_program(&input_program());
+        let mut session = MovieSession::record(&mut cpu, false).unwrap();
+        run_frames(&mut cpu, &mut session, &[[1, 0], [2, 0]]);
+        let checkpoint = cpu.save_state();
+        run_frames(&mut cpu, &mut session, &[[3, 0], [4, 0]]);
+
+        cpu.load_state(&checkpoint).unwrap();
+        session.mode = MovieMode::ReadOnly;
+        run_frames(&mut cpu, &mut session, &[[0, 0]]);
+        assert_eq!(session.movie.frames.len(), 4);
+        assert_eq!(cpu.controller(0).buttons(), 3);
+
+        cpu.load_state(&checkpoint).unwrap();
+        session.mode = MovieMode::ReadWrite;
+        run_frames(&mut cpu, &mut session, &[[5, 0]]);
+        let ports: Vec<u8> = session.movie.frames.iter().map(|frame| frame.ports[0]).collect();
+        assert_eq!(ports, vec![1, 2, 5]);
+        assert_eq!(session.movie.rerecord_count, 1);
+    }
+
+    #[test]
+    fn test_play_rejects_other_rom() {
+        let mut cpu = CPU::with_program(&input_program());
+        let movie = Movie::new(md5(b"some other rom"));
+        assert!(matches!(MovieSession::play(&mut cpu, movie, MovieMode::ReadOnly), Err(EmulatorError::MovieRomMismatch)));
+    }
+}
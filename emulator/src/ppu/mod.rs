@@ -0,0 +1,773 @@
+pub mod palette;
+pub mod viewer;
+mod test;
+
+use crate::common::constants::{CHR_ROM_PAGE_SIZE, NAMETABLE_START, PALETTE_START, PPU_DOTS_PER_SCANLINE, PRE_RENDER_SCANLINE, SCANLINES_PER_FRAME, SCREEN_HEIGHT, SCREEN_WIDTH, VBLANK_SCANLINE, VRAM_SIZE};
+use crate::common::errors::EmulatorError;
+use crate::common::types::{Mirroring, Nametable};
+use crate::ppu::palette::SYSTEM_PALETTE;
+use crate::savestate::{SaveState, Section};
+
+const CTRL_NAMETABLE: u8 = 0b0000_0011;
+const CTRL_INCREMENT: u8 = 0b0000_0100;
+const CTRL_SPRITE_TABLE: u8 = 0b0000_1000;
+const CTRL_BACKGROUND_TABLE: u8 = 0b0001_0000;
+const CTRL_TALL_SPRITES: u8 = 0b0010_0000;
+const CTRL_NMI: u8 = 0b1000_0000;
+const MASK_GREYSCALE: u8 = 0b0000_0001;
+const MASK_BACKGROUND_LEFT: u8 = 0b0000_0010;
+const MASK_SPRITES_LEFT: u8 = 0b0000_0100;
+const MASK_BACKGROUND: u8 = 0b0000_1000;
+const MASK_SPRITES: u8 = 0b0001_0000;
+const STATUS_SPRITE_OVERFLOW: u8 = 0b0010_0000;
+const STATUS_SPRITE_ZERO_HIT: u8 = 0b0100_0000;
+const STATUS_VBLANK: u8 = 0b1000_0000;
+const STATUS_CLEARED_ON_PRE_RENDER: u8 = 0b1110_0000;
+
+// Fields of the loopy v and t registers: yyy NN YYYYY XXXXX.
+const COARSE_X: u16 = 0x001F;
+const COARSE_Y: u16 = 0x03E0;
+const NAMETABLE_X: u16 = 0x0400;
+const NAMETABLE_Y: u16 = 0x0800;
+const FINE_Y: u16 = 0x7000;
+const HORIZONTAL_BITS: u16 = NAMETABLE_X | COARSE_X;
+const VERTICAL_BITS: u16 = FINE_Y | NAMETABLE_Y | COARSE_Y;
+
+const SPRITE_PALETTE: u8 = 0b0000_0011;
+const SPRITE_BEHIND_BACKGROUND: u8 = 0b0010_0000;
+const SPRITE_FLIP_HORIZONTAL: u8 = 0b0100_0000;
+const SPRITE_FLIP_VERTICAL: u8 = 0b1000_0000;
+const SPRITES_PER_LINE: usize = 8;
+const NAMETABLE_SIZE: usize = 0x400;
+/// Marks a saved nametable mapping as a page of cartridge RAM.
+const NAMETABLE_ON_CARTRIDGE: u8 = 0x80;
+/// Bits of the register latch fade back to 0 roughly 600ms after they were last driven high.
+const OPEN_BUS_DECAY_FRAMES: u64 = 36;
+
+/// A sprite picked by evaluation for the next line, with its row of pattern data already fetched.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct LineSprite {
+    x: u8,
+    attributes: u8,
+    pattern: [u8; 2],
+    sprite_zero: bool,
+}
+
+impl LineSprite {
+    /// The 2-bit pattern value at `column` of the sprite, after horizontal flipping.
+    fn pixel(&self, column: u8) -> u8 {
+        let bit = if self.attributes & SPRITE_FLIP_HORIZONTAL != 0 { column } else { 7 - column };
+        (self.pattern[1] >> bit & 1) << 1 | self.pattern[0] >> bit & 1
+    }
+}
+
+/// Renders a dot per PPU clock with the internal v, t, x and w registers and the background
+/// shift registers, fetching tiles at the same dots as the real chip. Sprites are evaluated for
+/// the next line at the end of each visible line.
+pub struct PPU {
+    chr: Vec<u8>,
+    chr_ram: bool,
+    /// The cartridge's wiring of the nametables, which power-on goes back to.
+    mirroring: Mirroring,
+    nametables: [Nametable; 4],
+    cartridge_vram: Vec<u8>,
+    vram: [u8; VRAM_SIZE],
+    palette: [u8; 32],
+    oam: [u8; 256],
+    ctrl: u8,
+    mask: u8,
+    oam_address: u8,
+    status: u8,
+    v: u16,
+    w: bool,
+    read_buffer: u8,
+    /// Set by a $2002 read on the dot before vblank starts, which keeps the flag and NMI from
+    /// being raised for that frame.
+    suppress_vblank: bool,
+    nmi: bool,
+    /// The data bus between the CPU and the PPU's registers, which is what write-only registers
+    /// and the undriven bits of readable ones return.
+    io_latch: u8,
+    /// The frame each bit of `io_latch` was last driven, for its decay.
+    io_latch_refreshed: [u64; 8],
+    t: u16,
+    fine_x: u8,
+    next_tile: u8,
+    next_attribute: u8,
+    next_pattern: [u8; 2],
+    pattern_shift: [u16; 2],
+    attribute_shift: [u16; 2],
+    scanline: u16,
+    dot: u16,
+    frame: u64,
+    odd_frame: bool,
+    line_sprites: Vec<LineSprite>,
+    /// Draws every sprite on a line instead of the first eight, which removes the flicker games
+    /// use to cycle through crowded lines. The overflow flag still behaves as on hardware.
+    sprite_limit: bool,
+    frame_buffer: Vec<u8>,
+}
+
+impl Default for PPU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PPU {
+    pub fn new() -> Self {
+        PPU {
+            chr: Vec::new(),
+            chr_ram: false,
+            mirroring: Mirroring::Horizontal,
+            nametables: Mirroring::Horizontal.nametables(),
+            cartridge_vram: Vec::new(),
+            vram: [0; VRAM_SIZE],
+            palette: [0; 32],
+            oam: [0; 256],
+            ctrl: 0,
+            mask: 0,
+            oam_address: 0,
+            status: 0,
+            v: 0,
+            w: false,
+            read_buffer: 0,
+            suppress_vblank: false,
+            nmi: false,
+            io_latch: 0,
+            io_latch_refreshed: [0; 8],
+            t: 0,
+            fine_x: 0,
+            next_tile: 0,
+            next_attribute: 0,
+            next_pattern: [0; 2],
+            pattern_shift: [0; 2],
+            attribute_shift: [0; 2],
+            scanline: 0,
+            dot: 0,
+            frame: 0,
+            odd_frame: false,
+            line_sprites: Vec::new(),
+            sprite_limit: true,
+            frame_buffer: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 3],
+        }
+    }
+
+    /// Cartridges without CHR ROM get 8KB of CHR RAM instead.
+    pub fn load_chr(&mut self, chr_rom: &[u8], mirroring: Mirroring) {
+        self.chr_ram = chr_rom.is_empty();
+        self.chr = if self.chr_ram { vec![0; CHR_ROM_PAGE_SIZE] } else { chr_rom.to_vec() };
+        self.mirroring = mirroring;
+        self.cartridge_vram.clear();
+        self.set_mirroring(mirroring);
+    }
+
+    /// Remaps the nametables, as mappers with switchable mirroring do at runtime.
+    pub fn set_mirroring(&mut self, mirroring: Mirroring) {
+        for (slot, nametable) in mirroring.nametables().into_iter().enumerate() {
+            self.map_nametable(slot, nametable);
+        }
+    }
+
+    /// Maps the nametable at `$2000 + slot * $400` to a page of the console's or the cartridge's
+    /// RAM. Cartridge RAM grows to cover the pages that are mapped.
+    pub fn map_nametable(&mut self, slot: usize, nametable: Nametable) {
+        if let Nametable::Cartridge(page) = nametable {
+            let size = (page as usize + 1) * NAMETABLE_SIZE;
+            if self.cartridge_vram.len() < size {
+                self.cartridge_vram.resize(size, 0);
+            }
+        }
+        self.nametables[slot % 4] = nametable;
+    }
+
+    /// Nametable RAM on the cartridge, for mappers that also expose it to the CPU.
+    pub fn cartridge_vram(&mut self) -> &mut [u8] {
+        &mut self.cartridge_vram
+    }
+
+    pub fn power_on(&mut self) {
+        let (chr, chr_ram, mirroring) = (std::mem::take(&mut self.chr), self.chr_ram, self.mirroring);
+        let cartridge_vram_size = self.cartridge_vram.len();
+        let sprite_limit = self.sprite_limit;
+        *self = PPU::new();
+        self.chr = if chr_ram { vec![0; chr.len()] } else { chr };
+        self.chr_ram = chr_ram;
+        self.mirroring = mirroring;
+        self.cartridge_vram = vec![0; cartridge_vram_size];
+        self.set_mirroring(mirroring);
+        self.sprite_limit = sprite_limit;
+    }
+
+    pub fn sprite_limit(&self) -> bool {
+        self.sprite_limit
+    }
+
+    /// Turning the limit off draws all the sprites on a line, not just the first eight.
+    pub fn set_sprite_limit(&mut self, enabled: bool) {
+        self.sprite_limit = enabled;
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub(crate) fn set_frame(&mut self, frame: u64) {
+        self.frame = frame;
+    }
+
+    pub fn scanline(&self) -> u16 {
+        self.scanline
+    }
+
+    pub fn dot(&self) -> u16 {
+        self.dot
+    }
+
+    /// The current VRAM address, the loopy v register.
+    pub fn vram_address(&self) -> u16 {
+        self.v
+    }
+
+    /// The last rendered picture as 256x240 RGB triples.
+    pub fn frame_buffer(&self) -> &[u8] {
+        &self.frame_buffer
+    }
+
+    pub fn oam(&self) -> &[u8; 256] {
+        &self.oam
+    }
+
+    pub(crate) fn poll_nmi(&mut self) -> bool {
+        std::mem::take(&mut self.nmi)
+    }
+
+    pub(crate) fn nmi_pending(&self) -> bool {
+        self.nmi
+    }
+
+    fn rendering(&self) -> bool {
+        self.mask & (MASK_BACKGROUND | MASK_SPRITES) != 0
+    }
+
+    pub fn tick(&mut self, dots: u64) {
+        for _ in 0..dots {
+            self.dot += 1;
+            if self.dot == PPU_DOTS_PER_SCANLINE - 1 && self.scanline == PRE_RENDER_SCANLINE && self.odd_frame && self.rendering() {
+                // Odd frames skip the last dot of the pre-render line while rendering.
+                self.dot = PPU_DOTS_PER_SCANLINE;
+            }
+            if self.dot == PPU_DOTS_PER_SCANLINE {
+                self.dot = 0;
+                self.scanline = (self.scanline + 1) % SCANLINES_PER_FRAME;
+                if self.scanline == 0 {
+                    self.odd_frame = !self.odd_frame;
+                }
+            }
+            self.render_dot();
+            if self.dot == 1 && self.scanline == VBLANK_SCANLINE {
+                if !std::mem::take(&mut self.suppress_vblank) {
+                    self.status |= STATUS_VBLANK;
+                    self.nmi = self.nmi || self.ctrl & CTRL_NMI != 0;
+                }
+                self.frame += 1;
+            }
+            if self.dot == 1 && self.scanline == PRE_RENDER_SCANLINE {
+                self.status &= !STATUS_CLEARED_ON_PRE_RENDER;
+            }
+        }
+    }
+
+    /// Reads register `$2000 + register % 8`, with the side effects the CPU's read has.
+    pub fn read_register(&mut self, register: u16) -> u8 {
+        let value = self.peek_register(register);
+        match register % 8 {
+            2 => {
+                self.refresh_io_latch(value, STATUS_CLEARED_ON_PRE_RENDER);
+                self.status &= !STATUS_VBLANK;
+                self.w = false;
+                if self.scanline == VBLANK_SCANLINE {
+                    match self.dot {
+                        0 => self.suppress_vblank = true,
+                        1 | 2 => self.nmi = false,
+                        _ => {}
+                    }
+                }
+            }
+            4 => self.refresh_io_latch(value, 0xFF),
+            7 => {
+                let address = self.v;
+                self.increment_address();
+                if address & 0x3FFF >= PALETTE_START {
+                    // Palette entries are 6 bits; the top two come from the latch.
+                    self.refresh_io_latch(value, 0x3F);
+                    self.read_buffer = self.read_vram(address - 0x1000);
+                } else {
+                    self.refresh_io_latch(value, 0xFF);
+                    self.read_buffer = self.read_vram(address);
+                }
+            }
+            _ => {}
+        }
+        value
+    }
+
+    /// What reading register `$2000 + register % 8` would return, without its side effects.
+    pub fn peek_register(&self, register: u16) -> u8 {
+        let open_bus = self.open_bus();
+        match register % 8 {
+            2 => self.status & STATUS_CLEARED_ON_PRE_RENDER | open_bus & !STATUS_CLEARED_ON_PRE_RENDER,
+            4 => self.oam[self.oam_address as usize],
+            7 => {
+                if self.v & 0x3FFF >= PALETTE_START {
+                    self.read_vram(self.v) & 0x3F | open_bus & 0xC0
+                } else {
+                    self.read_buffer
+                }
+            }
+            _ => open_bus,
+        }
+    }
+
+    /// The register latch with the bits that have decayed since they were last driven cleared.
+    fn open_bus(&self) -> u8 {
+        (0..8).filter(|&bit| self.io_latch >> bit & 1 != 0 && self.frame.saturating_sub(self.io_latch_refreshed[bit]) < OPEN_BUS_DECAY_FRAMES)
+            .fold(0, |latch, bit| latch | 1 << bit)
+    }
+
+    /// Drives the `mask` bits of the latch to `value`.
+    fn refresh_io_latch(&mut self, value: u8, mask: u8) {
+        self.io_latch = self.open_bus() & !mask | value & mask;
+        for bit in (0..8).filter(|bit| mask >> bit & 1 != 0) {
+            self.io_latch_refreshed[bit] = self.frame;
+        }
+    }
+
+    /// Writes register `$2000 + register % 8`.
+    pub fn write_register(&mut self, register: u16, data: u8) {
+        self.refresh_io_latch(data, 0xFF);
+        match register % 8 {
+            0 => {
+                let enables_nmi = self.ctrl & CTRL_NMI == 0 && data & CTRL_NMI != 0;
+                self.ctrl = data;
+                self.t = self.t & !(NAMETABLE_X | NAMETABLE_Y) | ((data & CTRL_NAMETABLE) as u16) << 10;
+                if enables_nmi && self.status & STATUS_VBLANK != 0 {
+                    self.nmi = true;
+                }
+            }
+            1 => self.mask = data,
+            3 => self.oam_address = data,
+            4 => {
+                self.oam[self.oam_address as usize] = data;
+                self.oam_address = self.oam_address.wrapping_add(1);
+            }
+            5 => {
+                if self.w {
+                    self.t = self.t & !(FINE_Y | COARSE_Y) | ((data & 0x07) as u16) << 12 | ((data >> 3) as u16) << 5;
+                } else {
+                    self.t = self.t & !COARSE_X | (data >> 3) as u16;
+                    self.fine_x = data & 0x07;
+                }
+                self.w = !self.w;
+            }
+            6 => {
+                if self.w {
+                    self.t = self.t & 0xFF00 | data as u16;
+                    self.v = self.t;
+                } else {
+                    self.t = self.t & 0x00FF | ((data & 0x3F) as u16) << 8;
+                }
+                self.w = !self.w;
+            }
+            7 => {
+                self.write_vram(self.v, data);
+                self.increment_address();
+            }
+            _ => {}
+        }
+    }
+
+    pub fn write_oam_dma(&mut self, page: &[u8; 256]) {
+        for byte in page {
+            self.oam[self.oam_address as usize] = *byte;
+            self.oam_address = self.oam_address.wrapping_add(1);
+        }
+    }
+
+    pub fn read_vram(&self, address: u16) -> u8 {
+        match address & 0x3FFF {
+            address @ 0x0000..=0x1FFF => self.chr.get(address as usize).copied().unwrap_or(0),
+            address @ NAMETABLE_START..=0x3EFF => match self.map_address(address) {
+                (Nametable::Console(page), offset) => self.vram[page as usize % 2 * NAMETABLE_SIZE + offset],
+                (Nametable::Cartridge(page), offset) => {
+                    self.cartridge_vram.get(page as usize * NAMETABLE_SIZE + offset).copied().unwrap_or(0)
+                }
+            },
+            address => self.palette[palette_index(address)],
+        }
+    }
+
+    fn write_vram(&mut self, address: u16, data: u8) {
+        match address & 0x3FFF {
+            address @ 0x0000..=0x1FFF => {
+                if self.chr_ram {
+                    self.chr[address as usize] = data;
+                }
+            }
+            address @ NAMETABLE_START..=0x3EFF => match self.map_address(address) {
+                (Nametable::Console(page), offset) => self.vram[page as usize % 2 * NAMETABLE_SIZE + offset] = data,
+                (Nametable::Cartridge(page), offset) => {
+                    if let Some(byte) = self.cartridge_vram.get_mut(page as usize * NAMETABLE_SIZE + offset) {
+                        *byte = data;
+                    }
+                }
+            },
+            address => self.palette[palette_index(address)] = data,
+        }
+    }
+
+    /// Accessing $2007 while rendering bumps v through both scroll increments instead.
+    fn increment_address(&mut self) {
+        if self.rendering() && (self.scanline < SCREEN_HEIGHT as u16 || self.scanline == PRE_RENDER_SCANLINE) {
+            self.v = increment_y(increment_x(self.v));
+        } else {
+            let step = if self.ctrl & CTRL_INCREMENT != 0 { 32 } else { 1 };
+            self.v = self.v.wrapping_add(step) & 0x7FFF;
+        }
+    }
+
+    /// The page a nametable address is mapped to and the offset into it. $3000-$3EFF mirrors
+    /// $2000-$2EFF.
+    fn map_address(&self, address: u16) -> (Nametable, usize) {
+        let index = (address - NAMETABLE_START) as usize % (4 * NAMETABLE_SIZE);
+        (self.nametables[index / NAMETABLE_SIZE], index % NAMETABLE_SIZE)
+    }
+
+    /// Runs the background pipeline for the current dot and outputs its pixel on visible lines.
+    fn render_dot(&mut self) {
+        let visible_line = self.scanline < SCREEN_HEIGHT as u16;
+        let dot = self.dot;
+        if self.rendering() && (visible_line || self.scanline == PRE_RENDER_SCANLINE) {
+            if (2..=257).contains(&dot) || (321..=337).contains(&dot) {
+                self.shift_background();
+                match (dot - 1) % 8 {
+                    0 => self.fetch_nametable(),
+                    2 => self.fetch_attribute(),
+                    4 => self.next_pattern[0] = self.read_vram(self.pattern_address()),
+                    6 => self.next_pattern[1] = self.read_vram(self.pattern_address() + 8),
+                    7 => self.v = increment_x(self.v),
+                    _ => {}
+                }
+            }
+            if dot == 256 {
+                self.v = increment_y(self.v);
+            }
+            if dot == 257 {
+                self.load_shifters();
+                self.v = self.v & !HORIZONTAL_BITS | self.t & HORIZONTAL_BITS;
+                if visible_line {
+                    self.evaluate_sprites();
+                } else {
+                    self.line_sprites.clear();
+                }
+            }
+            if self.scanline == PRE_RENDER_SCANLINE && (280..=304).contains(&dot) {
+                self.v = self.v & !VERTICAL_BITS | self.t & VERTICAL_BITS;
+            }
+        }
+        if visible_line && (1..=256).contains(&dot) {
+            self.output_pixel(dot as usize - 1, self.scanline as usize);
+        }
+    }
+
+    fn fetch_nametable(&mut self) {
+        self.load_shifters();
+        self.next_tile = self.read_vram(NAMETABLE_START | self.v & 0x0FFF);
+    }
+
+    fn fetch_attribute(&mut self) {
+        let v = self.v;
+        let attribute = self.read_vram(0x23C0 | v & (NAMETABLE_X | NAMETABLE_Y) | (v >> 4) & 0x38 | (v >> 2) & 0x07);
+        let shift = ((v >> 4) & 0x04) | (v & 0x02);
+        self.next_attribute = (attribute >> shift) & 0b11;
+    }
+
+    fn pattern_address(&self) -> u16 {
+        let table = if self.ctrl & CTRL_BACKGROUND_TABLE != 0 { 0x1000 } else { 0 };
+        table + self.next_tile as u16 * 16 + (self.v >> 12)
+    }
+
+    fn load_shifters(&mut self) {
+        for plane in 0..2 {
+            self.pattern_shift[plane] = self.pattern_shift[plane] & 0xFF00 | self.next_pattern[plane] as u16;
+            let attribute_bits = if self.next_attribute >> plane & 1 != 0 { 0xFF } else { 0x00 };
+            self.attribute_shift[plane] = self.attribute_shift[plane] & 0xFF00 | attribute_bits;
+        }
+    }
+
+    fn shift_background(&mut self) {
+        if self.mask & MASK_BACKGROUND != 0 {
+            for plane in 0..2 {
+                self.pattern_shift[plane] <<= 1;
+                self.attribute_shift[plane] <<= 1;
+            }
+        }
+    }
+
+    fn sprite_height(&self) -> u16 {
+        if self.ctrl & CTRL_TALL_SPRITES != 0 { 16 } else { 8 }
+    }
+
+    /// Picks the sprites on the next line out of OAM and fetches their pattern rows. Once eight
+    /// are found, the hardware keeps scanning for the overflow flag but also steps through each
+    /// sprite's bytes as if they were Y coordinates, which both misses and invents overflows.
+    fn evaluate_sprites(&mut self) {
+        let (line, height) = (self.scanline, self.sprite_height());
+        let in_range = |y: u8| line.wrapping_sub(y as u16) < height;
+        self.line_sprites.clear();
+        let mut found = 0;
+        for sprite in 0..64 {
+            let y = self.oam[sprite * 4];
+            if !in_range(y) {
+                continue;
+            }
+            found += 1;
+            if found > SPRITES_PER_LINE {
+                if self.sprite_limit {
+                    break;
+                }
+            } else if found == SPRITES_PER_LINE {
+                self.check_sprite_overflow(sprite + 1, in_range);
+            }
+            let line_sprite = self.fetch_sprite(sprite, line - y as u16);
+            self.line_sprites.push(line_sprite);
+        }
+    }
+
+    fn check_sprite_overflow(&mut self, first: usize, in_range: impl Fn(u8) -> bool) {
+        let (mut sprite, mut byte) = (first, 0);
+        while sprite < 64 {
+            if in_range(self.oam[sprite * 4 + byte]) {
+                self.status |= STATUS_SPRITE_OVERFLOW;
+                return;
+            }
+            sprite += 1;
+            byte = (byte + 1) % 4;
+        }
+    }
+
+    fn fetch_sprite(&self, sprite: usize, row: u16) -> LineSprite {
+        let (tile, attributes, x) = (self.oam[sprite * 4 + 1], self.oam[sprite * 4 + 2], self.oam[sprite * 4 + 3]);
+        let row = if attributes & SPRITE_FLIP_VERTICAL != 0 { self.sprite_height() - 1 - row } else { row };
+        let address = if self.ctrl & CTRL_TALL_SPRITES != 0 {
+            // 8x16 sprites pick their pattern table with bit 0 of the tile number.
+            let table = (tile as u16 & 1) * 0x1000;
+            table + (tile as u16 & 0xFE) * 16 + (row / 8) * 16 + row % 8
+        } else {
+            let table = if self.ctrl & CTRL_SPRITE_TABLE != 0 { 0x1000 } else { 0 };
+            table + tile as u16 * 16 + row
+        };
+        LineSprite {
+            x,
+            attributes,
+            pattern: [self.read_vram(address), self.read_vram(address + 8)],
+            sprite_zero: sprite == 0,
+        }
+    }
+
+    fn background_pixel(&self, x: usize) -> (u8, u8) {
+        if self.mask & MASK_BACKGROUND == 0 || x < 8 && self.mask & MASK_BACKGROUND_LEFT == 0 {
+            return (0, 0);
+        }
+        let bit = 15 - self.fine_x as u16;
+        let pixel = (self.pattern_shift[1] >> bit & 1) << 1 | self.pattern_shift[0] >> bit & 1;
+        let palette = (self.attribute_shift[1] >> bit & 1) << 1 | self.attribute_shift[0] >> bit & 1;
+        (pixel as u8, palette as u8)
+    }
+
+    /// The first opaque sprite pixel at `x`; lower OAM indices win regardless of priority.
+    fn sprite_pixel(&self, x: usize) -> Option<(u8, LineSprite)> {
+        if self.mask & MASK_SPRITES == 0 || x < 8 && self.mask & MASK_SPRITES_LEFT == 0 {
+            return None;
+        }
+        self.line_sprites.iter()
+            .filter(|sprite| x >= sprite.x as usize && x < sprite.x as usize + 8)
+            .map(|sprite| (sprite.pixel((x - sprite.x as usize) as u8), *sprite))
+            .find(|(pixel, _)| *pixel != 0)
+    }
+
+    fn output_pixel(&mut self, x: usize, y: usize) {
+        let greyscale = if self.mask & MASK_GREYSCALE != 0 { 0x30 } else { 0x3F };
+        let mut color = self.palette[0];
+        if !self.rendering() && self.v & 0x3FFF >= PALETTE_START {
+            // With rendering off the backdrop comes from wherever v points in the palette.
+            color = self.palette[palette_index(self.v)];
+        }
+        let (background, background_palette) = self.background_pixel(x);
+        if background != 0 {
+            color = self.palette[(background_palette * 4 + background) as usize];
+        }
+        if let Some((pixel, sprite)) = self.sprite_pixel(x) {
+            // Sprite 0 hits never happen at x=255, but do for sprites behind the background.
+            if sprite.sprite_zero && background != 0 && x != 255 {
+                self.status |= STATUS_SPRITE_ZERO_HIT;
+            }
+            if background == 0 || sprite.attributes & SPRITE_BEHIND_BACKGROUND == 0 {
+                color = self.palette[(16 + (sprite.attributes & SPRITE_PALETTE) * 4 + pixel) as usize];
+            }
+        }
+        let (red, green, blue) = SYSTEM_PALETTE[(color & greyscale) as usize];
+        let offset = (y * SCREEN_WIDTH + x) * 3;
+        self.frame_buffer[offset..offset + 3].copy_from_slice(&[red, green, blue]);
+    }
+
+    pub(crate) fn save_state(&self, state: &mut SaveState) {
+        let mut section = Section::new(*b"PPU ");
+        section.write_u8(self.ctrl);
+        section.write_u8(self.mask);
+        section.write_u8(self.status);
+        section.write_u8(self.oam_address);
+        section.write_u16(self.v);
+        section.write_u16(self.t);
+        section.write_u8(self.fine_x);
+        section.write_bool(self.w);
+        section.write_u8(self.read_buffer);
+        section.write_u16(self.scanline);
+        section.write_u16(self.dot);
+        section.write_u64(self.frame);
+        section.write_bool(self.odd_frame);
+        section.write_bool(self.nmi);
+        section.write_bool(self.suppress_vblank);
+        section.write_u8(self.next_tile);
+        section.write_u8(self.next_attribute);
+        section.write_bytes(&self.next_pattern);
+        for shift in self.pattern_shift.iter().chain(&self.attribute_shift) {
+            section.write_u16(*shift);
+        }
+        section.write_bytes(&self.vram);
+        section.write_bytes(&self.palette);
+        section.write_bytes(&self.oam);
+        let chr_ram: &[u8] = if self.chr_ram { &self.chr } else { &[] };
+        section.write_u32(chr_ram.len() as u32);
+        section.write_bytes(chr_ram);
+        section.write_u8(self.line_sprites.len() as u8);
+        for sprite in &self.line_sprites {
+            section.write_bytes(&[sprite.x, sprite.attributes, sprite.pattern[0], sprite.pattern[1]]);
+            section.write_bool(sprite.sprite_zero);
+        }
+        // Only mappings a mapper changed are saved; the rest come from the cartridge on load.
+        let remapped = self.nametables != self.mirroring.nametables();
+        section.write_bool(remapped);
+        if remapped {
+            section.write_bytes(&self.nametables.map(|nametable| match nametable {
+                Nametable::Console(page) => page,
+                Nametable::Cartridge(page) => NAMETABLE_ON_CARTRIDGE | page,
+            }));
+        }
+        section.write_u32(self.cartridge_vram.len() as u32);
+        section.write_bytes(&self.cartridge_vram);
+        section.write_u8(self.io_latch);
+        for refreshed in self.io_latch_refreshed {
+            section.write_u64(refreshed);
+        }
+        state.add_section(section);
+    }
+
+    pub(crate) fn load_state(&mut self, state: &SaveState) -> Result<(), EmulatorError> {
+        let mut section = state.section(*b"PPU ")?;
+        let mut ppu = PPU {
+            ctrl: section.read_u8()?,
+            mask: section.read_u8()?,
+            status: section.read_u8()?,
+            oam_address: section.read_u8()?,
+            v: section.read_u16()? & 0x7FFF,
+            t: section.read_u16()? & 0x7FFF,
+            fine_x: section.read_u8()? & 0x07,
+            w: section.read_bool()?,
+            read_buffer: section.read_u8()?,
+            scanline: section.read_u16()? % SCANLINES_PER_FRAME,
+            dot: section.read_u16()? % PPU_DOTS_PER_SCANLINE,
+            frame: section.read_u64()?,
+            odd_frame: section.read_bool()?,
+            nmi: section.read_bool()?,
+            suppress_vblank: section.read_bool()?,
+            next_tile: section.read_u8()?,
+            next_attribute: section.read_u8()? & 0b11,
+            next_pattern: section.read_array()?,
+            pattern_shift: [section.read_u16()?, section.read_u16()?],
+            attribute_shift: [section.read_u16()?, section.read_u16()?],
+            vram: section.read_array()?,
+            palette: section.read_array()?,
+            oam: section.read_array()?,
+            ..PPU::new()
+        };
+        let chr_ram_length = section.read_u32()? as usize;
+        let chr_ram = section.read_bytes(chr_ram_length)?;
+        for _ in 0..section.read_u8()? {
+            let [x, attributes, low, high] = section.read_array()?;
+            let sprite_zero = section.read_bool()?;
+            ppu.line_sprites.push(LineSprite { x, attributes, pattern: [low, high], sprite_zero });
+        }
+        let nametables = if section.read_bool()? {
+            section.read_array::<4>()?.map(|page| match page & NAMETABLE_ON_CARTRIDGE {
+                0 => Nametable::Console(page & 1),
+                _ => Nametable::Cartridge(page & !NAMETABLE_ON_CARTRIDGE),
+            })
+        } else {
+            self.mirroring.nametables()
+        };
+        let cartridge_vram_length = section.read_u32()? as usize;
+        let cartridge_vram = section.read_bytes(cartridge_vram_length)?;
+        ppu.io_latch = section.read_u8()?;
+        for refreshed in &mut ppu.io_latch_refreshed {
+            *refreshed = section.read_u64()?;
+        }
+        section.finish()?;
+        if chr_ram_length != 0 && (!self.chr_ram || chr_ram_length != self.chr.len()) {
+            return Err(EmulatorError::InvalidSaveState("CHR RAM size mismatch".to_string()));
+        }
+        ppu.chr = std::mem::take(&mut self.chr);
+        ppu.chr_ram = self.chr_ram;
+        if ppu.chr_ram {
+            ppu.chr.fill(0);
+            ppu.chr[..chr_ram_length].copy_from_slice(chr_ram);
+        }
+        ppu.mirroring = self.mirroring;
+        // States from before cartridge nametable RAM was saved leave it cleared.
+        ppu.cartridge_vram = if cartridge_vram_length == 0 { vec![0; self.cartridge_vram.len()] } else { cartridge_vram.to_vec() };
+        for (slot, nametable) in nametables.into_iter().enumerate() {
+            ppu.map_nametable(slot, nametable);
+        }
+        ppu.sprite_limit = self.sprite_limit;
+        ppu.frame_buffer = std::mem::take(&mut self.frame_buffer);
+        *self = ppu;
+        Ok(())
+    }
+}
+
+fn increment_x(v: u16) -> u16 {
+    if v & COARSE_X == COARSE_X {
+        (v & !COARSE_X) ^ NAMETABLE_X
+    } else {
+        v + 1
+    }
+}
+
+/// Moves v down a line, wrapping from row 29 into the next nametable. Rows 30 and 31 hold
+/// attributes and wrap back to row 0 of the same nametable.
+fn increment_y(v: u16) -> u16 {
+    if v & FINE_Y != FINE_Y {
+        return v + 0x1000;
+    }
+    let v = v & !FINE_Y;
+    match (v & COARSE_Y) >> 5 {
+        29 => (v & !COARSE_Y) ^ NAMETABLE_Y,
+        31 => v & !COARSE_Y,
+        _ => v + 0x20,
+    }
+}
+
+fn palette_index(address: u16) -> usize {
+    let index = (address & 0x1F) as usize;
+    if index >= 0x10 && index.is_multiple_of(4) { index - 0x10 } else { index }
+}
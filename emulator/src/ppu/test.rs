@@ -0,0 +1,600 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod test {
+    use crate::asm;
+    use crate::common::constants::{OAM_DMA_CYCLES, PRE_RENDER_SCANLINE, SCREEN_WIDTH};
+    use crate::common::png::encode_png;
+    use crate::common::types::{Mirroring, Nametable};
+    use crate::cpu::CPU;
+    use crate::cpu::types::Core;
+    use crate::memory::memory::Memory;
+    use crate::ppu::palette::SYSTEM_PALETTE;
+    use crate::ppu::PPU;
+    use crate::ppu::viewer::{SpriteInfo, NAMETABLES_HEIGHT, NAMETABLES_WIDTH, PALETTES_HEIGHT, PALETTES_WIDTH, PATTERN_TABLE_SIZE, SPRITES_HEIGHT, SPRITES_WIDTH};
+    use crate::savestate::SaveState;
+
+    /// A CPU running `program` with `nmi_handler` at $8100 and CHR RAM.
+    fn initialize_cpu(program: Vec<u8>, nmi_handler: Vec<u8>) -> CPU {
+        let mut cpu = CPU::with_interrupt_handlers(Core::default(), &program, &nmi_handler);
+        cpu.ppu_mut().load_chr(&[], Mirroring::Horizontal);
+        cpu
+    }
+
+    fn write_vram(cpu: &mut CPU, address: u16, data: &[u8]) {
+        cpu.write(0x2006, (address >> 8) as u8).unwrap();
+        cpu.write(0x2006, address as u8).unwrap();
+        for byte in data {
+            cpu.write(0x2007, *byte).unwrap();
+        }
+    }
+
+    fn pixel(cpu: &CPU, x: usize, y: usize) -> (u8, u8, u8) {
+        let offset = (y * SCREEN_WIDTH + x) * 3;
+        let frame = cpu.ppu().frame_buffer();
+        (frame[offset], frame[offset + 1], frame[offset + 2])
+    }
+
+    #[test]
+    fn test_vblank_nmi() {
+        let program = asm!(
+            "        LDA #$80",
+            "        STA $2000",
+            "loop:   JMP loop",
+        );
+        let handler = asm!(
+            "        INC $20",
+            "        RTI",
+        );
+        let mut cpu = initialize_cpu(program, handler);
+        for frame in 1..=3 {
+            cpu.run_frame(|_| Ok(())).unwrap();
+            assert_eq!(cpu.frame(), frame);
+            assert_eq!(cpu.scanline(), 241);
+        }
+        cpu.step().unwrap();
+        assert_eq!(cpu.read(0x20).unwrap(), 3);
+        assert!(cpu.status.interrupt_disable);
+        assert_eq!(cpu.stack_pointer, 0xFD - 3);
+    }
+
+    #[test]
+    fn test_status_read_clears_vblank() {
+        let mut cpu = initialize_cpu(asm!("loop: JMP loop"), Vec::new());
+        cpu.run_frame(|_| Ok(())).unwrap();
+        assert_eq!(cpu.read(0x2002).unwrap() & 0x80, 0x80);
+        assert_eq!(cpu.read(0x2002).unwrap() & 0x80, 0);
+
+        cpu.run_frame(|_| Ok(())).unwrap();
+        cpu.write(0x2000, 0x80).unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.program_counter, 0x8100);
+    }
+
+    #[test]
+    fn test_vram_access() {
+        let mut cpu = initialize_cpu(asm!("NOP"), Vec::new());
+        write_vram(&mut cpu, 0x2000, &[1, 2, 3]);
+        write_vram(&mut cpu, 0x2000, &[]);
+        assert_eq!(cpu.read(0x2007).unwrap(), 0);
+        assert_eq!(cpu.read(0x2007).unwrap(), 1);
+        assert_eq!(cpu.read(0x2007).unwrap(), 2);
+
+        cpu.write(0x2000, 0x04).unwrap();
+        write_vram(&mut cpu, 0x2400, &[0xAA, 0xBB]);
+        assert_eq!(cpu.ppu().read_vram(0x2400), 0xAA);
+        assert_eq!(cpu.ppu().read_vram(0x2420), 0xBB);
+        assert_eq!(cpu.ppu().read_vram(0x2000), 0xAA);
+
+        write_vram(&mut cpu, 0x3F10, &[0x21]);
+        assert_eq!(cpu.ppu().read_vram(0x3F00), 0x21);
+        write_vram(&mut cpu, 0x3F00, &[]);
+        assert_eq!(cpu.read(0x2007).unwrap(), 0x21);
+
+        write_vram(&mut cpu, 0x0010, &[0x55]);
+        assert_eq!(cpu.ppu().read_vram(0x0010), 0x55);
+    }
+
+    #[test]
+    fn test_nametable_mirroring() {
+        let mut ppu = PPU::new();
+        ppu.load_chr(&[0; 8192], Mirroring::Vertical);
+        ppu.write_register(6, 0x20);
+        ppu.write_register(6, 0x05);
+        ppu.write_register(7, 0x42);
+        assert_eq!(ppu.read_vram(0x2805), 0x42);
+        assert_eq!(ppu.read_vram(0x2405), 0);
+        assert_eq!(ppu.read_vram(0x3005), 0x42);
+
+        ppu.write_register(6, 0x00);
+        ppu.write_register(6, 0x00);
+        ppu.write_register(7, 0xFF);
+        assert_eq!(ppu.read_vram(0x0000), 0);
+    }
+
+    #[test]
+    fn test_mirroring_modes() {
+        let fill = |ppu: &mut PPU| {
+            for table in 0..4 {
+                ppu.write_register(6, 0x20 + table * 4);
+                ppu.write_register(6, 0x00);
+                ppu.write_register(7, table + 1);
+            }
+        };
+        let tables = |ppu: &PPU| [0x2000, 0x2400, 0x2800, 0x2C00].map(|address| ppu.read_vram(address));
+        let cases = [
+            (Mirroring::Horizontal, [2, 2, 4, 4]),
+            (Mirroring::Vertical, [3, 4, 3, 4]),
+            (Mirroring::FourScreen, [1, 2, 3, 4]),
+            (Mirroring::SingleScreenA, [4, 4, 4, 4]),
+            (Mirroring::SingleScreenB, [4, 4, 4, 4]),
+        ];
+        for (mirroring, expected) in cases {
+            let mut ppu = PPU::new();
+            ppu.load_chr(&[0; 8192], mirroring);
+            fill(&mut ppu);
+            assert_eq!(tables(&ppu), expected, "{:?}", mirroring);
+            assert_eq!(ppu.read_vram(0x3C00), expected[3]);
+        }
+
+        let mut ppu = PPU::new();
+        ppu.load_chr(&[0; 8192], Mirroring::SingleScreenA);
+        fill(&mut ppu);
+        ppu.set_mirroring(Mirroring::SingleScreenB);
+        assert_eq!(tables(&ppu), [0; 4]);
+        ppu.set_mirroring(Mirroring::Vertical);
+        assert_eq!(tables(&ppu), [4, 0, 4, 0]);
+    }
+
+    #[test]
+    fn test_cartridge_nametable_ram() {
+        let mut ppu = PPU::new();
+        ppu.load_chr(&[0; 8192], Mirroring::Horizontal);
+        ppu.map_nametable(3, Nametable::Cartridge(2));
+        assert_eq!(ppu.cartridge_vram().len(), 3 * 0x400);
+        ppu.cartridge_vram()[2 * 0x400 + 5] = 0x77;
+        assert_eq!(ppu.read_vram(0x2C05), 0x77);
+        ppu.write_register(6, 0x2C);
+        ppu.write_register(6, 0x06);
+        ppu.write_register(7, 0x88);
+        assert_eq!(ppu.cartridge_vram()[2 * 0x400 + 6], 0x88);
+        assert_eq!(ppu.read_vram(0x2806), 0);
+
+        let mut state = SaveState::new();
+        ppu.save_state(&mut state);
+        let mut loaded = PPU::new();
+        loaded.load_chr(&[0; 8192], Mirroring::Horizontal);
+        loaded.load_state(&state).unwrap();
+        assert_eq!(loaded.read_vram(0x2C06), 0x88);
+        loaded.power_on();
+        assert_eq!(loaded.read_vram(0x2C06), 0);
+        assert_eq!(loaded.nametables, Mirroring::Horizontal.nametables());
+    }
+
+    #[test]
+    fn test_oam_dma() {
+        let program = asm!(
+            "        LDA #$02",
+            "        STA $4014",
+        );
+        let mut cpu = initialize_cpu(program, Vec::new());
+        for offset in 0..256 {
+            cpu.write(0x0200 + offset, offset as u8).unwrap();
+        }
+        cpu.write(0x2003, 0x10).unwrap();
+        cpu.step().unwrap();
+        let cycles = cpu.cycles;
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycles - cycles, 4 + OAM_DMA_CYCLES);
+        assert_eq!(cpu.ppu().oam()[0x10], 0);
+        assert_eq!(cpu.ppu().oam()[0x0F], 0xFF);
+    }
+
+    #[test]
+    fn test_background_rendering() {
+        let mut cpu = initialize_cpu(asm!("loop: JMP loop"), Vec::new());
+        write_vram(&mut cpu, 0x0010, &[0xFF; 8]);
+        write_vram(&mut cpu, 0x2000, &[1]);
+        write_vram(&mut cpu, 0x23C0, &[0b0000_0001]);
+        write_vram(&mut cpu, 0x3F00, &[0x0F, 0x00, 0x00, 0x00, 0x00, 0x30]);
+        cpu.write(0x2000, 0).unwrap();
+        cpu.write(0x2005, 0).unwrap();
+        cpu.write(0x2005, 0).unwrap();
+        cpu.write(0x2001, 0b0000_1010).unwrap();
+        // v only picks up the scroll from t on the pre-render line, so the first frame is garbage.
+        cpu.run_frame(|_| Ok(())).unwrap();
+        cpu.run_frame(|_| Ok(())).unwrap();
+
+        let (black, white) = (SYSTEM_PALETTE[0x0F], SYSTEM_PALETTE[0x30]);
+        assert_eq!(pixel(&cpu, 0, 0), white);
+        assert_eq!(pixel(&cpu, 7, 7), white);
+        assert_eq!(pixel(&cpu, 8, 0), black);
+        assert_eq!(pixel(&cpu, 0, 8), black);
+
+        cpu.write(0x2005, 8).unwrap();
+        cpu.write(0x2005, 0).unwrap();
+        cpu.write(0x2001, 0b0000_1000).unwrap();
+        cpu.run_frame(|_| Ok(())).unwrap();
+        assert_eq!(pixel(&cpu, 0, 0), black);
+        assert_eq!(pixel(&cpu, 248, 0), white);
+    }
+
+    fn run_to(ppu: &mut PPU, scanline: u16, dot: u16) {
+        while (ppu.scanline(), ppu.dot()) != (scanline, dot) {
+            ppu.tick(1);
+        }
+    }
+
+    #[test]
+    fn test_scroll_registers() {
+        let mut ppu = PPU::new();
+        ppu.write_register(0, 0b0000_0011);
+        ppu.write_register(5, 0x7D);
+        ppu.write_register(5, 0x5E);
+        assert_eq!((ppu.t, ppu.fine_x), (0x6D6F, 5));
+
+        ppu.write_register(6, 0xFD);
+        assert_eq!(ppu.t, 0x3D6F);
+        assert_eq!(ppu.read_register(2) & 0x80, 0);
+        ppu.write_register(6, 0x2D);
+        assert_eq!(ppu.t, 0x2D6F);
+        ppu.write_register(6, 0xF0);
+        assert_eq!((ppu.t, ppu.vram_address()), (0x2DF0, 0x2DF0));
+    }
+
+    #[test]
+    fn test_odd_frames_skip_a_dot_while_rendering() {
+        let mut ppu = PPU::new();
+        let mut frame_lengths = Vec::new();
+        for mask in [0b0000_0000, 0b0000_1000] {
+            ppu.write_register(1, mask);
+            for _ in 0..2 {
+                run_to(&mut ppu, 0, 0);
+                let mut dots = 1;
+                ppu.tick(1);
+                while (ppu.scanline(), ppu.dot()) != (0, 0) {
+                    ppu.tick(1);
+                    dots += 1;
+                }
+                frame_lengths.push(dots);
+            }
+        }
+        assert_eq!(frame_lengths, [89342, 89342, 89342, 89341]);
+    }
+
+    #[test]
+    fn test_status_read_races_vblank() {
+        let mut ppu = PPU::new();
+        ppu.write_register(0, 0x80);
+        run_to(&mut ppu, 241, 0);
+        assert_eq!(ppu.read_register(2) & 0x80, 0);
+        ppu.tick(1);
+        assert_eq!(ppu.read_register(2) & 0x80, 0);
+        assert!(!ppu.poll_nmi());
+
+        run_to(&mut ppu, 240, 0);
+        run_to(&mut ppu, 241, 1);
+        assert_eq!(ppu.read_register(2) & 0x80, 0x80);
+        assert!(!ppu.poll_nmi());
+
+        run_to(&mut ppu, 240, 0);
+        run_to(&mut ppu, 241, 3);
+        assert_eq!(ppu.read_register(2) & 0x80, 0x80);
+        assert!(ppu.poll_nmi());
+    }
+
+    #[test]
+    fn test_peek_has_no_side_effects() {
+        let mut cpu = initialize_cpu(asm!("loop: JMP loop"), Vec::new());
+        write_vram(&mut cpu, 0x2400, &[0x11, 0x22]);
+        cpu.run_frame(|_| Ok(())).unwrap();
+        cpu.write(0x2006, 0x24).unwrap();
+        cpu.write(0x2006, 0x00).unwrap();
+        assert_eq!(cpu.peek(0x2002).unwrap() & 0x80, 0x80);
+        assert_eq!(cpu.peek(0x2002).unwrap() & 0x80, 0x80);
+        cpu.read(0x2007).unwrap();
+        assert_eq!(cpu.peek(0x2007).unwrap(), 0x11);
+        assert_eq!(cpu.peek(0x2007).unwrap(), 0x11);
+        assert_eq!(cpu.ppu().vram_address(), 0x2401);
+        assert_eq!(cpu.read(0x2007).unwrap(), 0x11);
+        assert_eq!(cpu.read(0x2002).unwrap() & 0x80, 0x80);
+        assert_eq!(cpu.peek(0x2002).unwrap() & 0x80, 0);
+    }
+
+    #[test]
+    fn test_open_bus() {
+        let mut ppu = PPU::new();
+        ppu.write_register(3, 0xA5);
+        assert_eq!(ppu.read_register(0), 0xA5);
+        assert_eq!(ppu.read_register(6), 0xA5);
+        ppu.status = 0x80;
+        assert_eq!(ppu.read_register(2), 0x85);
+        // The status read drives its top three bits onto the latch, vblank flag included.
+        assert_eq!(ppu.read_register(5), 0x85);
+
+        ppu.write_register(3, 0xFF);
+        ppu.write_register(6, 0x3F);
+        ppu.write_register(6, 0x00);
+        ppu.write_register(7, 0x2A);
+        ppu.write_register(6, 0x3F);
+        ppu.write_register(6, 0x00);
+        assert_eq!(ppu.read_register(7), 0x2A);
+        assert_eq!(ppu.read_register(1), 0x2A);
+    }
+
+    #[test]
+    fn test_open_bus_decay() {
+        let mut ppu = PPU::new();
+        ppu.write_register(3, 0xF0);
+        ppu.frame += 20;
+        ppu.status = 0x40;
+        ppu.read_register(2);
+        ppu.frame += 20;
+        // Bits 5 to 7 were refreshed by the status read, bit 4 has faded.
+        assert_eq!(ppu.read_register(0), 0x40);
+        ppu.write_register(3, 0xF0);
+        assert_eq!(ppu.read_register(0), 0xF0);
+        ppu.frame += 36;
+        assert_eq!(ppu.read_register(0), 0x00);
+        // A refresh newer than the frame counter, as a loaded state can have, counts as fresh.
+        ppu.write_register(3, 0xF0);
+        ppu.frame = 0;
+        assert_eq!(ppu.read_register(0), 0xF0);
+    }
+
+    #[test]
+    fn test_mid_frame_scroll_split() {
+        let mut ppu = PPU::new();
+        ppu.load_chr(&[], Mirroring::Horizontal);
+        let write = |ppu: &mut PPU, address: u16, data: &[u8]| {
+            ppu.write_register(6, (address >> 8) as u8);
+            ppu.write_register(6, address as u8);
+            for byte in data {
+                ppu.write_register(7, *byte);
+            }
+        };
+        write(&mut ppu, 0x0010, &[0xFF; 8]);
+        write(&mut ppu, 0x3F00, &[0x0F, 0x30]);
+        // A column of solid tiles at the left edge of the nametable, which repeats horizontally.
+        for row in 0..30 {
+            write(&mut ppu, 0x2000 + row * 32, &[1]);
+        }
+        ppu.write_register(0, 0);
+        ppu.write_register(5, 0);
+        ppu.write_register(5, 0);
+        ppu.write_register(1, 0b0000_1010);
+        run_to(&mut ppu, PRE_RENDER_SCANLINE, 0);
+        run_to(&mut ppu, 120, 100);
+        ppu.write_register(5, 4);
+        ppu.write_register(5, 0);
+        run_to(&mut ppu, 241, 0);
+
+        let pixel = |x: usize, y: usize| ppu.frame_buffer()[(y * SCREEN_WIDTH + x) * 3];
+        let (black, white) = (SYSTEM_PALETTE[0x0F].0, SYSTEM_PALETTE[0x30].0);
+        assert_eq!([pixel(0, 120), pixel(7, 120), pixel(8, 120)], [white, white, black]);
+        assert_eq!([pixel(0, 121), pixel(3, 121), pixel(4, 121)], [white, white, black]);
+        assert_eq!(pixel(252, 121), white);
+    }
+
+    /// Renders a frame with solid background tiles at the two top corners and `sprites` in OAM.
+    /// Tile 1 is solid, tile 2 has only its top-left pixel set.
+    fn render_sprites(sprites: &[[u8; 4]], ctrl: u8, mask: u8, sprite_limit: bool) -> PPU {
+        let mut ppu = PPU::new();
+        ppu.load_chr(&[], Mirroring::Horizontal);
+        ppu.set_sprite_limit(sprite_limit);
+        let write = |ppu: &mut PPU, address: u16, data: &[u8]| {
+            ppu.write_register(6, (address >> 8) as u8);
+            ppu.write_register(6, address as u8);
+            for byte in data {
+                ppu.write_register(7, *byte);
+            }
+        };
+        for table in [0x0000, 0x1000] {
+            write(&mut ppu, table + 0x10, &[0xFF; 8]);
+            write(&mut ppu, table + 0x20, &[0x80]);
+        }
+        write(&mut ppu, 0x1030, &[0, 0, 0, 0, 0, 0, 0, 0x01]);
+        write(&mut ppu, 0x2000, &[1]);
+        write(&mut ppu, 0x201F, &[1]);
+        write(&mut ppu, 0x3F00, &[0x0F, 0x30]);
+        write(&mut ppu, 0x3F10, &[0x0F, 0x16]);
+        let mut oam = [0xF0; 256];
+        for (index, sprite) in sprites.iter().enumerate() {
+            oam[index * 4..index * 4 + 4].copy_from_slice(sprite);
+        }
+        ppu.write_oam_dma(&oam);
+        ppu.write_register(0, ctrl);
+        ppu.write_register(5, 0);
+        ppu.write_register(5, 0);
+        ppu.write_register(1, mask);
+        run_to(&mut ppu, PRE_RENDER_SCANLINE, 0);
+        run_to(&mut ppu, 241, 0);
+        ppu
+    }
+
+    fn color(ppu: &PPU, x: usize, y: usize) -> u8 {
+        let (black, white, red) = (SYSTEM_PALETTE[0x0F], SYSTEM_PALETTE[0x30], SYSTEM_PALETTE[0x16]);
+        let offset = (y * SCREEN_WIDTH + x) * 3;
+        match (ppu.frame_buffer()[offset], ppu.frame_buffer()[offset + 1], ppu.frame_buffer()[offset + 2]) {
+            rgb if rgb == black => b'.',
+            rgb if rgb == white => b'W',
+            rgb if rgb == red => b'R',
+            _ => b'?',
+        }
+    }
+
+    #[test]
+    fn test_sprite_rendering() {
+        let ppu = render_sprites(&[[9, 2, 0x00, 20], [9, 2, 0xC0, 40]], 0, 0b0001_1110, true);
+        assert_eq!([color(&ppu, 20, 10), color(&ppu, 21, 10), color(&ppu, 20, 11)], *b"R..");
+        assert_eq!([color(&ppu, 47, 17), color(&ppu, 40, 10), color(&ppu, 20, 9)], *b"R..");
+
+        let ppu = render_sprites(&[[9, 1, 0x00, 20]], 0, 0b0000_1110, true);
+        assert_eq!(color(&ppu, 20, 10), b'.');
+    }
+
+    #[test]
+    fn test_tall_sprites() {
+        let ppu = render_sprites(&[[9, 3, 0x00, 100], [9, 3, 0x80, 120]], 0b0010_0000, 0b0001_1110, true);
+        assert_eq!([color(&ppu, 100, 10), color(&ppu, 107, 25), color(&ppu, 107, 17)], *b"RR.");
+        assert_eq!([color(&ppu, 127, 10), color(&ppu, 120, 25)], *b"RR");
+
+        let ppu = render_sprites(&[[9, 2, 0x00, 100]], 0b0010_1000, 0b0001_1110, true);
+        assert_eq!([color(&ppu, 100, 10), color(&ppu, 101, 10)], *b"R.");
+    }
+
+    #[test]
+    fn test_sprite_priority() {
+        let ppu = render_sprites(&[[0, 1, 0x20, 0], [0, 1, 0x00, 4]], 0, 0b0001_1110, true);
+        assert_eq!([color(&ppu, 0, 1), color(&ppu, 7, 1)], *b"WW");
+        assert_eq!([color(&ppu, 8, 1), color(&ppu, 11, 1), color(&ppu, 12, 1)], *b"RR.");
+    }
+
+    #[test]
+    fn test_sprite_zero_hit() {
+        let hit = |sprites: &[[u8; 4]], mask: u8| render_sprites(sprites, 0, mask, true).status & 0x40 != 0;
+        assert!(hit(&[[0, 2, 0x00, 0]], 0b0001_1110));
+        assert!(hit(&[[0, 2, 0x20, 0]], 0b0001_1110));
+        assert!(!hit(&[[0, 2, 0x00, 0]], 0b0001_1010));
+        assert!(!hit(&[[0, 2, 0x00, 0]], 0b0001_1100));
+        assert!(!hit(&[[0, 2, 0x00, 0]], 0b0000_1110));
+        assert!(!hit(&[[9, 1, 0x00, 0]], 0b0001_1110));
+        assert!(!hit(&[[1, 1, 0x00, 100], [0, 2, 0x00, 0]], 0b0001_1110));
+        assert!(hit(&[[0, 1, 0x00, 254]], 0b0001_1110));
+        assert!(!hit(&[[0, 1, 0x00, 255]], 0b0001_1110));
+
+        let mut ppu = render_sprites(&[[0, 2, 0x00, 0]], 0, 0b0001_1110, true);
+        run_to(&mut ppu, PRE_RENDER_SCANLINE, 2);
+        assert_eq!(ppu.read_register(2) & 0x40, 0);
+    }
+
+    #[test]
+    fn test_sprite_overflow() {
+        let overflow = |sprites: &[[u8; 4]]| render_sprites(sprites, 0, 0b0001_1110, true).status & 0x20 != 0;
+        let mut sprites = vec![[100, 0xF0, 0xF0, 0xF0]; 8];
+        assert!(!overflow(&sprites));
+        sprites.push([100, 0xF0, 0xF0, 0xF0]);
+        assert!(overflow(&sprites));
+
+        // After eight sprites the hardware reads the second sprite's tile number as its Y.
+        sprites[8] = [200, 0xF0, 0xF0, 0xF0];
+        sprites.push([0xF0, 100, 0xF0, 0xF0]);
+        assert!(overflow(&sprites));
+        sprites[9] = [100, 0xF0, 0xF0, 0xF0];
+        assert!(!overflow(&sprites));
+    }
+
+    #[test]
+    fn test_sprite_limit() {
+        let sprites: Vec<[u8; 4]> = (0..9).map(|sprite| [99, 1, 0x00, sprite * 10 + 20]).collect();
+        let ppu = render_sprites(&sprites, 0, 0b0001_1110, true);
+        assert_eq!([color(&ppu, 90, 100), color(&ppu, 100, 100)], *b"R.");
+        assert_ne!(ppu.status & 0x20, 0);
+
+        let ppu = render_sprites(&sprites, 0, 0b0001_1110, false);
+        assert_eq!([color(&ppu, 90, 100), color(&ppu, 100, 100)], *b"RR");
+        assert_ne!(ppu.status & 0x20, 0);
+    }
+
+    #[test]
+    fn test_pattern_table_image() {
+        let mut ppu = render_sprites(&[], 0, 0, true);
+        ppu.write_register(6, 0x3F);
+        ppu.write_register(6, 0x15);
+        ppu.write_register(7, 0x2A);
+        let at = |image: &[u8], x: usize, y: usize| {
+            let offset = (y * PATTERN_TABLE_SIZE + x) * 3;
+            (image[offset], image[offset + 1], image[offset + 2])
+        };
+        let image = ppu.pattern_table_image(0, 0);
+        assert_eq!(image.len(), PATTERN_TABLE_SIZE * PATTERN_TABLE_SIZE * 3);
+        assert_eq!([at(&image, 8, 0), at(&image, 15, 7)], [SYSTEM_PALETTE[0x30]; 2]);
+        assert_eq!([at(&image, 16, 0), at(&image, 17, 0)], [SYSTEM_PALETTE[0x30], SYSTEM_PALETTE[0x0F]]);
+        let image = ppu.pattern_table_image(1, 5);
+        assert_eq!([at(&image, 31, 7), at(&image, 30, 7)], [SYSTEM_PALETTE[0x2A], SYSTEM_PALETTE[0x0F]]);
+    }
+
+    #[test]
+    fn test_nametables_image() {
+        let mut ppu = render_sprites(&[], 0b0000_0001, 0, true);
+        ppu.write_register(5, 12);
+        ppu.write_register(5, 20);
+        let image = ppu.nametables_image();
+        assert_eq!(image.len(), NAMETABLES_WIDTH * NAMETABLES_HEIGHT * 3);
+        let at = |x: usize, y: usize| {
+            let offset = (y * NAMETABLES_WIDTH + x) * 3;
+            (image[offset], image[offset + 1], image[offset + 2])
+        };
+        let (black, white, outline) = (SYSTEM_PALETTE[0x0F], SYSTEM_PALETTE[0x30], (255, 0, 255));
+        // Horizontal mirroring repeats the top corner tiles in the second nametable.
+        assert_eq!([at(0, 0), at(263, 7), at(264, 7), at(0, 240)], [white, white, black, black]);
+        assert_eq!([at(268, 20), at(511, 20), at(11, 100), at(11, 259)], [outline; 4]);
+        assert_eq!([at(12, 259), at(267, 100)], [black; 2]);
+        assert_eq!(at(300, 100), black);
+    }
+
+    #[test]
+    fn test_sprite_list() {
+        let ppu = render_sprites(&[[9, 2, 0xE1, 20], [30, 1, 0x02, 40]], 0, 0, true);
+        let sprites = ppu.sprites();
+        assert_eq!(sprites.len(), 64);
+        assert_eq!(sprites[0], SpriteInfo {
+            index: 0, y: 9, tile: 2, x: 20, palette: 1, behind_background: true, flip_horizontal: true, flip_vertical: true,
+        });
+        assert_eq!((sprites[1].palette, sprites[1].behind_background, sprites[1].flip_horizontal), (2, false, false));
+
+        let image = ppu.sprites_image();
+        assert_eq!(image.len(), SPRITES_WIDTH * SPRITES_HEIGHT * 3);
+        let at = |x: usize, y: usize| image[(y * SPRITES_WIDTH + x) * 3];
+        assert_eq!([at(7, 7), at(0, 0)], [SYSTEM_PALETTE[0x00].0, SYSTEM_PALETTE[0x0F].0]);
+        let ppu = render_sprites(&[[9, 2, 0xC0, 20]], 0, 0, true);
+        let image = ppu.sprites_image();
+        assert_eq!([image[(7 * SPRITES_WIDTH + 7) * 3], image[0]], [SYSTEM_PALETTE[0x16].0, SYSTEM_PALETTE[0x0F].0]);
+    }
+
+    #[test]
+    fn test_palettes_image() {
+        let ppu = render_sprites(&[], 0, 0, true);
+        assert_eq!(ppu.palette_ram()[..2], [0x0F, 0x30]);
+        assert_eq!(ppu.palette_ram()[0x11], 0x16);
+        let image = ppu.palettes_image();
+        assert_eq!(image.len(), PALETTES_WIDTH * PALETTES_HEIGHT * 3);
+        let at = |x: usize, y: usize| {
+            let offset = (y * PALETTES_WIDTH + x) * 3;
+            (image[offset], image[offset + 1], image[offset + 2])
+        };
+        assert_eq!([at(16, 0), at(31, 15), at(16, 16), at(32, 0)], [SYSTEM_PALETTE[0x30], SYSTEM_PALETTE[0x30], SYSTEM_PALETTE[0x16], SYSTEM_PALETTE[0x00]]);
+    }
+
+    #[test]
+    fn test_ppu_state_round_trip() {
+        let mut cpu = initialize_cpu(asm!("loop: JMP loop"), Vec::new());
+        write_vram(&mut cpu, 0x0000, &[1, 2, 3]);
+        write_vram(&mut cpu, 0x2000, &[4, 5, 6]);
+        cpu.run_frame(|_| Ok(())).unwrap();
+        let state = cpu.save_state();
+
+        write_vram(&mut cpu, 0x0000, &[0; 3]);
+        write_vram(&mut cpu, 0x2000, &[0; 3]);
+        cpu.run_frame(|_| Ok(())).unwrap();
+        cpu.load_state(&state).unwrap();
+        assert_eq!(cpu.frame(), 1);
+        assert_eq!(cpu.ppu().read_vram(0x0002), 3);
+        assert_eq!(cpu.ppu().read_vram(0x2002), 6);
+        assert_eq!(cpu.save_state(), state);
+    }
+
+    #[test]
+    fn test_encode_png() {
+        let rgb: Vec<u8> = (0..2 * 2 * 3).map(|value| value as u8).collect();
+        let png = encode_png(2, 2, &rgb);
+        assert_eq!(png[..8], [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]);
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(png[16..29], [0, 0, 0, 2, 0, 0, 0, 2, 8, 2, 0, 0, 0]);
+        assert_eq!(png[png.len() - 12..], [0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]);
+
+        let data = &png[41..png.len() - 16];
+        assert_eq!(data[..7], [0x78, 0x01, 1, 14, 0, !14, 0xFF]);
+        assert_eq!(data[7..21], [0, 0, 1, 2, 3, 4, 5, 0, 6, 7, 8, 9, 10, 11]);
+        assert_eq!(data[21..], [0x01, 0x3B, 0x00, 0x43]);
+    }
+}
@@ -0,0 +1,158 @@
+//! Debug views of the PPU's memory as RGB images, like the viewers other emulators offer.
+
+use crate::common::constants::NAMETABLE_START;
+use crate::ppu::palette::SYSTEM_PALETTE;
+use crate::ppu::{CTRL_BACKGROUND_TABLE, CTRL_SPRITE_TABLE, CTRL_TALL_SPRITES, PPU, SPRITE_BEHIND_BACKGROUND,
+                 SPRITE_FLIP_HORIZONTAL, SPRITE_FLIP_VERTICAL, SPRITE_PALETTE};
+
+/// Pattern tables are 16x16 tiles.
+pub const PATTERN_TABLE_SIZE: usize = 128;
+/// The four nametables are laid out two by two, as they scroll.
+pub const NAMETABLES_WIDTH: usize = 512;
+pub const NAMETABLES_HEIGHT: usize = 480;
+/// OAM is drawn as an 8x8 grid of sprites with room for 8x16 ones.
+pub const SPRITES_WIDTH: usize = 64;
+pub const SPRITES_HEIGHT: usize = 128;
+/// Palette RAM is drawn as two rows of sixteen swatches, background then sprites.
+pub const PALETTE_SWATCH_SIZE: usize = 16;
+pub const PALETTES_WIDTH: usize = 16 * PALETTE_SWATCH_SIZE;
+pub const PALETTES_HEIGHT: usize = 2 * PALETTE_SWATCH_SIZE;
+const SCROLL_WINDOW_COLOR: (u8, u8, u8) = (255, 0, 255);
+
+/// A sprite's OAM entry with its attribute bits decoded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpriteInfo {
+    pub index: u8,
+    /// The top line, one less than where the sprite is drawn.
+    pub y: u8,
+    pub tile: u8,
+    pub x: u8,
+    /// Sprite palette 0 to 3.
+    pub palette: u8,
+    pub behind_background: bool,
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
+}
+
+impl PPU {
+    /// Pattern table 0 or 1 as 128x128 RGB, coloured with palette 0 to 7 (4 to 7 are the
+    /// sprite palettes).
+    pub fn pattern_table_image(&self, table: usize, palette: u8) -> Vec<u8> {
+        let mut image = vec![0; PATTERN_TABLE_SIZE * PATTERN_TABLE_SIZE * 3];
+        for tile in 0..256 {
+            let address = (table as u16 & 1) * 0x1000 + tile * 16;
+            let (left, top) = ((tile % 16) as usize * 8, (tile / 16) as usize * 8);
+            self.draw_tile(&mut image, PATTERN_TABLE_SIZE, (left, top), address, palette & 7, (false, false));
+        }
+        image
+    }
+
+    /// All four nametables as 512x480 RGB with the background pattern table in use, and the
+    /// 256x240 window the next frame starts scrolled to outlined.
+    pub fn nametables_image(&self) -> Vec<u8> {
+        let mut image = vec![0; NAMETABLES_WIDTH * NAMETABLES_HEIGHT * 3];
+        let pattern_table = if self.ctrl & CTRL_BACKGROUND_TABLE != 0 { 0x1000 } else { 0 };
+        for table in 0..4 {
+            let base = NAMETABLE_START + table * 0x400;
+            for row in 0..30 {
+                for column in 0..32 {
+                    let tile = self.read_vram(base + row * 32 + column) as u16;
+                    let attribute = self.read_vram(base + 0x3C0 + (row / 4) * 8 + column / 4);
+                    let palette = attribute >> ((row & 2) * 2 + (column & 2)) & 0b11;
+                    let left = (table % 2) as usize * 256 + column as usize * 8;
+                    let top = (table / 2) as usize * 240 + row as usize * 8;
+                    self.draw_tile(&mut image, NAMETABLES_WIDTH, (left, top), pattern_table + tile * 16, palette, (false, false));
+                }
+            }
+        }
+        let t = self.t as usize;
+        let left = (t >> 10 & 1) * 256 + (t & 0x1F) * 8 + self.fine_x as usize;
+        let top = (t >> 11 & 1) * 240 + (t >> 5 & 0x1F) * 8 + (t >> 12 & 7);
+        for offset in 0..256 {
+            for y in [top, top + 239] {
+                put_pixel(&mut image, NAMETABLES_WIDTH, (left + offset) % NAMETABLES_WIDTH, y % NAMETABLES_HEIGHT, SCROLL_WINDOW_COLOR);
+            }
+        }
+        for offset in 0..240 {
+            for x in [left, left + 255] {
+                put_pixel(&mut image, NAMETABLES_WIDTH, x % NAMETABLES_WIDTH, (top + offset) % NAMETABLES_HEIGHT, SCROLL_WINDOW_COLOR);
+            }
+        }
+        image
+    }
+
+    pub fn sprites(&self) -> Vec<SpriteInfo> {
+        self.oam.chunks(4).enumerate().map(|(index, entry)| SpriteInfo {
+            index: index as u8,
+            y: entry[0],
+            tile: entry[1],
+            x: entry[3],
+            palette: entry[2] & SPRITE_PALETTE,
+            behind_background: entry[2] & SPRITE_BEHIND_BACKGROUND != 0,
+            flip_horizontal: entry[2] & SPRITE_FLIP_HORIZONTAL != 0,
+            flip_vertical: entry[2] & SPRITE_FLIP_VERTICAL != 0,
+        }).collect()
+    }
+
+    /// The 64 sprites in OAM order as a 64x128 RGB grid, drawn with their palettes and flips.
+    pub fn sprites_image(&self) -> Vec<u8> {
+        let mut image = vec![0; SPRITES_WIDTH * SPRITES_HEIGHT * 3];
+        let tall = self.ctrl & CTRL_TALL_SPRITES != 0;
+        for sprite in self.sprites() {
+            let (left, top) = ((sprite.index % 8) as usize * 8, (sprite.index / 8) as usize * 16);
+            let flip = (sprite.flip_horizontal, sprite.flip_vertical);
+            let palette = 4 + sprite.palette;
+            if tall {
+                let address = (sprite.tile as u16 & 1) * 0x1000 + (sprite.tile as u16 & 0xFE) * 16;
+                let (upper, lower) = if sprite.flip_vertical { (address + 16, address) } else { (address, address + 16) };
+                self.draw_tile(&mut image, SPRITES_WIDTH, (left, top), upper, palette, flip);
+                self.draw_tile(&mut image, SPRITES_WIDTH, (left, top + 8), lower, palette, flip);
+            } else {
+                let table = if self.ctrl & CTRL_SPRITE_TABLE != 0 { 0x1000 } else { 0 };
+                self.draw_tile(&mut image, SPRITES_WIDTH, (left, top), table + sprite.tile as u16 * 16, palette, flip);
+            }
+        }
+        image
+    }
+
+    /// The 32 bytes of palette RAM, with the mirrored backdrop entries as written.
+    pub fn palette_ram(&self) -> &[u8; 32] {
+        &self.palette
+    }
+
+    /// Palette RAM as 256x32 RGB swatches, background palettes on top and sprite palettes below.
+    pub fn palettes_image(&self) -> Vec<u8> {
+        let mut image = vec![0; PALETTES_WIDTH * PALETTES_HEIGHT * 3];
+        for (entry, color) in self.palette.iter().enumerate() {
+            let (left, top) = ((entry % 16) * PALETTE_SWATCH_SIZE, (entry / 16) * PALETTE_SWATCH_SIZE);
+            for y in top..top + PALETTE_SWATCH_SIZE {
+                for x in left..left + PALETTE_SWATCH_SIZE {
+                    put_pixel(&mut image, PALETTES_WIDTH, x, y, SYSTEM_PALETTE[(color & 0x3F) as usize]);
+                }
+            }
+        }
+        image
+    }
+
+    /// Draws the 8x8 tile at `address` with its top-left corner at `(left, top)`. Transparent
+    /// pixels get the backdrop colour.
+    fn draw_tile(&self, image: &mut [u8], width: usize, (left, top): (usize, usize), address: u16, palette: u8,
+                 (flip_horizontal, flip_vertical): (bool, bool)) {
+        for row in 0..8 {
+            let source_row = if flip_vertical { 7 - row } else { row };
+            let (low, high) = (self.read_vram(address + source_row), self.read_vram(address + source_row + 8));
+            for column in 0..8 {
+                let bit = if flip_horizontal { column } else { 7 - column };
+                let pixel = (high >> bit & 1) << 1 | low >> bit & 1;
+                let entry = if pixel == 0 { 0 } else { palette as usize * 4 + pixel as usize };
+                let color = SYSTEM_PALETTE[(self.palette[entry] & 0x3F) as usize];
+                put_pixel(image, width, left + column as usize, top + row as usize, color);
+            }
+        }
+    }
+}
+
+fn put_pixel(image: &mut [u8], width: usize, x: usize, y: usize, (red, green, blue): (u8, u8, u8)) {
+    let offset = (y * width + x) * 3;
+    image[offset..offset + 3].copy_from_slice(&[red, green, blue]);
+}
@@ -0,0 +1,254 @@
+mod test;
+
+use std::collections::VecDeque;
+use crate::common::errors::EmulatorError;
+use crate::cpu::CPU;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RewindConfig {
+    pub frames_per_snapshot: u64,
+    pub snapshots_per_keyframe: usize,
+    pub memory_budget: usize,
+}
+
+impl Default for RewindConfig {
+    fn default() -> Self {
+        RewindConfig {
+            frames_per_snapshot: 1,
+            snapshots_per_keyframe: 60,
+            memory_budget: 32 * 1024 * 1024,
+        }
+    }
+}
+
+struct Snapshot {
+    frame: u64,
+    data: Vec<u8>,
+}
+
+/// A keyframe stored whole and the snapshots after it stored as deltas against it.
+struct Group {
+    keyframe: Snapshot,
+    deltas: Vec<Snapshot>,
+}
+
+impl Group {
+    fn size(&self) -> usize {
+        self.keyframe.data.len() + self.deltas.iter().map(|delta| delta.data.len()).sum::<usize>()
+    }
+
+    fn latest_frame(&self) -> u64 {
+        self.deltas.last().unwrap_or(&self.keyframe).frame
+    }
+}
+
+pub struct Rewind {
+    config: RewindConfig,
+    groups: VecDeque<Group>,
+    keyframe: Vec<u8>,
+    next_frame: u64,
+    memory_used: usize,
+    /// The buttons held during each frame in the history, so the frames between snapshots are
+    /// replayed with the input they were played with.
+    inputs: VecDeque<(u64, [u8; 2])>,
+}
+
+impl Default for Rewind {
+    fn default() -> Self {
+        Self::new(RewindConfig::default())
+    }
+}
+
+impl Rewind {
+    pub fn new(config: RewindConfig) -> Self {
+        Rewind {
+            config,
+            groups: VecDeque::new(),
+            keyframe: Vec::new(),
+            next_frame: 0,
+            memory_used: 0,
+            inputs: VecDeque::new(),
+        }
+    }
+
+    pub fn config(&self) -> RewindConfig {
+        self.config
+    }
+
+    pub fn memory_used(&self) -> usize {
+        self.memory_used
+    }
+
+    pub fn snapshot_count(&self) -> usize {
+        self.groups.iter().map(|group| 1 + group.deltas.len()).sum()
+    }
+
+    /// Number of frames the machine can currently be stepped back.
+    pub fn available_frames(&self) -> u64 {
+        match self.groups.front() {
+            Some(group) => self.next_frame - 1 - group.keyframe.frame,
+            None => 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.groups.clear();
+        self.keyframe.clear();
+        self.memory_used = 0;
+        self.inputs.clear();
+    }
+
+    /// Call once per emulated frame with the state at the end of that frame.
+    pub fn record(&mut self, cpu: &CPU) {
+        let frame = self.next_frame;
+        self.next_frame += 1;
+        self.inputs.push_back((frame, cpu.bus.buttons()));
+        if !frame.is_multiple_of(self.config.frames_per_snapshot.max(1)) {
+            return;
+        }
+        let state = cpu.save_state();
+        let start_group = self.groups.back()
+            .is_none_or(|group| group.deltas.len() + 1 >= self.config.snapshots_per_keyframe || state.len() != self.keyframe.len());
+        if start_group {
+            let keyframe = Snapshot { frame, data: compress(&state) };
+            self.memory_used += keyframe.data.len();
+            self.groups.push_back(Group { keyframe, deltas: Vec::new() });
+            self.keyframe = state;
+        } else {
+            let delta: Vec<u8> = state.iter().zip(&self.keyframe).map(|(byte, base)| byte ^ base).collect();
+            let delta = Snapshot { frame, data: compress(&delta) };
+            self.memory_used += delta.data.len();
+            self.groups.back_mut().unwrap().deltas.push(delta);
+        }
+        while self.memory_used > self.config.memory_budget && self.groups.len() > 1 {
+            let group = self.groups.pop_front().unwrap();
+            self.memory_used -= group.size();
+        }
+        let oldest = self.groups.front().map_or(frame, |group| group.keyframe.frame);
+        while self.inputs.front().is_some_and(|(frame, _)| *frame < oldest) {
+            self.inputs.pop_front();
+        }
+    }
+
+    /// Restores the state one frame before the current one. The frames since the snapshot before
+    /// it are re-emulated with the input recorded for them, so the PPU frame buffer matches too
+    /// (except at the oldest snapshot, which has nothing earlier to start from). Returns `false`
+    /// when no history is left.
+    pub fn step_back(&mut self, cpu: &mut CPU) -> Result<bool, EmulatorError> {
+        if self.available_frames() == 0 {
+            return Ok(false);
+        }
+        let target = self.next_frame - 2;
+        while self.groups.back().is_some_and(|group| group.keyframe.frame > target) {
+            let group = self.groups.pop_back().unwrap();
+            self.memory_used -= group.size();
+        }
+        let group = self.groups.back_mut().unwrap();
+        while group.latest_frame() > target {
+            let delta = group.deltas.pop().unwrap();
+            self.memory_used -= delta.data.len();
+        }
+        while self.inputs.back().is_some_and(|(frame, _)| *frame > target) {
+            self.inputs.pop_back();
+        }
+
+        self.keyframe = decompress(&group.keyframe.data);
+        let (frame, state) = self.snapshot_before(target);
+        cpu.load_state(&state)?;
+        for (_, buttons) in self.inputs.iter().filter(|(replayed, _)| (frame + 1..=target).contains(replayed)) {
+            cpu.controller(0).set_buttons(buttons[0]);
+            cpu.controller(1).set_buttons(buttons[1]);
+            if !cpu.run_frame(|_| Ok(()))? {
+                // The program stopped, which it can't have done the first time through, so the
+                // snapshot at the target is all there is to go on.
+                break;
+            }
+        }
+        // Anything the replay couldn't reproduce, like a reset, is put right by the snapshot
+        // taken at the target itself when there is one.
+        if let Some(state) = self.snapshot_at(target) {
+            cpu.load_state(&state)?;
+        }
+        self.next_frame = target + 1;
+        Ok(true)
+    }
+
+    /// The newest snapshot taken before `frame`, or the one at `frame` when there is none.
+    fn snapshot_before(&self, frame: u64) -> (u64, Vec<u8>) {
+        for group in self.groups.iter().rev() {
+            if let Some(delta) = group.deltas.iter().rev().find(|delta| delta.frame < frame) {
+                return (delta.frame, apply_delta(group, delta));
+            }
+            if group.keyframe.frame < frame {
+                return (group.keyframe.frame, decompress(&group.keyframe.data));
+            }
+        }
+        (frame, self.keyframe.clone())
+    }
+
+    /// The snapshot taken at `frame`, if it was one of the snapshotted frames.
+    fn snapshot_at(&self, frame: u64) -> Option<Vec<u8>> {
+        let group = self.groups.iter().rev().find(|group| group.keyframe.frame <= frame)?;
+        if group.keyframe.frame == frame {
+            return Some(decompress(&group.keyframe.data));
+        }
+        group.deltas.iter().find(|delta| delta.frame == frame).map(|delta| apply_delta(group, delta))
+    }
+}
+
+fn apply_delta(group: &Group, delta: &Snapshot) -> Vec<u8> {
+    let keyframe = decompress(&group.keyframe.data);
+    decompress(&delta.data).iter().zip(&keyframe).map(|(byte, base)| byte ^ base).collect()
+}
+
+/// Run-length encodes zero bytes, which dominate both XOR deltas and mostly idle memory:
+/// each block is a zero run length and a literal length (both LEB128), then the literals.
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut compressed = Vec::new();
+    let mut position = 0;
+    while position < data.len() {
+        let zeros = data[position..].iter().take_while(|byte| **byte == 0).count();
+        position += zeros;
+        let literals = data[position..].windows(2).position(|pair| pair == [0, 0]).unwrap_or(data.len() - position);
+        write_length(&mut compressed, zeros);
+        write_length(&mut compressed, literals);
+        compressed.extend_from_slice(&data[position..position + literals]);
+        position += literals;
+    }
+    compressed
+}
+
+pub fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut decompressed = Vec::new();
+    let mut position = 0;
+    while position < data.len() {
+        let zeros = read_length(data, &mut position);
+        let literals = read_length(data, &mut position);
+        decompressed.resize(decompressed.len() + zeros, 0);
+        decompressed.extend_from_slice(&data[position..position + literals]);
+        position += literals;
+    }
+    decompressed
+}
+
+fn write_length(output: &mut Vec<u8>, mut length: usize) {
+    while length >= 0x80 {
+        output.push((length & 0x7F) as u8 | 0x80);
+        length >>= 7;
+    }
+    output.push(length as u8);
+}
+
+fn read_length(data: &[u8], position: &mut usize) -> usize {
+    let mut length = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*position];
+        *position += 1;
+        length |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return length;
+        }
+        shift += 7;
+    }
+}
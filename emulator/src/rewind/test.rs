@@ -0,0 +1,154 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod test {
+    use crate::asm;
+    use crate::cpu::CPU;
+    use crate::memory::memory::Memory;
+    use crate::rewind::{compress, decompress, Rewind, RewindConfig};
+
+    fn counter_program() -> Vec<u8> {
+        asm!(
+            "loop:   INC $10",
+            "        BNE loop",
+            "        INC $11",
+            "        JMP loop",
+        )
+    }
+
+    fn run_and_record(cpu: &mut CPU, rewind: &mut Rewind, frames: usize) -> Vec<Vec<u8>> {
+        let mut states = Vec::new();
+        for _ in 0..frames {
+            cpu.run_frame(|_| Ok(())).unwrap();
+            rewind.record(cpu);
+            states.push(cpu.save_state());
+        }
+        states
+    }
+
+    #[test]
+    fn test_compression_round_trip() {
+        let inputs: Vec<Vec<u8>> = vec![
+            vec![],
+            vec![0; 1000],
+            vec![1, 2, 3],
+            vec![0, 1, 0, 0, 0, 2, 0],
+            (0..5000).map(|i| if i % 97 < 3 { i as u8 } else { 0 }).collect(),
+        ];
+        for input in inputs {
+            assert_eq!(decompress(&compress(&input)), input);
+        }
+        assert!(compress(&[0; 4096]).len() <= 4);
+    }
+
+    #[test]
+    fn test_step_back_every_frame() {
+        let mut cpu = CPU::with_program(&counter_program());
+        let mut rewind = Rewind::new(RewindConfig { snapshots_per_keyframe: 4, ..RewindConfig::default() });
+        let states = run_and_record(&mut cpu, &mut rewind, 10);
+        assert_eq!(rewind.snapshot_count(), 10);
+        assert_eq!(rewind.available_frames(), 9);
+
+        for expected in states[..9].iter().rev() {
+            assert!(rewind.step_back(&mut cpu).unwrap());
+            assert_eq!(&cpu.save_state(), expected);
+        }
+        assert!(!rewind.step_back(&mut cpu).unwrap());
+        assert_eq!(cpu.save_state(), states[0]);
+
+        let replayed = run_and_record(&mut cpu, &mut rewind, 3);
+        assert_eq!(replayed, states[1..4]);
+        assert_eq!(rewind.available_frames(), 3);
+    }
+
+    #[test]
+    fn test_step_back_with_sparse_snapshots() {
+        let mut cpu = CPU::with_program(&counter_program());
+        let mut rewind = Rewind::new(RewindConfig { frames_per_snapshot: 3, ..RewindConfig::default() });
+        let states = run_and_record(&mut cpu, &mut rewind, 8);
+        assert_eq!(rewind.snapshot_count(), 3);
+
+        for expected in states[..7].iter().rev() {
+            assert!(rewind.step_back(&mut cpu).unwrap());
+            assert_eq!(&cpu.save_state(), expected);
+        }
+        assert!(!rewind.step_back(&mut cpu).unwrap());
+    }
+
+    #[test]
+    fn test_step_back_replays_recorded_input() {
+        // Adds up the buttons read each time round, so any frame replayed with the wrong input
+        // leaves a different total.
+        let program = asm!(
+            "loop:   LDA #$01",
+            "        STA $4016",
+            "        LDA #$00",
+            "        STA $4016",
+            "        LDA $4016",
+            "        AND #$01",
+            "        CLC",
+            "        ADC $10",
+            "        STA $10",
+            "        JMP loop",
+        );
+        for frames_per_snapshot in [1, 3] {
+            let mut cpu = CPU::with_program(&program.clone());
+            let mut rewind = Rewind::new(RewindConfig { frames_per_snapshot, ..RewindConfig::default() });
+            let mut states = Vec::new();
+            for frame in 0..8 {
+                cpu.controller(0).set_buttons(if frame % 3 == 1 { 0x01 } else { 0x00 });
+                cpu.controller(1).set_buttons(frame);
+                cpu.run_frame(|_| Ok(())).unwrap();
+                rewind.record(&cpu);
+                states.push(cpu.save_state());
+            }
+
+            for expected in states[..7].iter().rev() {
+                assert!(rewind.step_back(&mut cpu).unwrap());
+                assert_eq!(&cpu.save_state(), expected, "{} frames per snapshot", frames_per_snapshot);
+            }
+        }
+    }
+
+    #[test]
+    fn test_step_back_restores_picture() {
+        let program = asm!(
+            "loop:   LDA #$3F",
+            "        STA $2006",
+            "        LDA #$00",
+            "        STA $2006",
+            "        INC $10",
+            "        LDA $10",
+            "        STA $2007",
+            "        JMP loop",
+        );
+        let mut cpu = CPU::with_program(&program);
+        let mut rewind = Rewind::default();
+        let mut pictures = Vec::new();
+        for _ in 0..4 {
+            cpu.run_frame(|_| Ok(())).unwrap();
+            rewind.record(&cpu);
+            pictures.push(cpu.ppu().frame_buffer().to_vec());
+        }
+        assert_ne!(pictures[1], pictures[2]);
+        for expected in pictures[1..3].iter().rev() {
+            assert!(rewind.step_back(&mut cpu).unwrap());
+            assert_eq!(cpu.ppu().frame_buffer(), expected.as_slice());
+        }
+    }
+
+    #[test]
+    fn test_memory_budget_evicts_oldest_history() {
+        let mut cpu = CPU::with_program(&counter_program());
+        let mut rewind = Rewind::new(RewindConfig { frames_per_snapshot: 1, snapshots_per_keyframe: 5, memory_budget: 0 });
+        run_and_record(&mut cpu, &mut rewind, 12);
+        assert_eq!(rewind.snapshot_count(), 2);
+        assert_eq!(rewind.available_frames(), 1);
+
+        let mut unlimited = Rewind::new(RewindConfig { memory_budget: usize::MAX, ..RewindConfig::default() });
+        let mut cpu = CPU::with_program(&counter_program());
+        let states = run_and_record(&mut cpu, &mut unlimited, 12);
+        assert!(unlimited.memory_used() < states[0].len());
+        assert_eq!(unlimited.available_frames(), 11);
+        assert_ne!(cpu.read(0x11).unwrap(), 0);
+    }
+}
@@ -0,0 +1,365 @@
+mod test;
+
+use crate::apu::APU;
+use crate::common::constants::{SAVE_STATE_HEADER_SIZE, SAVE_STATE_MAGIC, SAVE_STATE_VERSION, VRAM_SIZE};
+use crate::common::errors::EmulatorError;
+use crate::common::util::crc32;
+use crate::cpu::CPU;
+use crate::cpu::types::ProcessorStatus;
+
+type Migration = fn(&mut SaveState) -> Result<(), EmulatorError>;
+
+/// `MIGRATIONS[n]` upgrades a state from version `n + 1` to `n + 2`.
+const MIGRATIONS: [Migration; SAVE_STATE_VERSION as usize - 1] = [
+    add_controllers, add_ppu, add_apu, add_ppu_scroll_registers, add_line_sprites, add_nametable_mapping, add_ppu_io_latch,
+    add_open_bus, add_interrupt_latches,
+];
+
+fn add_controllers(state: &mut SaveState) -> Result<(), EmulatorError> {
+    let mut controllers = Section::new(*b"CTRL");
+    controllers.write_bytes(&[0; 6]);
+    state.add_section(controllers);
+    Ok(())
+}
+
+/// Adds a powered-on PPU in the version 3 layout, which `add_ppu_scroll_registers` converts.
+fn add_ppu(state: &mut SaveState) -> Result<(), EmulatorError> {
+    let mut ppu = Section::new(*b"PPU ");
+    ppu.write_bytes(&[0; 23 + VRAM_SIZE + 32 + 256 + 4]);
+    state.add_section(ppu);
+    Ok(())
+}
+
+fn add_apu(state: &mut SaveState) -> Result<(), EmulatorError> {
+    APU::new().save_state(state);
+    Ok(())
+}
+
+/// Replaces the PPU's scroll and address registers with the internal v, t, x and w registers and
+/// adds the dot renderer's state, which starts out empty and fills in over the next few dots.
+fn add_ppu_scroll_registers(state: &mut SaveState) -> Result<(), EmulatorError> {
+    let mut old = state.section(*b"PPU ")?;
+    let mut ppu = Section::new(*b"PPU ");
+    let ctrl = old.read_u8()?;
+    ppu.write_u8(ctrl);
+    ppu.write_bytes(old.read_bytes(3)?);
+    let [scroll_x, scroll_y] = old.read_array()?;
+    let v = old.read_u16()?;
+    let t = ((scroll_y & 0x07) as u16) << 12 | ((ctrl & 0x03) as u16) << 10 | ((scroll_y >> 3) as u16) << 5 | (scroll_x >> 3) as u16;
+    ppu.write_u16(v);
+    ppu.write_u16(t);
+    ppu.write_u8(scroll_x & 0x07);
+    let w = old.read_bool()?;
+    ppu.write_bool(w);
+    ppu.write_u8(old.read_u8()?);
+    let (scanline, dot, frame, nmi) = (old.read_u16()?, old.read_u16()?, old.read_u64()?, old.read_bool()?);
+    ppu.write_u16(scanline);
+    ppu.write_u16(dot);
+    ppu.write_u64(frame);
+    ppu.write_bool(frame % 2 == 1);
+    ppu.write_bool(nmi);
+    // Vblank suppression, the next tile's fetched bytes and the four shift registers.
+    ppu.write_bytes(&[0; 1 + 4 + 8]);
+    ppu.write_bytes(old.read_bytes(VRAM_SIZE + 32 + 256)?);
+    let chr_ram_length = old.read_u32()?;
+    ppu.write_u32(chr_ram_length);
+    ppu.write_bytes(old.read_bytes(chr_ram_length as usize)?);
+    old.finish()?;
+    state.add_section(ppu);
+    Ok(())
+}
+
+/// Appends an empty list of sprites evaluated for the next line to the PPU.
+fn add_line_sprites(state: &mut SaveState) -> Result<(), EmulatorError> {
+    state.section(*b"PPU ")?;
+    if let Some(mut ppu) = state.remove_section(*b"PPU ") {
+        ppu.write_u8(0);
+        state.add_section(ppu);
+    }
+    Ok(())
+}
+
+/// Appends the PPU's nametable mapping, left as the cartridge's, and no cartridge nametable RAM.
+fn add_nametable_mapping(state: &mut SaveState) -> Result<(), EmulatorError> {
+    state.section(*b"PPU ")?;
+    if let Some(mut ppu) = state.remove_section(*b"PPU ") {
+        ppu.write_bool(false);
+        ppu.write_u32(0);
+        state.add_section(ppu);
+    }
+    Ok(())
+}
+
+/// Appends the PPU's register latch, decayed to 0.
+fn add_ppu_io_latch(state: &mut SaveState) -> Result<(), EmulatorError> {
+    state.section(*b"PPU ")?;
+    if let Some(mut ppu) = state.remove_section(*b"PPU ") {
+        ppu.write_u8(0);
+        for _ in 0..8 {
+            ppu.write_u64(0);
+        }
+        state.add_section(ppu);
+    }
+    Ok(())
+}
+
+/// Appends the CPU data bus to RAM.
+fn add_open_bus(state: &mut SaveState) -> Result<(), EmulatorError> {
+    state.section(*b"RAM ")?;
+    if let Some(mut ram) = state.remove_section(*b"RAM ") {
+        ram.write_u8(0);
+        state.add_section(ram);
+    }
+    Ok(())
+}
+
+/// Appends the CPU's interrupt latches, with no interrupt seen on the last two cycles.
+fn add_interrupt_latches(state: &mut SaveState) -> Result<(), EmulatorError> {
+    state.section(*b"CPU ")?;
+    if let Some(mut registers) = state.remove_section(*b"CPU ") {
+        registers.write_bool(false);
+        registers.write_bool(false);
+        state.add_section(registers);
+    }
+    Ok(())
+}
+
+pub struct Section {
+    tag: [u8; 4],
+    data: Vec<u8>,
+}
+
+impl Section {
+    pub fn new(tag: [u8; 4]) -> Self {
+        Section { tag, data: Vec::new() }
+    }
+
+    pub fn tag(&self) -> [u8; 4] {
+        self.tag
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+}
+
+pub struct SectionReader<'a> {
+    tag: [u8; 4],
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> SectionReader<'a> {
+    pub fn read_u8(&mut self) -> Result<u8, EmulatorError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, EmulatorError> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            value => Err(self.error(&format!("invalid boolean {}", value))),
+        }
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, EmulatorError> {
+        Ok(u16::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, EmulatorError> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, EmulatorError> {
+        Ok(u64::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], EmulatorError> {
+        let bytes = self.data.get(self.position..self.position + length).ok_or_else(|| self.error("truncated"))?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    pub fn read_array<const N: usize>(&mut self) -> Result<[u8; N], EmulatorError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.read_bytes(N)?);
+        Ok(array)
+    }
+
+    pub fn finish(self) -> Result<(), EmulatorError> {
+        if self.position == self.data.len() {
+            Ok(())
+        } else {
+            Err(self.error("unexpected trailing data"))
+        }
+    }
+
+    fn error(&self, message: &str) -> EmulatorError {
+        EmulatorError::InvalidSaveState(format!("section {}: {}", String::from_utf8_lossy(&self.tag), message))
+    }
+}
+
+pub struct SaveState {
+    pub version: u16,
+    sections: Vec<Section>,
+}
+
+impl Default for SaveState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SaveState {
+    pub fn new() -> Self {
+        SaveState { version: SAVE_STATE_VERSION, sections: Vec::new() }
+    }
+
+    pub fn add_section(&mut self, section: Section) {
+        self.remove_section(section.tag);
+        self.sections.push(section);
+    }
+
+    pub fn remove_section(&mut self, tag: [u8; 4]) -> Option<Section> {
+        let index = self.sections.iter().position(|section| section.tag == tag)?;
+        Some(self.sections.remove(index))
+    }
+
+    pub fn has_section(&self, tag: [u8; 4]) -> bool {
+        self.sections.iter().any(|section| section.tag == tag)
+    }
+
+    pub fn section(&self, tag: [u8; 4]) -> Result<SectionReader<'_>, EmulatorError> {
+        self.sections.iter()
+            .find(|section| section.tag == tag)
+            .map(|section| SectionReader { tag, data: &section.data, position: 0 })
+            .ok_or_else(|| EmulatorError::InvalidSaveState(format!("missing section {}", String::from_utf8_lossy(&tag))))
+    }
+
+    pub fn encode(&self, rom_crc: u32) -> Vec<u8> {
+        let mut payload = Vec::new();
+        for section in &self.sections {
+            payload.extend_from_slice(&section.tag);
+            payload.extend_from_slice(&(section.data.len() as u32).to_le_bytes());
+            payload.extend_from_slice(&section.data);
+        }
+        let mut bytes = Vec::with_capacity(SAVE_STATE_HEADER_SIZE + payload.len());
+        bytes.extend_from_slice(&SAVE_STATE_MAGIC);
+        bytes.extend_from_slice(&self.version.to_le_bytes());
+        bytes.extend_from_slice(&rom_crc.to_le_bytes());
+        bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&crc32(&payload).to_le_bytes());
+        bytes.extend_from_slice(&payload);
+        bytes
+    }
+
+    /// Validates the header and checksums, then migrates the state to the current version.
+    pub fn decode(bytes: &[u8], rom_crc: u32) -> Result<SaveState, EmulatorError> {
+        let invalid = |message: &str| EmulatorError::InvalidSaveState(message.to_string());
+        if bytes.len() < SAVE_STATE_HEADER_SIZE || bytes[0..4] != SAVE_STATE_MAGIC {
+            return Err(invalid("not a save state"));
+        }
+        let field = |offset: usize| u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+        let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+        if version == 0 || version > SAVE_STATE_VERSION {
+            return Err(EmulatorError::UnsupportedSaveStateVersion(version));
+        }
+        if field(6) != rom_crc {
+            return Err(EmulatorError::SaveStateRomMismatch(field(6), rom_crc));
+        }
+        let payload = &bytes[SAVE_STATE_HEADER_SIZE..];
+        if payload.len() != field(10) as usize {
+            return Err(invalid("truncated payload"));
+        }
+        if crc32(payload) != field(14) {
+            return Err(invalid("checksum mismatch"));
+        }
+
+        let mut state = SaveState { version, sections: Vec::new() };
+        let mut rest = payload;
+        while !rest.is_empty() {
+            if rest.len() < 8 {
+                return Err(invalid("truncated section header"));
+            }
+            let tag = [rest[0], rest[1], rest[2], rest[3]];
+            let length = u32::from_le_bytes([rest[4], rest[5], rest[6], rest[7]]) as usize;
+            let data = rest.get(8..8 + length).ok_or_else(|| invalid("truncated section"))?;
+            state.sections.push(Section { tag, data: data.to_vec() });
+            rest = &rest[8 + length..];
+        }
+        state.migrate()?;
+        Ok(state)
+    }
+
+    fn migrate(&mut self) -> Result<(), EmulatorError> {
+        while self.version < SAVE_STATE_VERSION {
+            MIGRATIONS[self.version as usize - 1](self)?;
+            self.version += 1;
+        }
+        Ok(())
+    }
+}
+
+pub fn save(cpu: &CPU) -> Vec<u8> {
+    let mut state = SaveState::new();
+    let mut registers = Section::new(*b"CPU ");
+    registers.write_u16(cpu.program_counter);
+    registers.write_u8(cpu.stack_pointer);
+    registers.write_u8(cpu.register_a);
+    registers.write_u8(cpu.register_x);
+    registers.write_u8(cpu.register_y);
+    registers.write_u8(cpu.status.to_u8());
+    registers.write_u64(cpu.cycles);
+    registers.write_bool(cpu.interrupt_line);
+    registers.write_bool(cpu.interrupt_polled);
+    state.add_section(registers);
+    cpu.bus.save_state(&mut state);
+    state.encode(cpu.bus.rom_crc())
+}
+
+pub fn load(cpu: &mut CPU, bytes: &[u8]) -> Result<(), EmulatorError> {
+    let state = SaveState::decode(bytes, cpu.bus.rom_crc())?;
+    let mut registers = state.section(*b"CPU ")?;
+    let program_counter = registers.read_u16()?;
+    let stack_pointer = registers.read_u8()?;
+    let register_a = registers.read_u8()?;
+    let register_x = registers.read_u8()?;
+    let register_y = registers.read_u8()?;
+    let status = registers.read_u8()?;
+    let cycles = registers.read_u64()?;
+    let interrupt_line = registers.read_bool()?;
+    let interrupt_polled = registers.read_bool()?;
+    registers.finish()?;
+    cpu.bus.load_state(&state)?;
+
+    cpu.program_counter = program_counter;
+    cpu.stack_pointer = stack_pointer;
+    cpu.register_a = register_a;
+    cpu.register_x = register_x;
+    cpu.register_y = register_y;
+    cpu.status = ProcessorStatus::from_u8(status);
+    cpu.cycles = cycles;
+    cpu.interrupt_line = interrupt_line;
+    cpu.interrupt_polled = interrupt_polled;
+    Ok(())
+}
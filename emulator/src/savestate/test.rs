@@ -0,0 +1,237 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod test {
+    use crate::asm;
+    use crate::common::constants::{RAM_SIZE, SAVE_STATE_HEADER_SIZE, SAVE_STATE_VERSION, VRAM_SIZE};
+    use crate::common::errors::EmulatorError;
+    use crate::common::util::crc32;
+    use crate::cpu::CPU;
+    use crate::cpu::types::Core;
+    use crate::memory::memory::Memory;
+    use crate::savestate::{SaveState, Section};
+
+    fn counter_program() -> Vec<u8> {
+        asm!(
+            "loop:   INC $10",
+            "        INC $6000",
+            "        LDX $10",
+            "        JMP loop",
+        )
+    }
+
+    fn run(cpu: &mut CPU, instructions: usize) {
+        for _ in 0..instructions {
+            cpu.step().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_save_and_load_round_trip() {
+        let mut cpu = CPU::with_program(&counter_program());
+        run(&mut cpu, 10);
+        cpu.status.carry = true;
+        let state = cpu.save_state();
+        let (pc, x, cycles) = (cpu.program_counter, cpu.register_x, cpu.cycles);
+
+        run(&mut cpu, 25);
+        cpu.status.carry = false;
+        assert_ne!(cpu.read(0x10).unwrap(), 3);
+
+        cpu.load_state(&state).unwrap();
+        assert_eq!(cpu.program_counter, pc);
+        assert_eq!(cpu.register_x, x);
+        assert_eq!(cpu.cycles, cycles);
+        assert!(cpu.status.carry);
+        assert_eq!(cpu.read(0x10).unwrap(), 3);
+        assert_eq!(cpu.read(0x6000).unwrap(), 3);
+        assert_eq!(cpu.save_state(), state);
+    }
+
+    #[test]
+    fn test_load_rejects_other_rom() {
+        let cpu = CPU::with_program(&counter_program());
+        let state = cpu.save_state();
+        let mut other = CPU::with_program(&asm!("NOP"));
+        let result = other.load_state(&state);
+        assert!(matches!(result, Err(EmulatorError::SaveStateRomMismatch(..))));
+        assert!(result.unwrap_err().to_string().contains("different ROM"));
+    }
+
+    #[test]
+    fn test_load_rejects_corrupt_states() {
+        let mut cpu = CPU::with_program(&counter_program());
+        let state = cpu.save_state();
+
+        let mut corrupted = state.clone();
+        *corrupted.last_mut().unwrap() ^= 0xFF;
+        assert!(matches!(cpu.load_state(&corrupted), Err(EmulatorError::InvalidSaveState(_))));
+        assert!(matches!(cpu.load_state(&state[..state.len() - 1]), Err(EmulatorError::InvalidSaveState(_))));
+        assert!(matches!(cpu.load_state(b"NES\x1A"), Err(EmulatorError::InvalidSaveState(_))));
+
+        let mut future = state.clone();
+        future[4..6].copy_from_slice(&(SAVE_STATE_VERSION + 1).to_le_bytes());
+        assert!(matches!(cpu.load_state(&future), Err(EmulatorError::UnsupportedSaveStateVersion(_))));
+    }
+
+    #[test]
+    fn test_missing_section() {
+        let mut cpu = CPU::with_program(&counter_program());
+        let mut state = SaveState::decode(&cpu.save_state(), cpu.bus.rom_crc()).unwrap();
+        assert!(state.remove_section(*b"PRAM").is_some());
+        let result = cpu.load_state(&state.encode(cpu.bus.rom_crc()));
+        assert!(matches!(result, Err(EmulatorError::InvalidSaveState(message)) if message.contains("PRAM")));
+    }
+
+    #[test]
+    fn test_failed_load_leaves_machine_alone() {
+        let mut cpu = CPU::with_program(&counter_program());
+        run(&mut cpu, 10);
+        let mut state = SaveState::decode(&cpu.save_state(), cpu.bus.rom_crc()).unwrap();
+        state.add_section(Section::new(*b"APU "));
+        let broken = state.encode(cpu.bus.rom_crc());
+
+        cpu.write(0x0300, 0x42).unwrap();
+        cpu.run_frame(|_| Ok(())).unwrap();
+        let before = cpu.save_state();
+        assert!(cpu.load_state(&broken).is_err());
+        assert_eq!(cpu.save_state(), before);
+    }
+
+    #[test]
+    fn test_section_reader() {
+        let mut section = Section::new(*b"TEST");
+        section.write_u8(1);
+        section.write_bool(true);
+        section.write_u16(0x1234);
+        section.write_u32(0xDEAD_BEEF);
+        section.write_u64(u64::MAX);
+        let mut state = SaveState::new();
+        state.add_section(section);
+
+        let bytes = state.encode(0);
+        assert_eq!(bytes.len(), SAVE_STATE_HEADER_SIZE + 8 + 16);
+        let state = SaveState::decode(&bytes, 0).unwrap();
+        let mut reader = state.section(*b"TEST").unwrap();
+        assert_eq!(reader.read_u8().unwrap(), 1);
+        assert!(reader.read_bool().unwrap());
+        assert_eq!(reader.read_u16().unwrap(), 0x1234);
+        assert_eq!(reader.read_u32().unwrap(), 0xDEAD_BEEF);
+        assert_eq!(reader.read_u64().unwrap(), u64::MAX);
+        assert!(reader.read_u8().is_err());
+
+        let mut reader = state.section(*b"TEST").unwrap();
+        reader.read_u8().unwrap();
+        assert!(reader.finish().is_err());
+    }
+
+    /// Drops the data bus from RAM and the interrupt latches from the CPU, which states before
+    /// versions 9 and 10 don't have.
+    fn remove_version_9_and_10_fields(state: &mut SaveState) {
+        let cpu_ram = state.section(*b"RAM ").unwrap().read_bytes(RAM_SIZE).unwrap().to_vec();
+        let mut ram = Section::new(*b"RAM ");
+        ram.write_bytes(&cpu_ram);
+        state.add_section(ram);
+        let registers = state.section(*b"CPU ").unwrap().read_bytes(15).unwrap().to_vec();
+        let mut cpu = Section::new(*b"CPU ");
+        cpu.write_bytes(&registers);
+        state.add_section(cpu);
+    }
+
+    #[test]
+    fn test_load_restores_interrupt_latches() {
+        let program = asm!(
+            "        LDA #$80",
+            "        STA $2000",
+            "loop:   INX",
+            "        JMP loop",
+        );
+        let mut cpu = CPU::with_interrupt_handlers(Core::CycleStepped, &program, &asm!("RTI"));
+        let step = |cpu: &mut CPU| {
+            cpu.step().unwrap();
+            (cpu.program_counter, cpu.interrupted_at, cpu.cycles)
+        };
+        run(&mut cpu, 2);
+        let quiet = cpu.save_state();
+        let quiet_step = step(&mut cpu);
+
+        // Run until the NMI arrives on the last cycle of an instruction, so the one after it
+        // is interrupted because the line was already up when it started.
+        while cpu.interrupted_at.is_some() || !cpu.interrupt_line || cpu.interrupt_polled {
+            cpu.step().unwrap();
+        }
+        let pending = cpu.save_state();
+        let pending_step = step(&mut cpu);
+        assert!(pending_step.1.is_some());
+
+        cpu.load_state(&quiet).unwrap();
+        assert!(!cpu.interrupt_line && !cpu.interrupt_polled);
+        assert_eq!(step(&mut cpu), quiet_step);
+        cpu.load_state(&pending).unwrap();
+        assert!(cpu.interrupt_line && !cpu.interrupt_polled);
+        assert_eq!(step(&mut cpu), pending_step);
+    }
+
+    #[test]
+    fn test_migrates_version_1_states() {
+        let mut cpu = CPU::with_program(&counter_program());
+        run(&mut cpu, 10);
+        cpu.controller(0).set_buttons(0xFF);
+        let mut state = SaveState::decode(&cpu.save_state(), cpu.bus.rom_crc()).unwrap();
+        state.remove_section(*b"CTRL");
+        state.remove_section(*b"PPU ");
+        state.remove_section(*b"APU ");
+        remove_version_9_and_10_fields(&mut state);
+        state.version = 1;
+        let version_1 = state.encode(cpu.bus.rom_crc());
+
+        cpu.load_state(&version_1).unwrap();
+        assert_eq!(cpu.controller(0).buttons(), 0);
+        assert_eq!(cpu.read(0x10).unwrap(), 3);
+        let migrated = SaveState::decode(&version_1, cpu.bus.rom_crc()).unwrap();
+        assert_eq!(migrated.version, SAVE_STATE_VERSION);
+        assert!(migrated.has_section(*b"CTRL"));
+        assert!(migrated.has_section(*b"PPU "));
+        assert!(migrated.has_section(*b"APU "));
+    }
+
+    #[test]
+    fn test_migrates_version_4_ppu_registers() {
+        let mut cpu = CPU::with_program(&counter_program());
+        let mut state = SaveState::decode(&cpu.save_state(), cpu.bus.rom_crc()).unwrap();
+        let mut ppu = Section::new(*b"PPU ");
+        ppu.write_bytes(&[0x01, 0x08, 0x00, 0x00, 0x0D, 0x2B]);
+        ppu.write_u16(0x2345);
+        ppu.write_bool(true);
+        ppu.write_u8(0x99);
+        ppu.write_u16(100);
+        ppu.write_u16(50);
+        ppu.write_u64(7);
+        ppu.write_bool(false);
+        let mut vram = [0; VRAM_SIZE];
+        vram[5] = 0x42;
+        ppu.write_bytes(&vram);
+        ppu.write_bytes(&[0; 32 + 256]);
+        ppu.write_u32(0);
+        state.add_section(ppu);
+        remove_version_9_and_10_fields(&mut state);
+        state.version = 4;
+        let version_4 = state.encode(cpu.bus.rom_crc());
+
+        cpu.load_state(&version_4).unwrap();
+        assert_eq!((cpu.scanline(), cpu.ppu().dot(), cpu.frame()), (100, 50, 7));
+        assert_eq!(cpu.ppu().vram_address(), 0x2345);
+        assert_eq!(cpu.ppu().read_vram(0x2005), 0x42);
+        let migrated = SaveState::decode(&version_4, cpu.bus.rom_crc()).unwrap();
+        let mut reader = migrated.section(*b"PPU ").unwrap();
+        reader.read_bytes(6).unwrap();
+        assert_eq!(reader.read_u16().unwrap(), 0x34A1);
+        assert_eq!(reader.read_u8().unwrap(), 5);
+        assert!(reader.read_bool().unwrap());
+    }
+}
@@ -1,4 +1,5 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod test {
     use std::fs::File;
     use std::io::Read;
@@ -29,9 +30,10 @@ mod test {
         cpu.register_x = 2;
         cpu.register_y = 3;
         let mut result: Vec<String> = vec![];
-        cpu.run(|cpu| Ok({
+        cpu.run(|cpu| {
             result.push(trace(cpu)?);
-        })).unwrap();
+            Ok(())
+        }).unwrap();
         assert_eq!(
             "0064  A2 01     LDX #$01                        A:01 X:02 Y:03 P:24 SP:FD",
             result[0]
@@ -51,15 +53,16 @@ mod test {
         let mut cpu = initialize_cpu();
         cpu.write(100, 0x11).unwrap();
         cpu.write(101, 0x33).unwrap();
-        cpu.write(0x33, 00).unwrap();
-        cpu.write(0x34, 04).unwrap();
+        cpu.write(0x33, 0x00).unwrap();
+        cpu.write(0x34, 0x04).unwrap();
         cpu.write(0x400, 0xAA).unwrap();
         cpu.program_counter = 0x64;
         cpu.register_y = 0;
         let mut result: Vec<String> = vec![];
-        cpu.run(|cpu| Ok({
+        cpu.run(|cpu| {
             result.push(trace(cpu)?);
-        })).unwrap();
+            Ok(())
+        }).unwrap();
         assert_eq!(
             "0064  11 33     ORA ($33),Y = 0400 @ 0400 = AA  A:00 X:00 Y:00 P:24 SP:FD",
             result[0]
@@ -0,0 +1,102 @@
+use std::mem::size_of;
+use std::thread;
+use std::time::Duration;
+use sdl2::AudioSubsystem;
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
+use emulator::cpu::CPU;
+use crate::cli::Options;
+
+const SAMPLE_RATE: i32 = 48_000;
+const DEVICE_BUFFER_SAMPLES: u16 = 512;
+/// How much audio is kept queued, in seconds. Enough to ride out a late frame.
+const TARGET_LATENCY: f64 = 0.05;
+/// The largest change to the APU's output rate made to steer the queue towards its target. Half a
+/// percent is well below an audible change in pitch.
+const MAX_RATE_ADJUSTMENT: f64 = 0.005;
+const VOLUME_STEP: u8 = 10;
+const MAX_VOLUME: u8 = 100;
+
+/// Feeds the APU's samples to an SDL audio queue, either adjusting the APU's output rate so the
+/// queue neither runs dry nor grows (dynamic rate control), or, with audio-synced pacing,
+/// producing samples at the device's exact rate and letting the device's consumption set the pace.
+pub struct Audio {
+    queue: AudioQueue<f32>,
+    volume: u8,
+    muted: bool,
+    sync: bool,
+}
+
+impl Audio {
+    pub fn new(subsystem: &AudioSubsystem, options: &Options, cpu: &mut CPU) -> Result<Self, String> {
+        let desired = AudioSpecDesired { freq: Some(SAMPLE_RATE), channels: Some(1), samples: Some(DEVICE_BUFFER_SAMPLES) };
+        let queue = subsystem.open_queue::<f32, _>(None, &desired)?;
+        queue.resume();
+        let audio = Audio { queue, volume: options.volume, muted: false, sync: options.audio_sync };
+        cpu.apu().set_sample_rate(audio.output_rate());
+        Ok(audio)
+    }
+
+    pub fn volume(&self) -> u8 {
+        self.volume
+    }
+
+    pub fn muted(&self) -> bool {
+        self.muted
+    }
+
+    pub fn volume_up(&mut self) {
+        self.volume = (self.volume + VOLUME_STEP).min(MAX_VOLUME);
+    }
+
+    pub fn volume_down(&mut self) {
+        self.volume = self.volume.saturating_sub(VOLUME_STEP);
+    }
+
+    pub fn toggle_mute(&mut self) {
+        self.muted = !self.muted;
+    }
+
+    /// Queues the samples produced since the last call and sets the APU's rate for the next frame.
+    /// `silent` still queues the samples, as silence, so that audio-synced pacing keeps its timing.
+    pub fn update(&mut self, cpu: &mut CPU, silent: bool) -> Result<(), String> {
+        let gain = if self.muted || silent { 0.0 } else { self.volume as f32 / MAX_VOLUME as f32 };
+        let mut samples = cpu.apu().take_samples();
+        // Rewinding replays several frames at once; queuing all of them would only add latency.
+        let room = (2.0 * self.target() - self.queued()).max(0.0) as usize;
+        samples.truncate(room);
+        samples.iter_mut().for_each(|sample| *sample *= gain);
+        self.queue.queue_audio(&samples)?;
+
+        let rate = if self.sync {
+            self.output_rate()
+        } else {
+            let fill = (self.queued() / self.target()).min(2.0);
+            self.output_rate() * (1.0 + MAX_RATE_ADJUSTMENT * (1.0 - fill))
+        };
+        cpu.apu().set_sample_rate(rate);
+        Ok(())
+    }
+
+    /// With audio-synced pacing, sleeps until the device has drained the queue to its target.
+    pub fn wait(&self) {
+        while self.queued() > self.target() {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    pub fn sync(&self) -> bool {
+        self.sync
+    }
+
+    fn output_rate(&self) -> f64 {
+        self.queue.spec().freq as f64
+    }
+
+    fn target(&self) -> f64 {
+        self.output_rate() * TARGET_LATENCY
+    }
+
+    fn queued(&self) -> f64 {
+        (self.queue.size() as usize / size_of::<f32>()) as f64
+    }
+}
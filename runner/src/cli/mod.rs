@@ -0,0 +1,245 @@
+mod test;
+
+use std::path::{Path, PathBuf};
+use emulator::common::constants::XAA_MAGIC;
+use emulator::cpu::types::Core;
+use emulator::debugger::expression::Expression;
+
+pub const USAGE: &str = "\
+usage: runner <rom.nes> [options]
+
+options:
+  -s, --scale N           window scale factor, 1 to 8 (default 3)
+  -f, --fullscreen        start in fullscreen
+  -a, --aspect-correct    stretch pixels to the 8:7 aspect ratio of a TV
+  -c, --crop-overscan     hide the top and bottom 8 lines like most TVs
+      --no-sprite-limit   draw every sprite on a line instead of the first eight,
+                          which removes flicker
+  -r, --region REGION     ntsc or pal (default ntsc)
+  -v, --volume N          audio volume, 0 to 100 (default 100)
+      --audio-sync        pace emulation by the audio device instead of a timer
+      --fast-forward SPEED
+                          fast-forward speed, 2 to 16 times or max (default 4)
+      --slow-motion N     run slow motion at 1/N speed, 2 to 8 (default 2)
+      --show-fps          show the frame rate and emulation speed
+      --show-input        show the buttons held on each controller
+  -i, --input-config FILE read controller bindings from FILE instead of the user's
+                          config directory
+  -t, --trace FILE        write a CPU trace log to FILE
+      --pc ADDR           start at ADDR (hex) instead of the reset vector
+      --strict-access     stop with an error on reads and writes of unmapped
+                          memory instead of returning open bus
+      --cycle-stepped     run the CPU one bus cycle at a time, with interrupts
+                          polled where the hardware polls them
+      --xaa-magic N       constant (hex) the unstable XAA and LXA opcodes OR into
+                          A, which varies between consoles (default FF)
+  -l, --load-state SLOT   load save-state slot 0 to 9 on start
+  -m, --movie FILE        play an FM2 movie
+      --headless          run without a window
+  -h, --help              show this message
+
+headless options:
+      --frames N          number of frames to run (default 600)
+      --until EXPR        stop once EXPR holds at the end of a frame
+      --screenshot FILE   write the final frame as a PNG
+      --ram-dump FILE     write the 2KB of CPU RAM
+
+hotkeys:
+  Escape                  quit
+  P, Pause                pause or resume
+  N                       advance one frame, pausing first if running
+  Tab                     fast-forward while held
+  `                       toggle fast-forward
+  \\                        toggle slow motion
+  Backspace               rewind while held
+  F5, F7                  save or load the save-state slot
+  -, =, M                 volume down, volume up, mute
+  F2, F3                  show or hide the FPS counter or the input display
+  F9, F10, F11, F12       open or close the pattern table, nametable, sprite or
+                          palette viewer
+  F8                      change the palette the pattern tables are drawn with
+
+The FPS counter and input display are also drawn on headless screenshots.";
+
+const DEFAULT_SCALE: u32 = 3;
+const MAX_SCALE: u32 = 8;
+const STATE_SLOTS: u8 = 10;
+const DEFAULT_FRAMES: u64 = 600;
+const MAX_VOLUME: u8 = 100;
+const DEFAULT_FAST_FORWARD: u32 = 4;
+const FAST_FORWARD_SPEEDS: std::ops::RangeInclusive<u32> = 2..=16;
+const DEFAULT_SLOW_MOTION: u32 = 2;
+const SLOW_MOTION_DIVISORS: std::ops::RangeInclusive<u32> = 2..=8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Region {
+    Ntsc,
+    Pal,
+}
+
+pub struct Options {
+    pub rom: String,
+    pub scale: u32,
+    pub fullscreen: bool,
+    pub aspect_correction: bool,
+    pub crop_overscan: bool,
+    pub sprite_limit: bool,
+    pub region: Region,
+    pub volume: u8,
+    pub audio_sync: bool,
+    pub input_config: Option<String>,
+    /// `None` runs fast-forward uncapped.
+    pub fast_forward: Option<u32>,
+    pub slow_motion: u32,
+    pub show_fps: bool,
+    pub show_input: bool,
+    pub trace: Option<String>,
+    pub start_pc: Option<u16>,
+    pub strict_access: bool,
+    pub core: Core,
+    pub xaa_magic: u8,
+    pub state_slot: Option<u8>,
+    pub movie: Option<String>,
+    pub headless: bool,
+    pub frames: u64,
+    pub until: Option<Expression>,
+    pub screenshot: Option<String>,
+    pub ram_dump: Option<String>,
+}
+
+impl Options {
+    pub fn parse(args: &[String]) -> Result<Options, String> {
+        let mut rom = None;
+        let mut options = Options {
+            rom: String::new(),
+            scale: DEFAULT_SCALE,
+            fullscreen: false,
+            aspect_correction: false,
+            crop_overscan: false,
+            sprite_limit: true,
+            region: Region::Ntsc,
+            volume: MAX_VOLUME,
+            audio_sync: false,
+            input_config: None,
+            fast_forward: Some(DEFAULT_FAST_FORWARD),
+            slow_motion: DEFAULT_SLOW_MOTION,
+            show_fps: false,
+            show_input: false,
+            trace: None,
+            start_pc: None,
+            strict_access: false,
+            core: Core::InstructionStepped,
+            xaa_magic: XAA_MAGIC,
+            state_slot: None,
+            movie: None,
+            headless: false,
+            frames: DEFAULT_FRAMES,
+            until: None,
+            screenshot: None,
+            ram_dump: None,
+        };
+        let mut headless_only = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().cloned().ok_or_else(|| format!("{} needs a value", arg));
+            match arg.as_str() {
+                "-s" | "--scale" => {
+                    let scale = value()?;
+                    options.scale = scale.parse().ok().filter(|scale| (1..=MAX_SCALE).contains(scale))
+                        .ok_or_else(|| format!("invalid scale '{}': expected a number from 1 to {}", scale, MAX_SCALE))?;
+                }
+                "-f" | "--fullscreen" => options.fullscreen = true,
+                "-a" | "--aspect-correct" => options.aspect_correction = true,
+                "-c" | "--crop-overscan" => options.crop_overscan = true,
+                "--no-sprite-limit" => options.sprite_limit = false,
+                "-r" | "--region" => {
+                    let region = value()?;
+                    options.region = match region.to_ascii_lowercase().as_str() {
+                        "ntsc" => Region::Ntsc,
+                        "pal" => Region::Pal,
+                        _ => return Err(format!("invalid region '{}': expected ntsc or pal", region)),
+                    };
+                }
+                "-v" | "--volume" => {
+                    let volume = value()?;
+                    options.volume = volume.parse().ok().filter(|volume| *volume <= MAX_VOLUME)
+                        .ok_or_else(|| format!("invalid volume '{}': expected a number from 0 to {}", volume, MAX_VOLUME))?;
+                }
+                "--audio-sync" => options.audio_sync = true,
+                "--fast-forward" => {
+                    let speed = value()?;
+                    options.fast_forward = match speed.as_str() {
+                        "max" => None,
+                        _ => Some(speed.parse().ok().filter(|speed| FAST_FORWARD_SPEEDS.contains(speed))
+                            .ok_or_else(|| format!("invalid fast-forward speed '{}': expected 2 to 16 or max", speed))?),
+                    };
+                }
+                "--slow-motion" => {
+                    let divisor = value()?;
+                    options.slow_motion = divisor.parse().ok().filter(|divisor| SLOW_MOTION_DIVISORS.contains(divisor))
+                        .ok_or_else(|| format!("invalid slow motion divisor '{}': expected 2 to 8", divisor))?;
+                }
+                "--show-fps" => options.show_fps = true,
+                "--show-input" => options.show_input = true,
+                "-i" | "--input-config" => options.input_config = Some(value()?),
+                "-t" | "--trace" => options.trace = Some(value()?),
+                "--pc" => {
+                    let pc = value()?;
+                    let digits = pc.trim_start_matches('$').trim_start_matches("0x");
+                    options.start_pc = Some(u16::from_str_radix(digits, 16)
+                        .map_err(|_| format!("invalid address '{}': expected up to four hex digits", pc))?);
+                }
+                "--strict-access" => options.strict_access = true,
+                "--cycle-stepped" => options.core = Core::CycleStepped,
+                "--xaa-magic" => {
+                    let magic = value()?;
+                    let digits = magic.trim_start_matches('$').trim_start_matches("0x");
+                    options.xaa_magic = u8::from_str_radix(digits, 16)
+                        .map_err(|_| format!("invalid XAA constant '{}': expected up to two hex digits", magic))?;
+                }
+                "-l" | "--load-state" => {
+                    let slot = value()?;
+                    options.state_slot = Some(slot.parse().ok().filter(|slot| *slot < STATE_SLOTS)
+                        .ok_or_else(|| format!("invalid save-state slot '{}': expected 0 to {}", slot, STATE_SLOTS - 1))?);
+                }
+                "-m" | "--movie" => options.movie = Some(value()?),
+                "--headless" => options.headless = true,
+                "--frames" => {
+                    let frames = value()?;
+                    options.frames = frames.parse().map_err(|_| format!("invalid frame count '{}'", frames))?;
+                    headless_only = Some(arg);
+                }
+                "--until" => {
+                    let until = value()?;
+                    let condition = Expression::parse(&until).map_err(|error| format!("invalid --until condition: {}", error))?;
+                    options.until = Some(condition);
+                    headless_only = Some(arg);
+                }
+                "--screenshot" => {
+                    options.screenshot = Some(value()?);
+                    headless_only = Some(arg);
+                }
+                "--ram-dump" => {
+                    options.ram_dump = Some(value()?);
+                    headless_only = Some(arg);
+                }
+                _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+                _ if rom.is_none() => rom = Some(arg.clone()),
+                _ => return Err(format!("unexpected argument '{}': only one ROM can be given", arg)),
+            }
+        }
+        options.rom = rom.ok_or("missing ROM path")?;
+        if let Some(option) = headless_only.filter(|_| !options.headless) {
+            return Err(format!("{} only applies with --headless", option));
+        }
+        if options.movie.is_some() && options.state_slot.is_some() {
+            return Err("--movie and --load-state cannot be combined: movies start from their own state".to_string());
+        }
+        Ok(options)
+    }
+
+    /// Save states live next to the ROM as `<rom>.ss<slot>`.
+    pub fn state_path(&self, slot: u8) -> PathBuf {
+        Path::new(&self.rom).with_extension(format!("ss{}", slot))
+    }
+}
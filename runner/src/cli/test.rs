@@ -0,0 +1,92 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod test {
+    use std::path::PathBuf;
+    use emulator::cpu::types::Core;
+    use crate::cli::{Options, Region, DEFAULT_FRAMES, DEFAULT_SCALE};
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(&args.iter().map(|arg| arg.to_string()).collect::<Vec<String>>())
+    }
+
+    fn error(args: &[&str]) -> String {
+        parse(args).err().unwrap()
+    }
+
+    #[test]
+    fn test_defaults() {
+        let options = parse(&["game.nes"]).unwrap();
+        assert_eq!(options.rom, "game.nes");
+        assert_eq!(options.scale, DEFAULT_SCALE);
+        assert_eq!(options.region, Region::Ntsc);
+        assert_eq!(options.frames, DEFAULT_FRAMES);
+        assert_eq!(options.core, Core::InstructionStepped);
+        assert!(options.sprite_limit && !options.headless && options.start_pc.is_none());
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let options = parse(&[
+            "-s", "5", "--region", "PAL", "game.nes", "-v", "0", "--fast-forward", "max", "--pc", "$C000",
+            "--xaa-magic", "0xEE", "--cycle-stepped", "-l", "9", "--no-sprite-limit",
+        ]).unwrap();
+        assert_eq!(options.rom, "game.nes");
+        assert_eq!(options.scale, 5);
+        assert_eq!(options.region, Region::Pal);
+        assert_eq!(options.volume, 0);
+        assert_eq!(options.fast_forward, None);
+        assert_eq!(options.start_pc, Some(0xC000));
+        assert_eq!(options.xaa_magic, 0xEE);
+        assert_eq!(options.core, Core::CycleStepped);
+        assert_eq!(options.state_slot, Some(9));
+        assert!(!options.sprite_limit);
+        assert_eq!(options.state_path(9), PathBuf::from("game.ss9"));
+
+        let options = parse(&["--headless", "--frames", "10", "--until", "A == 1", "--screenshot", "out.png", "game.nes"]).unwrap();
+        assert_eq!(options.frames, 10);
+        assert!(options.until.is_some());
+        assert_eq!(options.screenshot.as_deref(), Some("out.png"));
+    }
+
+    #[test]
+    fn test_invalid_values() {
+        let errors = [
+            (&["game.nes", "--pc", "C0000"][..], "invalid address 'C0000': expected up to four hex digits"),
+            (&["game.nes", "--pc", "$XYZ"], "invalid address '$XYZ': expected up to four hex digits"),
+            (&["game.nes", "-s", "0"], "invalid scale '0': expected a number from 1 to 8"),
+            (&["game.nes", "--scale", "big"], "invalid scale 'big': expected a number from 1 to 8"),
+            (&["game.nes", "-l", "10"], "invalid save-state slot '10': expected 0 to 9"),
+            (&["game.nes", "--load-state", "-1"], "invalid save-state slot '-1': expected 0 to 9"),
+            (&["game.nes", "-r", "secam"], "invalid region 'secam': expected ntsc or pal"),
+            (&["game.nes", "-v", "101"], "invalid volume '101': expected a number from 0 to 100"),
+            (&["game.nes", "--fast-forward", "1"], "invalid fast-forward speed '1': expected 2 to 16 or max"),
+            (&["game.nes", "--slow-motion", "9"], "invalid slow motion divisor '9': expected 2 to 8"),
+            (&["game.nes", "--xaa-magic", "100"], "invalid XAA constant '100': expected up to two hex digits"),
+            (&["game.nes", "--headless", "--frames", "many"], "invalid frame count 'many'"),
+        ];
+        for (args, message) in errors {
+            assert_eq!(error(args), message, "{:?}", args);
+        }
+        assert!(error(&["game.nes", "--headless", "--until", "A =="]).starts_with("invalid --until condition: "));
+    }
+
+    #[test]
+    fn test_missing_values() {
+        for option in ["-s", "--region", "--pc", "--load-state", "--movie", "--trace", "--xaa-magic", "--frames"] {
+            assert_eq!(error(&["game.nes", option]), format!("{} needs a value", option));
+        }
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        assert_eq!(error(&[]), "missing ROM path");
+        assert_eq!(error(&["--headless"]), "missing ROM path");
+        assert_eq!(error(&["game.nes", "--turbo"]), "unknown option '--turbo'");
+        assert_eq!(error(&["game.nes", "-x"]), "unknown option '-x'");
+        assert_eq!(error(&["game.nes", "other.nes"]), "unexpected argument 'other.nes': only one ROM can be given");
+        assert_eq!(error(&["game.nes", "--screenshot", "out.png"]), "--screenshot only applies with --headless");
+        assert_eq!(error(&["game.nes", "--frames", "10"]), "--frames only applies with --headless");
+        assert_eq!(error(&["game.nes", "-m", "run.fm2", "-l", "1"]),
+                   "--movie and --load-state cannot be combined: movies start from their own state");
+    }
+}
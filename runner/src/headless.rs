@@ -0,0 +1,51 @@
+use std::fs;
+use std::io::Write;
+use emulator::common::constants::{RAM_SIZE, SCREEN_HEIGHT, SCREEN_WIDTH};
+use emulator::common::png::encode_png;
+use emulator::memory::memory::Memory;
+use crate::cli::Options;
+use crate::osd::Osd;
+use crate::{open_trace, run_frame, start};
+
+/// Emulates up to `frames` frames without a window, stopping early once the `until` condition
+/// holds at the end of a frame. Returns whether the condition was met (always true without one).
+pub fn run(options: &Options) -> Result<bool, String> {
+    let (mut cpu, mut session) = start(options)?;
+    let mut trace_log = open_trace(options)?;
+    let mut osd = Osd::new(options);
+
+    let mut met = options.until.is_none();
+    let mut frames = 0;
+    while frames < options.frames {
+        if let Some(session) = &mut session {
+            session.apply_input(&mut cpu, [0, 0]).map_err(|error| error.to_string())?;
+        }
+        let running = run_frame(&mut cpu, &mut trace_log)?;
+        osd.frame_presented(1);
+        frames += 1;
+        if options.until.as_ref().is_some_and(|condition| condition.is_true(&cpu)) {
+            met = true;
+            break;
+        }
+        if !running {
+            break;
+        }
+    }
+    println!("Stopped after {} frames at PC {:04X}", frames, cpu.program_counter);
+
+    if let Some(path) = &options.screenshot {
+        let mut frame = cpu.ppu().frame_buffer().to_vec();
+        osd.draw(&mut frame, [cpu.controller(0).buttons(), cpu.controller(1).buttons()]);
+        let png = encode_png(SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32, &frame);
+        fs::write(path, png).map_err(|error| format!("cannot write {}: {}", path, error))?;
+    }
+    if let Some(path) = &options.ram_dump {
+        let ram = (0..RAM_SIZE as u16).map(|address| cpu.peek(address)).collect::<Result<Vec<u8>, _>>()
+            .map_err(|error| error.to_string())?;
+        fs::write(path, ram).map_err(|error| format!("cannot write {}: {}", path, error))?;
+    }
+    if let Some(mut writer) = trace_log {
+        writer.flush().map_err(|error| format!("cannot write trace: {}", error))?;
+    }
+    Ok(met)
+}
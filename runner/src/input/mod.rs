@@ -0,0 +1,243 @@
+mod test;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use sdl2::GameControllerSubsystem;
+use sdl2::controller::{Axis, Button, GameController};
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use emulator::controller::{BUTTON_A, BUTTON_B, BUTTON_DOWN, BUTTON_LEFT, BUTTON_RIGHT, BUTTON_SELECT, BUTTON_START, BUTTON_UP};
+use crate::toml::{self, Value};
+
+const CONFIG_FILE: &str = "rust-nes-emulator/input.toml";
+const PORTS: [&str; 2] = ["port1", "port2"];
+const BUTTONS: [(&str, u8); 8] = [
+    ("a", BUTTON_A),
+    ("b", BUTTON_B),
+    ("select", BUTTON_SELECT),
+    ("start", BUTTON_START),
+    ("up", BUTTON_UP),
+    ("down", BUTTON_DOWN),
+    ("left", BUTTON_LEFT),
+    ("right", BUTTON_RIGHT),
+];
+const TURBO_BUTTONS: [(&str, u8); 2] = [("turbo_a", BUTTON_A), ("turbo_b", BUTTON_B)];
+const DEFAULT_TURBO_PERIOD: u32 = 2;
+const MAX_TURBO_PERIOD: i64 = 30;
+/// How far a stick has to be pushed, out of 32767, to press a direction.
+const AXIS_THRESHOLD: i16 = 16384;
+
+const DEFAULT_CONFIG: &str = r#"# Controller bindings for the runner.
+#
+# Each NES button takes a list of inputs: keyboard keys by their SDL names ("X", "Return",
+# "Right Shift", "Keypad 8"), game controller buttons as "pad:<button>" (a, b, x, y, back, start,
+# leftshoulder, rightshoulder, dpup, dpdown, dpleft, dpright) and stick directions as
+# "pad:<axis>+" or "pad:<axis>-" (leftx, lefty, rightx, righty). The first game controller
+# connected drives port 1 and the second port 2. The runner's hotkeys (see `runner --help`) take
+# precedence over bindings.
+#
+# turbo_a and turbo_b press A or B repeatedly while held, switching every `turbo_period` frames.
+
+turbo_period = 2
+
+[port1]
+a = ["X", "pad:a"]
+b = ["Z", "pad:x"]
+select = ["Right Shift", "pad:back"]
+start = ["Return", "pad:start"]
+up = ["Up", "pad:dpup", "pad:lefty-"]
+down = ["Down", "pad:dpdown", "pad:lefty+"]
+left = ["Left", "pad:dpleft", "pad:leftx-"]
+right = ["Right", "pad:dpright", "pad:leftx+"]
+turbo_a = ["S", "pad:b"]
+turbo_b = ["A", "pad:y"]
+
+[port2]
+a = ["pad:a"]
+b = ["pad:x"]
+select = ["pad:back"]
+start = ["pad:start"]
+up = ["pad:dpup", "pad:lefty-"]
+down = ["pad:dpdown", "pad:lefty+"]
+left = ["pad:dpleft", "pad:leftx-"]
+right = ["pad:dpright", "pad:leftx+"]
+turbo_a = ["pad:b"]
+turbo_b = ["pad:y"]
+"#;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Source {
+    Key(Keycode),
+    Button(Button),
+    /// A stick pushed in the positive (`true`) or negative direction.
+    Axis(Axis, bool),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Binding {
+    source: Source,
+    port: usize,
+    button: u8,
+    turbo: bool,
+}
+
+pub struct Bindings {
+    bindings: Vec<Binding>,
+    turbo_period: u32,
+}
+
+impl Bindings {
+    /// Reads the bindings from `path`, or from the user's config directory, where the defaults are
+    /// written on first use so that there is a file to edit.
+    pub fn load(path: Option<&str>) -> Result<Bindings, String> {
+        let path = match path {
+            Some(path) => PathBuf::from(path),
+            None => match config_path() {
+                Some(path) => path,
+                None => return Bindings::parse(DEFAULT_CONFIG),
+            },
+        };
+        if !path.exists() {
+            let written = path.parent().map_or(Ok(()), fs::create_dir_all).and_then(|_| fs::write(&path, DEFAULT_CONFIG));
+            if let Err(error) = written {
+                eprintln!("warning: cannot write default bindings to {}: {}", path.display(), error);
+            }
+            return Bindings::parse(DEFAULT_CONFIG);
+        }
+        let text = fs::read_to_string(&path).map_err(|error| format!("cannot read {}: {}", path.display(), error))?;
+        Bindings::parse(&text).map_err(|error| format!("{}: {}", path.display(), error))
+    }
+
+    fn parse(text: &str) -> Result<Bindings, String> {
+        let mut bindings = Bindings { bindings: Vec::new(), turbo_period: DEFAULT_TURBO_PERIOD };
+        for entry in toml::parse(text)? {
+            let error = |message: String| format!("line {}: {}", entry.line, message);
+            if entry.table.is_empty() {
+                if entry.key != "turbo_period" {
+                    return Err(error(format!("unknown setting '{}'", entry.key)));
+                }
+                bindings.turbo_period = entry.value.as_integer().filter(|period| (1..=MAX_TURBO_PERIOD).contains(period))
+                    .ok_or_else(|| error(format!("turbo_period must be a number of frames from 1 to {}", MAX_TURBO_PERIOD)))? as u32;
+                continue;
+            }
+            let port = PORTS.iter().position(|port| *port == entry.table)
+                .ok_or_else(|| error(format!("unknown table '{}': expected port1 or port2", entry.table)))?;
+            let (button, turbo) = match BUTTONS.iter().find(|(name, _)| *name == entry.key) {
+                Some((_, button)) => (*button, false),
+                None => match TURBO_BUTTONS.iter().find(|(name, _)| *name == entry.key) {
+                    Some((_, button)) => (*button, true),
+                    None => return Err(error(format!("unknown button '{}'", entry.key))),
+                },
+            };
+            let inputs = match &entry.value {
+                Value::Array(values) => values.iter().collect(),
+                value => vec![value],
+            };
+            for input in inputs {
+                let name = input.as_str().ok_or_else(|| error("inputs must be strings".to_string()))?;
+                let source = parse_source(name).ok_or_else(|| error(format!("unknown input '{}'", name)))?;
+                bindings.bindings.push(Binding { source, port, button, turbo });
+            }
+        }
+        Ok(bindings)
+    }
+}
+
+fn parse_source(name: &str) -> Option<Source> {
+    match name.strip_prefix("pad:") {
+        Some(pad_input) => {
+            if let Some(axis) = pad_input.strip_suffix('+') {
+                Axis::from_string(axis).map(|axis| Source::Axis(axis, true))
+            } else if let Some(axis) = pad_input.strip_suffix('-') {
+                Axis::from_string(axis).map(|axis| Source::Axis(axis, false))
+            } else {
+                Button::from_string(pad_input).map(Source::Button)
+            }
+        }
+        None => Keycode::from_name(name).map(Source::Key),
+    }
+}
+
+fn config_path() -> Option<PathBuf> {
+    let directory = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        env::var_os("XDG_CONFIG_HOME").map(PathBuf::from).or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    directory.map(|directory| directory.join(CONFIG_FILE))
+}
+
+/// Turns keyboard and game controller events into the buttons held on each controller port.
+pub struct InputMapper {
+    bindings: Bindings,
+    active: Vec<bool>,
+    subsystem: GameControllerSubsystem,
+    /// Open game controllers, in the order of the ports they drive.
+    pads: Vec<GameController>,
+    frame: u32,
+}
+
+impl InputMapper {
+    pub fn new(bindings: Bindings, subsystem: GameControllerSubsystem) -> Self {
+        let active = vec![false; bindings.bindings.len()];
+        InputMapper { bindings, active, subsystem, pads: Vec::new(), frame: 0 }
+    }
+
+    pub fn handle_event(&mut self, event: &Event) {
+        match *event {
+            Event::KeyDown { keycode: Some(keycode), repeat: false, .. } => self.set(Source::Key(keycode), None, true),
+            Event::KeyUp { keycode: Some(keycode), .. } => self.set(Source::Key(keycode), None, false),
+            Event::ControllerButtonDown { which, button, .. } => self.set_pad(which, Source::Button(button), true),
+            Event::ControllerButtonUp { which, button, .. } => self.set_pad(which, Source::Button(button), false),
+            Event::ControllerAxisMotion { which, axis, value, .. } => {
+                self.set_pad(which, Source::Axis(axis, true), value > AXIS_THRESHOLD);
+                self.set_pad(which, Source::Axis(axis, false), value < -AXIS_THRESHOLD);
+            }
+            Event::ControllerDeviceAdded { which, .. } => match self.subsystem.open(which) {
+                Ok(pad) => self.pads.push(pad),
+                Err(error) => eprintln!("warning: cannot open game controller {}: {}", which, error),
+            },
+            Event::ControllerDeviceRemoved { which, .. } => {
+                self.pads.retain(|pad| pad.instance_id() != which);
+                // Ports may have moved to other pads, so release everything the pads were holding.
+                for (binding, active) in self.bindings.bindings.iter().zip(self.active.iter_mut()) {
+                    if !matches!(binding.source, Source::Key(_)) {
+                        *active = false;
+                    }
+                }
+            }
+            _ => {/* do nothing */}
+        }
+    }
+
+    /// The buttons held on each port for the next frame, with turbo buttons pressed on
+    /// alternating runs of `turbo_period` frames.
+    pub fn next_frame(&mut self) -> [u8; 2] {
+        let turbo_pressed = (self.frame / self.bindings.turbo_period).is_multiple_of(2);
+        self.frame = self.frame.wrapping_add(1);
+        let mut buttons = [0; 2];
+        for (binding, active) in self.bindings.bindings.iter().zip(&self.active) {
+            if *active && (turbo_pressed || !binding.turbo) {
+                buttons[binding.port] |= binding.button;
+            }
+        }
+        buttons
+    }
+
+    fn set_pad(&mut self, instance_id: u32, source: Source, pressed: bool) {
+        if let Some(port) = self.pads.iter().position(|pad| pad.instance_id() == instance_id) {
+            self.set(source, Some(port), pressed);
+        }
+    }
+
+    fn set(&mut self, source: Source, port: Option<usize>, pressed: bool) {
+        for (binding, active) in self.bindings.bindings.iter().zip(self.active.iter_mut()) {
+            if binding.source == source && port.is_none_or(|port| port == binding.port) {
+                *active = pressed;
+            }
+        }
+    }
+}
@@ -0,0 +1,66 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod test {
+    use sdl2::controller::{Axis, Button};
+    use sdl2::keyboard::Keycode;
+    use emulator::controller::{BUTTON_A, BUTTON_B, BUTTON_SELECT, BUTTON_UP};
+    use crate::input::{parse_source, Bindings, Source, DEFAULT_CONFIG, DEFAULT_TURBO_PERIOD};
+
+    fn bound(bindings: &Bindings) -> Vec<(Source, usize, u8, bool)> {
+        bindings.bindings.iter().map(|binding| (binding.source, binding.port, binding.button, binding.turbo)).collect()
+    }
+
+    #[test]
+    fn test_default_config() {
+        let bindings = Bindings::parse(DEFAULT_CONFIG).unwrap();
+        assert_eq!(bindings.turbo_period, DEFAULT_TURBO_PERIOD);
+        let bound = bound(&bindings);
+        assert!(bound.contains(&(Source::Key(Keycode::X), 0, BUTTON_A, false)));
+        assert!(bound.contains(&(Source::Key(Keycode::S), 0, BUTTON_A, true)));
+        assert!(bound.contains(&(Source::Button(Button::X), 1, BUTTON_B, false)));
+        assert!(bound.contains(&(Source::Axis(Axis::LeftY, false), 1, BUTTON_UP, false)));
+    }
+
+    #[test]
+    fn test_parse_bindings() {
+        let text = "turbo_period = 5\n[port2]\nselect = \"Right Shift\"\nturbo_b = [\"pad:rightshoulder\", \"pad:rightx+\"]\n";
+        let bindings = Bindings::parse(text).unwrap();
+        assert_eq!(bindings.turbo_period, 5);
+        assert_eq!(bound(&bindings), vec![
+            (Source::Key(Keycode::RShift), 1, BUTTON_SELECT, false),
+            (Source::Button(Button::RightShoulder), 1, BUTTON_B, true),
+            (Source::Axis(Axis::RightX, true), 1, BUTTON_B, true),
+        ]);
+    }
+
+    #[test]
+    fn test_parse_source() {
+        assert_eq!(parse_source("Return"), Some(Source::Key(Keycode::Return)));
+        assert_eq!(parse_source("Keypad 8"), Some(Source::Key(Keycode::Kp8)));
+        assert_eq!(parse_source("pad:dpleft"), Some(Source::Button(Button::DPadLeft)));
+        assert_eq!(parse_source("pad:leftx-"), Some(Source::Axis(Axis::LeftX, false)));
+        assert_eq!(parse_source("pad:righttrigger+"), Some(Source::Axis(Axis::TriggerRight, true)));
+        assert_eq!(parse_source("pad:nothing"), None);
+        assert_eq!(parse_source("pad:nothing+"), None);
+        assert_eq!(parse_source("pad:"), None);
+        assert_eq!(parse_source("No Such Key"), None);
+    }
+
+    #[test]
+    fn test_invalid_bindings() {
+        let errors = [
+            ("volume = 3\n", "line 1: unknown setting 'volume'"),
+            ("turbo_period = 0\n", "line 1: turbo_period must be a number of frames from 1 to 30"),
+            ("turbo_period = 31\n", "line 1: turbo_period must be a number of frames from 1 to 30"),
+            ("turbo_period = \"2\"\n", "line 1: turbo_period must be a number of frames from 1 to 30"),
+            ("[port3]\na = \"X\"\n", "line 2: unknown table 'port3': expected port1 or port2"),
+            ("[port1]\n\nturbo_start = \"X\"\n", "line 3: unknown button 'turbo_start'"),
+            ("[port1]\na = [\"X\", 1]\n", "line 2: inputs must be strings"),
+            ("[port1]\na = [\"X\",\n     \"pad:z\"]\n", "line 2: unknown input 'pad:z'"),
+            ("[port1]\na = \"X\"\na = \"Y\"\n", "line 3: duplicate key 'a'"),
+        ];
+        for (text, message) in errors {
+            assert_eq!(Bindings::parse(text).err().unwrap(), message, "{:?}", text);
+        }
+    }
+}
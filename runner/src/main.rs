@@ -1,125 +1,317 @@
-use std::cell::RefCell;
-use std::fs::File;
-use std::io::{self, Read, Write};
-use std::rc::Rc;
-use rand::Rng;
-use sdl2::event::Event;
+mod audio;
+mod cli;
+mod headless;
+mod input;
+mod osd;
+mod playback;
+mod toml;
+mod video;
+mod viewer;
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::process;
+use std::time::Instant;
+use sdl2::event::{Event, WindowEvent};
 use sdl2::EventPump;
 use sdl2::keyboard::Keycode;
-use sdl2::pixels::{Color, PixelFormatEnum};
-use emulator::cpu::CPU;
-use emulator::memory::memory::Memory;
+use sdl2::pixels::PixelFormatEnum;
+use emulator::common::constants::{SCREEN_HEIGHT, SCREEN_WIDTH};
 use emulator::common::logger::trace;
+use emulator::cpu::CPU;
+use emulator::movie::{Movie, MovieMode, MovieSession};
+use emulator::rewind::Rewind;
+use crate::audio::Audio;
+use crate::cli::{Options, USAGE};
+use crate::input::{Bindings, InputMapper};
+use crate::osd::Osd;
+use crate::playback::{Mode, Playback};
+use crate::video::{FramePacer, Video};
+use crate::viewer::{View, Viewers};
 
 fn main() {
-    let mut cpu = CPU::new();
-    let sdl_context = sdl2::init().unwrap();
-    let video_subsystem = sdl_context.video().unwrap();
-    let window = video_subsystem
-        .window("Emulator", (32.0 * 10.0) as u32, (32.0 * 10.0) as u32)
-        .position_centered()
-        .build().unwrap();
-
-    let mut canvas = window.into_canvas().present_vsync().build().unwrap();
-    let mut event_pump = sdl_context.event_pump().unwrap();
-    canvas.set_scale(10.0, 10.0).unwrap();
-    let creator = canvas.texture_creator();
-    let mut texture = creator
-        .create_texture_target(PixelFormatEnum::RGB24, 32, 32).unwrap();
-    let mut screen_state = [0u8; 32 * 3 * 32];
-    let mut rng = rand::thread_rng();
-    let game = get_rom("../test roms/nestest.nes").expect("TODO: panic message");
-    cpu.load(&game).expect("TODO: panic message");
-    cpu.reset();
-    cpu.program_counter = 0xc000;
-    cpu.status.interrupt_disable = true;
-    let log_lines = Rc::new(RefCell::new(Vec::new()));
-    let log_lines_clone = log_lines.clone();
-    let run = cpu.run(move |cpu| Ok({
-        log_lines_clone.borrow_mut().push(trace(cpu)?);
-        handle_user_input(cpu, &mut event_pump);
-        cpu.write(0xfe, rng.gen_range(1..16)).expect("TODO: panic message");
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return;
+    }
+    let options = match Options::parse(&args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+    let result = if options.headless {
+        headless::run(&options).map(|met| if met { 0 } else { 1 })
+    } else {
+        run_window(&options).map(|_| 0)
+    };
+    match result {
+        Ok(code) => process::exit(code),
+        Err(message) => {
+            eprintln!("error: {}", message);
+            process::exit(1);
+        }
+    }
+}
 
-        if read_screen_state(cpu, &mut screen_state) {
-            texture.update(None, &screen_state, 32 * 3).unwrap();
-            canvas.copy(&texture, None, None).unwrap();
-            canvas.present();
+/// Loads the ROM and puts the machine in its starting state: the movie's, the requested save-state
+/// slot's, or power-on.
+fn start(options: &Options) -> Result<(CPU, Option<MovieSession>), String> {
+    let rom = fs::read(&options.rom).map_err(|error| format!("cannot read {}: {}", options.rom, error))?;
+    let mut cpu = CPU::with_core(options.core);
+    cpu.load(&rom).map_err(|error| format!("{}: {}", options.rom, error))?;
+    cpu.ppu_mut().set_sprite_limit(options.sprite_limit);
+    cpu.set_strict_access(options.strict_access);
+    cpu.set_xaa_magic(options.xaa_magic);
+    let session = match &options.movie {
+        Some(path) => {
+            let text = fs::read_to_string(path).map_err(|error| format!("cannot read {}: {}", path, error))?;
+            let movie = Movie::from_fm2(&text).map_err(|error| format!("{}: {}", path, error))?;
+            Some(MovieSession::play(&mut cpu, movie, MovieMode::ReadOnly).map_err(|error| format!("{}: {}", path, error))?)
+        }
+        None => {
+            cpu.power_on().map_err(|error| error.to_string())?;
+            None
         }
+    };
+    if let Some(slot) = options.state_slot {
+        load_state(&mut cpu, options, slot)?;
+    }
+    if let Some(pc) = options.start_pc {
+        cpu.program_counter = pc;
+    }
+    Ok((cpu, session))
+}
 
-        ::std::thread::sleep(std::time::Duration::new(0, 5_000));
-    }));
-    if let Err(e) = run {
-        println!("\n\nError: {:?}", e);
+fn open_trace(options: &Options) -> Result<Option<BufWriter<File>>, String> {
+    options.trace.as_ref()
+        .map(|path| File::create(path).map(BufWriter::new).map_err(|error| format!("cannot create {}: {}", path, error)))
+        .transpose()
+}
+
+/// Emulates one frame, logging every instruction when tracing. Returns `false` on BRK.
+fn run_frame(cpu: &mut CPU, trace_log: &mut Option<BufWriter<File>>) -> Result<bool, String> {
+    let mut write_error = None;
+    let running = cpu.run_frame(|cpu| {
+        if let Some(writer) = trace_log.as_mut() {
+            if let Err(error) = writeln!(writer, "{}", trace(cpu)?) {
+                write_error.get_or_insert(error);
+            }
+        }
+        Ok(())
+    }).map_err(|error| error.to_string())?;
+    match write_error {
+        Some(error) => Err(format!("cannot write trace: {}", error)),
+        None => Ok(running),
     }
-    write_log(log_lines.borrow().clone());
 }
 
-fn write_log(log_lines: Vec<String>) {
-    let mut file = File::create("../log.txt").expect("TODO: panic message");
-    for line in log_lines {
-        file.write_all(line.as_bytes());
-        file.write_all(b"\n");
+/// Emulates one frame with the current controller input and records it for rewinding.
+fn emulate_frame(cpu: &mut CPU, session: &mut Option<MovieSession>, mapper: &mut InputMapper,
+                 trace_log: &mut Option<BufWriter<File>>, rewind: &mut Rewind) -> Result<bool, String> {
+    let buttons = mapper.next_frame();
+    match session {
+        Some(session) => session.apply_input(cpu, buttons).map_err(|error| error.to_string())?,
+        None => {
+            cpu.controller(0).set_buttons(buttons[0]);
+            cpu.controller(1).set_buttons(buttons[1]);
+        }
     }
+    let running = run_frame(cpu, trace_log)?;
+    rewind.record(cpu);
+    Ok(running)
 }
 
-fn get_rom(path: &str) -> io::Result<Vec<u8>> {
-    let mut file = File::open(path)?;
-    let mut buffer = Vec::new();
-    file.read_to_end(&mut buffer)?;
-    Ok(buffer)
+fn save_state(cpu: &CPU, options: &Options, slot: u8) -> Result<(), String> {
+    let path = options.state_path(slot);
+    fs::write(&path, cpu.save_state()).map_err(|error| format!("cannot write {}: {}", path.display(), error))
 }
 
+fn load_state(cpu: &mut CPU, options: &Options, slot: u8) -> Result<(), String> {
+    let path = options.state_path(slot);
+    let state = fs::read(&path).map_err(|error| format!("cannot read save-state slot {} ({}): {}", slot, path.display(), error))?;
+    cpu.load_state(&state).map_err(|error| format!("{}: {}", path.display(), error))
+}
 
-fn handle_user_input(cpu: &mut CPU, event_pump: &mut EventPump) {
-    for event in event_pump.poll_iter() {
-        match event {
-            Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
-                std::process::exit(0)
-            },
-            Event::KeyDown { keycode: Some(Keycode::W), .. } => {
-                cpu.write(0xff, 0x77).unwrap();
-            },
-            Event::KeyDown { keycode: Some(Keycode::S), .. } => {
-                cpu.write(0xff, 0x73).unwrap();
-            },
-            Event::KeyDown { keycode: Some(Keycode::A), .. } => {
-                cpu.write(0xff, 0x61).unwrap();
-            },
-            Event::KeyDown { keycode: Some(Keycode::D), .. } => {
-                cpu.write(0xff, 0x64).unwrap();
+fn run_window(options: &Options) -> Result<(), String> {
+    let (mut cpu, mut session) = start(options)?;
+    let mut trace_log = open_trace(options)?;
+    let sdl_context = sdl2::init()?;
+    let video_subsystem = sdl_context.video()?;
+    let video = Video::new(options);
+    let (width, height) = video.window_size();
+    let mut window = video_subsystem.window("Emulator", width, height);
+    window.position_centered().resizable();
+    if options.fullscreen {
+        window.fullscreen_desktop();
+    }
+    let mut canvas = window.build().map_err(|error| error.to_string())?
+        .into_canvas().build().map_err(|error| error.to_string())?;
+    let mut event_pump = sdl_context.event_pump()?;
+    let mut mapper = InputMapper::new(Bindings::load(options.input_config.as_deref())?, sdl_context.game_controller()?);
+    let mut audio = match sdl_context.audio().and_then(|subsystem| Audio::new(&subsystem, options, &mut cpu)) {
+        Ok(audio) => Some(audio),
+        Err(message) => {
+            eprintln!("warning: no audio output: {}", message);
+            None
+        }
+    };
+    let creator = canvas.texture_creator();
+    let mut texture = creator.create_texture_streaming(PixelFormatEnum::RGB24, SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32)
+        .map_err(|error| error.to_string())?;
+
+    let slot = options.state_slot.unwrap_or(0);
+    let mut pacer = FramePacer::new(options.region);
+    let mut rewind = Rewind::default();
+    let mut input = Input::default();
+    let mut playback = Playback::new(options);
+    let mut osd = Osd::new(options);
+    let mut viewers = Viewers::default();
+    loop {
+        handle_user_input(&mut event_pump, &mut input, &mut mapper, &mut playback, &mut viewers);
+        if input.quit {
+            break;
+        }
+        if std::mem::take(&mut input.save_state) {
+            match save_state(&cpu, options, slot) {
+                Ok(()) => osd.message(format!("Saved state to slot {}", slot)),
+                Err(message) => report_error(&mut osd, &format!("Cannot save slot {}", slot), &message),
             }
-            _ => {/* do nothing */}
         }
+        if std::mem::take(&mut input.load_state) {
+            match load_state(&mut cpu, options, slot) {
+                Ok(()) => {
+                    rewind.clear();
+                    osd.message(format!("Loaded state from slot {}", slot));
+                }
+                Err(message) => report_error(&mut osd, &format!("Cannot load slot {}", slot), &message),
+            }
+        }
+        if let Some(audio) = &mut audio {
+            let volume_changed = input.volume_up || input.volume_down;
+            if std::mem::take(&mut input.volume_up) {
+                audio.volume_up();
+            }
+            if std::mem::take(&mut input.volume_down) {
+                audio.volume_down();
+            }
+            if volume_changed {
+                osd.message(format!("Volume {}%", audio.volume()));
+            }
+            if std::mem::take(&mut input.toggle_mute) {
+                audio.toggle_mute();
+                osd.message(if audio.muted() { "Audio muted" } else { "Audio unmuted" });
+            }
+        }
+        if std::mem::take(&mut input.toggle_fps) {
+            osd.toggle_fps();
+        }
+        if std::mem::take(&mut input.toggle_input_display) {
+            osd.toggle_input();
+        }
+        if let Some(view) = input.toggle_view.take() {
+            if let Err(message) = viewers.toggle(&video_subsystem, view) {
+                report_error(&mut osd, "Cannot open viewer", &message);
+            }
+        }
+        if std::mem::take(&mut input.cycle_palette) {
+            viewers.cycle_palette();
+        }
+        let mut running = true;
+        let mut emulated = 0;
+        if input.rewinding {
+            rewind.step_back(&mut cpu).map_err(|error| error.to_string())?;
+            emulated = 1;
+        } else {
+            let frames = playback.frames();
+            let deadline = Instant::now() + pacer.period();
+            while running && frames.map_or(Instant::now() < deadline, |frames| emulated < frames) {
+                running = emulate_frame(&mut cpu, &mut session, &mut mapper, &mut trace_log, &mut rewind)?;
+                emulated += 1;
+            }
+        }
+        if !running {
+            break;
+        }
+
+        let mut frame = cpu.ppu().frame_buffer().to_vec();
+        osd.set_status(if input.rewinding { Some("REWIND".to_string()) } else { playback.label() });
+        osd.draw(&mut frame, [cpu.controller(0).buttons(), cpu.controller(1).buttons()]);
+        osd.frame_presented(emulated);
+        texture.update(None, &frame, SCREEN_WIDTH * 3).map_err(|error| error.to_string())?;
+        canvas.clear();
+        canvas.copy(&texture, video.source(), video.destination(canvas.output_size()?))?;
+        // Only normal speed is audible; the other modes still queue silence to keep the timing.
+        let mode = if input.rewinding { Mode::Paused } else { playback.mode() };
+        if let Some(audio) = &mut audio {
+            audio.update(&mut cpu, mode != Mode::Normal)?;
+        }
+        match &audio {
+            _ if playback.uncapped() && !input.rewinding => {}
+            Some(audio) if audio.sync() && mode == Mode::Normal => audio.wait(),
+            _ => pacer.wait(),
+        }
+        canvas.present();
+        viewers.present(cpu.ppu())?;
+    }
+    if let Some(mut writer) = trace_log {
+        writer.flush().map_err(|error| format!("cannot write trace: {}", error))?;
     }
+    Ok(())
 }
 
-fn read_screen_state(cpu: &CPU, frame: &mut [u8; 32 * 3 * 32]) -> bool {
-    let mut frame_idx = 0;
-    let mut update = false;
-    for i in 0x0200..0x600 {
-        let color_idx = cpu.read(i as u16).unwrap();
-        let (b1, b2, b3) = color(color_idx).rgb();
-        if frame[frame_idx] != b1 || frame[frame_idx + 1] != b2 || frame[frame_idx + 2] != b3 {
-            frame[frame_idx] = b1;
-            frame[frame_idx + 1] = b2;
-            frame[frame_idx + 2] = b3;
-            update = true;
-        }
-        frame_idx += 3;
-    }
-    update
+/// Reports a failure on screen briefly and in full on the terminal.
+fn report_error(osd: &mut Osd, summary: &str, message: &str) {
+    eprintln!("error: {}", message);
+    osd.message(summary);
 }
 
-fn color(byte: u8) -> Color {
-    match byte {
-        0 => Color::BLACK,
-        1 => Color::WHITE,
-        2 | 9 => sdl2::pixels::Color::GREY,
-        3 | 10 => sdl2::pixels::Color::RED,
-        4 | 11 => sdl2::pixels::Color::GREEN,
-        5 | 12 => sdl2::pixels::Color::BLUE,
-        6 | 13 => sdl2::pixels::Color::MAGENTA,
-        7 | 14 => sdl2::pixels::Color::YELLOW,
-        _ => sdl2::pixels::Color::CYAN,
+#[derive(Default)]
+struct Input {
+    rewinding: bool,
+    save_state: bool,
+    load_state: bool,
+    volume_up: bool,
+    volume_down: bool,
+    toggle_mute: bool,
+    toggle_fps: bool,
+    toggle_input_display: bool,
+    toggle_view: Option<View>,
+    cycle_palette: bool,
+    quit: bool,
+}
+
+fn handle_user_input(event_pump: &mut EventPump, input: &mut Input, mapper: &mut InputMapper, playback: &mut Playback,
+                     viewers: &mut Viewers) {
+    for event in event_pump.poll_iter() {
+        match event {
+            ref event if viewers.handle_event(event) => {}
+            // Closing the main window quits even while viewers keep SDL from sending `Quit`.
+            Event::Window { win_event: WindowEvent::Close, .. } => input.quit = true,
+            Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => input.quit = true,
+            Event::KeyDown { keycode: Some(Keycode::Backspace), .. } => input.rewinding = true,
+            Event::KeyUp { keycode: Some(Keycode::Backspace), .. } => input.rewinding = false,
+            Event::KeyDown { keycode: Some(Keycode::F5), .. } => input.save_state = true,
+            Event::KeyDown { keycode: Some(Keycode::F7), .. } => input.load_state = true,
+            Event::KeyDown { keycode: Some(Keycode::Equals | Keycode::KpPlus), .. } => input.volume_up = true,
+            Event::KeyDown { keycode: Some(Keycode::Minus | Keycode::KpMinus), .. } => input.volume_down = true,
+            Event::KeyDown { keycode: Some(Keycode::M), .. } => input.toggle_mute = true,
+            Event::KeyDown { keycode: Some(Keycode::F2), .. } => input.toggle_fps = true,
+            Event::KeyDown { keycode: Some(Keycode::F3), .. } => input.toggle_input_display = true,
+            Event::KeyDown { keycode: Some(Keycode::F8), .. } => input.cycle_palette = true,
+            Event::KeyDown { keycode: Some(Keycode::F9), .. } => input.toggle_view = Some(View::PatternTables),
+            Event::KeyDown { keycode: Some(Keycode::F10), .. } => input.toggle_view = Some(View::Nametables),
+            Event::KeyDown { keycode: Some(Keycode::F11), .. } => input.toggle_view = Some(View::Sprites),
+            Event::KeyDown { keycode: Some(Keycode::F12), .. } => input.toggle_view = Some(View::Palettes),
+            Event::KeyDown { keycode: Some(Keycode::P | Keycode::Pause), repeat: false, .. } => playback.toggle_pause(),
+            Event::KeyDown { keycode: Some(Keycode::N), .. } => playback.advance_frame(),
+            Event::KeyDown { keycode: Some(Keycode::Tab), .. } => playback.hold_fast_forward(true),
+            Event::KeyUp { keycode: Some(Keycode::Tab), .. } => playback.hold_fast_forward(false),
+            Event::KeyDown { keycode: Some(Keycode::Backquote), repeat: false, .. } => playback.toggle_fast_forward(),
+            Event::KeyDown { keycode: Some(Keycode::Backslash), repeat: false, .. } => playback.toggle_slow_motion(),
+            event => mapper.handle_event(&event),
+        }
     }
-}
\ No newline at end of file
+}
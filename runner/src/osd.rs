@@ -0,0 +1,228 @@
+use std::time::{Duration, Instant};
+use emulator::common::constants::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use emulator::controller::{BUTTON_A, BUTTON_B, BUTTON_DOWN, BUTTON_LEFT, BUTTON_RIGHT, BUTTON_SELECT, BUTTON_START, BUTTON_UP};
+use crate::cli::Options;
+use crate::video::{frame_rate, OVERSCAN_LINES};
+
+const GLYPH_WIDTH: usize = 5;
+const GLYPH_HEIGHT: usize = 7;
+const ADVANCE: usize = GLYPH_WIDTH + 1;
+const LINE_HEIGHT: usize = GLYPH_HEIGHT + 3;
+const MARGIN: usize = 4;
+/// How long messages stay up, in displayed frames.
+const MESSAGE_FRAMES: u32 = 180;
+const MAX_MESSAGES: usize = 4;
+const MEASURE_PERIOD: Duration = Duration::from_millis(500);
+const WHITE: [u8; 3] = [0xFF, 0xFF, 0xFF];
+const GREY: [u8; 3] = [0x70, 0x70, 0x70];
+const BLACK: [u8; 3] = [0x00, 0x00, 0x00];
+const INPUT_LABELS: [(&str, u8); 8] = [
+    ("<", BUTTON_LEFT),
+    ("^", BUTTON_UP),
+    ("V", BUTTON_DOWN),
+    (">", BUTTON_RIGHT),
+    ("SE", BUTTON_SELECT),
+    ("ST", BUTTON_START),
+    ("B", BUTTON_B),
+    ("A", BUTTON_A),
+];
+
+/// A 5x7 font, one byte per row with the leftmost pixel in bit 4. Lowercase letters are drawn in
+/// uppercase and missing characters as '?'.
+const FONT: [(char, [u8; GLYPH_HEIGHT]); 58] = [
+    (' ', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    ('!', [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04]),
+    ('%', [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03]),
+    ('\'', [0x04, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00]),
+    ('(', [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02]),
+    (')', [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08]),
+    ('+', [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00]),
+    (',', [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08]),
+    ('-', [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00]),
+    ('.', [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C]),
+    ('/', [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00]),
+    ('0', [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E]),
+    ('1', [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('2', [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F]),
+    ('3', [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E]),
+    ('4', [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02]),
+    ('5', [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E]),
+    ('6', [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E]),
+    ('7', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08]),
+    ('8', [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E]),
+    ('9', [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C]),
+    (':', [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00]),
+    ('<', [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02]),
+    ('=', [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00]),
+    ('>', [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08]),
+    ('?', [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04]),
+    ('A', [0x0E, 0x11, 0x11, 0x11, 0x1F, 0x11, 0x11]),
+    ('B', [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E]),
+    ('C', [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E]),
+    ('D', [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C]),
+    ('E', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F]),
+    ('F', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10]),
+    ('G', [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F]),
+    ('H', [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11]),
+    ('I', [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('J', [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C]),
+    ('K', [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11]),
+    ('L', [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F]),
+    ('M', [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11]),
+    ('N', [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11]),
+    ('O', [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+    ('P', [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10]),
+    ('Q', [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D]),
+    ('R', [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11]),
+    ('S', [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E]),
+    ('T', [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04]),
+    ('U', [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+    ('V', [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04]),
+    ('W', [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A]),
+    ('X', [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11]),
+    ('Y', [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04]),
+    ('Z', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F]),
+    ('[', [0x0E, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0E]),
+    (']', [0x0E, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0E]),
+    ('^', [0x04, 0x0A, 0x11, 0x00, 0x00, 0x00, 0x00]),
+    ('_', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F]),
+    ('#', [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A]),
+    ('*', [0x00, 0x04, 0x15, 0x0E, 0x15, 0x04, 0x00]),
+];
+
+/// Text drawn over the emulated picture: timed messages, a status label for the playback mode,
+/// an FPS and emulation speed counter, and the buttons held on each controller.
+pub struct Osd {
+    top: usize,
+    bottom: usize,
+    frame_rate: f64,
+    show_fps: bool,
+    show_input: bool,
+    status: Option<String>,
+    messages: Vec<(String, u32)>,
+    measure_start: Instant,
+    displayed: u32,
+    emulated: u32,
+    fps: f64,
+    speed: f64,
+}
+
+impl Osd {
+    pub fn new(options: &Options) -> Self {
+        let crop = if options.crop_overscan { OVERSCAN_LINES as usize } else { 0 };
+        Osd {
+            top: crop + MARGIN,
+            bottom: SCREEN_HEIGHT - crop - MARGIN,
+            frame_rate: frame_rate(options.region),
+            show_fps: options.show_fps,
+            show_input: options.show_input,
+            status: None,
+            messages: Vec::new(),
+            measure_start: Instant::now(),
+            displayed: 0,
+            emulated: 0,
+            fps: 0.0,
+            speed: 0.0,
+        }
+    }
+
+    /// Shows `text` for a few seconds under any messages already up.
+    pub fn message(&mut self, text: impl Into<String>) {
+        if self.messages.len() == MAX_MESSAGES {
+            self.messages.remove(0);
+        }
+        self.messages.push((text.into(), MESSAGE_FRAMES));
+    }
+
+    pub fn set_status(&mut self, status: Option<String>) {
+        self.status = status;
+    }
+
+    pub fn toggle_fps(&mut self) {
+        self.show_fps = !self.show_fps;
+    }
+
+    pub fn toggle_input(&mut self) {
+        self.show_input = !self.show_input;
+    }
+
+    /// Counts a displayed frame for which `emulated` frames were run, and ages the messages.
+    pub fn frame_presented(&mut self, emulated: u32) {
+        self.displayed += 1;
+        self.emulated += emulated;
+        let elapsed = self.measure_start.elapsed();
+        if elapsed >= MEASURE_PERIOD {
+            let seconds = elapsed.as_secs_f64();
+            self.fps = self.displayed as f64 / seconds;
+            self.speed = self.emulated as f64 / seconds / self.frame_rate * 100.0;
+            self.measure_start = Instant::now();
+            self.displayed = 0;
+            self.emulated = 0;
+        }
+        self.messages.retain_mut(|(_, frames)| {
+            *frames -= 1;
+            *frames > 0
+        });
+    }
+
+    /// Draws the OSD onto an RGB frame of the NES picture, given the buttons held on each port.
+    pub fn draw(&self, frame: &mut [u8], buttons: [u8; 2]) {
+        if self.show_fps {
+            draw_text(frame, MARGIN, self.top, &format!("{:.0} FPS {:.0}%", self.fps, self.speed), WHITE);
+        }
+        if let Some(status) = &self.status {
+            draw_text(frame, SCREEN_WIDTH - MARGIN - text_width(status), self.top, status, WHITE);
+        }
+        let mut y = self.bottom - GLYPH_HEIGHT;
+        if self.show_input {
+            for (port, buttons) in buttons.iter().enumerate().rev() {
+                let mut x = MARGIN;
+                x += draw_text(frame, x, y, &format!("{}P", port + 1), WHITE) + ADVANCE;
+                for (label, button) in INPUT_LABELS {
+                    let color = if buttons & button != 0 { WHITE } else { GREY };
+                    x += draw_text(frame, x, y, label, color) + ADVANCE;
+                }
+                y -= LINE_HEIGHT;
+            }
+        }
+        for (text, _) in self.messages.iter().rev() {
+            draw_text(frame, MARGIN, y, text, WHITE);
+            y -= LINE_HEIGHT;
+        }
+    }
+}
+
+fn text_width(text: &str) -> usize {
+    (text.chars().count() * ADVANCE).saturating_sub(1)
+}
+
+/// Draws `text` with a drop shadow, clipped to the screen. Returns the width drawn.
+fn draw_text(frame: &mut [u8], x: usize, y: usize, text: &str, color: [u8; 3]) -> usize {
+    for (index, c) in text.chars().enumerate() {
+        let rows = glyph(c);
+        let left = x + index * ADVANCE;
+        draw_glyph(frame, left + 1, y + 1, &rows, BLACK);
+        draw_glyph(frame, left, y, &rows, color);
+    }
+    text_width(text)
+}
+
+fn draw_glyph(frame: &mut [u8], x: usize, y: usize, rows: &[u8; GLYPH_HEIGHT], color: [u8; 3]) {
+    for (row, bits) in rows.iter().enumerate() {
+        for column in 0..GLYPH_WIDTH {
+            let (px, py) = (x + column, y + row);
+            if bits & (0x10 >> column) != 0 && px < SCREEN_WIDTH && py < SCREEN_HEIGHT {
+                let offset = (py * SCREEN_WIDTH + px) * 3;
+                frame[offset..offset + 3].copy_from_slice(&color);
+            }
+        }
+    }
+}
+
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    let c = c.to_ascii_uppercase();
+    FONT.iter().find(|(glyph, _)| *glyph == c)
+        .or_else(|| FONT.iter().find(|(glyph, _)| *glyph == '?'))
+        .map(|(_, rows)| *rows)
+        .unwrap_or_default()
+}
@@ -0,0 +1,104 @@
+use crate::cli::Options;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Normal,
+    Paused,
+    FastForward,
+    SlowMotion,
+}
+
+/// Tracks pause, frame advance, fast-forward and slow motion, and decides how many frames to
+/// emulate in each display period.
+pub struct Playback {
+    paused: bool,
+    frame_advance: bool,
+    fast_forward_held: bool,
+    fast_forward_toggled: bool,
+    slow_motion: bool,
+    /// Frames per display period while fast-forwarding, or `None` to run as fast as possible.
+    fast_forward_speed: Option<u32>,
+    /// Display periods per frame in slow motion.
+    slow_motion_divisor: u32,
+    periods: u32,
+}
+
+impl Playback {
+    pub fn new(options: &Options) -> Self {
+        Playback {
+            paused: false,
+            frame_advance: false,
+            fast_forward_held: false,
+            fast_forward_toggled: false,
+            slow_motion: false,
+            fast_forward_speed: options.fast_forward,
+            slow_motion_divisor: options.slow_motion,
+            periods: 0,
+        }
+    }
+
+    /// Pausing wins over fast-forward, which wins over slow motion.
+    pub fn mode(&self) -> Mode {
+        if self.paused {
+            Mode::Paused
+        } else if self.fast_forward_held || self.fast_forward_toggled {
+            Mode::FastForward
+        } else if self.slow_motion {
+            Mode::SlowMotion
+        } else {
+            Mode::Normal
+        }
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    /// Emulates a single frame and pauses, or pauses first if running.
+    pub fn advance_frame(&mut self) {
+        self.frame_advance = self.paused;
+        self.paused = true;
+    }
+
+    pub fn hold_fast_forward(&mut self, held: bool) {
+        self.fast_forward_held = held;
+    }
+
+    pub fn toggle_fast_forward(&mut self) {
+        self.fast_forward_toggled = !self.fast_forward_toggled;
+    }
+
+    pub fn toggle_slow_motion(&mut self) {
+        self.slow_motion = !self.slow_motion;
+    }
+
+    /// A short description of the mode for the on-screen display, if it is not normal speed.
+    pub fn label(&self) -> Option<String> {
+        match self.mode() {
+            Mode::Normal => None,
+            Mode::Paused => Some("PAUSED".to_string()),
+            Mode::FastForward => Some(match self.fast_forward_speed {
+                Some(speed) => format!("FAST FORWARD {}X", speed),
+                None => "FAST FORWARD MAX".to_string(),
+            }),
+            Mode::SlowMotion => Some(format!("SLOW MOTION 1/{}", self.slow_motion_divisor)),
+        }
+    }
+
+    pub fn uncapped(&self) -> bool {
+        self.mode() == Mode::FastForward && self.fast_forward_speed.is_none()
+    }
+
+    /// The number of frames to emulate in the coming display period, or `None` for as many as fit.
+    pub fn frames(&mut self) -> Option<u32> {
+        match self.mode() {
+            Mode::Normal => Some(1),
+            Mode::Paused => Some(std::mem::take(&mut self.frame_advance) as u32),
+            Mode::FastForward => self.fast_forward_speed,
+            Mode::SlowMotion => {
+                self.periods = (self.periods + 1) % self.slow_motion_divisor;
+                Some((self.periods == 0) as u32)
+            }
+        }
+    }
+}
@@ -0,0 +1,243 @@
+//! A parser for the subset of TOML the runner's config files use: tables, bare or quoted keys,
+//! and string, integer, boolean and array values.
+
+mod test;
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Value::Integer(integer) => Some(*integer),
+            _ => None,
+        }
+    }
+}
+
+/// A `key = value` pair, with the table it appears under (empty before the first table header).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub table: String,
+    pub key: String,
+    pub value: Value,
+    pub line: usize,
+}
+
+pub fn parse(text: &str) -> Result<Vec<Entry>, String> {
+    let mut parser = Parser { chars: text.chars().peekable(), line: 1 };
+    let mut table = String::new();
+    let mut entries: Vec<Entry> = Vec::new();
+    loop {
+        parser.skip_blank();
+        match parser.peek() {
+            None => return Ok(entries),
+            Some('[') => {
+                parser.next();
+                parser.skip_spaces();
+                table = parser.key()?;
+                parser.skip_spaces();
+                parser.expect(']')?;
+                parser.end_of_line()?;
+            }
+            Some(_) => {
+                let line = parser.line;
+                let key = parser.key()?;
+                parser.skip_spaces();
+                parser.expect('=')?;
+                parser.skip_spaces();
+                let value = parser.value()?;
+                parser.end_of_line()?;
+                if entries.iter().any(|entry| entry.table == table && entry.key == key) {
+                    return Err(format!("line {}: duplicate key '{}'", line, key));
+                }
+                entries.push(Entry { table: table.clone(), key, value, line });
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let next = self.chars.next();
+        if next == Some('\n') {
+            self.line += 1;
+        }
+        next
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("line {}: {}", self.line, message)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.next() {
+            Some(found) if found == expected => Ok(()),
+            Some('\n') | None => Err(format!("line {}: expected '{}'", self.line - 1, expected)),
+            Some(found) => Err(self.error(&format!("expected '{}', found '{}'", expected, found))),
+        }
+    }
+
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.next();
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        if self.peek() == Some('#') {
+            while !matches!(self.peek(), Some('\n') | None) {
+                self.next();
+            }
+        }
+    }
+
+    /// Skips whitespace, line breaks and comments.
+    fn skip_blank(&mut self) {
+        loop {
+            self.skip_spaces();
+            self.skip_comment();
+            match self.peek() {
+                Some('\r' | '\n') => {
+                    self.next();
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), String> {
+        self.skip_spaces();
+        self.skip_comment();
+        if self.peek() == Some('\r') {
+            self.next();
+        }
+        match self.next() {
+            Some('\n') | None => Ok(()),
+            Some(found) => Err(self.error(&format!("unexpected '{}' after value", found))),
+        }
+    }
+
+    fn key(&mut self) -> Result<String, String> {
+        match self.peek() {
+            Some('"') => self.basic_string(),
+            Some('\'') => self.literal_string(),
+            _ => {
+                let mut key = String::new();
+                while let Some(c) = self.peek().filter(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '-') {
+                    key.push(c);
+                    self.next();
+                }
+                if key.is_empty() {
+                    return Err(self.error("expected a key"));
+                }
+                Ok(key)
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        match self.peek() {
+            Some('"') => self.basic_string().map(Value::String),
+            Some('\'') => self.literal_string().map(Value::String),
+            Some('[') => self.array(),
+            Some('t' | 'f') => {
+                let word = self.word();
+                match word.as_str() {
+                    "true" => Ok(Value::Boolean(true)),
+                    "false" => Ok(Value::Boolean(false)),
+                    _ => Err(self.error(&format!("invalid value '{}'", word))),
+                }
+            }
+            Some(c) if c.is_ascii_digit() || c == '+' || c == '-' => {
+                let word = self.word();
+                word.replace('_', "").parse().map(Value::Integer)
+                    .map_err(|_| self.error(&format!("invalid integer '{}'", word)))
+            }
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    fn word(&mut self) -> String {
+        let mut word = String::new();
+        while let Some(c) = self.peek().filter(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '+' | '-')) {
+            word.push(c);
+            self.next();
+        }
+        word
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        loop {
+            self.skip_blank();
+            if self.peek() == Some(']') {
+                self.next();
+                return Ok(Value::Array(values));
+            }
+            values.push(self.value()?);
+            self.skip_blank();
+            match self.next() {
+                Some(',') => {}
+                Some(']') => return Ok(Value::Array(values)),
+                _ => return Err(self.error("expected ',' or ']' in array")),
+            }
+        }
+    }
+
+    fn basic_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut string = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(string),
+                Some('\\') => string.push(match self.next() {
+                    Some('"') => '"',
+                    Some('\\') => '\\',
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    _ => return Err(self.error("unsupported escape sequence")),
+                }),
+                Some('\n') | None => return Err(self.error("unterminated string")),
+                Some(c) => string.push(c),
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String, String> {
+        self.expect('\'')?;
+        let mut string = String::new();
+        loop {
+            match self.next() {
+                Some('\'') => return Ok(string),
+                Some('\n') | None => return Err(self.error("unterminated string")),
+                Some(c) => string.push(c),
+            }
+        }
+    }
+}
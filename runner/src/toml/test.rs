@@ -0,0 +1,116 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod test {
+    use crate::toml::{parse, Entry, Value};
+
+    fn entry(table: &str, key: &str, value: Value, line: usize) -> Entry {
+        Entry { table: table.to_string(), key: key.to_string(), value, line }
+    }
+
+    fn string(value: &str) -> Value {
+        Value::String(value.to_string())
+    }
+
+    #[test]
+    fn test_parse_values() {
+        let text = "\
+top = 1
+[port1]
+a = \"X\"
+b = 'C:\\path'
+turbo = true
+off = false
+period = -1_000
+keys = [\"Up\", 'Down', 3]
+";
+        assert_eq!(parse(text).unwrap(), vec![
+            entry("", "top", Value::Integer(1), 1),
+            entry("port1", "a", string("X"), 3),
+            entry("port1", "b", string("C:\\path"), 4),
+            entry("port1", "turbo", Value::Boolean(true), 5),
+            entry("port1", "off", Value::Boolean(false), 6),
+            entry("port1", "period", Value::Integer(-1000), 7),
+            entry("port1", "keys", Value::Array(vec![string("Up"), string("Down"), Value::Integer(3)]), 8),
+        ]);
+    }
+
+    #[test]
+    fn test_comments_and_blank_lines() {
+        let text = "# header\n\n  [ port2 ]  # table\n\n# between\na = \"#\" # after\n";
+        assert_eq!(parse(text).unwrap(), vec![entry("port2", "a", string("#"), 6)]);
+        assert_eq!(parse("").unwrap(), vec![]);
+        assert_eq!(parse("# only a comment").unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_crlf_line_endings() {
+        let text = "# comment\r\n[port1]\r\na = [\"X\",\r\n  \"Y\"]\r\nb = 2\r\n";
+        assert_eq!(parse(text).unwrap(), vec![
+            entry("port1", "a", Value::Array(vec![string("X"), string("Y")]), 3),
+            entry("port1", "b", Value::Integer(2), 5),
+        ]);
+    }
+
+    #[test]
+    fn test_quoted_keys_and_escapes() {
+        let text = "\"key with spaces\" = \"tab\\tquote\\\"backslash\\\\\"\n'literal' = 1\n[\"quoted table\"]\nx = 2\n";
+        assert_eq!(parse(text).unwrap(), vec![
+            entry("", "key with spaces", string("tab\tquote\"backslash\\"), 1),
+            entry("", "literal", Value::Integer(1), 2),
+            entry("quoted table", "x", Value::Integer(2), 4),
+        ]);
+    }
+
+    #[test]
+    fn test_arrays_spanning_lines() {
+        let text = "\
+up = [
+    \"Up\",    # arrow key
+    \"pad:dpup\",
+]
+down = [
+]
+after = 1
+";
+        assert_eq!(parse(text).unwrap(), vec![
+            entry("", "up", Value::Array(vec![string("Up"), string("pad:dpup")]), 1),
+            entry("", "down", Value::Array(vec![]), 5),
+            entry("", "after", Value::Integer(1), 7),
+        ]);
+    }
+
+    #[test]
+    fn test_duplicate_keys() {
+        assert_eq!(parse("a = 1\n\nA = 2\na = 3\n").unwrap_err(), "line 4: duplicate key 'a'");
+        assert_eq!(parse("[port1]\na = 1\n[port1]\na = 2\n").unwrap_err(), "line 4: duplicate key 'a'");
+        assert_eq!(parse("a = 1\n[port1]\na = 2\n").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_error_line_numbers() {
+        let errors = [
+            ("a = 1\nb 2\n", "line 2: expected '=', found '2'"),
+            ("a = 1\n\nb =\n", "line 3: expected a value"),
+            ("a = 1\nb = \"open\n", "line 3: unterminated string"),
+            ("a = 1 2\n", "line 1: unexpected '2' after value"),
+            ("a = tru\n", "line 1: invalid value 'tru'"),
+            ("a = 12x\n", "line 1: invalid integer '12x'"),
+            ("a = \"\\q\"\n", "line 1: unsupported escape sequence"),
+            ("[port1\na = 1\n", "line 1: expected ']'"),
+            ("= 1\n", "line 1: expected a key"),
+            ("a = [\n  1,\n  2\n  3\n]\n", "line 4: expected ',' or ']' in array"),
+            ("a = [1,\n\n", "line 3: expected a value"),
+        ];
+        for (text, message) in errors {
+            assert_eq!(parse(text).unwrap_err(), message, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn test_value_accessors() {
+        assert_eq!(string("X").as_str(), Some("X"));
+        assert_eq!(Value::Integer(1).as_str(), None);
+        assert_eq!(Value::Integer(1).as_integer(), Some(1));
+        assert_eq!(Value::Boolean(true).as_integer(), None);
+    }
+}
@@ -0,0 +1,85 @@
+use std::thread;
+use std::time::{Duration, Instant};
+use sdl2::rect::Rect;
+use emulator::common::constants::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::cli::{Options, Region};
+
+const NTSC_FRAME_RATE: f64 = 60.0988;
+const PAL_FRAME_RATE: f64 = 50.007;
+const PIXEL_ASPECT_RATIO: f64 = 8.0 / 7.0;
+/// Lines hidden at the top and at the bottom by most TVs.
+pub const OVERSCAN_LINES: u32 = 8;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Video {
+    scale: u32,
+    aspect_correction: bool,
+    crop_overscan: bool,
+}
+
+impl Video {
+    pub fn new(options: &Options) -> Self {
+        Video { scale: options.scale, aspect_correction: options.aspect_correction, crop_overscan: options.crop_overscan }
+    }
+
+    /// The part of the frame buffer that is shown.
+    pub fn source(&self) -> Rect {
+        let crop = if self.crop_overscan { OVERSCAN_LINES } else { 0 };
+        Rect::new(0, crop as i32, SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32 - 2 * crop)
+    }
+
+    pub fn window_size(&self) -> (u32, u32) {
+        let source = self.source();
+        (self.scaled_width(source.width(), self.scale), source.height() * self.scale)
+    }
+
+    /// The largest integer scale of the picture that fits in `output`, centred.
+    pub fn destination(&self, (width, height): (u32, u32)) -> Rect {
+        let source = self.source();
+        let scale = (1..).take_while(|scale| self.scaled_width(source.width(), *scale) <= width && source.height() * scale <= height)
+            .last()
+            .unwrap_or(1);
+        let (picture_width, picture_height) = (self.scaled_width(source.width(), scale), source.height() * scale);
+        Rect::new((width as i32 - picture_width as i32) / 2, (height as i32 - picture_height as i32) / 2, picture_width, picture_height)
+    }
+
+    fn scaled_width(&self, width: u32, scale: u32) -> u32 {
+        let aspect = if self.aspect_correction { PIXEL_ASPECT_RATIO } else { 1.0 };
+        (width as f64 * scale as f64 * aspect).round() as u32
+    }
+}
+
+pub fn frame_rate(region: Region) -> f64 {
+    match region {
+        Region::Ntsc => NTSC_FRAME_RATE,
+        Region::Pal => PAL_FRAME_RATE,
+    }
+}
+
+/// Paces emulation at the console's refresh rate rather than the host display's.
+pub struct FramePacer {
+    period: Duration,
+    next: Instant,
+}
+
+impl FramePacer {
+    pub fn new(region: Region) -> Self {
+        FramePacer { period: Duration::from_secs_f64(1.0 / frame_rate(region)), next: Instant::now() }
+    }
+
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Sleeps until the next frame is due. After falling more than a frame behind, for example
+    /// while the window was being dragged, the schedule restarts instead of racing to catch up.
+    pub fn wait(&mut self) {
+        let now = Instant::now();
+        if self.next > now {
+            thread::sleep(self.next - now);
+        } else if now - self.next > self.period {
+            self.next = now;
+        }
+        self.next += self.period;
+    }
+}
@@ -0,0 +1,115 @@
+use sdl2::VideoSubsystem;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use emulator::ppu::PPU;
+use emulator::ppu::viewer::{NAMETABLES_HEIGHT, NAMETABLES_WIDTH, PALETTES_HEIGHT, PALETTES_WIDTH, PATTERN_TABLE_SIZE, SPRITES_HEIGHT, SPRITES_WIDTH};
+
+const PALETTES: u8 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum View {
+    PatternTables,
+    Nametables,
+    Sprites,
+    Palettes,
+}
+
+impl View {
+    fn title(self) -> &'static str {
+        match self {
+            View::PatternTables => "Pattern tables",
+            View::Nametables => "Nametables",
+            View::Sprites => "Sprites",
+            View::Palettes => "Palettes",
+        }
+    }
+
+    fn size(self) -> (usize, usize) {
+        match self {
+            View::PatternTables => (2 * PATTERN_TABLE_SIZE, PATTERN_TABLE_SIZE),
+            View::Nametables => (NAMETABLES_WIDTH, NAMETABLES_HEIGHT),
+            View::Sprites => (SPRITES_WIDTH, SPRITES_HEIGHT),
+            View::Palettes => (PALETTES_WIDTH, PALETTES_HEIGHT),
+        }
+    }
+
+    /// The larger views are shown at their own size, the small ones enlarged.
+    fn scale(self) -> u32 {
+        match self {
+            View::Nametables => 1,
+            View::PatternTables | View::Palettes => 2,
+            View::Sprites => 4,
+        }
+    }
+}
+
+/// The debug windows showing the PPU's pattern tables, nametables, sprites and palettes, each
+/// opened and closed on its own.
+#[derive(Default)]
+pub struct Viewers {
+    windows: Vec<(View, Canvas<Window>)>,
+    /// The palette the pattern tables are drawn with, 0 to 3 for the background and 4 to 7 for
+    /// sprites.
+    palette: u8,
+}
+
+impl Viewers {
+    pub fn toggle(&mut self, subsystem: &VideoSubsystem, view: View) -> Result<(), String> {
+        if let Some(index) = self.windows.iter().position(|(open, _)| *open == view) {
+            self.windows.remove(index);
+            return Ok(());
+        }
+        let (width, height) = view.size();
+        let window = subsystem.window(view.title(), width as u32 * view.scale(), height as u32 * view.scale())
+            .resizable()
+            .build()
+            .map_err(|error| error.to_string())?;
+        let canvas = window.into_canvas().build().map_err(|error| error.to_string())?;
+        self.windows.push((view, canvas));
+        Ok(())
+    }
+
+    pub fn cycle_palette(&mut self) {
+        self.palette = (self.palette + 1) % PALETTES;
+    }
+
+    /// Closes a viewer whose close button was clicked. Returns whether the event was for one.
+    pub fn handle_event(&mut self, event: &Event) -> bool {
+        if let Event::Window { window_id, win_event: WindowEvent::Close, .. } = event {
+            if let Some(index) = self.windows.iter().position(|(_, canvas)| canvas.window().id() == *window_id) {
+                self.windows.remove(index);
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn present(&mut self, ppu: &PPU) -> Result<(), String> {
+        for (view, canvas) in &mut self.windows {
+            let (width, height) = view.size();
+            let image = match view {
+                View::PatternTables => side_by_side(&ppu.pattern_table_image(0, self.palette), &ppu.pattern_table_image(1, self.palette), PATTERN_TABLE_SIZE),
+                View::Nametables => ppu.nametables_image(),
+                View::Sprites => ppu.sprites_image(),
+                View::Palettes => ppu.palettes_image(),
+            };
+            let creator = canvas.texture_creator();
+            let mut texture = creator.create_texture_streaming(PixelFormatEnum::RGB24, width as u32, height as u32)
+                .map_err(|error| error.to_string())?;
+            texture.update(None, &image, width * 3).map_err(|error| error.to_string())?;
+            canvas.clear();
+            canvas.copy(&texture, None, None)?;
+            canvas.present();
+        }
+        Ok(())
+    }
+}
+
+/// Joins two square RGB images of the same size into one twice as wide.
+fn side_by_side(left: &[u8], right: &[u8], size: usize) -> Vec<u8> {
+    left.chunks(size * 3).zip(right.chunks(size * 3))
+        .flat_map(|(left, right)| left.iter().chain(right).copied())
+        .collect()
+}